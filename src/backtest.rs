@@ -0,0 +1,408 @@
+use std::fmt;
+
+use crate::model::{ModelError, execute_buy, execute_sell, execute_sell_all};
+
+/// Price of a coin at a point in time.
+pub struct PricePoint {
+    pub timestamp: String,
+    pub price: f64,
+}
+
+/// Rule-based strategies that can be replayed over a price series. Periods
+/// are counted in price points.
+#[derive(Debug, Clone, Copy)]
+pub enum Strategy {
+    /// Buy `amount` euros every `period` price points.
+    Dca { amount: f64, period: usize },
+    /// Go all in when the short moving average crosses above the long one
+    /// and sell everything when it crosses below.
+    MaCrossover { short: usize, long: usize },
+    /// Keep `target` (0 to 1) of the portfolio value in the coin, trading
+    /// back to it whenever the weight drifts more than `threshold`.
+    Rebalance { target: f64, threshold: f64 },
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum, poise::ChoiceParameter)]
+pub enum StrategyKind {
+    #[name = "DCA"]
+    Dca,
+    #[name = "Moving average crossover"]
+    MaCrossover,
+    #[name = "Rebalance"]
+    Rebalance,
+}
+
+/// Optional strategy parameters, percentages are in 0 to 100.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StrategyParams {
+    /// DCA euros per buy, defaults to a tenth of the capital.
+    pub amount: Option<f64>,
+    /// DCA interval or short moving average period, in price points.
+    pub period: Option<usize>,
+    /// Long moving average period, in price points.
+    pub long_period: Option<usize>,
+    /// Rebalance target coin weight.
+    pub target: Option<f64>,
+    /// Rebalance drift threshold.
+    pub threshold: Option<f64>,
+}
+
+impl StrategyKind {
+    pub fn strategy(self, capital: f64, params: StrategyParams) -> Strategy {
+        match self {
+            StrategyKind::Dca => Strategy::Dca {
+                amount: params.amount.unwrap_or(capital / 10f64),
+                period: params.period.unwrap_or(1),
+            },
+            StrategyKind::MaCrossover => Strategy::MaCrossover {
+                short: params.period.unwrap_or(5),
+                long: params.long_period.unwrap_or(20),
+            },
+            StrategyKind::Rebalance => Strategy::Rebalance {
+                target: params.target.unwrap_or(50f64) / 100f64,
+                threshold: params.threshold.unwrap_or(5f64) / 100f64,
+            },
+        }
+    }
+}
+
+pub struct BacktestReport {
+    pub start: String,
+    pub end: String,
+    pub final_value: f64,
+    pub return_pct: f64,
+    pub buy_and_hold_return_pct: f64,
+    pub max_drawdown_pct: f64,
+    pub trades: u32,
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Period:       {} to {}", self.start, self.end)?;
+        writeln!(f, "Final value:  {:.2} euros", self.final_value)?;
+        writeln!(f, "Return:       {:+.2}%", self.return_pct)?;
+        writeln!(f, "Buy and hold: {:+.2}%", self.buy_and_hold_return_pct)?;
+        writeln!(f, "Max drawdown: {:.2}%", self.max_drawdown_pct)?;
+        write!(f, "Trades:       {}", self.trades)
+    }
+}
+
+/// Simulated account the strategy trades with.
+struct Simulation {
+    cash: f64,
+    coins: f64,
+    trades: u32,
+}
+
+impl Simulation {
+    fn value(&self, price: f64) -> f64 {
+        self.cash + self.coins * price
+    }
+
+    fn buy(&mut self, euro_amount: f64, price: f64) {
+        // Strategies may ask for more than available, those trades are just
+        // skipped like a real /buy would be rejected.
+        if let Ok(execution) = execute_buy(self.cash, euro_amount, price) {
+            self.cash -= execution.euro_amount;
+            self.coins += execution.coin_amount;
+            self.trades += 1;
+        }
+    }
+
+    fn sell(&mut self, euro_amount: f64, price: f64) {
+        if let Ok(execution) = execute_sell(self.coins, euro_amount, price) {
+            self.cash += execution.euro_amount;
+            self.coins -= execution.coin_amount;
+            self.trades += 1;
+        }
+    }
+
+    fn sell_all(&mut self, price: f64) {
+        if let Ok(execution) = execute_sell_all(self.coins, price) {
+            self.cash += execution.euro_amount;
+            self.coins -= execution.coin_amount;
+            self.trades += 1;
+        }
+    }
+}
+
+fn moving_average(prices: &[PricePoint], end: usize, period: usize) -> Option<f64> {
+    if period == 0 || end + 1 < period {
+        return None;
+    }
+    let window = &prices[end + 1 - period..=end];
+    Some(window.iter().map(|point| point.price).sum::<f64>() / period as f64)
+}
+
+/// Replay `strategy` over `prices` starting with `capital` euros. Each price
+/// point is one tick of the simulated clock and trades go through the same
+/// execution rules as /buy and /sell.
+pub fn run(
+    prices: &[PricePoint],
+    strategy: Strategy,
+    capital: f64,
+) -> Result<BacktestReport, ModelError> {
+    if prices.len() < 2 {
+        return Err(ModelError::InvalidValue("not enough price history.".into()));
+    }
+    if capital <= 0f64 {
+        return Err(ModelError::InvalidValue(
+            "'capital' must be positive.".into(),
+        ));
+    }
+    if prices.iter().any(|point| point.price <= 0f64) {
+        return Err(ModelError::InvalidValue("prices must be positive.".into()));
+    }
+    match strategy {
+        Strategy::Dca { amount, period } if amount <= 0f64 || period == 0 => {
+            return Err(ModelError::InvalidValue(
+                "'amount' and 'period' must be positive.".into(),
+            ));
+        }
+        Strategy::MaCrossover { short, long } if short == 0 || short >= long => {
+            return Err(ModelError::InvalidValue(
+                "'short' must be positive and lower than 'long'.".into(),
+            ));
+        }
+        Strategy::Rebalance { target, threshold }
+            if !(0f64..=1f64).contains(&target) || threshold <= 0f64 =>
+        {
+            return Err(ModelError::InvalidValue(
+                "'target' must be between 0 and 100 and 'threshold' positive.".into(),
+            ));
+        }
+        _ => {}
+    }
+
+    let mut simulation = Simulation {
+        cash: capital,
+        coins: 0f64,
+        trades: 0,
+    };
+    let mut peak_value = capital;
+    let mut max_drawdown = 0f64;
+
+    for (tick, point) in prices.iter().enumerate() {
+        let price = point.price;
+
+        match strategy {
+            Strategy::Dca { amount, period } => {
+                if tick % period == 0 {
+                    simulation.buy(amount.min(simulation.cash), price);
+                }
+            }
+            Strategy::MaCrossover { short, long } => {
+                let current = (
+                    moving_average(prices, tick, short),
+                    moving_average(prices, tick, long),
+                );
+                let previous = match tick {
+                    0 => (None, None),
+                    _ => (
+                        moving_average(prices, tick - 1, short),
+                        moving_average(prices, tick - 1, long),
+                    ),
+                };
+                if let (
+                    (Some(short_ma), Some(long_ma)),
+                    (Some(prev_short_ma), Some(prev_long_ma)),
+                ) = (current, previous)
+                {
+                    if prev_short_ma <= prev_long_ma && short_ma > long_ma {
+                        simulation.buy(simulation.cash, price);
+                    } else if prev_short_ma >= prev_long_ma && short_ma < long_ma {
+                        simulation.sell_all(price);
+                    }
+                }
+            }
+            Strategy::Rebalance { target, threshold } => {
+                let value = simulation.value(price);
+                let weight = simulation.coins * price / value;
+                if (weight - target).abs() > threshold || tick == 0 {
+                    let target_coin_value = value * target;
+                    let coin_value = simulation.coins * price;
+                    if target_coin_value > coin_value {
+                        simulation.buy(target_coin_value - coin_value, price);
+                    } else if target_coin_value < coin_value {
+                        simulation.sell(coin_value - target_coin_value, price);
+                    }
+                }
+            }
+        }
+
+        let value = simulation.value(price);
+        peak_value = peak_value.max(value);
+        max_drawdown = max_drawdown.max((peak_value - value) / peak_value);
+    }
+
+    let first = &prices[0];
+    let last = &prices[prices.len() - 1];
+    let final_value = simulation.value(last.price);
+    Ok(BacktestReport {
+        start: first.timestamp.clone(),
+        end: last.timestamp.clone(),
+        final_value,
+        return_pct: (final_value / capital - 1f64) * 100f64,
+        buy_and_hold_return_pct: (last.price / first.price - 1f64) * 100f64,
+        max_drawdown_pct: max_drawdown * 100f64,
+        trades: simulation.trades,
+    })
+}
+
+/// Parse a price series from CSV lines of `timestamp,price`. A header line
+/// is allowed.
+pub fn parse_csv(content: &str) -> anyhow::Result<Vec<PricePoint>> {
+    let mut prices = Vec::new();
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((timestamp, price)) = line.rsplit_once(',') else {
+            anyhow::bail!(
+                "Line {} is not formatted as timestamp,price",
+                line_number + 1
+            );
+        };
+        match price.trim().parse::<f64>() {
+            Ok(price) => prices.push(PricePoint {
+                timestamp: timestamp.trim().into(),
+                price,
+            }),
+            // Skip header
+            Err(_) if line_number == 0 => {}
+            Err(_) => anyhow::bail!("Line {} has an invalid price", line_number + 1),
+        }
+    }
+
+    Ok(prices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(prices: &[f64]) -> Vec<PricePoint> {
+        prices
+            .iter()
+            .enumerate()
+            .map(|(tick, price)| PricePoint {
+                timestamp: format!("2024-01-0{} 00:00:00", tick + 1),
+                price: *price,
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn dca_buys_every_period() {
+        let prices = series(&[10f64, 20f64, 10f64, 20f64]);
+        let strategy = Strategy::Dca {
+            amount: 25f64,
+            period: 1,
+        };
+
+        let report = run(&prices, strategy, 100f64).unwrap();
+
+        // 2.5 + 1.25 + 2.5 + 1.25 coins valued at 20 euros.
+        assert_close(report.final_value, 150f64);
+        assert_close(report.return_pct, 50f64);
+        assert_close(report.buy_and_hold_return_pct, 100f64);
+        // Peak of 125 euros down to 87.5 euros.
+        assert_close(report.max_drawdown_pct, 30f64);
+        assert_eq!(report.trades, 4);
+        assert_eq!(report.start, "2024-01-01 00:00:00");
+        assert_eq!(report.end, "2024-01-04 00:00:00");
+    }
+
+    #[test]
+    fn dca_skips_buys_without_cash() {
+        let prices = series(&[10f64, 10f64, 10f64, 10f64]);
+        let strategy = Strategy::Dca {
+            amount: 60f64,
+            period: 2,
+        };
+
+        let report = run(&prices, strategy, 100f64).unwrap();
+
+        assert_close(report.final_value, 100f64);
+        assert_eq!(report.trades, 2);
+    }
+
+    #[test]
+    fn ma_crossover_trades_on_crosses() {
+        let prices = series(&[10f64, 8f64, 12f64, 6f64]);
+        let strategy = Strategy::MaCrossover { short: 1, long: 2 };
+
+        let report = run(&prices, strategy, 100f64).unwrap();
+
+        // Buys everything at 12 and sells everything at 6.
+        assert_close(report.final_value, 50f64);
+        assert_close(report.return_pct, -50f64);
+        assert_close(report.max_drawdown_pct, 50f64);
+        assert_eq!(report.trades, 2);
+    }
+
+    #[test]
+    fn rebalance_keeps_target_weight() {
+        let prices = series(&[10f64, 20f64, 20f64]);
+        let strategy = Strategy::Rebalance {
+            target: 0.5f64,
+            threshold: 0.05f64,
+        };
+
+        let report = run(&prices, strategy, 100f64).unwrap();
+
+        // 5 coins bought at 10, 1.25 sold at 20 to get back to 50%.
+        assert_close(report.final_value, 150f64);
+        assert_eq!(report.trades, 2);
+    }
+
+    #[test]
+    fn invalid_inputs_are_rejected() {
+        let dca = Strategy::Dca {
+            amount: 10f64,
+            period: 1,
+        };
+
+        assert!(matches!(
+            run(&series(&[10f64]), dca, 100f64),
+            Err(ModelError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            run(&series(&[10f64, 11f64]), dca, 0f64),
+            Err(ModelError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            run(&series(&[10f64, 0f64]), dca, 100f64),
+            Err(ModelError::InvalidValue(_))
+        ));
+        assert!(matches!(
+            run(
+                &series(&[10f64, 11f64]),
+                Strategy::MaCrossover { short: 5, long: 5 },
+                100f64
+            ),
+            Err(ModelError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn csv_header_is_skipped() {
+        let prices = parse_csv("timestamp,price\n2024-01-01,10\n\n2024-01-02, 12.5\n").unwrap();
+
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[1].timestamp, "2024-01-02");
+        assert_close(prices[1].price, 12.5f64);
+        assert!(parse_csv("2024-01-01,10\n2024-01-02,abc").is_err());
+    }
+}
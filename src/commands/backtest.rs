@@ -0,0 +1,50 @@
+use crate::{
+    Context, Error,
+    backtest::{self, StrategyKind, StrategyParams},
+    model::ModelError,
+};
+
+/// Replay a trading strategy over the recorded price history of a coin.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+#[allow(clippy::too_many_arguments)]
+pub async fn backtest(
+    ctx: Context<'_>,
+    #[description = "Coin symbol"] coin_symbol: String,
+    #[description = "Strategy to replay"] strategy: StrategyKind,
+    #[description = "Starting capital in euros"] capital: f64,
+    #[description = "DCA euros per buy"] amount: Option<f64>,
+    #[description = "DCA interval or short moving average period, in prices"] period: Option<usize>,
+    #[description = "Long moving average period, in prices"] long_period: Option<usize>,
+    #[description = "Rebalance target coin weight in percent"] target: Option<f64>,
+    #[description = "Rebalance drift threshold in percent"] threshold: Option<f64>,
+) -> Result<(), Error> {
+    let prices = ctx.data().price_history(&coin_symbol).await?;
+    let strategy = strategy.strategy(
+        capital,
+        StrategyParams {
+            amount,
+            period,
+            long_period,
+            target,
+            threshold,
+        },
+    );
+
+    match backtest::run(&prices, strategy, capital) {
+        Ok(report) => {
+            ctx.say(format!(
+                "Backtest of **{}** over `{}` prices\n```\n{}\n```",
+                coin_symbol.to_uppercase(),
+                prices.len(),
+                report
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
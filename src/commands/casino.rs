@@ -0,0 +1,723 @@
+use std::time::{Duration, Instant};
+
+use poise::{
+    CreateReply, ReplyHandle,
+    futures_util::StreamExt,
+    serenity_prelude::{
+        ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+        CreateInteractionResponse, CreateInteractionResponseMessage, User, UserId,
+    },
+};
+use tracing::{error, info};
+
+use crate::{
+    Context, Error,
+    commands::get_user_name,
+    model::{
+        BlackjackAction, BlackjackGame, CrashBet, CrashResult, DuelGame, DuelResult, HandResult,
+        ModelError, RouletteBet, RouletteSpin, SeedOwner,
+    },
+};
+
+fn blackjack_message(game: &BlackjackGame) -> String {
+    let mut message = match &game.outcome {
+        Some(_) => format!("**Dealer:** {}\n", game.dealer),
+        // Hole card stays hidden until the dealer plays
+        None => format!("**Dealer:** {} 🂠\n", game.dealer.cards[0]),
+    };
+
+    for (index, hand) in game.hands.iter().enumerate() {
+        let result = match game.outcome.as_ref().map(|outcome| outcome.results[index]) {
+            Some(HandResult::Blackjack) => " - **Blackjack!**",
+            Some(HandResult::Win) => " - **Win**",
+            Some(HandResult::Push) => " - **Push**",
+            Some(HandResult::Lose) => " - **Lose**",
+            Some(HandResult::Bust) => " - **Bust**",
+            None if index == game.active_hand => " ◀",
+            None => "",
+        };
+        message += &format!(
+            "**Hand {}:** {} (bet `{}` euros){}\n",
+            index + 1,
+            hand.hand,
+            hand.bet,
+            result
+        );
+    }
+
+    if let Some(outcome) = &game.outcome {
+        let net = outcome.payout - outcome.total_bet;
+        message += &if net > 0f64 {
+            format!("You won {} euros :euro:", net)
+        } else if net < 0f64 {
+            format!("Ups, you lost {} euros", -net)
+        } else {
+            "Your bet was returned".to_string()
+        };
+    }
+    if let Some(roll_id) = game.roll_id {
+        message += &format!("\n-# Roll #{}", roll_id);
+    }
+
+    message
+}
+
+fn blackjack_buttons(game: &BlackjackGame, prefix: &str) -> Vec<CreateActionRow> {
+    if game.outcome.is_some() {
+        return vec![];
+    }
+
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}hit", prefix))
+            .label("Hit")
+            .style(ButtonStyle::Primary),
+        CreateButton::new(format!("{}stand", prefix))
+            .label("Stand")
+            .style(ButtonStyle::Secondary),
+        CreateButton::new(format!("{}double", prefix))
+            .label("Double")
+            .style(ButtonStyle::Success)
+            .disabled(!game.can_double()),
+        CreateButton::new(format!("{}split", prefix))
+            .label("Split")
+            .style(ButtonStyle::Success)
+            .disabled(!game.can_split()),
+    ])]
+}
+
+/// Play blackjack against the dealer.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn blackjack(
+    ctx: Context<'_>,
+    #[description = "Bet amount in euros"] bet: f64,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id.get();
+    let mut game = match ctx.data().blackjack_start(user_id, bet).await {
+        Ok(game) => game,
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    // Button ids are prefixed with the invocation id so that only this
+    // game's buttons are collected.
+    let prefix = format!("{}-", ctx.id());
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .content(blackjack_message(&game))
+                .components(blackjack_buttons(&game, &prefix)),
+        )
+        .await?;
+
+    while game.outcome.is_none() {
+        let collector_prefix = prefix.clone();
+        let Some(interaction) = ComponentInteractionCollector::new(ctx)
+            .author_id(ctx.author().id)
+            .channel_id(ctx.channel_id())
+            .timeout(ctx.data().blackjack_timeout())
+            .filter(move |interaction| interaction.data.custom_id.starts_with(&collector_prefix))
+            .await
+        else {
+            break;
+        };
+
+        let action = match &interaction.data.custom_id[prefix.len()..] {
+            "hit" => BlackjackAction::Hit,
+            "stand" => BlackjackAction::Stand,
+            "double" => BlackjackAction::Double,
+            _ => BlackjackAction::Split,
+        };
+
+        match ctx.data().blackjack_play(user_id, action).await {
+            Ok(new_game) => game = new_game,
+            Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+                interaction
+                    .create_response(
+                        ctx,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(error.to_string())
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await?;
+                continue;
+            }
+            Err(error) => Err(error)?,
+        }
+
+        interaction
+            .create_response(
+                ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .content(blackjack_message(&game))
+                        .components(blackjack_buttons(&game, &prefix)),
+                ),
+            )
+            .await?;
+    }
+
+    // Timed out waiting for an action
+    if game.outcome.is_none()
+        && let Some(game) = ctx.data().blackjack_expire(user_id).await?
+    {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content(format!(
+                        "Timed out, standing on every hand\n{}",
+                        blackjack_message(&game)
+                    ))
+                    .components(vec![]),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn roulette_message(spin: &RouletteSpin) -> String {
+    let mut message = format!("The ball landed on **{}** ({})", spin.number, spin.color());
+    let winners: Vec<String> = spin
+        .wagers
+        .iter()
+        .filter(|wager| wager.payout > 0f64)
+        .map(|wager| {
+            format!(
+                "<@{}> won `{}` euros on **{}**",
+                wager.user_id,
+                wager.payout - wager.amount,
+                wager.bet
+            )
+        })
+        .collect();
+
+    if winners.is_empty() {
+        message += "\nNo winners this round";
+    } else {
+        message += &format!("\n{}", winners.join("\n"));
+    }
+    message += &format!("\n-# Roll #{}, house nonce `{}`", spin.roll_id, spin.nonce);
+    message
+}
+
+/// Spin the slot machine, lost bets feed the progressive jackpot.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn slots(
+    ctx: Context<'_>,
+    #[description = "Bet amount in euros"] bet: f64,
+) -> Result<(), Error> {
+    match ctx.data().slots_spin(ctx.author().id.get(), bet).await {
+        Ok(spin) => {
+            let result = match spin.jackpot_won {
+                Some(jackpot) => format!("**JACKPOT!** You won {} euros :euro:", jackpot),
+                None if spin.payout > bet => {
+                    format!("You won {} euros :euro:", spin.payout - bet)
+                }
+                None if spin.payout > 0f64 => format!("You got {} euros back", spin.payout),
+                None => format!("Ups, you lost {} euros", bet),
+            };
+            ctx.say(format!(
+                "| {} |\n{}\n**Jackpot:** `{:.2}` euros\n-# Roll #{}",
+                spin.symbols.join(" | "),
+                result,
+                spin.jackpot,
+                spin.roll_id
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Bet on the roulette table of this channel: number, split (17-20), red, black, odd, even, dozen1-3.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn roulette(
+    ctx: Context<'_>,
+    #[description = "Number, split (17-20), red, black, odd, even or dozen1 to dozen3"] bet: String,
+    #[description = "Bet amount in euros"] amount: f64,
+) -> Result<(), Error> {
+    let roulette_bet = match RouletteBet::parse(&bet) {
+        Ok(roulette_bet) => roulette_bet,
+        Err(error) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let channel_id = ctx.channel_id();
+    let opened_round = match ctx
+        .data()
+        .roulette_bet(
+            channel_id.get(),
+            ctx.author().id.get(),
+            roulette_bet,
+            amount,
+        )
+        .await
+    {
+        Ok(opened_round) => opened_round,
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    let round_secs = ctx.data().roulette_round_secs();
+    if opened_round {
+        let house_seed = ctx.data().fairness_seed(SeedOwner::House).await?;
+        ctx.say(format!(
+            "**{}** opened a roulette round with `{}` euros on **{}**\nThe wheel spins in {} seconds, place your bets!\n-# House server seed hash `{}`",
+            ctx.author().name,
+            amount,
+            roulette_bet,
+            round_secs,
+            house_seed.server_seed_hash
+        ))
+        .await?;
+
+        // The first bet of a round schedules the spin
+        let model = ctx.data().clone();
+        let http = ctx.serenity_context().http.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(round_secs)).await;
+            match model.roulette_spin(channel_id.get()).await {
+                Ok(Some(spin)) => {
+                    if let Err(error) = channel_id.say(&http, roulette_message(&spin)).await {
+                        error!("Failed to announce roulette spin: {}", error);
+                    }
+                }
+                Ok(None) => {}
+                Err(error) => error!("Failed to spin roulette: {}", error),
+            }
+        });
+    } else {
+        ctx.say(format!(
+            "**{}** bet `{}` euros on **{}**",
+            ctx.author().name,
+            amount,
+            roulette_bet
+        ))
+        .await?;
+    }
+
+    Ok(())
+}
+
+fn crash_message(multiplier: f64, bets: &[CrashBet]) -> String {
+    let mut message = format!("🚀 **x{:.2}**\n", multiplier);
+    for bet in bets {
+        message += &format!("- <@{}> `{}` euros", bet.user_id, bet.amount);
+        if let Some(cashed_out) = bet.cashed_out {
+            message += &format!(" - cashed out at **x{:.2}**", cashed_out);
+        }
+        message += "\n";
+    }
+    message
+}
+
+fn crash_result_message(result: &CrashResult) -> String {
+    let mut message = format!("💥 Crashed at **x{:.2}**\n", result.crash_point);
+    for bet in &result.bets {
+        message += &match bet.cashed_out {
+            Some(cashed_out) => format!(
+                "- <@{}> cashed out at **x{:.2}** and won `{}` euros\n",
+                bet.user_id,
+                cashed_out,
+                bet.payout()
+            ),
+            None => format!("- <@{}> lost `{}` euros\n", bet.user_id, bet.amount),
+        };
+    }
+    message
+        + &format!(
+            "-# Roll #{}, house nonce `{}`",
+            result.roll_id, result.nonce
+        )
+}
+
+/// Bet on the channel's crash round and cash out before the multiplier crashes.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn crash(
+    ctx: Context<'_>,
+    #[description = "Amount to bet in euros"] bet: f64,
+) -> Result<(), Error> {
+    let channel_id = ctx.channel_id();
+    let opened_round = match ctx
+        .data()
+        .crash_bet(channel_id.get(), ctx.author().id.get(), bet)
+        .await
+    {
+        Ok(opened_round) => opened_round,
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    if !opened_round {
+        ctx.say(format!(
+            "**{}** joined the crash round with `{}` euros",
+            ctx.author().name,
+            bet
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    // The first bet of a round flies it until it crashes. Once opened, the
+    // round must always be cancelled or settled, or the channel's later bets
+    // would be escrowed into a round nobody flies
+    let reply = match crash_take_off(ctx, bet).await {
+        Ok(reply) => reply,
+        Err(error) => {
+            for bet in ctx.data().crash_cancel(channel_id.get()).await? {
+                info!(
+                    "Refunded {} euros of a cancelled crash round to {}",
+                    bet.amount, bet.user_id
+                );
+            }
+            Err(error)?
+        }
+    };
+
+    let prefix = format!("{}-", ctx.id());
+    let collector_prefix = prefix.clone();
+    let mut cash_outs = ComponentInteractionCollector::new(ctx)
+        .channel_id(channel_id)
+        .filter(move |interaction| interaction.data.custom_id.starts_with(&collector_prefix))
+        .stream();
+    let cash_out_button = vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}cashout", prefix))
+            .label("Cash out")
+            .style(ButtonStyle::Success),
+    ])];
+    let mut ticker = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let Some(flight) = ctx.data().crash_flight(channel_id.get()).await else {
+                    break;
+                };
+                if flight.crashed {
+                    break;
+                }
+                // Keep flying if the message is gone, the round is still settled
+                if let Err(error) = reply
+                    .edit(
+                        ctx,
+                        CreateReply::default()
+                            .content(crash_message(flight.multiplier, &flight.bets))
+                            .components(cash_out_button.clone()),
+                    )
+                    .await
+                {
+                    error!("Failed to update crash round: {}", error);
+                }
+            }
+            Some(interaction) = cash_outs.next() => {
+                let content = match ctx
+                    .data()
+                    .crash_cash_out(channel_id.get(), interaction.user.id.get())
+                    .await
+                {
+                    Ok(bet) => format!(
+                        "You cashed out at **x{:.2}** and won `{}` euros :euro:",
+                        bet.cashed_out.unwrap_or_default(),
+                        bet.payout()
+                    ),
+                    Err(error @ ModelError::InvalidValue(_)) => error.to_string(),
+                    Err(error) => {
+                        error!("Failed to cash out crash bet: {}", error);
+                        "Something went wrong, you are still riding".to_string()
+                    }
+                };
+                if let Err(error) = interaction
+                    .create_response(
+                        ctx,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(content)
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await
+                {
+                    error!("Failed to answer crash cash out: {}", error);
+                }
+            }
+        }
+    }
+
+    if let Some(result) = ctx.data().crash_settle(channel_id.get()).await? {
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content(crash_result_message(&result))
+                    .components(vec![]),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Announce a crash round, wait for the other bets and launch it.
+async fn crash_take_off(ctx: Context<'_>, bet: f64) -> Result<ReplyHandle<'_>, Error> {
+    let join_secs = ctx.data().crash_join_secs();
+    let house_seed = ctx.data().fairness_seed(SeedOwner::House).await?;
+    let reply = ctx
+        .say(format!(
+            "**{}** opened a crash round with `{}` euros\nTaking off in {} seconds, join with `/crash`!\n-# House server seed hash `{}`",
+            ctx.author().name,
+            bet,
+            join_secs,
+            house_seed.server_seed_hash
+        ))
+        .await?;
+    tokio::time::sleep(Duration::from_secs(join_secs)).await;
+    ctx.data().crash_launch(ctx.channel_id().get()).await?;
+
+    Ok(reply)
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum DuelGameChoice {
+    #[name = "Coin flip"]
+    CoinFlip,
+    Dice,
+    #[name = "Rock paper scissors"]
+    RockPaperScissors,
+}
+
+fn duel_message(result: &DuelResult, game: DuelGame, challenger: &User, opponent: &User) -> String {
+    let rounds: Vec<String> = result
+        .outcome
+        .rounds
+        .iter()
+        .map(|(challenger_play, opponent_play)| match game {
+            DuelGame::CoinFlip => format!("The coin landed on **{}**", challenger_play),
+            _ => format!(
+                "**{}** {} vs {} **{}**",
+                challenger.name, challenger_play, opponent_play, opponent.name
+            ),
+        })
+        .collect();
+    let fee = if result.fee > 0f64 {
+        format!(" (house fee `{}` euros)", result.fee)
+    } else {
+        String::new()
+    };
+
+    format!(
+        "{}\n<@{}> won `{}` euros :euro:{}\n-# Roll #{}",
+        rounds.join("\n"),
+        result.winner_id,
+        result.payout,
+        fee,
+        result.roll_id
+    )
+}
+
+/// Challenge another user to a duel, the winner takes both stakes.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn duel(
+    ctx: Context<'_>,
+    #[description = "User to challenge"] opponent: User,
+    #[description = "Stake of each player in euros"] amount: f64,
+    #[description = "Game to play, coin flip by default"] game: Option<DuelGameChoice>,
+) -> Result<(), Error> {
+    let challenger = ctx.author();
+    let game = match game {
+        None | Some(DuelGameChoice::CoinFlip) => DuelGame::CoinFlip,
+        Some(DuelGameChoice::Dice) => DuelGame::Dice,
+        Some(DuelGameChoice::RockPaperScissors) => DuelGame::RockPaperScissors,
+    };
+    if opponent.bot {
+        ctx.say("You can't duel a bot.").await?;
+        return Ok(());
+    }
+
+    let duel_id = match ctx
+        .data()
+        .duel_challenge(challenger.id.get(), opponent.id.get(), amount, game)
+        .await
+    {
+        Ok(duel_id) => duel_id,
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    let prefix = format!("{}-", ctx.id());
+    let game_name = match game {
+        DuelGame::CoinFlip => "coin flip",
+        DuelGame::Dice => "dice",
+        DuelGame::RockPaperScissors => "rock paper scissors",
+    };
+    let reply = match ctx
+        .send(
+            CreateReply::default()
+                .content(format!(
+                    "<@{}>, **{}** challenges you to a {} duel for `{}` euros each!",
+                    opponent.id, challenger.name, game_name, amount
+                ))
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(format!("{}accept", prefix))
+                        .label("Accept")
+                        .style(ButtonStyle::Success),
+                    CreateButton::new(format!("{}decline", prefix))
+                        .label("Decline")
+                        .style(ButtonStyle::Danger),
+                ])]),
+        )
+        .await
+    {
+        Ok(reply) => reply,
+        Err(error) => {
+            ctx.data().duel_refund(duel_id).await?;
+            Err(error)?
+        }
+    };
+
+    // The opponent gets the timeout once, not again after every click
+    let deadline = Instant::now() + ctx.data().duel_timeout();
+    loop {
+        // Only the opponent can accept, the challenger can still call it off
+        let collector_prefix = prefix.clone();
+        let (challenger_id, opponent_id) = (challenger.id, opponent.id);
+        let Some(interaction) = ComponentInteractionCollector::new(ctx)
+            .channel_id(ctx.channel_id())
+            .timeout(deadline.saturating_duration_since(Instant::now()))
+            .filter(move |interaction| {
+                let custom_id = &interaction.data.custom_id;
+                custom_id.starts_with(&collector_prefix)
+                    && (interaction.user.id == opponent_id
+                        || (interaction.user.id == challenger_id && custom_id.ends_with("decline")))
+            })
+            .await
+        else {
+            ctx.data().duel_refund(duel_id).await?;
+            reply
+                .edit(
+                    ctx,
+                    CreateReply::default()
+                        .content(format!(
+                            "**{}** didn't accept the duel in time, the stake was returned",
+                            opponent.name
+                        ))
+                        .components(vec![]),
+                )
+                .await?;
+            break;
+        };
+
+        if interaction.data.custom_id.ends_with("decline") {
+            ctx.data().duel_refund(duel_id).await?;
+            let content = if interaction.user.id == challenger.id {
+                format!("**{}** called off the duel", challenger.name)
+            } else {
+                format!("**{}** declined the duel", opponent.name)
+            };
+            interaction
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .content(content)
+                            .components(vec![]),
+                    ),
+                )
+                .await?;
+            break;
+        }
+
+        match ctx.data().duel_accept(duel_id).await {
+            Ok(result) => {
+                interaction
+                    .create_response(
+                        ctx,
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new()
+                                .content(duel_message(&result, game, challenger, &opponent))
+                                .components(vec![]),
+                        ),
+                    )
+                    .await?;
+                break;
+            }
+            Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+                interaction
+                    .create_response(
+                        ctx,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(error.to_string())
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await?;
+            }
+            Err(error) => {
+                ctx.data().duel_refund(duel_id).await?;
+                Err(error)?
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use crate::{
+    Context, Error,
+    model::{FairnessSeed, ModelError, SeedOwner},
+    permissions::*,
+};
+
+fn rotation_message(revealed: &FairnessSeed, seed: &FairnessSeed) -> String {
+    format!(
+        "**Revealed Server Seed:** `{}`\n**Hash:** `{}`\n**Client Seed:** `{}` (`{}` bets)\n\n**New Server Seed Hash:** `{}`\n**Client Seed:** `{}`",
+        revealed.server_seed,
+        revealed.server_seed_hash,
+        revealed.client_seed,
+        revealed.nonce,
+        seed.server_seed_hash,
+        seed.client_seed
+    )
+}
+
+/// Provably fair gambling: every bet rolls from a committed server seed, your client seed and a nonce.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Casino",
+    subcommands(
+        "fairness_show",
+        "fairness_rotate",
+        "fairness_verify",
+        "fairness_rotate_house"
+    ),
+    subcommand_required
+)]
+pub async fn fairness(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Displays your active seeds and the house seeds used by shared games.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "show")]
+pub async fn fairness_show(ctx: Context<'_>) -> Result<(), Error> {
+    let seed = ctx
+        .data()
+        .fairness_seed(SeedOwner::User(ctx.author().id.get()))
+        .await?;
+    let house_seed = ctx.data().fairness_seed(SeedOwner::House).await?;
+
+    ctx.say(format!(
+        "**Server Seed Hash:** `{}`\n**Client Seed:** `{}`\n**Next Nonce:** `{}`\n\n**House Server Seed Hash:** `{}`\n**House Client Seed:** `{}`\n**House Next Nonce:** `{}`",
+        seed.server_seed_hash,
+        seed.client_seed,
+        seed.nonce,
+        house_seed.server_seed_hash,
+        house_seed.client_seed,
+        house_seed.nonce
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Reveal your server seed and start a new seed pair, optionally with your own client seed.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "rotate")]
+pub async fn fairness_rotate(
+    ctx: Context<'_>,
+    #[description = "New client seed, keeps the current one if empty"] client_seed: Option<String>,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .rotate_seed(
+            SeedOwner::User(ctx.author().id.get()),
+            client_seed.as_deref(),
+        )
+        .await
+    {
+        Ok((revealed, seed)) => {
+            ctx.say(rotation_message(&revealed, &seed)).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Reveal the house server seed and start a new house seed pair.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Casino",
+    rename = "rotate-house",
+    check = "is_admin"
+)]
+pub async fn fairness_rotate_house(
+    ctx: Context<'_>,
+    #[description = "New client seed, keeps the current one if empty"] client_seed: Option<String>,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .rotate_seed(SeedOwner::House, client_seed.as_deref())
+        .await
+    {
+        Ok((revealed, seed)) => {
+            ctx.say(rotation_message(&revealed, &seed)).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Recompute the outcome of a past roll from its revealed seeds.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "verify")]
+pub async fn fairness_verify(
+    ctx: Context<'_>,
+    #[description = "Roll number shown in the game message"] roll_id: i64,
+) -> Result<(), Error> {
+    match ctx.data().verify_roll(roll_id).await {
+        Ok(verification) => {
+            let roll = &verification.roll;
+            ctx.say(format!(
+                "**Roll #{}** ({})\n**Server Seed:** `{}`\n**Server Seed Hash:** `{}`\n**Client Seed:** `{}`\n**Nonce:** `{}`\n**Recorded Outcome:** `{}`\n**Recomputed Outcome:** `{}`\n{}",
+                roll.roll_id,
+                roll.game,
+                roll.seed.server_seed,
+                roll.seed.server_seed_hash,
+                roll.seed.client_seed,
+                roll.nonce,
+                roll.outcome,
+                verification.recomputed,
+                if verification.is_valid() {
+                    "The outcome is valid :white_check_mark:"
+                } else {
+                    "The outcome does not match :x:"
+                }
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
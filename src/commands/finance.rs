@@ -1,6 +1,11 @@
 use poise::serenity_prelude::{User, UserId};
 
-use crate::{Context, Error, commands::get_user_name, model::ModelError, permissions::*};
+use crate::{
+    Context, Error,
+    commands::{format_duration, get_user_name},
+    model::{DailyClaim, DailyStatus, ModelError, RewardPeriod},
+    permissions::*,
+};
 
 /// Displays current money balance (in euros). If bank account does not exist, create one.
 #[poise::command(
@@ -69,6 +74,54 @@ pub async fn give(ctx: Context<'_>, dst_user: User, amount: f64) -> anyhow::Resu
     Ok(())
 }
 
+/// Give crypto coins to another user. The coins keep their original purchase price.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    rename = "give-coin",
+    aliases("givecoin")
+)]
+pub async fn give_coin(
+    ctx: Context<'_>,
+    dst_user: User,
+    #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
+    #[description = "Amount of coins to give"] amount: f64,
+) -> anyhow::Result<()> {
+    let src_user_id = ctx.author().id.get();
+    let dst_user_id = dst_user.id.get();
+
+    match ctx
+        .data()
+        .give_coin(src_user_id, dst_user_id, &coin_symbol, amount)
+        .await
+    {
+        Ok(_) => {
+            ctx.say(format!(
+                "{} gave {} `{}` {}.",
+                ctx.author().name,
+                dst_user.name,
+                amount,
+                coin_symbol.to_uppercase()
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InsuficientCoins) | Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
 /// ADMIN COMMAND: Inject money (in euros) to another user.
 #[poise::command(
     prefix_command,
@@ -96,7 +149,7 @@ pub async fn bless(ctx: Context<'_>, dst_user: User, amount: f64) -> anyhow::Res
     Ok(())
 }
 
-/// Bank leaderboard. Who's the wealthiest.
+/// Bank leaderboard. Who's the wealthiest, counting euros, savings, coins and pool shares minus loans.
 #[poise::command(prefix_command, slash_command, category = "Finance")]
 pub async fn leaderboard(ctx: Context<'_>) -> Result<(), Error> {
     let bank_data = ctx.data().leaderboard().await?;
@@ -107,12 +160,12 @@ pub async fn leaderboard(ctx: Context<'_>) -> Result<(), Error> {
     }
 
     let mut output = String::new();
-    for (user_id, balance) in bank_data {
+    for (user_id, net_worth) in bank_data {
         output.push_str(
             format!(
-                "- **{}** has `{}` euros\n",
+                "- **{}** is worth `{}` euros\n",
                 get_user_name(&ctx, UserId::new(user_id)).await,
-                balance
+                net_worth
             )
             .as_str(),
         );
@@ -147,9 +200,26 @@ pub async fn price(
 
 /// Displays list of owned coins amount, the profit percentage, and absolute profit in euros.
 #[poise::command(prefix_command, slash_command, category = "Finance")]
-pub async fn portfolio(ctx: Context<'_>) -> Result<(), Error> {
-    let portfolio_data = match ctx.data().portfolio(ctx.author().id.get()).await {
-        Ok(portfolio_data) => portfolio_data,
+pub async fn portfolio(
+    ctx: Context<'_>,
+    #[description = "Show a tournament account instead (tournament id)"] tournament: Option<i64>,
+) -> Result<(), Error> {
+    let user_id = ctx.author().id.get();
+    let portfolio_res = match tournament {
+        Some(tournament_id) => ctx
+            .data()
+            .tournament_portfolio(tournament_id, user_id)
+            .await
+            .map(|(balance, portfolio_data)| (Some(balance), portfolio_data)),
+        None => ctx
+            .data()
+            .portfolio(user_id)
+            .await
+            .map(|portfolio_data| (None, portfolio_data)),
+    };
+
+    let (balance, portfolio_data) = match portfolio_res {
+        Ok(portfolio) => portfolio,
         Err(ModelError::BankAccountNotFound(_)) => {
             ctx.say(format!(
                 "User **{}** has no bank account",
@@ -158,15 +228,22 @@ pub async fn portfolio(ctx: Context<'_>) -> Result<(), Error> {
             .await?;
             return Ok(());
         }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
         Err(error) => Err(error)?,
     };
 
-    if portfolio_data.is_empty() {
+    if portfolio_data.is_empty() && balance.is_none() {
         ctx.say("Empty portfolio!").await?;
         return Ok(());
     }
 
-    let mut portfolio_str = "Portfolio:\n".to_string();
+    let mut portfolio_str = match balance {
+        Some(balance) => format!("Tournament Portfolio:\n- Euros: `{}`\n", balance),
+        None => "Portfolio:\n".to_string(),
+    };
     for (coin_symbol, total_amount, total_value) in &portfolio_data {
         portfolio_str.push_str(
             format!(
@@ -187,11 +264,28 @@ pub async fn buy(
     ctx: Context<'_>,
     #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
     #[description = "Value in euros of the amount of crypto you want to buy"] value: f64,
+    #[description = "Trade in a tournament account instead (tournament id)"] tournament: Option<
+        i64,
+    >,
 ) -> Result<(), Error> {
-    let amount_and_price_res = ctx
-        .data()
-        .buy(ctx.author().id.get(), &coin_symbol, value)
-        .await;
+    let user_id = ctx.author().id.get();
+    let amount_and_price_res = match tournament {
+        Some(tournament_id) => {
+            ctx.data()
+                .tournament_buy(tournament_id, user_id, &coin_symbol, value)
+                .await
+        }
+        None => {
+            ctx.data()
+                .buy(
+                    user_id,
+                    ctx.guild_id().map(|guild_id| guild_id.get()),
+                    &coin_symbol,
+                    value,
+                )
+                .await
+        }
+    };
 
     match amount_and_price_res {
         Ok((amount, price)) => {
@@ -225,11 +319,28 @@ pub async fn sell(
     ctx: Context<'_>,
     #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
     #[description = "Value in euros of the amount of crypto you want to sell"] value: f64,
+    #[description = "Trade in a tournament account instead (tournament id)"] tournament: Option<
+        i64,
+    >,
 ) -> Result<(), Error> {
-    let amount_and_price_res = ctx
-        .data()
-        .sell(ctx.author().id.get(), &coin_symbol, value)
-        .await;
+    let user_id = ctx.author().id.get();
+    let amount_and_price_res = match tournament {
+        Some(tournament_id) => {
+            ctx.data()
+                .tournament_sell(tournament_id, user_id, &coin_symbol, Some(value))
+                .await
+        }
+        None => {
+            ctx.data()
+                .sell(
+                    user_id,
+                    ctx.guild_id().map(|guild_id| guild_id.get()),
+                    &coin_symbol,
+                    value,
+                )
+                .await
+        }
+    };
 
     match amount_and_price_res {
         Ok((amount, price)) => {
@@ -262,11 +373,27 @@ pub async fn sell(
 pub async fn sellall(
     ctx: Context<'_>,
     #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
+    #[description = "Trade in a tournament account instead (tournament id)"] tournament: Option<
+        i64,
+    >,
 ) -> Result<(), Error> {
-    let amount_and_price_res = ctx
-        .data()
-        .sell_all(ctx.author().id.get(), &coin_symbol)
-        .await;
+    let user_id = ctx.author().id.get();
+    let amount_and_price_res = match tournament {
+        Some(tournament_id) => {
+            ctx.data()
+                .tournament_sell(tournament_id, user_id, &coin_symbol, None)
+                .await
+        }
+        None => {
+            ctx.data()
+                .sell_all(
+                    user_id,
+                    ctx.guild_id().map(|guild_id| guild_id.get()),
+                    &coin_symbol,
+                )
+                .await
+        }
+    };
 
     match amount_and_price_res {
         Ok((amount, price)) => {
@@ -285,7 +412,7 @@ pub async fn sellall(
             ))
             .await?;
         }
-        Err(error @ ModelError::InsuficientCoins) => {
+        Err(error @ ModelError::InvalidValue(_)) | Err(error @ ModelError::InsuficientCoins) => {
             ctx.say(error.to_string()).await?;
         }
         Err(error) => Err(error)?,
@@ -307,16 +434,18 @@ pub async fn coin(
         .await;
 
     match has_won_res {
-        Ok(true) => {
+        Ok((true, roll_id)) => {
             ctx.say(format!(
-                "Congratulations, the coin landed on {}!\nYou won {} euros :euro:",
+                "Congratulations, the coin landed on {}!\nYou won {} euros :euro:\n-# Roll #{}",
                 choice.to_lowercase(),
-                bet
+                bet,
+                roll_id
             ))
             .await?;
         }
-        Ok(false) => {
-            ctx.say(format!("Ups, you lost {} euros", bet)).await?;
+        Ok((false, roll_id)) => {
+            ctx.say(format!("Ups, you lost {} euros\n-# Roll #{}", bet, roll_id))
+                .await?;
         }
         Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
             ctx.say(error.to_string()).await?;
@@ -334,19 +463,180 @@ pub async fn coin(
     Ok(())
 }
 
-/// Claim daily reward.
+/// Claim daily reward. Claiming on consecutive days grows the reward.
 #[poise::command(prefix_command, slash_command, category = "Finance")]
 pub async fn daily(ctx: Context<'_>) -> Result<(), Error> {
-    match ctx.data().daily(ctx.author().id.get()).await {
-        Ok(true) => {
+    let guild_id = ctx.guild_id().map(|guild_id| guild_id.get());
+    match ctx.data().daily(ctx.author().id.get(), guild_id).await {
+        Ok(DailyStatus {
+            claim: DailyClaim::Claimed(reward),
+            next_claim_secs,
+        }) => {
+            let mut output = format!(
+                "Claimed daily reward `{}` euros\n**Streak:** {} days\n-# Next claim in {}",
+                reward.amount,
+                reward.streak,
+                format_duration(next_claim_secs)
+            );
+            if reward.streak_reset {
+                output.push_str("\n-# You missed a day, the streak started over");
+            }
+            ctx.say(output).await?;
+        }
+        Ok(DailyStatus {
+            claim: DailyClaim::AlreadyClaimed,
+            next_claim_secs,
+        }) => {
             ctx.say(format!(
-                "Claimed daily reward `{}` euros",
-                ctx.data().daily_amount
+                "Already claimed reward today, next claim in {}",
+                format_duration(next_claim_secs)
             ))
             .await?;
         }
-        Ok(false) => {
-            ctx.say("Already claimed reward today").await?;
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Claim weekly bonus, once per week starting on monday.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn weekly(ctx: Context<'_>) -> Result<(), Error> {
+    claim_bonus(ctx, RewardPeriod::Weekly).await
+}
+
+/// Claim monthly bonus, once per calendar month.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn monthly(ctx: Context<'_>) -> Result<(), Error> {
+    claim_bonus(ctx, RewardPeriod::Monthly).await
+}
+
+/// Timezone the daily reward resets in.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    subcommands("timezone_user", "timezone_server"),
+    subcommand_required
+)]
+pub async fn timezone(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Set your own timezone for the daily reward, empty goes back to the server timezone.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "user")]
+pub async fn timezone_user(
+    ctx: Context<'_>,
+    #[description = "Timezone name (ie: Europe/Lisbon)"] timezone: Option<String>,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .set_user_timezone(ctx.author().id.get(), timezone.as_deref())
+        .await
+    {
+        Ok(()) => {
+            ctx.say(match timezone {
+                Some(timezone) => format!(
+                    "**{}** daily reward now resets in {}",
+                    ctx.author().name,
+                    timezone
+                ),
+                None => format!(
+                    "**{}** daily reward now resets in the server timezone",
+                    ctx.author().name
+                ),
+            })
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Set the timezone and reset time of the daily reward in this server.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    rename = "server",
+    guild_only,
+    check = "is_admin"
+)]
+pub async fn timezone_server(
+    ctx: Context<'_>,
+    #[description = "Timezone name (ie: Europe/Lisbon)"] timezone: Option<String>,
+    #[description = "Local time the reward resets at (ie: 00:00)"] reset_time: Option<String>,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .set_guild_schedule(guild_id.get(), timezone.as_deref(), reset_time.as_deref())
+        .await
+    {
+        Ok(()) => {
+            let schedule = ctx
+                .data()
+                .daily_schedule(ctx.author().id.get(), Some(guild_id.get()))
+                .await;
+            ctx.say(match schedule {
+                Ok(schedule) => format!(
+                    "Daily reward now resets at {} ({})",
+                    schedule.reset_time.format("%H:%M"),
+                    schedule.timezone.name()
+                ),
+                Err(_) => "Updated the daily reward schedule".to_string(),
+            })
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+async fn claim_bonus(ctx: Context<'_>, period: RewardPeriod) -> Result<(), Error> {
+    match ctx.data().claim_bonus(ctx.author().id.get(), period).await {
+        Ok(Some(amount)) => {
+            ctx.say(format!(
+                "Claimed {} bonus `{}` euros",
+                period.as_str(),
+                amount
+            ))
+            .await?;
+        }
+        Ok(None) => {
+            ctx.say(match period {
+                RewardPeriod::Weekly => "Already claimed bonus this week",
+                RewardPeriod::Monthly => "Already claimed bonus this month",
+            })
+            .await?;
         }
         Err(ModelError::BankAccountNotFound(_)) => {
             ctx.say(format!(
@@ -0,0 +1,254 @@
+use crate::{
+    Context, Error,
+    model::{BankLoan, ModelError},
+    permissions::*,
+};
+
+fn loan_message(loan: &BankLoan) -> String {
+    format!(
+        "**Loan #{}** ({})\n**Borrowed:** `{}` euros\n**Owed:** `{}` euros\n**Daily Interest:** `{}%`\n**Due:** {} UTC",
+        loan.loan_id,
+        loan.status.as_str(),
+        loan.principal,
+        loan.owed,
+        loan.rate * 100f64,
+        loan.due_at
+    )
+}
+
+/// Savings account, euros in savings earn daily interest.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    subcommands("savings_info", "savings_deposit", "savings_withdraw"),
+    subcommand_required
+)]
+pub async fn savings(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Displays your savings and the daily interest rate.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "info")]
+pub async fn savings_info(ctx: Context<'_>) -> Result<(), Error> {
+    match ctx.data().savings(ctx.author().id.get()).await {
+        Ok(savings) => {
+            let (savings_rate, _) = ctx.data().interest_rates().await?;
+            ctx.say(format!(
+                "**{}** has `{}` euros in savings\n**Daily Interest:** `{}%`",
+                ctx.author().name,
+                savings,
+                savings_rate * 100f64
+            ))
+            .await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Move euros from your balance to savings.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    rename = "deposit"
+)]
+pub async fn savings_deposit(
+    ctx: Context<'_>,
+    #[description = "Amount in euros"] amount: f64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .savings_deposit(ctx.author().id.get(), amount)
+        .await
+    {
+        Ok(savings) => {
+            ctx.say(format!(
+                "**{}** deposited `{}` euros, savings are now `{}` euros",
+                ctx.author().name,
+                amount,
+                savings
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Move euros from savings back to your balance.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    rename = "withdraw"
+)]
+pub async fn savings_withdraw(
+    ctx: Context<'_>,
+    #[description = "Amount in euros"] amount: f64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .savings_withdraw(ctx.author().id.get(), amount)
+        .await
+    {
+        Ok(savings) => {
+            ctx.say(format!(
+                "**{}** withdrew `{}` euros, savings are now `{}` euros",
+                ctx.author().name,
+                amount,
+                savings
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Bank loans with daily interest. Defaulted loans freeze trading until repaid.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    subcommands("loan_take", "loan_repay", "loan_info", "loan_rates"),
+    subcommand_required
+)]
+pub async fn loan(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Borrow euros from the bank, collected from your balance on the due date.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "take")]
+pub async fn loan_take(
+    ctx: Context<'_>,
+    #[description = "Amount to borrow in euros"] amount: f64,
+) -> Result<(), Error> {
+    match ctx.data().take_loan(ctx.author().id.get(), amount).await {
+        Ok(loan) => {
+            ctx.say(loan_message(&loan)).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Repay your loan, everything owed if no amount is given.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "repay")]
+pub async fn loan_repay(
+    ctx: Context<'_>,
+    #[description = "Amount to repay in euros"] amount: Option<f64>,
+) -> Result<(), Error> {
+    match ctx.data().repay_loan(ctx.author().id.get(), amount).await {
+        Ok(loan) => {
+            ctx.say(loan_message(&loan)).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays your loan and the current interest rates.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "info")]
+pub async fn loan_info(ctx: Context<'_>) -> Result<(), Error> {
+    let (savings_rate, loan_rate) = ctx.data().interest_rates().await?;
+    let mut output = match ctx.data().bank_loan(ctx.author().id.get()).await? {
+        Some(loan) => format!("{}\n", loan_message(&loan)),
+        None => "You have no loan\n".to_string(),
+    };
+    output.push_str(
+        format!(
+            "-# Daily interest: `{}%` on savings, `{}%` on new loans",
+            savings_rate * 100f64,
+            loan_rate * 100f64
+        )
+        .as_str(),
+    );
+    ctx.say(output).await?;
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Set the daily interest rates, loans keep the rate they were taken with.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    rename = "rates",
+    check = "is_admin"
+)]
+pub async fn loan_rates(
+    ctx: Context<'_>,
+    #[description = "Daily savings interest (ie: 0.001 is 0.1%)"] savings_rate: Option<f64>,
+    #[description = "Daily loan interest (ie: 0.01 is 1%)"] loan_rate: Option<f64>,
+) -> Result<(), Error> {
+    match ctx.data().set_interest_rates(savings_rate, loan_rate).await {
+        Ok((savings_rate, loan_rate)) => {
+            ctx.say(format!(
+                "Daily interest is now `{}%` on savings and `{}%` on new loans",
+                savings_rate * 100f64,
+                loan_rate * 100f64
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,291 @@
+use poise::{
+    CreateReply,
+    serenity_prelude::{
+        ButtonStyle, ComponentInteractionCollector, CreateActionRow, CreateButton,
+        CreateInteractionResponse, CreateInteractionResponseMessage, User, UserId,
+    },
+};
+
+use crate::{
+    Context, Error,
+    commands::{format_duration, get_user_name},
+    model::{Debt, DebtStatus, ModelError, parse_duration},
+};
+
+fn debt_message(debt: &Debt) -> String {
+    format!(
+        "**Debt #{}** ({})\n**Lender:** <@{}>\n**Borrower:** <@{}>\n**Lent:** `{}` euros\n**Interest:** `{}%`\n**Owed:** `{}` euros\n**Due:** {} UTC",
+        debt.debt_id,
+        debt.status.as_str(),
+        debt.lender_id,
+        debt.borrower_id,
+        debt.principal,
+        debt.interest * 100f64,
+        debt.owed,
+        debt.due_at
+    )
+}
+
+/// Offer to lend euros to a user, repaid with interest by the due date (ie: /lend @user 100 0.1 1w).
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn lend(
+    ctx: Context<'_>,
+    #[description = "User to lend to"] borrower: User,
+    #[description = "Amount to lend in euros"] amount: f64,
+    #[description = "Interest added to the debt (ie: 0.1 is 10%)"] interest: f64,
+    #[description = "When the debt is due (ie: 1d, 1w)"] due: String,
+) -> Result<(), Error> {
+    let lender = ctx.author();
+    if borrower.bot {
+        ctx.say("You can't lend to a bot.").await?;
+        return Ok(());
+    }
+    let due_secs = match parse_duration(&due) {
+        Ok(due_secs) => due_secs,
+        Err(error) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let offer_id = match ctx
+        .data()
+        .lend_offer(
+            lender.id.get(),
+            borrower.id.get(),
+            amount,
+            interest,
+            due_secs,
+            ctx.channel_id().get(),
+        )
+        .await
+    {
+        Ok(offer_id) => offer_id,
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    let prefix = format!("{}-", ctx.id());
+    let reply = match ctx
+        .send(
+            CreateReply::default()
+                .content(format!(
+                    "<@{}>, **{}** offers to lend you `{}` euros, you will owe `{}` euros in {}",
+                    borrower.id,
+                    lender.name,
+                    amount,
+                    amount * (1f64 + interest),
+                    format_duration(due_secs)
+                ))
+                .components(vec![CreateActionRow::Buttons(vec![
+                    CreateButton::new(format!("{}accept", prefix))
+                        .label("Accept")
+                        .style(ButtonStyle::Success),
+                    CreateButton::new(format!("{}decline", prefix))
+                        .label("Decline")
+                        .style(ButtonStyle::Danger),
+                ])]),
+        )
+        .await
+    {
+        Ok(reply) => reply,
+        Err(error) => {
+            ctx.data().lend_refund(offer_id).await?;
+            Err(error)?
+        }
+    };
+
+    // Only the borrower can accept, the lender can still call it off
+    let collector_prefix = prefix.clone();
+    let (lender_id, borrower_id) = (lender.id, borrower.id);
+    let Some(interaction) = ComponentInteractionCollector::new(ctx)
+        .channel_id(ctx.channel_id())
+        .timeout(ctx.data().lend_timeout())
+        .filter(move |interaction| {
+            let custom_id = &interaction.data.custom_id;
+            custom_id.starts_with(&collector_prefix)
+                && (interaction.user.id == borrower_id
+                    || (interaction.user.id == lender_id && custom_id.ends_with("decline")))
+        })
+        .await
+    else {
+        ctx.data().lend_refund(offer_id).await?;
+        reply
+            .edit(
+                ctx,
+                CreateReply::default()
+                    .content(format!(
+                        "**{}** didn't accept the offer in time, the euros were returned",
+                        borrower.name
+                    ))
+                    .components(vec![]),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    if interaction.data.custom_id.ends_with("decline") {
+        ctx.data().lend_refund(offer_id).await?;
+        let content = if interaction.user.id == lender.id {
+            format!("**{}** called off the offer", lender.name)
+        } else {
+            format!("**{}** declined the offer", borrower.name)
+        };
+        interaction
+            .create_response(
+                ctx,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new()
+                        .content(content)
+                        .components(vec![]),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    match ctx.data().lend_accept(offer_id).await {
+        Ok(debt) => {
+            interaction
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .content(debt_message(&debt))
+                            .components(vec![]),
+                    ),
+                )
+                .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            interaction
+                .create_response(
+                    ctx,
+                    CreateInteractionResponse::UpdateMessage(
+                        CreateInteractionResponseMessage::new()
+                            .content(error.to_string())
+                            .components(vec![]),
+                    ),
+                )
+                .await?;
+        }
+        Err(error) => {
+            ctx.data().lend_refund(offer_id).await?;
+            Err(error)?
+        }
+    }
+
+    Ok(())
+}
+
+/// Repay what you owe to a user, everything owed if no amount is given.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn repay(
+    ctx: Context<'_>,
+    #[description = "User you owe"] lender: User,
+    #[description = "Amount to repay in euros"] amount: Option<f64>,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .repay_debt(ctx.author().id.get(), lender.id.get(), amount)
+        .await
+    {
+        Ok(debts) => {
+            let mut output = String::new();
+            for debt in debts {
+                let status = match debt.status {
+                    DebtStatus::Repaid => "repaid".to_string(),
+                    _ => format!("`{}` euros still owed", debt.owed),
+                };
+                output.push_str(format!("- Debt #{}: {}\n", debt.debt_id, status).as_str());
+            }
+            ctx.say(output).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the debts you owe and are owed.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn debts(ctx: Context<'_>) -> Result<(), Error> {
+    let user_id = ctx.author().id.get();
+    match ctx.data().debts(user_id).await {
+        Ok(debts) => {
+            if debts.is_empty() {
+                ctx.say("You have no debts").await?;
+                return Ok(());
+            }
+
+            let (mut owing, mut owed) = (String::new(), String::new());
+            for debt in debts {
+                if debt.borrower_id == user_id {
+                    owing.push_str(
+                        format!(
+                            "- #{} `{}` euros to **{}**, due {} UTC ({})\n",
+                            debt.debt_id,
+                            debt.owed,
+                            get_user_name(&ctx, UserId::new(debt.lender_id)).await,
+                            debt.due_at,
+                            debt.status.as_str()
+                        )
+                        .as_str(),
+                    );
+                } else {
+                    owed.push_str(
+                        format!(
+                            "- #{} `{}` euros from **{}**, due {} UTC ({})\n",
+                            debt.debt_id,
+                            debt.owed,
+                            get_user_name(&ctx, UserId::new(debt.borrower_id)).await,
+                            debt.due_at,
+                            debt.status.as_str()
+                        )
+                        .as_str(),
+                    );
+                }
+            }
+
+            let mut output = String::new();
+            if !owing.is_empty() {
+                output.push_str(format!("__You owe__\n{}", owing).as_str());
+            }
+            if !owed.is_empty() {
+                output.push_str(format!("__You are owed__\n{}", owed).as_str());
+            }
+            ctx.say(output).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
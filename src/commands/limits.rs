@@ -0,0 +1,184 @@
+use crate::{
+    Context, Error,
+    model::{GamblingLimits, ModelError, parse_duration},
+    permissions::*,
+};
+
+fn limits_message(limits: &GamblingLimits) -> String {
+    let limit = |limit: Option<f64>| match limit {
+        Some(limit) => format!("`{}` euros", limit),
+        None => "none".to_string(),
+    };
+    let mut output = format!(
+        "**Daily Loss Limit:** {} (lost `{}` euros in the last 24 hours)\n**Weekly Loss Limit:** {} (lost `{}` euros in the last 7 days)\n**Max Bet:** {}\n**Server Max Bet:** {}\n",
+        limit(limits.daily_loss_limit),
+        limits.daily_loss,
+        limit(limits.weekly_loss_limit),
+        limits.weekly_loss,
+        limit(limits.max_bet),
+        limit(limits.server_max_bet)
+    );
+    if let Some(pending_at) = &limits.pending_at {
+        let changes: Vec<String> = [
+            ("daily loss limit", limits.pending_daily_loss_limit),
+            ("weekly loss limit", limits.pending_weekly_loss_limit),
+            ("max bet", limits.pending_max_bet),
+        ]
+        .into_iter()
+        .filter_map(|(name, pending)| {
+            // 0 is a limit removal
+            let pending = pending?;
+            Some(format!(
+                "{} to {}",
+                name,
+                limit(Some(pending).filter(|pending| *pending > 0f64))
+            ))
+        })
+        .collect();
+        output.push_str(
+            format!(
+                "**Pending:** {} at {} UTC\n",
+                changes.join(", "),
+                pending_at
+            )
+            .as_str(),
+        );
+    }
+    if let Some(excluded_until) = &limits.excluded_until {
+        output.push_str(format!("**Self-excluded until:** {} UTC\n", excluded_until).as_str());
+    }
+    output
+}
+
+/// Responsible gambling: loss limits and bet caps enforced on every game.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Casino",
+    subcommands("limits_show", "limits_set", "limits_server"),
+    subcommand_required
+)]
+pub async fn limits(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Displays your gambling limits and recent losses.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "show")]
+pub async fn limits_show(ctx: Context<'_>) -> Result<(), Error> {
+    match ctx.data().gambling_limits(ctx.author().id.get()).await {
+        Ok(limits) => {
+            ctx.say(limits_message(&limits)).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Set your own loss limits and bet cap in euros, 0 removes a limit.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "set")]
+pub async fn limits_set(
+    ctx: Context<'_>,
+    #[description = "Most euros you can lose in 24 hours, 0 removes it"] daily_loss: Option<f64>,
+    #[description = "Most euros you can lose in 7 days, 0 removes it"] weekly_loss: Option<f64>,
+    #[description = "Biggest bet you can place, 0 removes it"] max_bet: Option<f64>,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .set_gambling_limits(ctx.author().id.get(), daily_loss, weekly_loss, max_bet)
+        .await
+    {
+        Ok(limits) => {
+            ctx.say(limits_message(&limits)).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Set the maximum bet of every user, empty removes it.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Casino",
+    rename = "server",
+    check = "is_admin"
+)]
+pub async fn limits_server(
+    ctx: Context<'_>,
+    #[description = "Maximum bet in euros, removes the limit if empty"] max_bet: Option<f64>,
+) -> Result<(), Error> {
+    match ctx.data().set_server_max_bet(max_bet).await {
+        Ok(()) => {
+            ctx.say(match max_bet {
+                Some(max_bet) => format!("Bets are now limited to `{}` euros", max_bet),
+                None => "Removed the server max bet".to_string(),
+            })
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Block yourself from every gambling command for a duration, it can't be undone.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn selfexclude(
+    ctx: Context<'_>,
+    #[description = "How long to exclude yourself for (ie: 1d, 1w)"] duration: String,
+) -> Result<(), Error> {
+    let duration_secs = match parse_duration(&duration) {
+        Ok(duration_secs) => duration_secs,
+        Err(error) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    match ctx
+        .data()
+        .self_exclude(ctx.author().id.get(), duration_secs)
+        .await
+    {
+        Ok(excluded_until) => {
+            ctx.say(format!(
+                "**{}** is excluded from gambling until {} UTC",
+                ctx.author().name,
+                excluded_until
+            ))
+            .await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use crate::{Context, Error, model::ModelError};
+
+/// Server lottery drawn on a schedule, the more tickets you hold the likelier you win the pot.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Casino",
+    subcommands("lottery_buy", "lottery_info", "lottery_history"),
+    subcommand_required
+)]
+pub async fn lottery(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Buy tickets for the next lottery draw.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "buy")]
+pub async fn lottery_buy(
+    ctx: Context<'_>,
+    #[description = "Number of tickets to buy"] tickets: u32,
+) -> Result<(), Error> {
+    match ctx.data().lottery_buy(ctx.author().id.get(), tickets).await {
+        Ok(draw) => {
+            ctx.say(format!(
+                "**{}** bought {} tickets for `{}` euros\nThe pot is now `{}` euros with {} tickets, drawn at {} UTC",
+                ctx.author().name,
+                tickets,
+                tickets as f64 * ctx.data().lottery_ticket_price(),
+                draw.pot,
+                draw.tickets,
+                draw.draw_time
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the pot of the next draw and how many tickets you hold.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "info")]
+pub async fn lottery_info(ctx: Context<'_>) -> Result<(), Error> {
+    match ctx.data().lottery_info(ctx.author().id.get()).await {
+        Ok((draw, user_tickets)) => {
+            ctx.say(format!(
+                "**Lottery Draw #{}**\n**Draw:** {} UTC\n**Pot:** `{}` euros\n**Tickets Sold:** {}\n**Ticket Price:** `{}` euros\n**Your Tickets:** {}",
+                draw.draw_id,
+                draw.draw_time,
+                draw.pot,
+                draw.tickets,
+                ctx.data().lottery_ticket_price(),
+                user_tickets
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the winners of past lottery draws.
+#[poise::command(prefix_command, slash_command, category = "Casino", rename = "history")]
+pub async fn lottery_history(ctx: Context<'_>) -> Result<(), Error> {
+    let draws = ctx.data().lottery_history(10).await?;
+    if draws.is_empty() {
+        ctx.say("No lottery has been drawn yet.").await?;
+        return Ok(());
+    }
+
+    let mut output = String::from("**Past Lottery Draws**\n");
+    for draw in draws {
+        output.push_str(
+            format!(
+                "- #{} ({} UTC): <@{}> won `{}` euros with ticket {} of {} (roll #{})\n",
+                draw.draw_id,
+                draw.draw_time,
+                draw.winner_id.unwrap_or_default(),
+                draw.prize.unwrap_or_default(),
+                draw.winning_ticket.unwrap_or_default(),
+                draw.tickets,
+                draw.roll_id.unwrap_or_default()
+            )
+            .as_str(),
+        );
+    }
+    ctx.say(output).await?;
+
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use crate::{
+    Context, Error,
+    model::{Market, ModelError},
+    permissions::*,
+};
+
+fn market_message(market: &Market) -> String {
+    let status = if market.resolved {
+        "Resolved".to_string()
+    } else if market.closed {
+        "Closed, waiting for resolution".to_string()
+    } else {
+        format!("Open until {} UTC", market.closes_at)
+    };
+    let mut output = format!(
+        "**Market #{}:** {}\n{} - created by <@{}> - pot `{}` euros\n",
+        market.market_id,
+        market.question,
+        status,
+        market.creator_id,
+        market.pool()
+    );
+    for (index, outcome) in market.outcomes.iter().enumerate() {
+        let winner = if market.winning_outcome == Some(outcome.outcome_id) {
+            " :trophy:"
+        } else {
+            ""
+        };
+        output.push_str(
+            format!(
+                "{}. **{}** `{}` euros ({:.1}%){}\n",
+                index + 1,
+                outcome.name,
+                outcome.pool,
+                market.probability(outcome) * 100f64,
+                winner
+            )
+            .as_str(),
+        );
+    }
+    output
+}
+
+/// Prediction markets on server events, winners share the pot in proportion to their bets.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    subcommands(
+        "market_create",
+        "market_list",
+        "market_info",
+        "market_bet",
+        "market_resolve",
+        "market_cancel"
+    ),
+    subcommand_required
+)]
+pub async fn market(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// MOD COMMAND: Create a prediction market. Dates are in UTC (ie: 2025-01-31 18:00).
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    rename = "create",
+    check = "is_mod"
+)]
+pub async fn market_create(
+    ctx: Context<'_>,
+    #[description = "Question the market bets on"] question: String,
+    #[description = "Outcomes, comma separated (ie: yes,no)"] outcomes: String,
+    #[description = "Date bets close in UTC (ie: 2025-01-31 18:00)"] closes: String,
+) -> Result<(), Error> {
+    let outcomes: Vec<String> = outcomes
+        .split(',')
+        .map(|outcome| outcome.trim().to_string())
+        .collect();
+
+    match ctx
+        .data()
+        .create_market(ctx.author().id.get(), &question, &outcomes, &closes)
+        .await
+    {
+        Ok(market_id) => {
+            ctx.say(format!(
+                "Created market `#{}` **{}**, bet with `/market bet {} <outcome> <amount>`",
+                market_id, question, market_id
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the markets that are not resolved yet.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    rename = "list"
+)]
+pub async fn market_list(ctx: Context<'_>) -> Result<(), Error> {
+    let markets = ctx.data().markets().await?;
+
+    if markets.is_empty() {
+        ctx.say("No open markets").await?;
+        return Ok(());
+    }
+
+    let output: Vec<String> = markets.iter().map(market_message).collect();
+    ctx.say(output.join("\n")).await?;
+
+    Ok(())
+}
+
+/// Displays the outcomes of a market and how much was bet on each.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    rename = "info"
+)]
+pub async fn market_info(
+    ctx: Context<'_>,
+    #[description = "Market id (see /market list)"] market_id: i64,
+) -> Result<(), Error> {
+    match ctx.data().market(market_id).await {
+        Ok(market) => {
+            ctx.say(market_message(&market)).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Bet euros on an outcome of an open market.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    rename = "bet"
+)]
+pub async fn market_bet(
+    ctx: Context<'_>,
+    #[description = "Market id (see /market list)"] market_id: i64,
+    #[description = "Outcome name or number"] outcome: String,
+    #[description = "Amount to bet in euros"] amount: f64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .market_bet(ctx.author().id.get(), market_id, &outcome, amount)
+        .await
+    {
+        Ok(market) => {
+            ctx.say(format!(
+                "**{}** bet `{}` euros on **{}**\n{}",
+                ctx.author().name,
+                amount,
+                outcome,
+                market_message(&market)
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// MOD COMMAND: Resolve a market to an outcome and pay its winners.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    rename = "resolve",
+    check = "is_mod"
+)]
+pub async fn market_resolve(
+    ctx: Context<'_>,
+    #[description = "Market id (see /market list)"] market_id: i64,
+    #[description = "Winning outcome name or number"] outcome: String,
+) -> Result<(), Error> {
+    match ctx.data().resolve_market(market_id, &outcome).await {
+        Ok(resolution) => {
+            let mut output = market_message(&resolution.market);
+            if resolution.refunded {
+                output.push_str("Nobody bet on the winning outcome, every bet was refunded\n");
+            }
+            for (user_id, payout) in &resolution.payouts {
+                output.push_str(format!("- <@{}> got `{}` euros\n", user_id, payout).as_str());
+            }
+            ctx.say(output).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// MOD COMMAND: Cancel a market and refund every bet.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Predictions",
+    rename = "cancel",
+    check = "is_mod"
+)]
+pub async fn market_cancel(
+    ctx: Context<'_>,
+    #[description = "Market id (see /market list)"] market_id: i64,
+) -> Result<(), Error> {
+    match ctx.data().cancel_market(market_id).await {
+        Ok(market) => {
+            ctx.say(format!(
+                "Cancelled market `#{}` **{}**, every bet was refunded",
+                market.market_id, market.question
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -10,6 +10,57 @@ pub use white_monster::*;
 pub mod finance;
 pub use finance::*;
 
+pub mod backtest;
+pub use backtest::*;
+
+pub mod casino;
+pub use casino::*;
+
+pub mod fairness;
+pub use fairness::*;
+
+pub mod interest;
+pub use interest::*;
+
+pub mod lending;
+pub use lending::*;
+
+pub mod limits;
+pub use limits::*;
+
+pub mod lottery;
+pub use lottery::*;
+
+pub mod markets;
+pub use markets::*;
+
+pub mod order_book;
+pub use order_book::*;
+
+pub mod payments;
+pub use payments::*;
+
+pub mod predict;
+pub use predict::*;
+
+pub mod shop;
+pub use shop::*;
+
+pub mod standing_orders;
+pub use standing_orders::*;
+
+pub mod stats;
+pub use stats::*;
+
+pub mod token;
+pub use token::*;
+
+pub mod tournaments;
+pub use tournaments::*;
+
+pub mod work;
+pub use work::*;
+
 pub async fn get_user_name(ctx: &crate::Context<'_>, user_id: UserId) -> String {
     if let Some(cached_user) = ctx.cache().user(user_id) {
         cached_user.display_name().into()
@@ -23,6 +74,18 @@ pub async fn get_user_name(ctx: &crate::Context<'_>, user_id: UserId) -> String
     }
 }
 
+/// Format a number of seconds like 5h 12m.
+pub fn format_duration(secs: u64) -> String {
+    let (hours, minutes) = (secs / 3600, secs % 3600 / 60);
+    if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
 // #[derive(thiserror::Error, Debug)]
 // pub enum ModelError {
 //     #[error("Invalid input")]
@@ -0,0 +1,192 @@
+use poise::serenity_prelude::UserId;
+
+use crate::{
+    Context, Error,
+    commands::get_user_name,
+    model::{ModelError, OrderSide, PlacedOrder},
+};
+
+async fn place_order(
+    ctx: Context<'_>,
+    coin_symbol: String,
+    side: OrderSide,
+    price: f64,
+    amount: f64,
+) -> Result<(), Error> {
+    let placed_order_res = ctx
+        .data()
+        .place_order(ctx.author().id.get(), &coin_symbol, side, price, amount)
+        .await;
+
+    match placed_order_res {
+        Ok(PlacedOrder {
+            order_id,
+            fills,
+            remaining,
+            cancelled,
+        }) => {
+            let mut output = String::new();
+            for order_id in &cancelled {
+                output.push_str(
+                    format!("- Cancelled your crossing order `#{}`\n", order_id).as_str(),
+                );
+            }
+            for fill in &fills {
+                output.push_str(
+                    format!(
+                        "- {} `{}` {} at `{}` euros with **{}**\n",
+                        match side {
+                            OrderSide::Bid => "Bought",
+                            OrderSide::Ask => "Sold",
+                        },
+                        fill.amount,
+                        coin_symbol.to_uppercase(),
+                        fill.price,
+                        get_user_name(&ctx, UserId::new(fill.maker_user_id)).await
+                    )
+                    .as_str(),
+                );
+            }
+            match order_id {
+                Some(order_id) => output.push_str(
+                    format!(
+                        "Order `#{}` is open for `{}` {} at `{}` euros",
+                        order_id,
+                        remaining,
+                        coin_symbol.to_uppercase(),
+                        price
+                    )
+                    .as_str(),
+                ),
+                None => output.push_str("Order completely filled"),
+            }
+            ctx.say(output).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(
+            error @ ModelError::InvalidValue(_)
+            | error @ ModelError::InsuficientFunds
+            | error @ ModelError::InsuficientCoins,
+        ) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Place a buy order in the server order book. Euros are held until the order is filled or cancelled.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn bid(
+    ctx: Context<'_>,
+    #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
+    #[description = "Price in euros per coin"] price: f64,
+    #[description = "Amount of coins to buy"] amount: f64,
+) -> Result<(), Error> {
+    place_order(ctx, coin_symbol, OrderSide::Bid, price, amount).await
+}
+
+/// Place a sell order in the server order book. Coins are held until the order is filled or cancelled.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn ask(
+    ctx: Context<'_>,
+    #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
+    #[description = "Price in euros per coin"] price: f64,
+    #[description = "Amount of coins to sell"] amount: f64,
+) -> Result<(), Error> {
+    place_order(ctx, coin_symbol, OrderSide::Ask, price, amount).await
+}
+
+/// Displays the order book depth of a coin.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn book(
+    ctx: Context<'_>,
+    #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
+) -> Result<(), Error> {
+    let depth = ctx.data().order_book(&coin_symbol, 10).await?;
+
+    if depth.bids.is_empty() && depth.asks.is_empty() {
+        ctx.say(format!("No open orders for {}", coin_symbol.to_uppercase()))
+            .await?;
+        return Ok(());
+    }
+
+    let mut output = format!("**{}** order book\n__Asks:__\n", coin_symbol.to_uppercase());
+    // Show asks from the highest to the lowest so the spread is in the middle.
+    for (price, amount) in depth.asks.iter().rev() {
+        output.push_str(format!("- `{}` at `{}` euros\n", amount, price).as_str());
+    }
+    output.push_str("__Bids:__\n");
+    for (price, amount) in &depth.bids {
+        output.push_str(format!("- `{}` at `{}` euros\n", amount, price).as_str());
+    }
+    ctx.say(output).await?;
+
+    Ok(())
+}
+
+/// Displays your open orders.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn orders(ctx: Context<'_>) -> Result<(), Error> {
+    let orders = ctx.data().user_orders(ctx.author().id.get()).await?;
+
+    if orders.is_empty() {
+        ctx.say("No open orders").await?;
+        return Ok(());
+    }
+
+    let mut output = "Open orders:\n".to_string();
+    for order in &orders {
+        output.push_str(
+            format!(
+                "- `#{}` {} `{}` {} at `{}` euros\n",
+                order.order_id,
+                match order.side {
+                    OrderSide::Bid => "Buy",
+                    OrderSide::Ask => "Sell",
+                },
+                order.amount,
+                order.coin_symbol,
+                order.price
+            )
+            .as_str(),
+        );
+    }
+    ctx.say(output).await?;
+
+    Ok(())
+}
+
+/// Cancel one of your open orders, returning the held euros or coins.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn cancelorder(
+    ctx: Context<'_>,
+    #[description = "Order id (see /orders)"] order_id: i64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .cancel_order(ctx.author().id.get(), order_id)
+        .await
+    {
+        Ok(order) => {
+            ctx.say(format!(
+                "Cancelled order `#{}` of `{}` {}",
+                order.order_id, order.amount, order.coin_symbol
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,347 @@
+use std::time::Instant;
+
+use poise::{
+    CreateReply,
+    serenity_prelude::{
+        ButtonStyle, ComponentInteraction, ComponentInteractionCollector, CreateActionRow,
+        CreateButton, CreateInteractionResponse, CreateInteractionResponseMessage, User, UserId,
+        parse_user_mention,
+    },
+};
+
+use crate::{
+    Context, Error,
+    commands::get_user_name,
+    model::{ModelError, PaymentRequest, PaymentRequestStatus},
+};
+
+fn request_buttons(prefix: &str) -> Vec<CreateActionRow> {
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{}accept", prefix))
+            .label("Pay")
+            .style(ButtonStyle::Success),
+        CreateButton::new(format!("{}decline", prefix))
+            .label("Decline")
+            .style(ButtonStyle::Danger),
+    ])]
+}
+
+fn split_message(requester: &User, amount: f64, requests: &[PaymentRequest]) -> String {
+    let mut output = format!(
+        "**{}** split a bill of `{}` euros, everyone owes `{}` euros\n",
+        requester.name,
+        amount,
+        requests
+            .first()
+            .map(|request| request.amount)
+            .unwrap_or_default()
+    );
+    for request in requests {
+        let status = match request.status {
+            PaymentRequestStatus::Pending => "⏳",
+            PaymentRequestStatus::Paid => "✅",
+            PaymentRequestStatus::Declined | PaymentRequestStatus::Expired => "❌",
+        };
+        output.push_str(
+            format!(
+                "- {} <@{}> {}\n",
+                status,
+                request.payer_id,
+                request.status.as_str()
+            )
+            .as_str(),
+        );
+    }
+    output
+}
+
+async fn ephemeral_error(
+    ctx: Context<'_>,
+    interaction: &ComponentInteraction,
+    error: ModelError,
+) -> Result<(), Error> {
+    interaction
+        .create_response(
+            ctx,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(error.to_string())
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Request euros from a user, transferred when they accept.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn request(
+    ctx: Context<'_>,
+    #[description = "User to request euros from"] payer: User,
+    #[description = "Amount in euros"] amount: f64,
+    #[description = "What the euros are for"]
+    #[rest]
+    reason: String,
+) -> Result<(), Error> {
+    let requester = ctx.author();
+    if payer.bot {
+        ctx.say("You can't request euros from a bot.").await?;
+        return Ok(());
+    }
+
+    let request = match ctx
+        .data()
+        .request_payment(requester.id.get(), payer.id.get(), amount, &reason)
+        .await
+    {
+        Ok(request) => request,
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    let prefix = format!("{}-", ctx.id());
+    let reply = match ctx
+        .send(
+            CreateReply::default()
+                .content(format!(
+                    "<@{}>, **{}** requests `{}` euros for: {}",
+                    payer.id, requester.name, amount, reason
+                ))
+                .components(request_buttons(&prefix)),
+        )
+        .await
+    {
+        Ok(reply) => reply,
+        Err(error) => {
+            ctx.data()
+                .close_payment_request(request.request_id, PaymentRequestStatus::Expired)
+                .await?;
+            Err(error)?
+        }
+    };
+
+    // The payer gets the timeout once, not again after every click
+    let deadline = Instant::now() + ctx.data().payment_request_timeout();
+    loop {
+        // Only the payer can pay, the requester can still call it off
+        let collector_prefix = prefix.clone();
+        let (requester_id, payer_id) = (requester.id, payer.id);
+        let Some(interaction) = ComponentInteractionCollector::new(ctx)
+            .channel_id(ctx.channel_id())
+            .timeout(deadline.saturating_duration_since(Instant::now()))
+            .filter(move |interaction| {
+                let custom_id = &interaction.data.custom_id;
+                custom_id.starts_with(&collector_prefix)
+                    && (interaction.user.id == payer_id
+                        || (interaction.user.id == requester_id && custom_id.ends_with("decline")))
+            })
+            .await
+        else {
+            ctx.data()
+                .close_payment_request(request.request_id, PaymentRequestStatus::Expired)
+                .await?;
+            reply
+                .edit(
+                    ctx,
+                    CreateReply::default()
+                        .content(format!(
+                            "**{}** didn't answer the request of `{}` euros in time",
+                            payer.name, amount
+                        ))
+                        .components(vec![]),
+                )
+                .await?;
+            break;
+        };
+
+        let result = if interaction.data.custom_id.ends_with("decline") {
+            ctx.data()
+                .close_payment_request(request.request_id, PaymentRequestStatus::Declined)
+                .await
+        } else {
+            ctx.data().pay_payment_request(request.request_id).await
+        };
+        match result {
+            Ok(request) => {
+                let content = match request.status {
+                    PaymentRequestStatus::Paid => format!(
+                        "**{}** paid `{}` euros to **{}** for: {}",
+                        payer.name, request.amount, requester.name, request.reason
+                    ),
+                    _ if interaction.user.id == requester.id => {
+                        format!("**{}** called off the request", requester.name)
+                    }
+                    _ => format!("**{}** declined the request", payer.name),
+                };
+                interaction
+                    .create_response(
+                        ctx,
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new()
+                                .content(content)
+                                .components(vec![]),
+                        ),
+                    )
+                    .await?;
+                break;
+            }
+            Err(error @ ModelError::InsuficientFunds) => {
+                ephemeral_error(ctx, &interaction, error).await?;
+            }
+            Err(error) => Err(error)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Split a bill evenly with other users, requesting their share from each of them.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn split(
+    ctx: Context<'_>,
+    #[description = "Total amount of the bill in euros"] amount: f64,
+    #[description = "Users to split with (ie: @user1 @user2)"]
+    #[rest]
+    users: String,
+) -> Result<(), Error> {
+    let requester = ctx.author();
+    let payer_ids = users
+        .split_whitespace()
+        .filter_map(parse_user_mention)
+        .map(|user_id| user_id.get())
+        .collect::<Vec<_>>();
+
+    let mut requests = match ctx
+        .data()
+        .split_bill(requester.id.get(), &payer_ids, amount, "Split bill")
+        .await
+    {
+        Ok(requests) => requests,
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    let prefix = format!("{}-", ctx.id());
+    let reply = match ctx
+        .send(
+            CreateReply::default()
+                .content(split_message(requester, amount, &requests))
+                .components(request_buttons(&prefix)),
+        )
+        .await
+    {
+        Ok(reply) => reply,
+        Err(error) => {
+            for request in &requests {
+                ctx.data()
+                    .close_payment_request(request.request_id, PaymentRequestStatus::Expired)
+                    .await?;
+            }
+            Err(error)?
+        }
+    };
+
+    // Payers share one deadline, answering doesn't extend it
+    let deadline = Instant::now() + ctx.data().payment_request_timeout();
+
+    while requests
+        .iter()
+        .any(|request| request.status == PaymentRequestStatus::Pending)
+    {
+        // Every payer answers their own share
+        let collector_prefix = prefix.clone();
+        let pending_ids = requests
+            .iter()
+            .filter(|request| request.status == PaymentRequestStatus::Pending)
+            .map(|request| UserId::new(request.payer_id))
+            .collect::<Vec<_>>();
+        let Some(interaction) = ComponentInteractionCollector::new(ctx)
+            .channel_id(ctx.channel_id())
+            .timeout(deadline.saturating_duration_since(Instant::now()))
+            .filter(move |interaction| {
+                interaction.data.custom_id.starts_with(&collector_prefix)
+                    && pending_ids.contains(&interaction.user.id)
+            })
+            .await
+        else {
+            for request in requests
+                .iter_mut()
+                .filter(|request| request.status == PaymentRequestStatus::Pending)
+            {
+                *request = ctx
+                    .data()
+                    .close_payment_request(request.request_id, PaymentRequestStatus::Expired)
+                    .await?;
+            }
+            reply
+                .edit(
+                    ctx,
+                    CreateReply::default()
+                        .content(split_message(requester, amount, &requests))
+                        .components(vec![]),
+                )
+                .await?;
+            return Ok(());
+        };
+
+        let Some(index) = requests
+            .iter()
+            .position(|request| request.payer_id == interaction.user.id.get())
+        else {
+            continue;
+        };
+        let request_id = requests[index].request_id;
+        let result = if interaction.data.custom_id.ends_with("decline") {
+            ctx.data()
+                .close_payment_request(request_id, PaymentRequestStatus::Declined)
+                .await
+        } else {
+            ctx.data().pay_payment_request(request_id).await
+        };
+        match result {
+            Ok(request) => {
+                requests[index] = request;
+                let mut message = CreateInteractionResponseMessage::new()
+                    .content(split_message(requester, amount, &requests));
+                if requests
+                    .iter()
+                    .all(|request| request.status != PaymentRequestStatus::Pending)
+                {
+                    message = message.components(vec![]);
+                }
+                interaction
+                    .create_response(ctx, CreateInteractionResponse::UpdateMessage(message))
+                    .await?;
+            }
+            Err(error @ ModelError::InsuficientFunds) => {
+                ephemeral_error(ctx, &interaction, error).await?;
+            }
+            Err(error) => Err(error)?,
+        }
+    }
+
+    Ok(())
+}
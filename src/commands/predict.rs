@@ -0,0 +1,71 @@
+use crate::{
+    Context, Error,
+    model::{ModelError, PriceDirection, parse_duration},
+};
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum PriceDirectionChoice {
+    Up,
+    Down,
+}
+
+/// Bet on the price of a coin going up or down within a duration (ie: /predict btc up 50 1h).
+#[poise::command(prefix_command, slash_command, category = "Predictions")]
+pub async fn predict(
+    ctx: Context<'_>,
+    #[description = "Crypto currency symbol (ie: btc, eth, ...)"] coin_symbol: String,
+    #[description = "Whether the price goes up or down"] direction: PriceDirectionChoice,
+    #[description = "Amount to bet in euros"] amount: f64,
+    #[description = "When the bet is resolved (ie: 30m, 1h, 1d)"] duration: String,
+) -> Result<(), Error> {
+    let direction = match direction {
+        PriceDirectionChoice::Up => PriceDirection::Up,
+        PriceDirectionChoice::Down => PriceDirection::Down,
+    };
+    let duration_secs = match parse_duration(&duration) {
+        Ok(duration_secs) => duration_secs,
+        Err(error) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    match ctx
+        .data()
+        .predict(
+            ctx.author().id.get(),
+            ctx.channel_id().get(),
+            &coin_symbol,
+            direction,
+            amount,
+            duration_secs,
+        )
+        .await
+    {
+        Ok(prediction) => {
+            ctx.say(format!(
+                "**{}** bet `{}` euros on **{}** going **{}** from `{}` euros\nResolved at {} UTC",
+                ctx.author().name,
+                prediction.amount,
+                prediction.coin_symbol,
+                prediction.direction.as_str(),
+                prediction.entry_price,
+                prediction.expires_at
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,352 @@
+use poise::serenity_prelude::{Role, RoleId, User};
+use tracing::warn;
+
+use crate::{
+    Context, Error,
+    commands::format_duration,
+    model::{ModelError, parse_duration},
+    permissions::*,
+};
+
+/// Displays the items for sale.
+#[poise::command(prefix_command, slash_command, category = "Shop")]
+pub async fn shop(ctx: Context<'_>) -> Result<(), Error> {
+    let items = ctx.data().shop_items().await?;
+    if items.is_empty() {
+        ctx.say("The shop is empty").await?;
+        return Ok(());
+    }
+
+    let mut output = "__Shop__\n".to_string();
+    for item in items {
+        let stock = match item.stock {
+            Some(0) => " (sold out)".to_string(),
+            Some(stock) => format!(" ({} left)", stock),
+            None => String::new(),
+        };
+        let kind = if item.consumable {
+            "consumable"
+        } else {
+            "permanent"
+        };
+        let role = match (item.role_id, item.duration_secs) {
+            (Some(role_id), Some(duration_secs)) => format!(
+                ", grants <@&{}> for {}",
+                role_id,
+                format_duration(duration_secs)
+            ),
+            (Some(role_id), None) => format!(", grants <@&{}>", role_id),
+            _ => String::new(),
+        };
+        output.push_str(
+            format!(
+                "- **{}** `{}` euros{}\n  {} -# {}{}\n",
+                item.name, item.price, stock, item.description, kind, role
+            )
+            .as_str(),
+        );
+    }
+    ctx.say(output).await?;
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Manage the items sold in the shop.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Shop",
+    rename = "shop-item",
+    subcommands("shop_item_add", "shop_item_remove", "shop_item_restock"),
+    subcommand_required,
+    check = "is_admin"
+)]
+pub async fn shop_item(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// ADMIN COMMAND: Put a new item for sale.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Shop",
+    rename = "add",
+    check = "is_admin"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn shop_item_add(
+    ctx: Context<'_>,
+    #[description = "Item name"] name: String,
+    #[description = "Price in euros"] price: f64,
+    #[description = "Item description"] description: String,
+    #[description = "Items for sale, unlimited if empty"] stock: Option<i64>,
+    #[description = "Discord role granted to buyers"] role: Option<Role>,
+    #[description = "How long the role is granted for (ie: 1d, 1w), forever if empty"]
+    duration: Option<String>,
+    #[description = "Whether the item is used up, permanent by default"] consumable: Option<bool>,
+) -> Result<(), Error> {
+    let duration_secs = match duration.as_deref().map(parse_duration).transpose() {
+        Ok(duration_secs) => duration_secs,
+        Err(error) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    match ctx
+        .data()
+        .add_shop_item(
+            &name,
+            &description,
+            price,
+            stock,
+            role.map(|role| role.id.get()),
+            duration_secs,
+            consumable.unwrap_or(false),
+        )
+        .await
+    {
+        Ok(item) => {
+            ctx.say(format!(
+                "**{}** is now sold for `{}` euros",
+                item.name, item.price
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Stop selling an item, users keep the ones they own.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Shop",
+    rename = "remove",
+    check = "is_admin"
+)]
+pub async fn shop_item_remove(
+    ctx: Context<'_>,
+    #[description = "Item name"] name: String,
+) -> Result<(), Error> {
+    match ctx.data().remove_shop_item(&name).await {
+        Ok(item) => {
+            ctx.say(format!("**{}** was removed from the shop", item.name))
+                .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// ADMIN COMMAND: Set the stock of an item.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Shop",
+    rename = "restock",
+    check = "is_admin"
+)]
+pub async fn shop_item_restock(
+    ctx: Context<'_>,
+    #[description = "Item name"] name: String,
+    #[description = "Items for sale, unlimited if empty"] stock: Option<i64>,
+) -> Result<(), Error> {
+    match ctx.data().restock_shop_item(&name, stock).await {
+        Ok(item) => {
+            let stock = match item.stock {
+                Some(stock) => format!("`{}`", stock),
+                None => "unlimited".to_string(),
+            };
+            ctx.say(format!("**{}** stock is now {}", item.name, stock))
+                .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Buy an item from the shop.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Shop",
+    rename = "buyitem",
+    aliases("buy-item")
+)]
+pub async fn buy_item(
+    ctx: Context<'_>,
+    #[description = "Item name"] name: String,
+    #[description = "How many to buy, 1 by default"] quantity: Option<i64>,
+) -> Result<(), Error> {
+    // Roles can only be granted in a server
+    let guild_id = ctx.guild_id();
+    match ctx.data().shop_item(&name).await {
+        Ok(item) if item.role_id.is_some() && guild_id.is_none() => {
+            ctx.say(format!(
+                "**{}** grants a role, it can only be bought in a server",
+                item.name
+            ))
+            .await?;
+            return Ok(());
+        }
+        Ok(_) => {}
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    }
+
+    let quantity = quantity.unwrap_or(1);
+    match ctx
+        .data()
+        .buy_item(ctx.author().id.get(), &name, quantity)
+        .await
+    {
+        Ok((item, owned)) => {
+            let mut output = format!(
+                "**{}** bought {} **{}** for `{}` euros, they own {} now",
+                ctx.author().name,
+                quantity,
+                item.name,
+                item.price * quantity as f64,
+                owned
+            );
+            if let (Some(role_id), Some(guild_id)) = (item.role_id, guild_id) {
+                let user_id = ctx.author().id;
+                if let Err(error) = ctx
+                    .http()
+                    .add_member_role(
+                        guild_id,
+                        user_id,
+                        RoleId::new(role_id),
+                        Some("Bought in the shop"),
+                    )
+                    .await
+                {
+                    // Most likely the bot lacks the permission to manage the role
+                    warn!("Failed to grant role {}: {}", role_id, error);
+                    ctx.data()
+                        .refund_item(user_id.get(), item.item_id, quantity)
+                        .await?;
+                    ctx.say(format!(
+                        "I couldn't grant <@&{}>, ask an admin to check my permissions. The purchase was refunded",
+                        role_id
+                    ))
+                    .await?;
+                    return Ok(());
+                }
+                if item.duration_secs.is_some() {
+                    let expires_at = ctx
+                        .data()
+                        .record_role_grant(
+                            user_id.get(),
+                            guild_id.get(),
+                            ctx.channel_id().get(),
+                            &item,
+                            quantity,
+                        )
+                        .await?;
+                    output.push_str(
+                        format!("\n-# <@&{}> until {} UTC", role_id, expires_at).as_str(),
+                    );
+                }
+            }
+            ctx.say(output).await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the items a user owns.
+#[poise::command(prefix_command, slash_command, category = "Shop")]
+pub async fn inventory(
+    ctx: Context<'_>,
+    #[description = "User to show, yourself if empty"] user: Option<User>,
+) -> Result<(), Error> {
+    let user = user.as_ref().unwrap_or(ctx.author());
+    match ctx.data().inventory(user.id.get()).await {
+        Ok(items) => {
+            if items.is_empty() {
+                ctx.say(format!("**{}** owns no items", user.name)).await?;
+                return Ok(());
+            }
+
+            let mut output = format!("__**{}** inventory__\n", user.name);
+            for owned in items {
+                output.push_str(
+                    format!(
+                        "- **{}** x{}: {}\n",
+                        owned.item.name, owned.quantity, owned.item.description
+                    )
+                    .as_str(),
+                );
+            }
+            ctx.say(output).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!("User **{}** has no bank account", user.name))
+                .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Use up one of your consumable items.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Shop",
+    rename = "useitem",
+    aliases("use-item")
+)]
+pub async fn use_item(
+    ctx: Context<'_>,
+    #[description = "Item name"] name: String,
+) -> Result<(), Error> {
+    match ctx.data().use_item(ctx.author().id.get(), &name).await {
+        Ok((name, left)) => {
+            ctx.say(format!(
+                "**{}** used **{}**, {} left",
+                ctx.author().name,
+                name,
+                left
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use poise::serenity_prelude::{User, UserId};
+
+use crate::{
+    Context, Error,
+    commands::get_user_name,
+    model::{ModelError, OrderFrequency},
+};
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum OrderFrequencyChoice {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+/// Recurring payments to other users, paid automatically every period.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Finance",
+    rename = "standing-order",
+    aliases("standingorder"),
+    subcommands(
+        "standing_order_create",
+        "standing_order_list",
+        "standing_order_cancel"
+    ),
+    subcommand_required
+)]
+pub async fn standing_order(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Pay a user every day, week or month, starting one period from now.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "create")]
+pub async fn standing_order_create(
+    ctx: Context<'_>,
+    #[description = "User to pay"] recipient: User,
+    #[description = "Amount in euros"] amount: f64,
+    #[description = "How often to pay"] frequency: OrderFrequencyChoice,
+) -> Result<(), Error> {
+    let frequency = match frequency {
+        OrderFrequencyChoice::Daily => OrderFrequency::Daily,
+        OrderFrequencyChoice::Weekly => OrderFrequency::Weekly,
+        OrderFrequencyChoice::Monthly => OrderFrequency::Monthly,
+    };
+
+    match ctx
+        .data()
+        .create_standing_order(
+            ctx.author().id.get(),
+            recipient.id.get(),
+            amount,
+            frequency,
+            ctx.channel_id().get(),
+        )
+        .await
+    {
+        Ok(order) => {
+            ctx.say(format!(
+                "**Standing order #{}**: `{}` euros {} to **{}**\n**Next payment:** {} UTC",
+                order.order_id,
+                order.amount,
+                order.frequency.as_str(),
+                recipient.name,
+                order.next_run_at
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(user_id)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                get_user_name(&ctx, UserId::new(user_id)).await
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the standing orders you pay and receive.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "list")]
+pub async fn standing_order_list(ctx: Context<'_>) -> Result<(), Error> {
+    let user_id = ctx.author().id.get();
+    match ctx.data().standing_orders(user_id).await {
+        Ok(orders) => {
+            if orders.is_empty() {
+                ctx.say("You have no standing orders").await?;
+                return Ok(());
+            }
+
+            let mut output = "__Standing orders__\n".to_string();
+            for order in orders {
+                let line = if order.user_id == user_id {
+                    format!(
+                        "- #{} you pay `{}` euros {} to **{}**, next at {} UTC\n",
+                        order.order_id,
+                        order.amount,
+                        order.frequency.as_str(),
+                        get_user_name(&ctx, UserId::new(order.recipient_id)).await,
+                        order.next_run_at
+                    )
+                } else {
+                    format!(
+                        "- #{} **{}** pays you `{}` euros {}, next at {} UTC\n",
+                        order.order_id,
+                        get_user_name(&ctx, UserId::new(order.user_id)).await,
+                        order.amount,
+                        order.frequency.as_str(),
+                        order.next_run_at
+                    )
+                };
+                output.push_str(line.as_str());
+            }
+            ctx.say(output).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Cancel one of your standing orders.
+#[poise::command(prefix_command, slash_command, category = "Finance", rename = "cancel")]
+pub async fn standing_order_cancel(
+    ctx: Context<'_>,
+    #[description = "Standing order number"] order_id: i64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .cancel_standing_order(ctx.author().id.get(), order_id)
+        .await
+    {
+        Ok(()) => {
+            ctx.say(format!("Standing order #{} was cancelled", order_id))
+                .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
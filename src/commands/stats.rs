@@ -0,0 +1,86 @@
+use poise::serenity_prelude::{User, UserId};
+
+use crate::{
+    Context, Error,
+    commands::get_user_name,
+    model::{GambleRanking, GambleStats, ModelError},
+};
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum GambleRankingChoice {
+    Luckiest,
+    Unluckiest,
+}
+
+fn stats_message(user_name: &str, stats: &GambleStats) -> String {
+    let streak = match stats.streak {
+        0 => "none".to_string(),
+        streak if streak > 0 => format!("{} wins", streak),
+        streak => format!("{} losses", -streak),
+    };
+    format!(
+        "__**{}** gambling stats__\n**Bets:** `{}` ({} won, {} lost)\n**Win Rate:** `{:.1}%`\n**Total Wagered:** `{}` euros\n**Net Result:** `{}` euros\n**Biggest Win:** `{}` euros\n**Biggest Loss:** `{}` euros\n**Current Streak:** {}",
+        user_name,
+        stats.bets,
+        stats.wins,
+        stats.losses,
+        stats.win_rate() * 100f64,
+        stats.wagered,
+        stats.net,
+        stats.biggest_win,
+        stats.biggest_loss,
+        streak
+    )
+}
+
+/// Displays the gambling record of a user, or the luckiest and unluckiest gamblers.
+#[poise::command(prefix_command, slash_command, category = "Casino")]
+pub async fn gamblestats(
+    ctx: Context<'_>,
+    #[description = "User to show, yourself if empty"] user: Option<User>,
+    #[description = "Show a leaderboard instead"] ranking: Option<GambleRankingChoice>,
+) -> Result<(), Error> {
+    if let Some(ranking) = ranking {
+        let ranking = match ranking {
+            GambleRankingChoice::Luckiest => GambleRanking::Luckiest,
+            GambleRankingChoice::Unluckiest => GambleRanking::Unluckiest,
+        };
+        let gamblers = ctx.data().gamble_leaderboard(ranking, 10).await?;
+        if gamblers.is_empty() {
+            ctx.say("Nobody gambled yet").await?;
+            return Ok(());
+        }
+
+        let mut output = match ranking {
+            GambleRanking::Luckiest => "__Luckiest gamblers__\n".to_string(),
+            GambleRanking::Unluckiest => "__Unluckiest gamblers__\n".to_string(),
+        };
+        for (user_id, net, wagered) in gamblers {
+            output.push_str(
+                format!(
+                    "- **{}** net `{}` euros over `{}` euros wagered\n",
+                    get_user_name(&ctx, UserId::new(user_id)).await,
+                    net,
+                    wagered
+                )
+                .as_str(),
+            );
+        }
+        ctx.say(output).await?;
+        return Ok(());
+    }
+
+    let user = user.as_ref().unwrap_or(ctx.author());
+    match ctx.data().gamble_stats(user.id.get()).await {
+        Ok(stats) => {
+            ctx.say(stats_message(&user.name, &stats)).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!("User **{}** has no bank account", user.name))
+                .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
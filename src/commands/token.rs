@@ -0,0 +1,241 @@
+use crate::{
+    Context, Error,
+    model::{ModelError, SwapSide},
+    permissions::*,
+};
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum SwapSideChoice {
+    Buy,
+    Sell,
+}
+
+/// ADMIN COMMAND: Create a server token backed by a liquidity pool funded with your euros.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Market",
+    guild_only,
+    check = "is_admin"
+)]
+pub async fn mint(
+    ctx: Context<'_>,
+    #[description = "Token symbol"] coin_symbol: String,
+    #[description = "Token name"] name: String,
+    #[description = "Total supply of tokens, all placed in the pool"] supply: f64,
+    #[description = "Euros placed in the pool, sets the initial price"] euros: f64,
+) -> Result<(), Error> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .mint_token(
+            ctx.author().id.get(),
+            guild_id.get(),
+            &coin_symbol,
+            &name,
+            supply,
+            euros,
+        )
+        .await
+    {
+        Ok(()) => {
+            ctx.say(format!(
+                "Minted `{}` **{}** ({}) at `{}` euros each",
+                supply,
+                name,
+                coin_symbol.to_uppercase(),
+                euros / supply
+            ))
+            .await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_) | error @ ModelError::InsuficientFunds) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Preview a server token trade: tokens received or needed, average price and price impact.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn quote(
+    ctx: Context<'_>,
+    #[description = "Server token symbol"] coin_symbol: String,
+    #[description = "Buy or sell"] side: SwapSideChoice,
+    #[description = "Value in euros of the trade"] value: f64,
+) -> Result<(), Error> {
+    let side = match side {
+        SwapSideChoice::Buy => SwapSide::Buy,
+        SwapSideChoice::Sell => SwapSide::Sell,
+    };
+
+    match ctx
+        .data()
+        .swap_quote(
+            ctx.guild_id().map(|guild_id| guild_id.get()),
+            &coin_symbol,
+            side,
+            value,
+        )
+        .await
+    {
+        Ok(quote) => {
+            ctx.say(format!(
+                "{} `{}` {} for `{}` euros\n**Average Price:** `{}` euros (spot `{}`)\n**Price Impact:** `{:.2}%`",
+                match side {
+                    SwapSide::Buy => "Buy",
+                    SwapSide::Sell => "Sell",
+                },
+                quote.token_amount,
+                coin_symbol.to_uppercase(),
+                quote.euro_amount,
+                quote.price,
+                quote.spot_price,
+                quote.price_impact * 100f64
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the liquidity pool of a server token and your share of it.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn pool(
+    ctx: Context<'_>,
+    #[description = "Server token symbol"] coin_symbol: String,
+) -> Result<(), Error> {
+    let Some(pool) = ctx.data().server_token(&coin_symbol).await? else {
+        ctx.say("'symbol' is not a server token.").await?;
+        return Ok(());
+    };
+    let shares = ctx
+        .data()
+        .liquidity_shares(ctx.author().id.get(), &coin_symbol)
+        .await?;
+
+    let share_fraction = if pool.total_shares > 0f64 {
+        shares / pool.total_shares
+    } else {
+        0f64
+    };
+    ctx.say(format!(
+        "**{}** ({}) pool\n**Reserves:** `{}` euros / `{}` {}\n**Price:** `{}` euros\n**Your Shares:** `{}` ({:.2}%)",
+        pool.name,
+        pool.coin_symbol,
+        pool.euro_reserve,
+        pool.token_reserve,
+        pool.coin_symbol,
+        pool.spot_price(),
+        shares,
+        share_fraction * 100f64
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Provide liquidity to a server token pool with euros and matching tokens, earning swap fees.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn addliquidity(
+    ctx: Context<'_>,
+    #[description = "Server token symbol"] coin_symbol: String,
+    #[description = "Euros to deposit, tokens are added at the pool price"] euros: f64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .add_liquidity(
+            ctx.author().id.get(),
+            ctx.guild_id().map(|guild_id| guild_id.get()),
+            &coin_symbol,
+            euros,
+        )
+        .await
+    {
+        Ok((token_amount, shares)) => {
+            ctx.say(format!(
+                "Deposited `{}` euros and `{}` {} for `{}` pool shares",
+                euros,
+                token_amount,
+                coin_symbol.to_uppercase(),
+                shares
+            ))
+            .await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(
+            error @ ModelError::InvalidValue(_)
+            | error @ ModelError::InsuficientFunds
+            | error @ ModelError::InsuficientCoins,
+        ) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Redeem server token pool shares for euros and tokens.
+#[poise::command(prefix_command, slash_command, category = "Market")]
+pub async fn removeliquidity(
+    ctx: Context<'_>,
+    #[description = "Server token symbol"] coin_symbol: String,
+    #[description = "Pool shares to redeem"] shares: f64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .remove_liquidity(
+            ctx.author().id.get(),
+            ctx.guild_id().map(|guild_id| guild_id.get()),
+            &coin_symbol,
+            shares,
+        )
+        .await
+    {
+        Ok((euro_amount, token_amount)) => {
+            ctx.say(format!(
+                "Withdrew `{}` euros and `{}` {}",
+                euro_amount,
+                token_amount,
+                coin_symbol.to_uppercase()
+            ))
+            .await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use poise::serenity_prelude::UserId;
+
+use crate::{Context, Error, commands::get_user_name, model::ModelError, permissions::*};
+
+/// Trading tournaments with isolated accounts. Trade with the tournament option of /buy and /sell.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Tournaments",
+    subcommands(
+        "tournament_create",
+        "tournament_list",
+        "tournament_join",
+        "tournament_standings"
+    ),
+    subcommand_required
+)]
+pub async fn tournament(_: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// ADMIN COMMAND: Create a trading tournament. Dates are in UTC (ie: 2025-01-31 18:00).
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Tournaments",
+    rename = "create",
+    check = "is_admin"
+)]
+pub async fn tournament_create(
+    ctx: Context<'_>,
+    #[description = "Tournament name"] name: String,
+    #[description = "Start date in UTC (ie: 2025-01-31 18:00)"] start: String,
+    #[description = "End date in UTC (ie: 2025-02-07 18:00)"] end: String,
+    #[description = "Starting capital in euros of each participant"] capital: f64,
+    #[description = "Prizes in euros by rank, comma separated (ie: 500,250,100)"] prizes: String,
+) -> Result<(), Error> {
+    let Ok(prizes) = prizes
+        .split(',')
+        .map(|prize| prize.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+    else {
+        ctx.say("Invalid value, 'prizes' must be comma separated amounts.")
+            .await?;
+        return Ok(());
+    };
+
+    match ctx
+        .data()
+        .create_tournament(
+            &name,
+            &start,
+            &end,
+            capital,
+            &prizes,
+            ctx.channel_id().get(),
+        )
+        .await
+    {
+        Ok(tournament_id) => {
+            ctx.say(format!(
+                "Created tournament `#{}` **{}**, join with `/tournament join {}`",
+                tournament_id, name, tournament_id
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays upcoming and running tournaments.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Tournaments",
+    rename = "list"
+)]
+pub async fn tournament_list(ctx: Context<'_>) -> Result<(), Error> {
+    let tournaments = ctx.data().tournaments().await?;
+
+    if tournaments.is_empty() {
+        ctx.say("No upcoming tournaments").await?;
+        return Ok(());
+    }
+
+    let mut output = "Tournaments:\n".to_string();
+    for tournament in &tournaments {
+        output.push_str(
+            format!(
+                "- `#{}` **{}** from `{}` to `{}` UTC, capital `{}` euros, prizes `{:?}`\n",
+                tournament.tournament_id,
+                tournament.name,
+                tournament.start_time,
+                tournament.end_time,
+                tournament.starting_capital,
+                tournament.prizes
+            )
+            .as_str(),
+        );
+    }
+    ctx.say(output).await?;
+
+    Ok(())
+}
+
+/// Join a tournament, receiving an isolated account with the starting capital.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Tournaments",
+    rename = "join"
+)]
+pub async fn tournament_join(
+    ctx: Context<'_>,
+    #[description = "Tournament id (see /tournament list)"] tournament_id: i64,
+) -> Result<(), Error> {
+    match ctx
+        .data()
+        .join_tournament(tournament_id, ctx.author().id.get())
+        .await
+    {
+        Ok(()) => {
+            ctx.say(format!(
+                "**{}** joined tournament `#{}`",
+                ctx.author().name,
+                tournament_id
+            ))
+            .await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Tournament leaderboard by return percentage.
+#[poise::command(
+    prefix_command,
+    slash_command,
+    category = "Tournaments",
+    rename = "standings"
+)]
+pub async fn tournament_standings(
+    ctx: Context<'_>,
+    #[description = "Tournament id (see /tournament list)"] tournament_id: i64,
+) -> Result<(), Error> {
+    let (tournament, standings) = match ctx.data().tournament_standings(tournament_id).await {
+        Ok(tournament_standings) => tournament_standings,
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+            return Ok(());
+        }
+        Err(error) => Err(error)?,
+    };
+
+    if standings.is_empty() {
+        ctx.say("No participants in tournament").await?;
+        return Ok(());
+    }
+
+    let mut output = format!(
+        "{} standings of **{}**\n",
+        if tournament.finished { "Final" } else { "Live" },
+        tournament.name
+    );
+    for (rank, standing) in standings.iter().enumerate() {
+        output.push_str(
+            format!(
+                "{}. **{}** `{:+.2}%` (`{}` euros)\n",
+                rank + 1,
+                get_user_name(&ctx, UserId::new(standing.user_id)).await,
+                standing.return_pct,
+                standing.value
+            )
+            .as_str(),
+        );
+    }
+    ctx.say(output).await?;
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::{
+    Context, Error,
+    commands::format_duration,
+    model::{ModelError, WorkResult, WorkStatus},
+};
+
+/// Work a shift for euros, the highest level job you unlocked by default.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn work(
+    ctx: Context<'_>,
+    #[description = "Job to work, see /jobs"] job: Option<String>,
+) -> Result<(), Error> {
+    match ctx.data().work(ctx.author().id.get(), job.as_deref()).await {
+        Ok(WorkResult {
+            status: WorkStatus::Worked(shift),
+            next_work_secs,
+        }) => {
+            let mut output = format!(
+                "**{}** worked as {} and earned `{}` euros\n-# Next shift in {}",
+                ctx.author().name,
+                shift.job,
+                shift.pay,
+                format_duration(next_work_secs)
+            );
+            if shift.leveled_up {
+                output.push_str(
+                    format!(
+                        "\nReached job level {}! Check the new jobs with /jobs",
+                        shift.level
+                    )
+                    .as_str(),
+                );
+            }
+            ctx.say(output).await?;
+        }
+        Ok(WorkResult {
+            status: WorkStatus::OnCooldown,
+            next_work_secs,
+        }) => {
+            ctx.say(format!(
+                "You're still resting, next shift in {}",
+                format_duration(next_work_secs)
+            ))
+            .await?;
+        }
+        Err(error @ ModelError::InvalidValue(_)) => {
+            ctx.say(error.to_string()).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
+
+/// Displays the jobs you can work and the ones still locked.
+#[poise::command(prefix_command, slash_command, category = "Finance")]
+pub async fn jobs(ctx: Context<'_>) -> Result<(), Error> {
+    match ctx.data().work_level(ctx.author().id.get()).await {
+        Ok((shifts, level)) => {
+            let mut output = format!(
+                "__Jobs__\n**Job Level:** {} ({} shifts worked)\n",
+                level, shifts
+            );
+            for job in ctx.data().jobs() {
+                let lock = if job.level <= level { "✅" } else { "🔒" };
+                output.push_str(
+                    format!(
+                        "- {} **{}** (level {}) `{}`-`{}` euros, every {}\n",
+                        lock,
+                        job.name,
+                        job.level,
+                        job.min_pay,
+                        job.max_pay,
+                        format_duration(job.cooldown_secs)
+                    )
+                    .as_str(),
+                );
+            }
+            ctx.say(output).await?;
+        }
+        Err(ModelError::BankAccountNotFound(_)) => {
+            ctx.say(format!(
+                "User **{}** has no bank account",
+                ctx.author().name
+            ))
+            .await?;
+        }
+        Err(error) => Err(error)?,
+    }
+
+    Ok(())
+}
@@ -11,6 +11,390 @@ pub struct Config {
     pub use_cmc_sandbox_api: bool,
     #[serde(default)]
     pub bot_admins: Vec<u64>,
+    #[serde(default)]
+    pub bot_mods: Vec<u64>,
+    #[serde(default)]
+    pub amm: AmmConfig,
+    #[serde(default)]
+    pub blackjack: BlackjackConfig,
+    #[serde(default)]
+    pub roulette: RouletteConfig,
+    #[serde(default)]
+    pub slots: SlotsConfig,
+    #[serde(default)]
+    pub duel: DuelConfig,
+    #[serde(default)]
+    pub crash: CrashConfig,
+    #[serde(default)]
+    pub lottery: LotteryConfig,
+    #[serde(default)]
+    pub markets: MarketsConfig,
+    #[serde(default)]
+    pub predict: PredictConfig,
+    #[serde(default)]
+    pub rewards: RewardsConfig,
+    #[serde(default)]
+    pub interest: InterestConfig,
+    #[serde(default)]
+    pub debts: DebtsConfig,
+    #[serde(default)]
+    pub payments: PaymentsConfig,
+    #[serde(default)]
+    pub work: WorkConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct AmmConfig {
+    /// Fraction of every swap kept in the pool for liquidity providers.
+    pub fee: f64,
+}
+
+impl Default for AmmConfig {
+    fn default() -> Self {
+        Self { fee: 0.003 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct BlackjackConfig {
+    /// Number of 52 card decks in the shoe.
+    pub decks: u32,
+    /// Whether the dealer draws on a soft 17.
+    pub dealer_hits_soft_17: bool,
+    /// Payout of a natural blackjack per euro bet, 1.5 pays 3 to 2.
+    pub blackjack_payout: f64,
+    /// Seconds without an action before standing on every hand.
+    pub timeout_secs: u64,
+}
+
+impl Default for BlackjackConfig {
+    fn default() -> Self {
+        Self {
+            decks: 6,
+            dealer_hits_soft_17: false,
+            blackjack_payout: 1.5,
+            timeout_secs: 120,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RouletteConfig {
+    /// Seconds a round stays open for bets before the wheel spins.
+    pub round_secs: u64,
+}
+
+impl Default for RouletteConfig {
+    fn default() -> Self {
+        Self { round_secs: 30 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SlotsConfig {
+    /// Symbols on each reel strip, a symbol repeated on a strip is more
+    /// likely to be shown.
+    pub reels: Vec<Vec<String>>,
+    /// Winning lines checked in order, the first match pays.
+    pub paytable: Vec<SlotsPayline>,
+    /// Symbol that wins the progressive jackpot when shown on every reel.
+    pub jackpot_symbol: String,
+    /// Euros the jackpot starts from and resets to after being won.
+    pub jackpot_seed: f64,
+    /// Share of every lost bet added to the jackpot.
+    pub jackpot_share: f64,
+    /// Return to player the reels and paytable are designed for, checked by
+    /// the `simulate-slots` command.
+    pub target_rtp: f64,
+}
+
+#[derive(Deserialize)]
+pub struct SlotsPayline {
+    /// Symbol required on each reel, `*` matches any symbol.
+    pub symbols: Vec<String>,
+    /// Euros paid per euro bet.
+    pub multiplier: f64,
+}
+
+impl Default for SlotsConfig {
+    fn default() -> Self {
+        let strip: Vec<String> = [
+            ("🍒", 7),
+            ("🍋", 5),
+            ("🔔", 4),
+            ("⭐", 2),
+            ("7️⃣", 1),
+            ("💎", 1),
+        ]
+        .into_iter()
+        .flat_map(|(symbol, count)| std::iter::repeat_n(symbol.to_string(), count))
+        .collect();
+        let payline = |symbols: [&str; 3], multiplier: f64| SlotsPayline {
+            symbols: symbols.map(String::from).to_vec(),
+            multiplier,
+        };
+
+        Self {
+            reels: vec![strip.clone(), strip.clone(), strip],
+            paytable: vec![
+                payline(["7️⃣", "7️⃣", "7️⃣"], 100.0),
+                payline(["⭐", "⭐", "⭐"], 50.0),
+                payline(["🔔", "🔔", "🔔"], 25.0),
+                payline(["🍋", "🍋", "🍋"], 12.0),
+                payline(["🍒", "🍒", "🍒"], 6.0),
+                payline(["🍒", "🍒", "*"], 2.8),
+            ],
+            jackpot_symbol: "💎".into(),
+            jackpot_seed: 100.0,
+            jackpot_share: 0.01,
+            target_rtp: 0.95,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DuelConfig {
+    /// Fraction of the pot of every duel kept by the house.
+    pub fee: f64,
+    /// Seconds the opponent has to accept a duel.
+    pub timeout_secs: u64,
+}
+
+impl Default for DuelConfig {
+    fn default() -> Self {
+        Self {
+            fee: 0.0,
+            timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CrashConfig {
+    /// Seconds a round stays open for bets before taking off.
+    pub join_secs: u64,
+    /// Growth rate of the multiplier per second, it reaches `e^(growth * t)`.
+    pub growth: f64,
+    /// Fraction of the expected return kept by the house.
+    pub house_edge: f64,
+    /// Highest multiplier a round can reach before crashing.
+    pub max_multiplier: f64,
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        Self {
+            join_secs: 15,
+            growth: 0.1,
+            house_edge: 0.01,
+            max_multiplier: 100.0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LotteryConfig {
+    /// Price in euros of a ticket.
+    pub ticket_price: f64,
+    /// Fraction of the pot kept by the house.
+    pub house_cut: f64,
+    /// Weekday of the draw (ie: sunday), or daily.
+    pub draw_day: String,
+    /// UTC time of the draw (ie: 20:00).
+    pub draw_time: String,
+    /// Channel the results of every draw are announced in.
+    pub channel_id: Option<u64>,
+}
+
+impl Default for LotteryConfig {
+    fn default() -> Self {
+        Self {
+            ticket_price: 10.0,
+            house_cut: 0.05,
+            draw_day: "sunday".into(),
+            draw_time: "20:00".into(),
+            channel_id: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct MarketsConfig {
+    /// Fraction of the pot of every resolved market kept by the house.
+    pub fee: f64,
+}
+
+impl Default for MarketsConfig {
+    fn default() -> Self {
+        Self { fee: 0.0 }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PredictConfig {
+    /// Euros paid per euro bet on a right price prediction.
+    pub multiplier: f64,
+    /// Shortest and longest time a prediction can run, in seconds.
+    pub min_duration_secs: u64,
+    pub max_duration_secs: u64,
+}
+
+impl Default for PredictConfig {
+    fn default() -> Self {
+        Self {
+            multiplier: 1.9,
+            min_duration_secs: 5 * 60,
+            max_duration_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct RewardsConfig {
+    /// Euros paid by the first daily claim of a streak.
+    pub daily_amount: f64,
+    /// Euros added to the daily reward per consecutive day claimed.
+    pub streak_bonus: f64,
+    /// Most euros a daily claim can pay, however long the streak.
+    pub max_daily_amount: f64,
+    /// Hours after the end of a missed day during which a claim still keeps
+    /// the streak going.
+    pub grace_hours: u32,
+    pub weekly_amount: f64,
+    pub monthly_amount: f64,
+    /// Default timezone days are counted in (ie: Europe/Lisbon), guilds and
+    /// users can set their own.
+    pub timezone: String,
+    /// Local time the daily reward resets at (ie: 00:00).
+    pub reset_time: String,
+}
+
+impl Default for RewardsConfig {
+    fn default() -> Self {
+        Self {
+            daily_amount: 100.0,
+            streak_bonus: 10.0,
+            max_daily_amount: 300.0,
+            grace_hours: 6,
+            weekly_amount: 500.0,
+            monthly_amount: 2000.0,
+            timezone: "UTC".into(),
+            reset_time: "00:00".into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct InterestConfig {
+    /// Daily interest paid on savings, admins can change it at runtime.
+    pub savings_rate: f64,
+    /// Daily interest charged on bank loans, admins can change it at runtime.
+    pub loan_rate: f64,
+    /// Days a loan has to be repaid in before it is collected.
+    pub loan_days: u32,
+    /// Largest loan the bank grants.
+    pub max_loan: f64,
+    /// Channel defaulted loans are announced in.
+    pub channel_id: Option<u64>,
+}
+
+impl Default for InterestConfig {
+    fn default() -> Self {
+        Self {
+            savings_rate: 0.001,
+            loan_rate: 0.01,
+            loan_days: 7,
+            max_loan: 1000.0,
+            channel_id: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DebtsConfig {
+    /// Seconds the borrower has to accept a lend offer.
+    pub offer_timeout_secs: u64,
+    /// Hours before the due date borrowers are reminded of a debt.
+    pub reminder_hours: u32,
+}
+
+impl Default for DebtsConfig {
+    fn default() -> Self {
+        Self {
+            offer_timeout_secs: 300,
+            reminder_hours: 24,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PaymentsConfig {
+    /// Seconds payers have to answer a payment request or bill split.
+    pub request_timeout_secs: u64,
+}
+
+impl Default for PaymentsConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout_secs: 900,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct WorkConfig {
+    /// Shifts worked to reach each next job level.
+    pub shifts_per_level: u32,
+    /// Jobs users can work, each unlocked at a level.
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Deserialize)]
+pub struct Job {
+    pub name: String,
+    /// Job level required to work it, users start at level 1.
+    pub level: u32,
+    /// Euros paid per shift, picked at random between both.
+    pub min_pay: f64,
+    pub max_pay: f64,
+    /// Seconds to wait after a shift before working again.
+    pub cooldown_secs: u64,
+}
+
+impl Default for WorkConfig {
+    fn default() -> Self {
+        let job = |name: &str, level: u32, min_pay: f64, max_pay: f64, cooldown_secs: u64| Job {
+            name: name.into(),
+            level,
+            min_pay,
+            max_pay,
+            cooldown_secs,
+        };
+
+        Self {
+            shifts_per_level: 10,
+            jobs: vec![
+                job("Cashier", 1, 20.0, 50.0, 3600),
+                job("Barista", 2, 40.0, 80.0, 3600),
+                job("Developer", 4, 80.0, 160.0, 7200),
+                job("Trader", 7, 50.0, 400.0, 7200),
+            ],
+        }
+    }
 }
 
 #[derive(Debug, Error)]
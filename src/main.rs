@@ -1,21 +1,26 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use poise::serenity_prelude::{self as serenity};
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use std::{sync::Arc, time::Duration};
 use tracing::{error, info};
-use tracing_subscriber::{self, EnvFilter};
 
-use crate::{config::Config, model::ModelError};
+use crate::{
+    backtest::{StrategyKind, StrategyParams},
+    config::Config,
+    model::ModelError,
+};
 
+mod backtest;
 mod commands;
 mod config;
 mod model;
 mod permissions;
+mod scheduler;
 
 // Types used by all command functions
 type Error = anyhow::Error;
-type Context<'a> = poise::Context<'a, model::Model, anyhow::Error>;
+type Context<'a> = poise::Context<'a, Arc<model::Model>, anyhow::Error>;
 
-async fn on_error(error: poise::FrameworkError<'_, model::Model, anyhow::Error>) {
+async fn on_error(error: poise::FrameworkError<'_, Arc<model::Model>, anyhow::Error>) {
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx, .. } => {
@@ -38,6 +43,116 @@ struct Args {
     /// Path to config toml file
     #[arg(short, long)]
     config: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Replay a trading strategy over recorded or imported prices
+    Backtest(BacktestArgs),
+    /// Measure the slot machine return to player over simulated spins
+    SimulateSlots {
+        /// Number of spins to simulate
+        #[arg(long, default_value_t = 1_000_000)]
+        spins: u64,
+        /// Maximum allowed difference from the configured target RTP
+        #[arg(long, default_value_t = 0.01)]
+        tolerance: f64,
+        /// Euros bet on every spin, the jackpot seed returns more to small bets
+        #[arg(long, default_value_t = 1.0)]
+        bet: f64,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct BacktestArgs {
+    /// Strategy to replay
+    #[arg(long, value_enum)]
+    strategy: StrategyKind,
+    /// Coin symbol to read the recorded price history of
+    #[arg(long, required_unless_present = "csv")]
+    symbol: Option<String>,
+    /// CSV file with timestamp,price lines to use instead of recorded prices
+    #[arg(long)]
+    csv: Option<String>,
+    /// Starting capital in euros
+    #[arg(long, default_value_t = 1000.0)]
+    capital: f64,
+    /// DCA euros per buy
+    #[arg(long)]
+    amount: Option<f64>,
+    /// DCA interval or short moving average period, in price points
+    #[arg(long)]
+    period: Option<usize>,
+    /// Long moving average period, in price points
+    #[arg(long)]
+    long_period: Option<usize>,
+    /// Rebalance target coin weight in percent
+    #[arg(long)]
+    target: Option<f64>,
+    /// Rebalance drift threshold in percent
+    #[arg(long)]
+    threshold: Option<f64>,
+}
+
+async fn run_simulate_slots(
+    config_file: &str,
+    spins: u64,
+    tolerance: f64,
+    bet: f64,
+) -> anyhow::Result<()> {
+    let config = Config::from_file(config_file).await?;
+    let simulation = model::simulate_slots(&config.slots, spins, bet)?;
+
+    println!("Spins:         {}", simulation.spins);
+    println!("Hit frequency: {:.2}%", simulation.hit_frequency * 100f64);
+    println!("Jackpot hits:  {}", simulation.jackpot_hits);
+    println!("Paytable RTP:  {:.2}%", simulation.paytable_rtp * 100f64);
+    println!("Jackpot RTP:   {:.2}%", simulation.jackpot_rtp * 100f64);
+    println!("Seed RTP:      {:.2}%", simulation.seed_rtp * 100f64);
+    println!("Total RTP:     {:.2}%", simulation.rtp() * 100f64);
+    println!("Target RTP:    {:.2}%", config.slots.target_rtp * 100f64);
+
+    if (simulation.rtp() - config.slots.target_rtp).abs() > tolerance {
+        anyhow::bail!(
+            "RTP is more than {:.2}% away from the target",
+            tolerance * 100f64
+        );
+    }
+    println!("RTP is within {:.2}% of the target", tolerance * 100f64);
+
+    Ok(())
+}
+
+async fn run_backtest(args: BacktestArgs, config_file: &str) -> anyhow::Result<()> {
+    let prices = match (&args.csv, &args.symbol) {
+        (Some(csv_file), _) => backtest::parse_csv(&tokio::fs::read_to_string(csv_file).await?)?,
+        (None, Some(coin_symbol)) => {
+            let config = Config::from_file(config_file).await?;
+            model::Model::new(config)
+                .await?
+                .price_history(coin_symbol)
+                .await?
+        }
+        (None, None) => anyhow::bail!("Either --symbol or --csv is required"),
+    };
+
+    let strategy = args.strategy.strategy(
+        args.capital,
+        StrategyParams {
+            amount: args.amount,
+            period: args.period,
+            long_period: args.long_period,
+            target: args.target,
+            threshold: args.threshold,
+        },
+    );
+    let report = backtest::run(&prices, strategy, args.capital)?;
+    println!("{:?} over {} prices\n{}", strategy, prices.len(), report);
+
+    Ok(())
 }
 
 #[tokio::main]
@@ -52,8 +167,32 @@ async fn main() {
         .init();
 
     // Load bot config from toml file
-    let args = Args::try_parse().unwrap();
+    let args = Args::parse();
     let config_file = args.config.unwrap_or(".config.toml".into());
+
+    // Offline subcommands don't connect to discord
+    match args.command {
+        Some(Command::Backtest(backtest_args)) => {
+            if let Err(error) = run_backtest(backtest_args, &config_file).await {
+                error!("Backtest failed: {:?}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(Command::SimulateSlots {
+            spins,
+            tolerance,
+            bet,
+        }) => {
+            if let Err(error) = run_simulate_slots(&config_file, spins, tolerance, bet).await {
+                error!("Slots simulation failed: {:?}", error);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+
     let config = Config::from_file(&config_file).await.unwrap();
     let discord_token = config.discord_token.clone();
     info!("Loaded config file {}", &config_file);
@@ -71,6 +210,7 @@ async fn main() {
             // Finance
             commands::bank(),
             commands::give(),
+            commands::give_coin(),
             commands::bless(),
             commands::leaderboard(),
             commands::price(),
@@ -80,6 +220,52 @@ async fn main() {
             commands::sellall(),
             commands::coin(),
             commands::daily(),
+            commands::weekly(),
+            commands::monthly(),
+            commands::timezone(),
+            commands::savings(),
+            commands::loan(),
+            commands::lend(),
+            commands::repay(),
+            commands::debts(),
+            commands::request(),
+            commands::split(),
+            commands::standing_order(),
+            commands::shop(),
+            commands::shop_item(),
+            commands::buy_item(),
+            commands::inventory(),
+            commands::use_item(),
+            commands::work(),
+            commands::jobs(),
+            commands::backtest(),
+            // Casino
+            commands::blackjack(),
+            commands::roulette(),
+            commands::slots(),
+            commands::duel(),
+            commands::crash(),
+            commands::lottery(),
+            commands::fairness(),
+            commands::limits(),
+            commands::selfexclude(),
+            commands::gamblestats(),
+            // Market
+            commands::bid(),
+            commands::ask(),
+            commands::book(),
+            commands::orders(),
+            commands::cancelorder(),
+            commands::mint(),
+            commands::quote(),
+            commands::pool(),
+            commands::addliquidity(),
+            commands::removeliquidity(),
+            // Tournaments
+            commands::tournament(),
+            // Predictions
+            commands::market(),
+            commands::predict(),
         ],
         // If true, discord bot owner account will be added automatically
         initialize_owners: false,
@@ -111,7 +297,10 @@ async fn main() {
             Box::pin(async move {
                 info!("Connected as {}", _ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(model::Model::new(config).await?)
+                let model = Arc::new(model::Model::new(config).await?);
+                scheduler::refund_interrupted_games(&model).await;
+                tokio::spawn(scheduler::run(model.clone(), ctx.http.clone()));
+                Ok(model)
             })
         })
         .options(options)
@@ -1,13 +1,77 @@
-use std::{collections::HashMap, ops::Mul};
+use std::collections::HashMap;
 
-use poise::serenity_prelude::model::permissions;
 use rand::Rng;
 use reqwest::Client;
 use serde::Deserialize;
-use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
+use sqlx::{SqliteConnection, SqlitePool, sqlite::SqliteConnectOptions};
+use tokio::sync::Mutex;
 use tracing::{trace, warn};
 
-use crate::{config::Config, permissions::Permission};
+use crate::{backtest::PricePoint, config::Config, permissions::Permission};
+
+mod amm;
+pub use amm::*;
+
+mod blackjack;
+pub use blackjack::*;
+
+mod crash;
+pub use crash::*;
+
+mod debt;
+pub use debt::*;
+
+mod duel;
+pub use duel::*;
+
+mod fairness;
+pub use fairness::*;
+
+mod gambling;
+pub use gambling::*;
+
+mod interest;
+pub use interest::*;
+
+mod lottery;
+pub use lottery::*;
+
+mod market;
+pub use market::*;
+
+mod order_book;
+pub use order_book::*;
+
+mod payment;
+pub use payment::*;
+
+mod prediction;
+pub use prediction::*;
+
+mod rewards;
+pub use rewards::*;
+
+mod roulette;
+pub use roulette::*;
+
+mod shop;
+
+mod slots;
+pub use slots::*;
+
+mod standing_order;
+pub use standing_order::*;
+
+mod stats;
+pub use stats::*;
+
+mod tournament;
+
+mod trade;
+pub use trade::*;
+
+mod work;
+pub use work::*;
 
 /// Model errors are errors that will be sent to the user.
 #[derive(thiserror::Error, Debug)]
@@ -36,7 +100,8 @@ pub struct Model {
     config: Config,
     db_pool: SqlitePool,
     permissions: HashMap<u64, Permission>,
-    pub daily_amount: f64,
+    blackjack_games: Mutex<HashMap<u64, BlackjackGame>>,
+    crash_rounds: Mutex<HashMap<u64, CrashRound>>,
 }
 
 pub struct CoinInfo {
@@ -68,55 +133,697 @@ struct CmcQuoteData {
     price: f64,
 }
 
-impl Model {
-    pub async fn new(config: Config) -> ModelResult<Self> {
-        // Connect to SQLite (creates data.db if it doesn't exist)
-        let db_pool = SqlitePool::connect_with(
-            SqliteConnectOptions::new()
-                .filename("data.db")
-                .create_if_missing(true),
+impl Model {
+    pub async fn new(config: Config) -> ModelResult<Self> {
+        // Connect to SQLite (creates data.db if it doesn't exist)
+        let db_pool = SqlitePool::connect_with(
+            SqliteConnectOptions::new()
+                .filename("data.db")
+                .create_if_missing(true),
+        )
+        .await?;
+
+        Self::with_pool(config, db_pool).await
+    }
+
+    /// Model backed by a private in-memory database.
+    #[cfg(test)]
+    pub(crate) async fn in_memory(config: Config) -> ModelResult<Self> {
+        let db_pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+
+        Self::with_pool(config, db_pool).await
+    }
+
+    async fn with_pool(config: Config, db_pool: SqlitePool) -> ModelResult<Self> {
+        // Enable foreign_keys in sqlite
+        sqlx::query(r#"PRAGMA foreign_keys = ON;"#)
+            .execute(&db_pool)
+            .await?;
+
+        // Create white monster counter table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS white_monster_counter (
+                user_id TEXT NOT NULL PRIMARY KEY,
+                count INT NOT NULL
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create bank table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS bank (
+                user_id TEXT NOT NULL PRIMARY KEY,
+                balance REAL NOT NULL,
+                last_daily DATETIME NOT NULL,
+                daily_streak INTEGER NOT NULL DEFAULT 0,
+                next_daily_at DATETIME,
+                last_weekly DATETIME,
+                last_monthly DATETIME,
+                timezone TEXT,
+                savings REAL NOT NULL DEFAULT 0,
+                work_shifts INTEGER NOT NULL DEFAULT 0,
+                work_available_at DATETIME,
+                savings_accrued_at DATETIME
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+        add_column(
+            &db_pool,
+            "bank",
+            "daily_streak",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+        .await?;
+        add_column(&db_pool, "bank", "next_daily_at", "DATETIME").await?;
+        add_column(&db_pool, "bank", "last_weekly", "DATETIME").await?;
+        add_column(&db_pool, "bank", "last_monthly", "DATETIME").await?;
+        add_column(&db_pool, "bank", "timezone", "TEXT").await?;
+        add_column(&db_pool, "bank", "savings", "REAL NOT NULL DEFAULT 0").await?;
+        add_column(
+            &db_pool,
+            "bank",
+            "work_shifts",
+            "INTEGER NOT NULL DEFAULT 0",
+        )
+        .await?;
+        add_column(&db_pool, "bank", "work_available_at", "DATETIME").await?;
+        add_column(&db_pool, "bank", "savings_accrued_at", "DATETIME").await?;
+
+        // Create bank loans table. Loans are 'active' until repaid, or
+        // 'defaulted' if they couldn't be collected on the due date.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS bank_loans (
+                loan_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                principal REAL NOT NULL,
+                rate REAL NOT NULL,
+                owed REAL NOT NULL,
+                due_at DATETIME NOT NULL,
+                status TEXT NOT NULL DEFAULT 'active',
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                accrued_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create debts table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS debts (
+                debt_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                lender_id TEXT NOT NULL,
+                borrower_id TEXT NOT NULL,
+                principal REAL NOT NULL,
+                interest REAL NOT NULL,
+                owed REAL NOT NULL,
+                due_at DATETIME NOT NULL,
+                channel_id TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'active',
+                reminded BOOLEAN NOT NULL DEFAULT FALSE,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (lender_id) REFERENCES bank(user_id),
+                FOREIGN KEY (borrower_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create lend offers table. Offers wait here with the lender's
+        // euros in escrow until the borrower answers.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS lend_offers (
+                offer_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                lender_id TEXT NOT NULL,
+                borrower_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                interest REAL NOT NULL,
+                due_secs INTEGER NOT NULL,
+                channel_id TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (lender_id) REFERENCES bank(user_id),
+                FOREIGN KEY (borrower_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create bill splits table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS bill_splits (
+                split_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                requester_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                reason TEXT NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (requester_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create payment requests table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payment_requests (
+                request_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                requester_id TEXT NOT NULL,
+                payer_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                reason TEXT NOT NULL,
+                split_id INTEGER,
+                status TEXT NOT NULL DEFAULT 'pending',
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (requester_id) REFERENCES bank(user_id),
+                FOREIGN KEY (payer_id) REFERENCES bank(user_id),
+                FOREIGN KEY (split_id) REFERENCES bill_splits(split_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create standing orders table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS standing_orders (
+                order_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                recipient_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                frequency TEXT NOT NULL,
+                next_run_at DATETIME NOT NULL,
+                channel_id TEXT NOT NULL,
+                active BOOLEAN NOT NULL DEFAULT TRUE,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id),
+                FOREIGN KEY (recipient_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create shop items table. Removed items stay in the inventories.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS shop_items (
+                item_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                price REAL NOT NULL,
+                stock INTEGER,
+                role_id TEXT,
+                duration_secs INTEGER,
+                consumable BOOLEAN NOT NULL,
+                active BOOLEAN NOT NULL DEFAULT TRUE,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create inventory table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS inventory (
+                user_id TEXT NOT NULL,
+                item_id INTEGER NOT NULL,
+                quantity INTEGER NOT NULL,
+                PRIMARY KEY (user_id, item_id),
+                FOREIGN KEY (user_id) REFERENCES bank(user_id),
+                FOREIGN KEY (item_id) REFERENCES shop_items(item_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create role grants table. Grants of timed roles are removed once
+        // they expire.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS role_grants (
+                grant_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                guild_id TEXT NOT NULL,
+                role_id TEXT NOT NULL,
+                item_id INTEGER NOT NULL,
+                channel_id TEXT NOT NULL,
+                expires_at DATETIME NOT NULL,
+                active BOOLEAN NOT NULL DEFAULT TRUE,
+                removal_failed BOOLEAN NOT NULL DEFAULT FALSE,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id),
+                FOREIGN KEY (item_id) REFERENCES shop_items(item_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create guild settings table. NULL settings fall back to the config.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS guild_settings (
+                guild_id TEXT NOT NULL PRIMARY KEY,
+                timezone TEXT,
+                reset_time TEXT
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create portfolio table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS transactions (
+                transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                coin_symbol TEXT NOT NULL,
+                amount REAL NOT NULL,
+                price REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create order book table. Open orders hold their escrowed funds
+        // (bids) or coins (asks) until filled or cancelled.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS orders (
+                order_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                coin_symbol TEXT NOT NULL,
+                side TEXT NOT NULL,
+                price REAL NOT NULL,
+                amount REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create server tokens table. Each server token has a liquidity pool
+        // of euros and tokens.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS server_tokens (
+                coin_symbol TEXT NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL,
+                euro_reserve REAL NOT NULL,
+                token_reserve REAL NOT NULL,
+                total_shares REAL NOT NULL,
+                guild_id TEXT
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create liquidity pool shares table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS liquidity_shares (
+                user_id TEXT NOT NULL,
+                coin_symbol TEXT NOT NULL,
+                shares REAL NOT NULL,
+                PRIMARY KEY (user_id, coin_symbol),
+                FOREIGN KEY (user_id) REFERENCES bank(user_id),
+                FOREIGN KEY (coin_symbol) REFERENCES server_tokens(coin_symbol)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create tournaments tables. Participants trade with an isolated
+        // account that starts with the tournament capital.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tournaments (
+                tournament_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                start_time DATETIME NOT NULL,
+                end_time DATETIME NOT NULL,
+                starting_capital REAL NOT NULL,
+                prizes TEXT NOT NULL,
+                channel_id TEXT NOT NULL,
+                finished BOOLEAN NOT NULL DEFAULT FALSE
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tournament_accounts (
+                tournament_id INTEGER NOT NULL,
+                user_id TEXT NOT NULL,
+                balance REAL NOT NULL,
+                final_value REAL,
+                PRIMARY KEY (tournament_id, user_id),
+                FOREIGN KEY (tournament_id) REFERENCES tournaments(tournament_id),
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tournament_transactions (
+                transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                tournament_id INTEGER NOT NULL,
+                user_id TEXT NOT NULL,
+                coin_symbol TEXT NOT NULL,
+                amount REAL NOT NULL,
+                price REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (tournament_id, user_id) REFERENCES tournament_accounts(tournament_id, user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create ledger table. Casino bets, payouts and other euro balance
+        // changes are recorded here with a short description.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS ledger (
+                entry_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                game TEXT,
+                description TEXT NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create blackjack games table. Games are played in memory, this
+        // only keeps the escrowed bets so they are refunded if the bot
+        // restarts in the middle of a game.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS blackjack_games (
+                user_id TEXT NOT NULL PRIMARY KEY,
+                bet REAL NOT NULL,
+                started_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create roulette wagers table. Bets of the open round of each
+        // channel wait here until the wheel is spun.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS roulette_wagers (
+                wager_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                bet TEXT NOT NULL,
+                amount REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create crash bets table. Bets of the round of each channel wait
+        // here until it crashes.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS crash_bets (
+                bet_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                cashed_out REAL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create duels table. Challenges wait here with the challenger's
+        // stake in escrow until the opponent answers.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS duels (
+                duel_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                challenger_id TEXT NOT NULL,
+                opponent_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                game TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (challenger_id) REFERENCES bank(user_id),
+                FOREIGN KEY (opponent_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create gamble outcomes table. Every settled bet is recorded with
+        // the euros wagered and paid back for the gambling statistics.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS gamble_outcomes (
+                outcome_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                game TEXT NOT NULL,
+                bet REAL NOT NULL,
+                payout REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create gambling limits table. Limits are set by each user, NULL
+        // means no limit. Loosened limits wait in the pending columns until
+        // pending_at, 0 pending removes the limit.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS gambling_limits (
+                user_id TEXT NOT NULL PRIMARY KEY,
+                daily_loss_limit REAL,
+                weekly_loss_limit REAL,
+                max_bet REAL,
+                excluded_until DATETIME,
+                pending_daily_loss_limit REAL,
+                pending_weekly_loss_limit REAL,
+                pending_max_bet REAL,
+                pending_at DATETIME,
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT NOT NULL PRIMARY KEY,
+                value TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create jackpots table, holding the progressive jackpot pool of
+        // each game
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS jackpots (
+                game TEXT NOT NULL PRIMARY KEY,
+                amount REAL NOT NULL
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create provably fair tables. Every bet rolls with a server seed
+        // committed by its hash, a client seed and a nonce, and its outcome
+        // is stored so it can be recomputed once the server seed is revealed.
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS fairness_seeds (
+                seed_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                owner TEXT NOT NULL,
+                server_seed TEXT NOT NULL,
+                server_seed_hash TEXT NOT NULL,
+                client_seed TEXT NOT NULL,
+                nonce INTEGER NOT NULL DEFAULT 0,
+                revealed BOOLEAN NOT NULL DEFAULT FALSE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                revealed_at DATETIME
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS fair_rolls (
+                roll_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                seed_id INTEGER NOT NULL,
+                nonce INTEGER NOT NULL,
+                user_id TEXT,
+                game TEXT NOT NULL,
+                params TEXT NOT NULL,
+                outcome TEXT NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (seed_id) REFERENCES fairness_seeds(seed_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Create lottery tables
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS lottery_draws (
+                draw_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                draw_time DATETIME NOT NULL,
+                pot REAL NOT NULL DEFAULT 0,
+                drawn BOOLEAN NOT NULL DEFAULT FALSE,
+                winner_id TEXT,
+                winning_ticket INTEGER,
+                prize REAL,
+                roll_id INTEGER,
+                FOREIGN KEY (roll_id) REFERENCES fair_rolls(roll_id)
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS lottery_tickets (
+                purchase_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                draw_id INTEGER NOT NULL,
+                user_id TEXT NOT NULL,
+                tickets INTEGER NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (draw_id) REFERENCES lottery_draws(draw_id),
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
+            )
+            "#,
         )
+        .execute(&db_pool)
         .await?;
 
-        // Enable foreign_keys in sqlite
-        sqlx::query(r#"PRAGMA foreign_keys = ON;"#)
-            .execute(&db_pool)
-            .await?;
+        // Create prediction market tables
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS markets (
+                market_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                question TEXT NOT NULL,
+                creator_id TEXT NOT NULL,
+                closes_at DATETIME NOT NULL,
+                resolved BOOLEAN NOT NULL DEFAULT FALSE,
+                winning_outcome INTEGER,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
 
-        // Create white monster counter table
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS white_monster_counter (
-                user_id TEXT NOT NULL PRIMARY KEY,
-                count INT NOT NULL
+            CREATE TABLE IF NOT EXISTS market_outcomes (
+                outcome_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                market_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                FOREIGN KEY (market_id) REFERENCES markets(market_id)
             )
             "#,
         )
         .execute(&db_pool)
         .await?;
 
-        // Create bank table
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS bank (
-                user_id TEXT NOT NULL PRIMARY KEY,
-                balance REAL NOT NULL,
-                last_daily DATETIME NOT NULL
+            CREATE TABLE IF NOT EXISTS market_bets (
+                bet_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                market_id INTEGER NOT NULL,
+                outcome_id INTEGER NOT NULL,
+                user_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (market_id) REFERENCES markets(market_id),
+                FOREIGN KEY (outcome_id) REFERENCES market_outcomes(outcome_id),
+                FOREIGN KEY (user_id) REFERENCES bank(user_id)
             )
             "#,
         )
         .execute(&db_pool)
         .await?;
 
-        // Create portfolio table
+        // Create price predictions table
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS transactions (
-                transaction_id INTEGER PRIMARY KEY AUTOINCREMENT,
+            CREATE TABLE IF NOT EXISTS price_predictions (
+                prediction_id INTEGER PRIMARY KEY AUTOINCREMENT,
                 user_id TEXT NOT NULL,
                 coin_symbol TEXT NOT NULL,
+                direction TEXT NOT NULL,
                 amount REAL NOT NULL,
-                price REAL NOT NULL,
+                entry_price REAL NOT NULL,
+                expires_at DATETIME NOT NULL,
+                channel_id TEXT NOT NULL,
+                resolved BOOLEAN NOT NULL DEFAULT FALSE,
+                exit_price REAL,
+                payout REAL,
                 timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
                 FOREIGN KEY (user_id) REFERENCES bank(user_id)
             )
@@ -125,26 +832,38 @@ impl Model {
         .execute(&db_pool)
         .await?;
 
-        let permissions =
-            HashMap::from_iter(config.bot_admins.iter().map(|id| (*id, Permission::Admin)));
+        // Create price history table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS price_history (
+                price_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                coin_symbol TEXT NOT NULL,
+                price REAL NOT NULL,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&db_pool)
+        .await?;
+
+        // Admins listed as mods keep the admin permission
+        let permissions = HashMap::from_iter(
+            config
+                .bot_mods
+                .iter()
+                .map(|id| (*id, Permission::Mod))
+                .chain(config.bot_admins.iter().map(|id| (*id, Permission::Admin))),
+        );
         Ok(Self {
             config,
             db_pool,
             permissions,
-            daily_amount: 100f64,
+            blackjack_games: Mutex::new(HashMap::new()),
+            crash_rounds: Mutex::new(HashMap::new()),
         })
     }
 
     pub fn user_has_permission(&self, user_id: u64, permission: Permission) -> bool {
-        println!("{:?}", self.permissions);
-        println!("{:?}", self.permissions.get(&user_id));
-        println!(
-            "{:?}",
-            self.permissions
-                .get(&user_id)
-                .is_some_and(|p| *p < permission)
-        );
-
         self.permissions
             .get(&user_id)
             .is_some_and(|p| *p >= permission)
@@ -184,7 +903,7 @@ impl Model {
         .fetch_one(&self.db_pool)
         .await?;
 
-        Ok(u32::try_from(wm_counter).map_err(|_| ModelError::UnexpectedError)?)
+        u32::try_from(wm_counter).map_err(|_| ModelError::UnexpectedError)
     }
 
     pub async fn dec_wm_counter(&self, user_id: u64, amount: u32) -> ModelResult<u32> {
@@ -201,7 +920,7 @@ impl Model {
         .fetch_one(&self.db_pool)
         .await?;
 
-        Ok(u32::try_from(wm_counter).map_err(|_| ModelError::UnexpectedError)?)
+        u32::try_from(wm_counter).map_err(|_| ModelError::UnexpectedError)
     }
 
     pub async fn balance(&self, user_id: u64) -> ModelResult<f64> {
@@ -228,7 +947,7 @@ impl Model {
         // UnexpectedError.
         sqlx::query(
             r#"
-            INSERT INTO bank VALUES ($1, 0, DATE('now', '-1 day'))
+            INSERT INTO bank (user_id, balance, last_daily) VALUES ($1, 0, DATE('now', '-1 day'))
             "#,
         )
         .bind(user_id.to_string())
@@ -294,6 +1013,53 @@ impl Model {
         Ok((src_new_balance, dst_new_balance))
     }
 
+    /// Move `amount` coins of `coin_symbol` from src_user to dst_user. The
+    /// coins carry the sender's average cost basis, which is returned.
+    pub async fn give_coin(
+        &self,
+        src_user_id: u64,
+        dst_user_id: u64,
+        coin_symbol: &str,
+        amount: f64,
+    ) -> ModelResult<f64> {
+        // Check if src_user_id and dst_user_id account exists.
+        self.balance(src_user_id).await?;
+        self.balance(dst_user_id).await?;
+        self.check_trading_allowed(src_user_id).await?;
+
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        if src_user_id == dst_user_id {
+            return Err(ModelError::InvalidValue(
+                "can't give coins to yourself.".into(),
+            ));
+        }
+
+        let coin_symbol = coin_symbol.to_uppercase();
+        let mut tx = self.db_pool.begin().await?;
+
+        if owned_coins(&mut tx, src_user_id, &coin_symbol).await? < amount {
+            return Err(ModelError::InsuficientCoins);
+        }
+
+        // Both sides of the transfer are recorded at the sender's cost basis
+        // so neither portfolio gains or loses profit by the move.
+        let basis = cost_basis(&mut tx, src_user_id, &coin_symbol).await?;
+        trace!(
+            "Transfering {} {} from {} to {} at {}",
+            amount, coin_symbol, src_user_id, dst_user_id, basis
+        );
+        insert_transaction(&mut tx, src_user_id, &coin_symbol, -amount, basis).await?;
+        insert_transaction(&mut tx, dst_user_id, &coin_symbol, amount, basis).await?;
+
+        tx.commit().await?;
+
+        Ok(basis)
+    }
+
     pub async fn bless(&self, dst_user_id: u64, amount: f64) -> ModelResult<f64> {
         let new_balance_res = sqlx::query_scalar(
             r#"
@@ -314,26 +1080,93 @@ impl Model {
         }
     }
 
+    /// Users ordered by net worth: balance and savings minus owed loans,
+    /// plus the current value of their coins and liquidity pool shares.
+    /// Funds and coins escrowed in open orders still count as owned.
     pub async fn leaderboard(&self) -> ModelResult<Vec<(u64, f64)>> {
         let bank_data: Vec<(String, f64)> = sqlx::query_as(
             r#"
-            SELECT user_id, balance
-            FROM bank
-            ORDER BY balance DESC
+            SELECT user_id, balance + savings - COALESCE(
+                (SELECT SUM(owed) FROM bank_loans l WHERE l.user_id = b.user_id AND status <> 'repaid'),
+                0.0
+            ) + COALESCE(
+                (SELECT SUM(price * amount) FROM orders o WHERE o.user_id = b.user_id AND side = 'bid'),
+                0.0
+            )
+            FROM bank b
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let holdings_data: Vec<(String, String, f64)> = sqlx::query_as(
+            r#"
+            SELECT user_id, coin_symbol, SUM(amount) AS total_amount
+            FROM (
+                SELECT user_id, coin_symbol, amount FROM transactions
+                UNION ALL
+                SELECT user_id, coin_symbol, amount FROM orders WHERE side = 'ask'
+            )
+            GROUP BY user_id, coin_symbol
+            HAVING total_amount > 0
             "#,
         )
         .fetch_all(&self.db_pool)
         .await?;
 
-        let bank_data_converted = bank_data
+        let mut net_worth: HashMap<u64, f64> = bank_data
             .iter()
-            .map(|(user_id, count)| (user_id.parse::<u64>().unwrap(), *count))
-            .collect::<Vec<(u64, f64)>>();
-        Ok(bank_data_converted)
+            .map(|(user_id, balance)| (user_id.parse::<u64>().unwrap(), *balance))
+            .collect();
+
+        // Fetch each coin price only once.
+        let mut prices: HashMap<String, f64> = HashMap::new();
+        for (user_id, coin_symbol, total_amount) in holdings_data {
+            let price = match prices.get(&coin_symbol) {
+                Some(price) => *price,
+                None => {
+                    let price = match self.coin_info(&coin_symbol).await {
+                        Ok(coin_info) => coin_info.current_price,
+                        Err(_) => {
+                            warn!("Could not value {} for leaderboard", coin_symbol);
+                            0f64
+                        }
+                    };
+                    prices.insert(coin_symbol, price);
+                    price
+                }
+            };
+            *net_worth
+                .entry(user_id.parse::<u64>().unwrap())
+                .or_default() += total_amount * price;
+        }
+
+        for (user_id, value) in self.liquidity_values().await? {
+            *net_worth.entry(user_id).or_default() += value;
+        }
+
+        let mut leaderboard_data = net_worth.into_iter().collect::<Vec<(u64, f64)>>();
+        leaderboard_data.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(leaderboard_data)
     }
 
     // Return crypto coin name and price
     pub async fn coin_info(&self, coin_symbol: &str) -> ModelResult<CoinInfo> {
+        // Server tokens are priced by their liquidity pool.
+        if let Some(pool) = self.server_token(coin_symbol).await? {
+            return Ok(CoinInfo {
+                symbol: coin_symbol.into(),
+                name: pool.name.clone(),
+                current_price: pool.spot_price(),
+            });
+        }
+
+        self.market_coin_info(coin_symbol).await
+    }
+
+    /// Name and price of a coin from CoinMarketCap, never from a server
+    /// token pool that users can move themselves.
+    async fn market_coin_info(&self, coin_symbol: &str) -> ModelResult<CoinInfo> {
         let url = format!(
             "https://{}-api.coinmarketcap.com/v1/cryptocurrency/quotes/latest?symbol={}&convert=eur",
             if self.config.use_cmc_sandbox_api {
@@ -356,227 +1189,152 @@ impl Model {
 
         if response.status.error_code != 0 {
             warn!(
-                "Cmc request returned with error_code {}",
-                response.status.error_code
+                "Cmc request returned with error_code {}: {}",
+                response.status.error_code,
+                response.status.error_message.as_deref().unwrap_or_default()
             );
         }
 
-        let data = response.data.ok_or(ModelError::UnexpectedError)?;
+        // CoinMarketCap answers unknown symbols with a bad request error.
+        let data = match response.data {
+            Some(data) => data,
+            None if response.status.error_code == 400 => {
+                return Err(ModelError::InvalidValue("'symbol' does not exist.".into()));
+            }
+            None => return Err(ModelError::UnexpectedError),
+        };
 
         let crypto_data = data
             .get(coin_symbol.to_uppercase().as_str())
             .ok_or(ModelError::InvalidValue("'symbol' does not exist.".into()))?;
 
+        let current_price = crypto_data
+            .quote
+            .get("EUR")
+            .ok_or(ModelError::UnexpectedError)?
+            .price;
+
+        // Quotes are kept as price history for backtesting, at most one per
+        // coin every minute so frequent lookups don't skew the series.
+        sqlx::query(
+            r#"
+            INSERT INTO price_history (coin_symbol, price)
+            SELECT $1, $2
+            WHERE NOT EXISTS (
+                SELECT 1 FROM price_history
+                WHERE coin_symbol = $1
+                AND strftime('%Y-%m-%d %H:%M', timestamp) = strftime('%Y-%m-%d %H:%M', 'now')
+            )
+            "#,
+        )
+        .bind(coin_symbol.to_uppercase())
+        .bind(current_price)
+        .execute(&self.db_pool)
+        .await?;
+
         Ok(CoinInfo {
             symbol: coin_symbol.into(),
             name: crypto_data.name.clone(),
-            current_price: crypto_data
-                .quote
-                .get("EUR")
-                .ok_or(ModelError::UnexpectedError)?
-                .price,
+            current_price,
         })
     }
 
-    pub async fn portfolio(&self, user_id: u64) -> ModelResult<Vec<(String, f64, f64)>> {
-        // Check if user account exists
-        self.balance(user_id).await?;
-
-        let mut portfolio_raw_data: Vec<(String, f64, f64)> = sqlx::query_as(
+    /// Recorded prices of a coin, oldest first.
+    pub async fn price_history(&self, coin_symbol: &str) -> ModelResult<Vec<PricePoint>> {
+        let price_data: Vec<(String, f64)> = sqlx::query_as(
             r#"
-            SELECT coin_symbol, SUM(amount) AS total_amount, 0. AS total_value
-            FROM transactions
-            WHERE user_id = $1
-            GROUP BY coin_symbol
-            HAVING total_amount > 0;
+            SELECT timestamp, price
+            FROM price_history
+            WHERE coin_symbol = $1
+            ORDER BY timestamp, price_id
             "#,
         )
-        .bind(user_id.to_string())
+        .bind(coin_symbol.to_uppercase())
         .fetch_all(&self.db_pool)
         .await?;
 
-        for (coin_symbol, total_amount, total_value) in &mut portfolio_raw_data {
-            let current_price = self.coin_info(&coin_symbol).await?.current_price;
-            *total_value = total_amount.mul(current_price);
-        }
+        Ok(price_data
+            .into_iter()
+            .map(|(timestamp, price)| PricePoint { timestamp, price })
+            .collect())
+    }
+
+    pub async fn portfolio(&self, user_id: u64) -> ModelResult<Vec<(String, f64, f64)>> {
+        // Check if user account exists
+        self.balance(user_id).await?;
 
-        Ok(portfolio_raw_data)
+        self.account_portfolio(Account::Bank(user_id)).await
     }
 
     /// Create a transaction, returns amount of coins bought/sold and current price
     pub async fn buy(
         &self,
         user_id: u64,
+        guild_id: Option<u64>,
         coin_symbol: &str,
         euro_amount: f64,
     ) -> ModelResult<(f64, f64)> {
-        // Check if amount is positive
-        if euro_amount <= 0f64 {
-            return Err(ModelError::InvalidValue(
-                "'amount' must be positive.".into(),
-            ));
-        }
+        self.check_trading_allowed(user_id).await?;
 
-        // Get current coin price. Indirectly also checks if symbol is valid.
-        let coin_info = self.coin_info(coin_symbol).await?;
-
-        // Convert user_id to string here for reuse.
-        let user_id_str = user_id.to_string();
-
-        // Convert euro_amount to coin_amount.
-        let coin_amount = euro_amount / coin_info.current_price;
-
-        // Check if user has bank account and suficient balance.
-        let balance = self.balance(user_id).await?;
-        if balance < coin_amount {
-            return Err(ModelError::InsuficientFunds);
+        // Server tokens are bought from their liquidity pool.
+        if self.server_token(coin_symbol).await?.is_some() {
+            return self
+                .swap_buy(user_id, guild_id, coin_symbol, euro_amount)
+                .await;
         }
 
-        trace!("Creating transaction of {} {}", coin_amount, coin_symbol);
-        sqlx::query(
-            r#"
-            INSERT INTO transactions (user_id, coin_symbol, amount, price)
-            VALUES ($1, $2, $3, $4)
-            "#,
-        )
-        .bind(&user_id_str)
-        .bind(coin_symbol.to_uppercase())
-        .bind(coin_amount)
-        .bind(coin_info.current_price)
-        .execute(&self.db_pool)
-        .await?;
-
-        // Remove euros_amount from balance and make this a db transaction.
-        self.bless(user_id, -euro_amount).await?;
+        // Check if user account exists
+        self.balance(user_id).await?;
 
-        Ok((coin_amount, coin_info.current_price))
+        self.trade_buy(Account::Bank(user_id), coin_symbol, euro_amount)
+            .await
     }
 
     pub async fn sell(
         &self,
         user_id: u64,
+        guild_id: Option<u64>,
         coin_symbol: &str,
         euro_amount: f64,
     ) -> ModelResult<(f64, f64)> {
-        // Check if user account exists
-        self.balance(user_id).await?;
-
-        // Check if amount is positive (we are selling a positive ammount)
-        if euro_amount <= 0f64 {
-            return Err(ModelError::InvalidValue(
-                "'amount' must be positive.".into(),
-            ));
-        }
-
-        // Get current coin price. Indirectly also checks if symbol is valid
-        let coin_info = self.coin_info(coin_symbol).await?;
-
-        // Convert user_id to string here for reuse.
-        let user_id_str = user_id.to_string();
-
-        // Convert euro_amount to coin_amount
-        let coin_amount = euro_amount / coin_info.current_price;
-
-        // Check if user_id has this amount of coins
-        let owned_coin_amount: f64 = sqlx::query_scalar(
-            r#"
-            SELECT SUM(amount)
-            FROM transactions
-            WHERE user_id = $1 AND coin_symbol = $2;
-            "#,
-        )
-        .bind(user_id_str)
-        .bind(coin_symbol)
-        .fetch_one(&self.db_pool)
-        .await?;
+        self.check_trading_allowed(user_id).await?;
 
-        // Only proceed with transaction if user has at least the amount of
-        // coins intended to sell.
-        if owned_coin_amount < coin_amount {
-            return Err(ModelError::InsuficientCoins);
+        // Server tokens are sold to their liquidity pool.
+        if self.server_token(coin_symbol).await?.is_some() {
+            return self
+                .swap_sell(user_id, guild_id, coin_symbol, euro_amount)
+                .await;
         }
 
-        // Create sell transaction
-        trace!(
-            "Creating transaction of {} {}",
-            -1f64 * coin_amount,
-            coin_symbol
-        );
-        sqlx::query(
-            r#"
-            INSERT INTO transactions (user_id, coin_symbol, amount, price)
-            VALUES ($1, $2, $3, $4)
-            "#,
-        )
-        .bind(user_id.to_string())
-        .bind(coin_symbol)
-        .bind(-1f64 * coin_amount)
-        .bind(coin_info.current_price)
-        .execute(&self.db_pool)
-        .await?;
-
-        // Add euros_amount to balance and make this a db transaction
-        self.bless(user_id, euro_amount).await?;
-
-        Ok((coin_amount, coin_info.current_price))
-    }
-
-    pub async fn sell_all(&self, user_id: u64, coin_symbol: &str) -> ModelResult<(f64, f64)> {
         // Check if user account exists
         self.balance(user_id).await?;
 
-        // Get current coin price. Indirectly also checks if symbol is valid
-        let coin_info = self.coin_info(coin_symbol).await?;
+        self.trade_sell(Account::Bank(user_id), coin_symbol, Some(euro_amount))
+            .await
+    }
 
-        // Convert user_id to string here for reuse.
-        let user_id_str = user_id.to_string();
-        let coin_symbol = coin_symbol.to_uppercase();
-        // Get amount of coins owned by this user_id
-        let owned_coin_amount: f64 = sqlx::query_scalar(
-            r#"
-            SELECT SUM(amount)
-            FROM transactions
-            WHERE user_id = $1 AND coin_symbol = $2;
-            "#,
-        )
-        .bind(user_id_str)
-        .bind(&coin_symbol)
-        .fetch_one(&self.db_pool)
-        .await?;
+    pub async fn sell_all(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+    ) -> ModelResult<(f64, f64)> {
+        self.check_trading_allowed(user_id).await?;
 
-        // Only proceed with transaction if user has positive amount of
-        // coins intended to sell.
-        if owned_coin_amount <= 0f64 {
-            return Err(ModelError::InsuficientCoins);
+        // Server tokens are sold to their liquidity pool.
+        if self.server_token(coin_symbol).await?.is_some() {
+            return self.swap_sell_all(user_id, guild_id, coin_symbol).await;
         }
 
-        // Create sell transaction
-        trace!(
-            "Creating transaction of {} {}",
-            -1f64 * owned_coin_amount,
-            coin_symbol
-        );
-        sqlx::query(
-            r#"
-            INSERT INTO transactions (user_id, coin_symbol, amount, price)
-            VALUES ($1, $2, $3, $4)
-            "#,
-        )
-        .bind(user_id.to_string())
-        .bind(&coin_symbol)
-        .bind(-1f64 * owned_coin_amount)
-        .bind(coin_info.current_price)
-        .execute(&self.db_pool)
-        .await?;
-
-        // Add euros_amount to balance and make this a db transaction
-        self.bless(user_id, owned_coin_amount * coin_info.current_price)
-            .await?;
+        // Check if user account exists
+        self.balance(user_id).await?;
 
-        Ok((owned_coin_amount, coin_info.current_price))
+        self.trade_sell(Account::Bank(user_id), coin_symbol, None)
+            .await
     }
 
-    pub async fn coin_flip(&self, user_id: u64, choice: &str, bet: f64) -> ModelResult<bool> {
+    /// Check that `bet` is a valid amount for `user_id` to gamble.
+    async fn check_bet(&self, user_id: u64, bet: f64) -> ModelResult<()> {
         // Bet must be positive amount
         if bet <= 0f64 {
             return Err(ModelError::InvalidValue(
@@ -584,58 +1342,238 @@ impl Model {
             ));
         }
 
+        self.check_gambling_limits(user_id, bet).await?;
         if self.balance(user_id).await? < bet {
             return Err(ModelError::InsuficientFunds);
         }
 
-        // Flip coin with 50/50 probability
-        let flip_result = rand::rng().random_bool(0.5);
+        Ok(())
+    }
+
+    /// Bet on heads or tails, returns whether the bet was won and the roll
+    /// id to verify it with.
+    pub async fn coin_flip(
+        &self,
+        user_id: u64,
+        choice: &str,
+        bet: f64,
+    ) -> ModelResult<(bool, i64)> {
+        self.check_bet(user_id, bet).await?;
+        if choice != "heads" && choice != "tails" {
+            return Err(ModelError::InvalidValue(
+                "'choice' must be 'heads' or 'tails'.".into(),
+            ));
+        }
+
+        let mut tx = self.db_pool.begin().await?;
 
-        // true is heads, false is tails
-        let has_won = match choice {
-            "heads" => flip_result,
-            "tails" => !flip_result,
-            _ => {
-                return Err(ModelError::InvalidValue(
-                    "'choice' must be 'heads' or 'tails'.".into(),
-                ));
-            }
-        };
+        // Flip coin with 50/50 probability, true is heads, false is tails
+        let mut roll = next_roll(&mut tx, SeedOwner::User(user_id)).await?;
+        let flip_result = coin_flip_outcome(&mut roll.rng);
+        let outcome = if flip_result { "heads" } else { "tails" };
+        let roll_id = roll
+            .record(&mut tx, Some(user_id), "coinflip", "", outcome)
+            .await?;
+        let has_won = choice == outcome;
 
         // Update user funds, add bet if won, subtract bet otherwise
-        sqlx::query(
-            r#"
-            UPDATE bank
-            SET balance = balance + $2
-            WHERE user_id = $1
-            "#,
+        if has_won {
+            deposit(&mut tx, user_id, bet).await?;
+        } else {
+            withdraw(&mut tx, user_id, bet).await?;
+        }
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            if has_won { bet } else { -bet },
+            Some("coinflip"),
+            "Coin flip",
         )
-        .bind(user_id.to_string())
-        .bind(if has_won { bet } else { -bet })
-        .execute(&self.db_pool)
         .await?;
+        let payout = if has_won { 2f64 * bet } else { 0f64 };
+        insert_gamble_outcome(&mut tx, user_id, "coinflip", bet, payout).await?;
+        tx.commit().await?;
 
-        Ok(has_won)
+        Ok((has_won, roll_id))
     }
+}
 
-    pub async fn daily(&self, user_id: u64) -> ModelResult<bool> {
-        // Check if user account exists
-        self.balance(user_id).await?;
+/// Flip a coin with 50/50 probability, true is heads.
+pub fn coin_flip_outcome(rng: &mut impl Rng) -> bool {
+    rng.random_bool(0.5)
+}
 
-        let res = sqlx::query(
-            r#"
-            UPDATE bank
-            SET last_daily = CURRENT_TIMESTAMP,
-                balance = balance + $2
-            WHERE user_id = $1
-            AND DATE(last_daily) <> DATE(CURRENT_TIMESTAMP)
-            "#,
-        )
-        .bind(user_id.to_string())
-        .bind(self.daily_amount)
-        .execute(&self.db_pool)
+/// Add `column` to `table` in databases created before the column existed.
+async fn add_column(
+    db_pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> ModelResult<()> {
+    let exists: bool =
+        sqlx::query_scalar(r#"SELECT COUNT(*) > 0 FROM pragma_table_info($1) WHERE name = $2"#)
+            .bind(table)
+            .bind(column)
+            .fetch_one(db_pool)
+            .await?;
+    if !exists {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, definition
+        ))
+        .execute(db_pool)
         .await?;
+    }
+
+    Ok(())
+}
+
+/// Remove `amount` euros from `user_id` balance. Returns the new balance or
+/// ModelError::InsuficientFunds if the balance is not enough.
+async fn withdraw(conn: &mut SqliteConnection, user_id: u64, amount: f64) -> ModelResult<f64> {
+    let new_balance = sqlx::query_scalar(
+        r#"
+        UPDATE bank SET balance = balance - $2
+        WHERE user_id = $1 AND balance >= $2
+        RETURNING balance
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(amount)
+    .fetch_optional(conn)
+    .await?;
+
+    new_balance.ok_or(ModelError::InsuficientFunds)
+}
+
+/// Add `amount` euros to `user_id` balance. Returns the new balance.
+async fn deposit(conn: &mut SqliteConnection, user_id: u64, amount: f64) -> ModelResult<f64> {
+    let new_balance = sqlx::query_scalar(
+        r#"
+        UPDATE bank SET balance = balance + $2
+        WHERE user_id = $1
+        RETURNING balance
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(amount)
+    .fetch_optional(conn)
+    .await?;
+
+    new_balance.ok_or(ModelError::BankAccountNotFound(user_id))
+}
+
+/// Amount of `coin_symbol` currently owned by `user_id`.
+async fn owned_coins(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    coin_symbol: &str,
+) -> ModelResult<f64> {
+    let owned_coin_amount = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(SUM(amount), 0.0)
+        FROM transactions
+        WHERE user_id = $1 AND coin_symbol = $2
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(coin_symbol)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(owned_coin_amount)
+}
+
+/// Average price paid per coin of `coin_symbol` by `user_id`, considering
+/// every acquisition (buys and incoming transfers).
+async fn cost_basis(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    coin_symbol: &str,
+) -> ModelResult<f64> {
+    let basis = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(SUM(amount * price) / SUM(amount), 0.0)
+        FROM transactions
+        WHERE user_id = $1 AND coin_symbol = $2 AND amount > 0
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(coin_symbol)
+    .fetch_one(conn)
+    .await?;
+
+    Ok(basis)
+}
+
+/// Record a coin movement in the transactions ledger. Positive amounts are
+/// acquisitions, negative amounts are disposals.
+async fn insert_transaction(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    coin_symbol: &str,
+    amount: f64,
+    price: f64,
+) -> ModelResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (user_id, coin_symbol, amount, price)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(coin_symbol)
+    .bind(amount)
+    .bind(price)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Record a euro balance change of `amount` for `user_id` in the ledger.
+/// Bets and payouts of gambling games set `game`, they count towards the
+/// user's loss limits.
+async fn insert_ledger_entry(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    amount: f64,
+    game: Option<&str>,
+    description: &str,
+) -> ModelResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO ledger (user_id, amount, game, description)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(amount)
+    .bind(game)
+    .bind(description)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn admins_have_mod_permission() {
+        let config = Config {
+            bot_admins: vec![1],
+            bot_mods: vec![1, 2],
+            ..Config::default()
+        };
+        let model = Model::in_memory(config).await.unwrap();
 
-        Ok(res.rows_affected() > 0)
+        assert!(model.user_has_permission(1, Permission::Admin));
+        assert!(model.user_has_permission(1, Permission::Mod));
+        assert!(model.user_has_permission(2, Permission::Mod));
+        assert!(!model.user_has_permission(2, Permission::Admin));
+        assert!(!model.user_has_permission(3, Permission::Mod));
     }
 }
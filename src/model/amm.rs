@@ -0,0 +1,595 @@
+use sqlx::SqliteConnection;
+use tracing::trace;
+
+use super::{
+    Model, ModelError, ModelResult, cost_basis, deposit, insert_transaction, owned_coins, withdraw,
+};
+
+/// Pool shares locked forever when a token is minted, so a pool can never
+/// be drained down to empty reserves.
+const MINIMUM_LIQUIDITY: f64 = 1.0;
+
+/// Liquidity pool of a server token. Prices follow the constant product
+/// formula `euro_reserve * token_reserve = k`.
+pub struct TokenPool {
+    pub coin_symbol: String,
+    pub name: String,
+    pub euro_reserve: f64,
+    pub token_reserve: f64,
+    pub total_shares: f64,
+    /// Server the token was minted in, it can only be traded there. None for
+    /// tokens minted before tokens were scoped to a server.
+    pub guild_id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapSide {
+    Buy,
+    Sell,
+}
+
+/// Expected result of a swap against a pool.
+pub struct SwapQuote {
+    pub euro_amount: f64,
+    pub token_amount: f64,
+    /// Pool price before the swap.
+    pub spot_price: f64,
+    /// Average price paid (or received) per token, fee included.
+    pub price: f64,
+    /// Relative difference between `price` and `spot_price`.
+    pub price_impact: f64,
+}
+
+impl TokenPool {
+    pub fn spot_price(&self) -> f64 {
+        if self.token_reserve > 0f64 {
+            self.euro_reserve / self.token_reserve
+        } else {
+            0f64
+        }
+    }
+
+    fn check_guild(&self, guild_id: Option<u64>) -> ModelResult<()> {
+        if self.guild_id.is_some() && self.guild_id != guild_id {
+            return Err(ModelError::InvalidValue(
+                "'symbol' is a token of another server.".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_liquidity(&self) -> ModelResult<()> {
+        if self.euro_reserve <= 0f64 || self.token_reserve <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "token pool has no liquidity.".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn quote(&self, euro_amount: f64, token_amount: f64) -> SwapQuote {
+        let spot_price = self.spot_price();
+        let price = euro_amount / token_amount;
+        SwapQuote {
+            euro_amount,
+            token_amount,
+            spot_price,
+            price,
+            price_impact: (price - spot_price).abs() / spot_price,
+        }
+    }
+
+    /// Tokens received for `euros_in` euros.
+    fn quote_buy(&self, euros_in: f64, fee: f64) -> ModelResult<SwapQuote> {
+        self.check_liquidity()?;
+        let effective_in = euros_in * (1f64 - fee);
+        let tokens_out = self.token_reserve * effective_in / (self.euro_reserve + effective_in);
+        Ok(self.quote(euros_in, tokens_out))
+    }
+
+    /// Tokens needed to receive exactly `euros_out` euros.
+    fn quote_sell(&self, euros_out: f64, fee: f64) -> ModelResult<SwapQuote> {
+        self.check_liquidity()?;
+        if euros_out >= self.euro_reserve {
+            return Err(ModelError::InvalidValue(
+                "'amount' exceeds the pool liquidity.".into(),
+            ));
+        }
+        let effective_in = self.token_reserve * euros_out / (self.euro_reserve - euros_out);
+        let tokens_in = effective_in / (1f64 - fee);
+        Ok(self.quote(euros_out, tokens_in))
+    }
+
+    /// Euros received for `tokens_in` tokens.
+    fn quote_sell_tokens(&self, tokens_in: f64, fee: f64) -> ModelResult<SwapQuote> {
+        self.check_liquidity()?;
+        let effective_in = tokens_in * (1f64 - fee);
+        let euros_out = self.euro_reserve * effective_in / (self.token_reserve + effective_in);
+        Ok(self.quote(euros_out, tokens_in))
+    }
+}
+
+impl Model {
+    /// Liquidity pool of a server token, None if `coin_symbol` is not a
+    /// server token.
+    pub async fn server_token(&self, coin_symbol: &str) -> ModelResult<Option<TokenPool>> {
+        let mut conn = self.db_pool.acquire().await?;
+        let pool = fetch_pool(&mut conn, &coin_symbol.to_uppercase()).await?;
+        Ok(pool)
+    }
+
+    /// ADMIN: Create a server token of `guild_id`. The whole supply is put in
+    /// a liquidity pool together with `euro_liquidity` euros from the admin
+    /// balance, who receives the initial pool shares minus the locked
+    /// minimum liquidity.
+    pub async fn mint_token(
+        &self,
+        user_id: u64,
+        guild_id: u64,
+        coin_symbol: &str,
+        name: &str,
+        supply: f64,
+        euro_liquidity: f64,
+    ) -> ModelResult<()> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        if supply <= 0f64 || euro_liquidity <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'supply' and 'euros' must be positive.".into(),
+            ));
+        }
+        if euro_liquidity <= MINIMUM_LIQUIDITY {
+            return Err(ModelError::InvalidValue(format!(
+                "'euros' must be more than {}.",
+                MINIMUM_LIQUIDITY
+            )));
+        }
+
+        // Server tokens shadow CoinMarketCap coins, so don't allow reusing
+        // their symbols. Only an explicit unknown symbol answer is trusted,
+        // any other failure could hide an existing coin.
+        match self.coin_info(coin_symbol).await {
+            Err(ModelError::InvalidValue(_)) => {}
+            Ok(_) => {
+                return Err(ModelError::InvalidValue("'symbol' already exists.".into()));
+            }
+            Err(error) => return Err(error),
+        }
+
+        let coin_symbol = coin_symbol.to_uppercase();
+        let mut tx = self.db_pool.begin().await?;
+
+        withdraw(&mut tx, user_id, euro_liquidity).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO server_tokens (coin_symbol, name, euro_reserve, token_reserve, total_shares, guild_id)
+            VALUES ($1, $2, $3, $4, $3, $5)
+            "#,
+        )
+        .bind(&coin_symbol)
+        .bind(name)
+        .bind(euro_liquidity)
+        .bind(supply)
+        .bind(guild_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+
+        add_shares(
+            &mut tx,
+            user_id,
+            &coin_symbol,
+            euro_liquidity - MINIMUM_LIQUIDITY,
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Expected result of buying tokens for `euro_amount` euros or selling
+    /// tokens for `euro_amount` euros.
+    pub async fn swap_quote(
+        &self,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+        side: SwapSide,
+        euro_amount: f64,
+    ) -> ModelResult<SwapQuote> {
+        if euro_amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        let pool = self
+            .server_token(coin_symbol)
+            .await?
+            .ok_or(ModelError::InvalidValue(
+                "'symbol' is not a server token.".into(),
+            ))?;
+        pool.check_guild(guild_id)?;
+
+        match side {
+            SwapSide::Buy => pool.quote_buy(euro_amount, self.config.amm.fee),
+            SwapSide::Sell => pool.quote_sell(euro_amount, self.config.amm.fee),
+        }
+    }
+
+    /// Buy server tokens from the pool, returns amount of tokens bought and
+    /// average price.
+    pub async fn swap_buy(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+        euro_amount: f64,
+    ) -> ModelResult<(f64, f64)> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        if euro_amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        let coin_symbol = coin_symbol.to_uppercase();
+        let mut tx = self.db_pool.begin().await?;
+
+        let mut pool = load_pool(&mut tx, &coin_symbol, guild_id).await?;
+        let quote = pool.quote_buy(euro_amount, self.config.amm.fee)?;
+
+        withdraw(&mut tx, user_id, euro_amount).await?;
+        trace!(
+            "Swapping {} euros for {} {}",
+            euro_amount, quote.token_amount, coin_symbol
+        );
+        insert_transaction(
+            &mut tx,
+            user_id,
+            &coin_symbol,
+            quote.token_amount,
+            quote.price,
+        )
+        .await?;
+
+        pool.euro_reserve += euro_amount;
+        pool.token_reserve -= quote.token_amount;
+        store_pool(&mut tx, &pool).await?;
+
+        tx.commit().await?;
+
+        Ok((quote.token_amount, quote.price))
+    }
+
+    /// Sell server tokens to the pool for exactly `euro_amount` euros,
+    /// returns amount of tokens sold and average price.
+    pub async fn swap_sell(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+        euro_amount: f64,
+    ) -> ModelResult<(f64, f64)> {
+        if euro_amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        self.swap_sell_with(user_id, guild_id, coin_symbol, |pool, fee| {
+            pool.quote_sell(euro_amount, fee)
+        })
+        .await
+    }
+
+    /// Sell every owned server token to the pool, returns amount of tokens
+    /// sold and average price.
+    pub async fn swap_sell_all(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+    ) -> ModelResult<(f64, f64)> {
+        let owned_coin_amount = {
+            let mut conn = self.db_pool.acquire().await?;
+            owned_coins(&mut conn, user_id, &coin_symbol.to_uppercase()).await?
+        };
+        if owned_coin_amount <= 0f64 {
+            return Err(ModelError::InsuficientCoins);
+        }
+
+        self.swap_sell_with(user_id, guild_id, coin_symbol, |pool, fee| {
+            pool.quote_sell_tokens(owned_coin_amount, fee)
+        })
+        .await
+    }
+
+    async fn swap_sell_with(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+        quote_fn: impl FnOnce(&TokenPool, f64) -> ModelResult<SwapQuote>,
+    ) -> ModelResult<(f64, f64)> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let coin_symbol = coin_symbol.to_uppercase();
+        let mut tx = self.db_pool.begin().await?;
+
+        let mut pool = load_pool(&mut tx, &coin_symbol, guild_id).await?;
+        let quote = quote_fn(&pool, self.config.amm.fee)?;
+
+        if owned_coins(&mut tx, user_id, &coin_symbol).await? < quote.token_amount {
+            return Err(ModelError::InsuficientCoins);
+        }
+
+        trace!(
+            "Swapping {} {} for {} euros",
+            quote.token_amount, coin_symbol, quote.euro_amount
+        );
+        insert_transaction(
+            &mut tx,
+            user_id,
+            &coin_symbol,
+            -quote.token_amount,
+            quote.price,
+        )
+        .await?;
+        deposit(&mut tx, user_id, quote.euro_amount).await?;
+
+        pool.euro_reserve -= quote.euro_amount;
+        pool.token_reserve += quote.token_amount;
+        store_pool(&mut tx, &pool).await?;
+
+        tx.commit().await?;
+
+        Ok((quote.token_amount, quote.price))
+    }
+
+    /// Pool shares owned by a user.
+    pub async fn liquidity_shares(&self, user_id: u64, coin_symbol: &str) -> ModelResult<f64> {
+        let shares = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(shares), 0.0)
+            FROM liquidity_shares
+            WHERE user_id = $1 AND coin_symbol = $2
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(coin_symbol.to_uppercase())
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(shares)
+    }
+
+    /// Value in euros of every user's pool shares.
+    pub async fn liquidity_values(&self) -> ModelResult<Vec<(u64, f64)>> {
+        // Both sides of a pool are worth the same at spot price, so a share
+        // is worth twice its part of the euro reserve.
+        let values: Vec<(String, f64)> = sqlx::query_as(
+            r#"
+            SELECT s.user_id, SUM(s.shares * 2 * p.euro_reserve / p.total_shares)
+            FROM liquidity_shares s
+            JOIN server_tokens p ON p.coin_symbol = s.coin_symbol
+            WHERE p.total_shares > 0
+            GROUP BY s.user_id
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        values
+            .into_iter()
+            .map(|(user_id, value)| {
+                let user_id = user_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?;
+                Ok((user_id, value))
+            })
+            .collect()
+    }
+
+    /// Provide liquidity with `euro_amount` euros and the matching amount of
+    /// tokens at the current pool price. Returns tokens deposited and shares
+    /// received.
+    pub async fn add_liquidity(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+        euro_amount: f64,
+    ) -> ModelResult<(f64, f64)> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+        self.check_trading_allowed(user_id).await?;
+
+        if euro_amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        let coin_symbol = coin_symbol.to_uppercase();
+        let mut tx = self.db_pool.begin().await?;
+
+        let mut pool = load_pool(&mut tx, &coin_symbol, guild_id).await?;
+        pool.check_liquidity()?;
+
+        let token_amount = euro_amount * pool.token_reserve / pool.euro_reserve;
+        let shares = pool.total_shares * euro_amount / pool.euro_reserve;
+
+        if owned_coins(&mut tx, user_id, &coin_symbol).await? < token_amount {
+            return Err(ModelError::InsuficientCoins);
+        }
+        withdraw(&mut tx, user_id, euro_amount).await?;
+        let basis = cost_basis(&mut tx, user_id, &coin_symbol).await?;
+        insert_transaction(&mut tx, user_id, &coin_symbol, -token_amount, basis).await?;
+
+        pool.euro_reserve += euro_amount;
+        pool.token_reserve += token_amount;
+        pool.total_shares += shares;
+        store_pool(&mut tx, &pool).await?;
+        add_shares(&mut tx, user_id, &coin_symbol, shares).await?;
+
+        tx.commit().await?;
+
+        Ok((token_amount, shares))
+    }
+
+    /// Redeem pool shares for their part of both reserves, including the
+    /// fees collected since they were added. Returns euros and tokens
+    /// received.
+    pub async fn remove_liquidity(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+        coin_symbol: &str,
+        shares: f64,
+    ) -> ModelResult<(f64, f64)> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        if shares <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'shares' must be positive.".into(),
+            ));
+        }
+
+        let coin_symbol = coin_symbol.to_uppercase();
+        let mut tx = self.db_pool.begin().await?;
+
+        let mut pool = load_pool(&mut tx, &coin_symbol, guild_id).await?;
+        // Pools minted before the locked liquidity existed are protected by
+        // refusing to redeem the last shares.
+        if pool.total_shares - shares < MINIMUM_LIQUIDITY {
+            return Err(ModelError::InvalidValue(
+                "the pool must keep a minimum liquidity.".into(),
+            ));
+        }
+        let removed = sqlx::query(
+            r#"
+            UPDATE liquidity_shares SET shares = shares - $3
+            WHERE user_id = $1 AND coin_symbol = $2 AND shares >= $3
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(&coin_symbol)
+        .bind(shares)
+        .execute(&mut *tx)
+        .await?;
+        if removed.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue("not enough pool shares.".into()));
+        }
+
+        let fraction = shares / pool.total_shares;
+        let euro_amount = pool.euro_reserve * fraction;
+        let token_amount = pool.token_reserve * fraction;
+        let spot_price = pool.spot_price();
+
+        deposit(&mut tx, user_id, euro_amount).await?;
+        insert_transaction(&mut tx, user_id, &coin_symbol, token_amount, spot_price).await?;
+
+        pool.euro_reserve -= euro_amount;
+        pool.token_reserve -= token_amount;
+        pool.total_shares -= shares;
+        store_pool(&mut tx, &pool).await?;
+
+        tx.commit().await?;
+
+        Ok((euro_amount, token_amount))
+    }
+}
+
+async fn fetch_pool(
+    conn: &mut SqliteConnection,
+    coin_symbol: &str,
+) -> ModelResult<Option<TokenPool>> {
+    let pool_row: Option<(String, f64, f64, f64, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT name, euro_reserve, token_reserve, total_shares, guild_id
+        FROM server_tokens
+        WHERE coin_symbol = $1
+        "#,
+    )
+    .bind(coin_symbol)
+    .fetch_optional(conn)
+    .await?;
+
+    let Some((name, euro_reserve, token_reserve, total_shares, guild_id)) = pool_row else {
+        return Ok(None);
+    };
+    let guild_id = guild_id
+        .map(|guild_id| guild_id.parse::<u64>())
+        .transpose()
+        .map_err(|_| ModelError::UnexpectedError)?;
+
+    Ok(Some(TokenPool {
+        coin_symbol: coin_symbol.into(),
+        name,
+        euro_reserve,
+        token_reserve,
+        total_shares,
+        guild_id,
+    }))
+}
+
+/// Pool of a server token that can be traded in `guild_id`.
+async fn load_pool(
+    conn: &mut SqliteConnection,
+    coin_symbol: &str,
+    guild_id: Option<u64>,
+) -> ModelResult<TokenPool> {
+    let pool = fetch_pool(conn, coin_symbol)
+        .await?
+        .ok_or(ModelError::InvalidValue(
+            "'symbol' is not a server token.".into(),
+        ))?;
+    pool.check_guild(guild_id)?;
+    Ok(pool)
+}
+
+async fn store_pool(conn: &mut SqliteConnection, pool: &TokenPool) -> ModelResult<()> {
+    sqlx::query(
+        r#"
+        UPDATE server_tokens
+        SET euro_reserve = $2, token_reserve = $3, total_shares = $4
+        WHERE coin_symbol = $1
+        "#,
+    )
+    .bind(&pool.coin_symbol)
+    .bind(pool.euro_reserve)
+    .bind(pool.token_reserve)
+    .bind(pool.total_shares)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn add_shares(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    coin_symbol: &str,
+    shares: f64,
+) -> ModelResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO liquidity_shares (user_id, coin_symbol, shares)
+        VALUES ($1, $2, $3) ON CONFLICT(user_id, coin_symbol) DO
+        UPDATE SET shares = shares + $3
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(coin_symbol)
+    .bind(shares)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
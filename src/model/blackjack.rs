@@ -0,0 +1,782 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use rand::{Rng, seq::SliceRandom};
+
+use super::{
+    FairRng, Model, ModelError, ModelResult, Roll, SeedOwner, deposit, insert_gamble_outcome,
+    insert_ledger_entry, next_roll, withdraw,
+};
+use crate::config::BlackjackConfig;
+
+/// Maximum number of hands a player can have after splitting.
+const MAX_HANDS: usize = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Card {
+    /// 1 is an ace, 11 to 13 are jack, queen and king.
+    rank: u8,
+    suit: u8,
+}
+
+impl Card {
+    fn value(&self) -> u32 {
+        match self.rank {
+            1 => 11,
+            11..=13 => 10,
+            rank => rank as u32,
+        }
+    }
+}
+
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rank = match self.rank {
+            1 => "A".to_string(),
+            11 => "J".to_string(),
+            12 => "Q".to_string(),
+            13 => "K".to_string(),
+            rank => rank.to_string(),
+        };
+        let suit = ["♠", "♥", "♦", "♣"][self.suit as usize];
+        write!(f, "{}{}", rank, suit)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Hand {
+    pub cards: Vec<Card>,
+}
+
+impl Hand {
+    /// Best total of the hand, counting aces as 1 when 11 would bust.
+    /// Returns the total and whether an ace is still counted as 11.
+    fn total(&self) -> (u32, bool) {
+        let mut total: u32 = self.cards.iter().map(Card::value).sum();
+        let mut soft_aces = self.cards.iter().filter(|card| card.rank == 1).count();
+        while total > 21 && soft_aces > 0 {
+            total -= 10;
+            soft_aces -= 1;
+        }
+        (total, soft_aces > 0)
+    }
+
+    pub fn value(&self) -> u32 {
+        self.total().0
+    }
+
+    pub fn is_bust(&self) -> bool {
+        self.value() > 21
+    }
+
+    pub fn is_blackjack(&self) -> bool {
+        self.cards.len() == 2 && self.value() == 21
+    }
+}
+
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cards: Vec<String> = self.cards.iter().map(Card::to_string).collect();
+        write!(f, "{} `{}`", cards.join(" "), self.value())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayerHand {
+    pub hand: Hand,
+    pub bet: f64,
+    pub finished: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlackjackAction {
+    Hit,
+    Stand,
+    Double,
+    Split,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandResult {
+    Blackjack,
+    Win,
+    Push,
+    Lose,
+    Bust,
+}
+
+/// Result of a settled game. `payout` includes the returned bets.
+#[derive(Debug, Clone)]
+pub struct BlackjackOutcome {
+    pub total_bet: f64,
+    pub payout: f64,
+    pub results: Vec<HandResult>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlackjackGame {
+    shoe: Shoe,
+    roll: Roll,
+    pub dealer: Hand,
+    pub hands: Vec<PlayerHand>,
+    pub active_hand: usize,
+    pub outcome: Option<BlackjackOutcome>,
+    /// Provably fair roll the shoe was shuffled with, set once settled.
+    pub roll_id: Option<i64>,
+    last_action: Instant,
+}
+
+/// Shuffled decks cards are dealt from, reshuffled with the same RNG if it
+/// runs out.
+#[derive(Debug, Clone)]
+struct Shoe {
+    decks: u32,
+    cards: Vec<Card>,
+    dealt: Vec<Card>,
+}
+
+impl Shoe {
+    fn new(decks: u32) -> Self {
+        Self {
+            decks,
+            cards: Vec::new(),
+            dealt: Vec::new(),
+        }
+    }
+
+    fn draw(&mut self, rng: &mut impl Rng) -> Card {
+        if self.cards.is_empty() {
+            self.cards = (0..self.decks.max(1))
+                .flat_map(|_| (0..4).flat_map(|suit| (1..=13).map(move |rank| Card { rank, suit })))
+                .collect();
+            self.cards.shuffle(rng);
+        }
+        let card = self.cards.pop().unwrap_or(Card { rank: 1, suit: 0 });
+        self.dealt.push(card);
+        card
+    }
+
+    /// Cards dealt so far, in order.
+    fn dealt_cards(&self) -> String {
+        let cards: Vec<String> = self.dealt.iter().map(Card::to_string).collect();
+        cards.join(" ")
+    }
+}
+
+/// Recompute the first `cards` cards dealt from a shoe of `decks` decks.
+pub fn blackjack_replay(decks: u32, cards: usize, rng: &mut FairRng) -> String {
+    let mut shoe = Shoe::new(decks);
+    for _ in 0..cards {
+        shoe.draw(rng);
+    }
+    shoe.dealt_cards()
+}
+
+impl BlackjackGame {
+    fn new(decks: u32, bet: f64, roll: Roll) -> Self {
+        let mut game = Self {
+            shoe: Shoe::new(decks),
+            roll,
+            dealer: Hand::default(),
+            hands: vec![PlayerHand {
+                hand: Hand::default(),
+                bet,
+                finished: false,
+            }],
+            active_hand: 0,
+            outcome: None,
+            roll_id: None,
+            last_action: Instant::now(),
+        };
+        for _ in 0..2 {
+            let card = game.draw();
+            game.hands[0].hand.cards.push(card);
+            let card = game.draw();
+            game.dealer.cards.push(card);
+        }
+        game
+    }
+
+    fn draw(&mut self) -> Card {
+        self.shoe.draw(&mut self.roll.rng)
+    }
+
+    /// Whether the game ends right after the deal because someone has a
+    /// natural blackjack.
+    fn has_natural(&self) -> bool {
+        self.hands[0].hand.is_blackjack() || self.dealer.is_blackjack()
+    }
+
+    pub fn can_double(&self) -> bool {
+        self.outcome.is_none() && self.hands[self.active_hand].hand.cards.len() == 2
+    }
+
+    pub fn can_split(&self) -> bool {
+        let cards = &self.hands[self.active_hand].hand.cards;
+        self.outcome.is_none()
+            && self.hands.len() < MAX_HANDS
+            && cards.len() == 2
+            && cards[0].value() == cards[1].value()
+    }
+
+    pub fn total_bet(&self) -> f64 {
+        self.hands.iter().map(|hand| hand.bet).sum()
+    }
+
+    fn is_expired(&self, timeout: Duration) -> bool {
+        self.last_action.elapsed() >= timeout
+    }
+
+    /// Move to the next hand that is still being played.
+    fn advance(&mut self) {
+        while self.active_hand < self.hands.len() {
+            let active = &mut self.hands[self.active_hand];
+            // Nothing left to decide on 21 or a bust
+            if active.hand.value() >= 21 {
+                active.finished = true;
+            }
+            if !active.finished {
+                break;
+            }
+            self.active_hand += 1;
+        }
+        // Keep the index valid once every hand is finished
+        self.active_hand = self.active_hand.min(self.hands.len() - 1);
+    }
+
+    fn is_player_done(&self) -> bool {
+        self.hands.iter().all(|hand| hand.finished)
+    }
+
+    fn hit(&mut self) {
+        let card = self.draw();
+        self.hands[self.active_hand].hand.cards.push(card);
+        self.advance();
+    }
+
+    fn stand(&mut self) {
+        self.hands[self.active_hand].finished = true;
+        self.advance();
+    }
+
+    fn stand_all(&mut self) {
+        for hand in self.hands.iter_mut() {
+            hand.finished = true;
+        }
+    }
+
+    /// Double the bet of the active hand, draw exactly one more card and
+    /// finish it. The extra bet must already be escrowed.
+    fn double(&mut self) {
+        let card = self.draw();
+        let active = &mut self.hands[self.active_hand];
+        active.bet *= 2f64;
+        active.hand.cards.push(card);
+        active.finished = true;
+        self.advance();
+    }
+
+    /// Split the active hand in two with the same bet each. The extra bet
+    /// must already be escrowed. Split aces get a single card each.
+    fn split(&mut self) {
+        let split_aces = self.hands[self.active_hand].hand.cards[0].rank == 1;
+        let card = self.hands[self.active_hand].hand.cards.remove(1);
+        let bet = self.hands[self.active_hand].bet;
+        self.hands.insert(
+            self.active_hand + 1,
+            PlayerHand {
+                hand: Hand { cards: vec![card] },
+                bet,
+                finished: false,
+            },
+        );
+        for index in [self.active_hand, self.active_hand + 1] {
+            let card = self.draw();
+            let hand = &mut self.hands[index];
+            hand.hand.cards.push(card);
+            hand.finished = split_aces;
+        }
+        self.advance();
+    }
+
+    /// Play the dealer hand and settle every player hand.
+    fn settle(&mut self, config: &BlackjackConfig) -> &BlackjackOutcome {
+        self.stand_all();
+
+        // Dealer only draws if some hand is still in play
+        let natural = self.hands.len() == 1 && self.has_natural();
+        if !natural && self.hands.iter().any(|hand| !hand.hand.is_bust()) {
+            loop {
+                let (total, soft) = self.dealer.total();
+                if total < 17 || (total == 17 && soft && config.dealer_hits_soft_17) {
+                    let card = self.draw();
+                    self.dealer.cards.push(card);
+                } else {
+                    break;
+                }
+            }
+        }
+
+        let dealer_value = self.dealer.value();
+        let dealer_blackjack = self.dealer.is_blackjack();
+        let mut payout = 0f64;
+        let mut results = Vec::new();
+        for hand in self.hands.iter() {
+            // Only the initial hand can be a natural blackjack, split hands
+            // reaching 21 with two cards count as a regular 21.
+            let player_blackjack = natural && hand.hand.is_blackjack();
+            let result = if hand.hand.is_bust() {
+                HandResult::Bust
+            } else if player_blackjack && dealer_blackjack {
+                HandResult::Push
+            } else if player_blackjack {
+                HandResult::Blackjack
+            } else if dealer_blackjack {
+                HandResult::Lose
+            } else if dealer_value > 21 || hand.hand.value() > dealer_value {
+                HandResult::Win
+            } else if hand.hand.value() == dealer_value {
+                HandResult::Push
+            } else {
+                HandResult::Lose
+            };
+            payout += match result {
+                HandResult::Blackjack => hand.bet + hand.bet * config.blackjack_payout,
+                HandResult::Win => 2f64 * hand.bet,
+                HandResult::Push => hand.bet,
+                HandResult::Lose | HandResult::Bust => 0f64,
+            };
+            results.push(result);
+        }
+
+        self.outcome.insert(BlackjackOutcome {
+            total_bet: self.total_bet(),
+            payout,
+            results,
+        })
+    }
+}
+
+impl Model {
+    /// How long a blackjack game waits for an action before standing.
+    pub fn blackjack_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.blackjack.timeout_secs)
+    }
+
+    /// Start a blackjack game escrowing `bet` from the user balance. The game
+    /// is already settled if someone was dealt a natural blackjack.
+    pub async fn blackjack_start(&self, user_id: u64, bet: f64) -> ModelResult<BlackjackGame> {
+        self.check_bet(user_id, bet).await?;
+
+        let timeout = self.blackjack_timeout();
+        let abandoned = {
+            let mut games = self.blackjack_games.lock().await;
+            if games
+                .get(&user_id)
+                .is_some_and(|game| !game.is_expired(timeout))
+            {
+                return Err(ModelError::InvalidValue(
+                    "you already have a blackjack game in progress.".into(),
+                ));
+            }
+            games.remove(&user_id)
+        };
+        // Game abandoned without the timeout being handled, stand on it
+        // before dealing a new one.
+        if let Some(mut game) = abandoned {
+            self.blackjack_settle(user_id, &mut game).await?;
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, user_id, bet).await?;
+        insert_ledger_entry(&mut tx, user_id, -bet, Some("blackjack"), "Blackjack bet").await?;
+        // The escrow row also guards games being played outside the table
+        let res = sqlx::query(
+            r#"
+            INSERT INTO blackjack_games (user_id, bet) VALUES ($1, $2)
+            ON CONFLICT (user_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(bet)
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue(
+                "you already have a blackjack game in progress.".into(),
+            ));
+        }
+        let roll = next_roll(&mut tx, SeedOwner::User(user_id)).await?;
+        tx.commit().await?;
+
+        let mut game = BlackjackGame::new(self.config.blackjack.decks, bet, roll);
+        if game.has_natural() {
+            self.blackjack_settle(user_id, &mut game).await?;
+        } else {
+            self.blackjack_games
+                .lock()
+                .await
+                .insert(user_id, game.clone());
+        }
+
+        Ok(game)
+    }
+
+    /// Play an action on the user's game. Doubling and splitting escrow an
+    /// extra bet. The game is settled once every hand is finished, or right
+    /// away standing on every hand if it timed out.
+    pub async fn blackjack_play(
+        &self,
+        user_id: u64,
+        action: BlackjackAction,
+    ) -> ModelResult<BlackjackGame> {
+        // The game is taken out of the table while it is played, so other
+        // games don't wait on its database work
+        let Some(mut game) = self.blackjack_games.lock().await.remove(&user_id) else {
+            return Err(ModelError::InvalidValue(
+                "you have no blackjack game in progress.".into(),
+            ));
+        };
+
+        if game.is_expired(self.blackjack_timeout()) {
+            self.blackjack_settle(user_id, &mut game).await?;
+            return Ok(game);
+        }
+
+        if let Err(error) = self.blackjack_action(user_id, &mut game, action).await {
+            self.blackjack_games.lock().await.insert(user_id, game);
+            return Err(error);
+        }
+        game.last_action = Instant::now();
+
+        if !game.is_player_done() {
+            self.blackjack_games
+                .lock()
+                .await
+                .insert(user_id, game.clone());
+            return Ok(game);
+        }
+
+        self.blackjack_settle(user_id, &mut game).await?;
+        Ok(game)
+    }
+
+    /// Apply an action to a game in play.
+    async fn blackjack_action(
+        &self,
+        user_id: u64,
+        game: &mut BlackjackGame,
+        action: BlackjackAction,
+    ) -> ModelResult<()> {
+        match action {
+            BlackjackAction::Hit => game.hit(),
+            BlackjackAction::Stand => game.stand(),
+            BlackjackAction::Double | BlackjackAction::Split => {
+                let allowed = match action {
+                    BlackjackAction::Double => game.can_double(),
+                    _ => game.can_split(),
+                };
+                if !allowed {
+                    return Err(ModelError::InvalidValue(
+                        "that action is not allowed on this hand.".into(),
+                    ));
+                }
+
+                // The extra bet is a new bet for the limits and balance
+                let extra_bet = game.hands[game.active_hand].bet;
+                self.check_bet(user_id, extra_bet).await?;
+                let mut tx = self.db_pool.begin().await?;
+                withdraw(&mut tx, user_id, extra_bet).await?;
+                let description = match action {
+                    BlackjackAction::Double => "Blackjack double",
+                    _ => "Blackjack split",
+                };
+                insert_ledger_entry(&mut tx, user_id, -extra_bet, Some("blackjack"), description)
+                    .await?;
+                sqlx::query(r#"UPDATE blackjack_games SET bet = bet + $2 WHERE user_id = $1"#)
+                    .bind(user_id.to_string())
+                    .bind(extra_bet)
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await?;
+
+                match action {
+                    BlackjackAction::Double => game.double(),
+                    _ => game.split(),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stand on every hand of a game that timed out. Returns the settled game
+    /// or None if there was no game in progress.
+    pub async fn blackjack_expire(&self, user_id: u64) -> ModelResult<Option<BlackjackGame>> {
+        let Some(mut game) = self.blackjack_games.lock().await.remove(&user_id) else {
+            return Ok(None);
+        };
+        self.blackjack_settle(user_id, &mut game).await?;
+        Ok(Some(game))
+    }
+
+    /// Settle a game, pay out the winnings and record the dealt cards so
+    /// the shuffle can be verified.
+    async fn blackjack_settle(&self, user_id: u64, game: &mut BlackjackGame) -> ModelResult<()> {
+        let payout = game.settle(&self.config.blackjack).payout;
+
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query(r#"DELETE FROM blackjack_games WHERE user_id = $1"#)
+            .bind(user_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        let params = format!("{}:{}", game.shoe.decks, game.shoe.dealt.len());
+        let roll_id = game
+            .roll
+            .record(
+                &mut tx,
+                Some(user_id),
+                "blackjack",
+                &params,
+                &game.shoe.dealt_cards(),
+            )
+            .await?;
+        if payout > 0f64 {
+            deposit(&mut tx, user_id, payout).await?;
+            insert_ledger_entry(
+                &mut tx,
+                user_id,
+                payout,
+                Some("blackjack"),
+                "Blackjack payout",
+            )
+            .await?;
+        }
+        let bet = game.hands.iter().map(|hand| hand.bet).sum();
+        insert_gamble_outcome(&mut tx, user_id, "blackjack", bet, payout).await?;
+        tx.commit().await?;
+        game.roll_id = Some(roll_id);
+
+        Ok(())
+    }
+
+    /// Refund the escrowed bets of games left unfinished by a restart.
+    /// Returns the refunded users and amounts.
+    pub async fn refund_blackjack_games(&self) -> ModelResult<Vec<(u64, f64)>> {
+        let mut tx = self.db_pool.begin().await?;
+        let games: Vec<(String, f64)> =
+            sqlx::query_as(r#"DELETE FROM blackjack_games RETURNING user_id, bet"#)
+                .fetch_all(&mut *tx)
+                .await?;
+
+        let mut refunds = Vec::new();
+        for (user_id, bet) in games {
+            let user_id = user_id
+                .parse::<u64>()
+                .map_err(|_| ModelError::UnexpectedError)?;
+            deposit(&mut tx, user_id, bet).await?;
+            insert_ledger_entry(&mut tx, user_id, bet, Some("blackjack"), "Blackjack refund")
+                .await?;
+            refunds.push((user_id, bet));
+        }
+        tx.commit().await?;
+
+        Ok(refunds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hand(ranks: &[u8]) -> Hand {
+        Hand {
+            cards: ranks
+                .iter()
+                .map(|rank| Card {
+                    rank: *rank,
+                    suit: 0,
+                })
+                .collect(),
+        }
+    }
+
+    /// Game betting 10 euros with fixed hands, `shoe` holds the next cards
+    /// dealt in order.
+    fn game(player: &[u8], dealer: &[u8], shoe: &[u8]) -> BlackjackGame {
+        let mut game = BlackjackGame::new(1, 10f64, Roll::new("server", "client", 0));
+        game.hands[0].hand = hand(player);
+        game.dealer = hand(dealer);
+        game.shoe.cards = shoe
+            .iter()
+            .rev()
+            .map(|rank| Card {
+                rank: *rank,
+                suit: 0,
+            })
+            .collect();
+        game
+    }
+
+    #[test]
+    fn hand_totals() {
+        assert_eq!(hand(&[1, 13]).total(), (21, true));
+        assert!(hand(&[1, 13]).is_blackjack());
+        assert_eq!(hand(&[1, 1, 9]).total(), (21, true));
+        assert!(!hand(&[1, 1, 9]).is_blackjack());
+        assert_eq!(hand(&[1, 1, 9, 5]).total(), (16, false));
+        assert_eq!(hand(&[1, 6, 10]).total(), (17, false));
+        assert!(hand(&[13, 12, 5]).is_bust());
+        assert!(!hand(&[13, 12, 1]).is_bust());
+    }
+
+    #[test]
+    fn dealer_soft_17_rule() {
+        let mut config = BlackjackConfig::default();
+
+        let mut stands = game(&[10, 9], &[1, 6], &[2]);
+        let outcome = stands.settle(&config).clone();
+        assert_eq!(outcome.results, vec![HandResult::Win]);
+        assert_eq!(outcome.payout, 20f64);
+
+        config.dealer_hits_soft_17 = true;
+        let mut hits = game(&[10, 9], &[1, 6], &[2]);
+        let outcome = hits.settle(&config).clone();
+        assert_eq!(hits.dealer.value(), 19);
+        assert_eq!(outcome.results, vec![HandResult::Push]);
+        assert_eq!(outcome.payout, 10f64);
+    }
+
+    #[test]
+    fn dealer_draws_to_17() {
+        let mut game = game(&[10, 8], &[10, 2], &[3, 4, 10]);
+        let outcome = game.settle(&BlackjackConfig::default()).clone();
+
+        assert_eq!(game.dealer.value(), 19);
+        assert_eq!(outcome.results, vec![HandResult::Lose]);
+        assert_eq!(outcome.payout, 0f64);
+    }
+
+    #[test]
+    fn naturals() {
+        let config = BlackjackConfig::default();
+
+        let mut natural = game(&[1, 13], &[10, 7], &[]);
+        assert!(natural.has_natural());
+        let outcome = natural.settle(&config).clone();
+        assert_eq!(outcome.results, vec![HandResult::Blackjack]);
+        assert_eq!(outcome.payout, 25f64);
+
+        let mut both = game(&[1, 13], &[1, 12], &[]);
+        assert_eq!(both.settle(&config).results, vec![HandResult::Push]);
+
+        let mut dealer = game(&[10, 9], &[1, 12], &[]);
+        assert_eq!(dealer.settle(&config).results, vec![HandResult::Lose]);
+    }
+
+    #[test]
+    fn bust_loses_without_dealer_drawing() {
+        let mut game = game(&[10, 6], &[10, 6], &[10, 5]);
+        game.hit();
+        assert!(game.is_player_done());
+
+        let outcome = game.settle(&BlackjackConfig::default()).clone();
+        assert_eq!(game.dealer.value(), 16);
+        assert_eq!(outcome.results, vec![HandResult::Bust]);
+        assert_eq!(outcome.payout, 0f64);
+    }
+
+    #[test]
+    fn double_draws_one_card() {
+        let mut game = game(&[5, 6], &[10, 7], &[10]);
+        assert!(game.can_double());
+        game.double();
+
+        assert!(game.is_player_done());
+        assert_eq!(game.total_bet(), 20f64);
+        let outcome = game.settle(&BlackjackConfig::default()).clone();
+        assert_eq!(outcome.results, vec![HandResult::Win]);
+        assert_eq!(outcome.payout, 40f64);
+    }
+
+    #[test]
+    fn split_hands_play_in_order() {
+        let mut game = game(&[13, 13], &[10, 9], &[1, 9]);
+        assert!(game.can_split());
+        game.split();
+
+        // The first hand reached 21 and is finished, the second is active.
+        assert_eq!(game.hands.len(), 2);
+        assert!(game.hands[0].finished);
+        assert_eq!(game.active_hand, 1);
+        game.stand();
+        assert!(game.is_player_done());
+
+        // A split hand with 21 in two cards is not a natural blackjack.
+        let outcome = game.settle(&BlackjackConfig::default()).clone();
+        assert_eq!(outcome.results, vec![HandResult::Win, HandResult::Push]);
+        assert_eq!(outcome.total_bet, 20f64);
+        assert_eq!(outcome.payout, 30f64);
+    }
+
+    #[test]
+    fn split_aces_get_one_card() {
+        let mut game = game(&[1, 1], &[10, 9], &[5, 6]);
+        game.split();
+
+        assert!(game.is_player_done());
+        assert_eq!(game.hands[0].hand.value(), 16);
+        assert_eq!(game.hands[1].hand.value(), 17);
+        assert!(!game.can_split());
+    }
+
+    #[tokio::test]
+    async fn double_checks_the_extra_bet() {
+        let model = Model::in_memory(crate::config::Config::default())
+            .await
+            .unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+        model
+            .set_gambling_limits(1, None, None, Some(5f64))
+            .await
+            .unwrap();
+        model
+            .blackjack_games
+            .lock()
+            .await
+            .insert(1, game(&[5, 6], &[10, 7], &[10]));
+
+        let result = model.blackjack_play(1, BlackjackAction::Double).await;
+        assert!(matches!(result, Err(ModelError::InvalidValue(_))));
+        assert!(
+            model
+                .blackjack_play(1, BlackjackAction::Split)
+                .await
+                .is_err()
+        );
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+        // Refused actions put the game back on the table
+        assert!(model.blackjack_games.lock().await.contains_key(&1));
+    }
+
+    #[tokio::test]
+    async fn blackjack_is_not_restarted_while_played() {
+        let model = Model::in_memory(crate::config::Config::default())
+            .await
+            .unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+        // A game taken out of the table by an action in progress
+        sqlx::query("INSERT INTO blackjack_games (user_id, bet) VALUES ('1', 10)")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+
+        let result = model.blackjack_start(1, 10f64).await;
+        assert!(matches!(result, Err(ModelError::InvalidValue(_))));
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+    }
+}
@@ -0,0 +1,482 @@
+use std::time::Instant;
+
+use rand::Rng;
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{
+    Model, ModelError, ModelResult, SeedOwner, deposit, insert_gamble_outcome, insert_ledger_entry,
+    next_roll, withdraw,
+};
+
+#[derive(Debug, Clone)]
+pub struct CrashBet {
+    pub user_id: u64,
+    pub amount: f64,
+    /// Multiplier the bet was cashed out at, None while riding.
+    pub cashed_out: Option<f64>,
+}
+
+impl CrashBet {
+    /// Euros paid back including the bet, 0 if it rode the crash.
+    pub fn payout(&self) -> f64 {
+        self.cashed_out
+            .map_or(0f64, |multiplier| self.amount * multiplier)
+    }
+}
+
+/// Round that took off, the crash point stays hidden until it is reached.
+/// It is recorded with its roll at launch so it can't be rolled again.
+struct Flight {
+    roll_id: i64,
+    nonce: i64,
+    crash_point: f64,
+    started: Instant,
+}
+
+#[derive(Default)]
+pub struct CrashRound {
+    bets: Vec<CrashBet>,
+    flight: Option<Flight>,
+}
+
+/// Current state of a round in the air.
+pub struct CrashFlight {
+    pub multiplier: f64,
+    pub crashed: bool,
+    pub bets: Vec<CrashBet>,
+}
+
+/// Crash point of a round and every settled bet.
+pub struct CrashResult {
+    pub crash_point: f64,
+    pub bets: Vec<CrashBet>,
+    pub roll_id: i64,
+    /// House seed nonce of the roll.
+    pub nonce: i64,
+}
+
+/// Multiplier a round crashes at, rounded down to cents. A `house_edge`
+/// share of rounds crash instantly at 1x.
+pub fn crash_point(house_edge: f64, max_multiplier: f64, rng: &mut impl Rng) -> f64 {
+    let roll: f64 = rng.random();
+    let point = ((1f64 - house_edge) / (1f64 - roll)).clamp(1f64, max_multiplier);
+    (point * 100f64).floor() / 100f64
+}
+
+/// Multiplier of a round `elapsed_secs` after take off, rounded down to
+/// cents.
+pub fn crash_multiplier(growth: f64, elapsed_secs: f64) -> f64 {
+    let multiplier = (growth * elapsed_secs).exp();
+    (multiplier * 100f64).floor() / 100f64
+}
+
+impl Model {
+    /// Seconds a crash round stays open for bets.
+    pub fn crash_join_secs(&self) -> u64 {
+        self.config.crash.join_secs
+    }
+
+    fn flight_multiplier(&self, flight: &Flight) -> f64 {
+        crash_multiplier(
+            self.config.crash.growth,
+            flight.started.elapsed().as_secs_f64(),
+        )
+    }
+
+    /// Join the open crash round of a channel, deducting the bet from the
+    /// user balance. Returns true if this bet opened a new round, which must
+    /// then take off with `crash_launch` once the round is over.
+    pub async fn crash_bet(&self, channel_id: u64, user_id: u64, amount: f64) -> ModelResult<bool> {
+        self.check_bet(user_id, amount).await?;
+
+        let mut rounds = self.crash_rounds.lock().await;
+        if let Some(round) = rounds.get(&channel_id) {
+            if round.flight.is_some() {
+                return Err(ModelError::InvalidValue(
+                    "the round already took off, wait for the next one.".into(),
+                ));
+            }
+            if round.bets.iter().any(|bet| bet.user_id == user_id) {
+                return Err(ModelError::InvalidValue(
+                    "you already have a bet in this round.".into(),
+                ));
+            }
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, user_id, amount).await?;
+        insert_ledger_entry(&mut tx, user_id, -amount, Some("crash"), "Crash bet").await?;
+        sqlx::query(
+            r#"
+            INSERT INTO crash_bets (channel_id, user_id, amount)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(channel_id.to_string())
+        .bind(user_id.to_string())
+        .bind(amount)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!("User {} bet {} euros in crash", user_id, amount);
+        let round = rounds.entry(channel_id).or_default();
+        round.bets.push(CrashBet {
+            user_id,
+            amount,
+            cashed_out: None,
+        });
+
+        Ok(round.bets.len() == 1)
+    }
+
+    /// Close the round of a channel for bets and start the multiplier.
+    pub async fn crash_launch(&self, channel_id: u64) -> ModelResult<()> {
+        let mut rounds = self.crash_rounds.lock().await;
+        let Some(round) = rounds.get_mut(&channel_id) else {
+            return Ok(());
+        };
+
+        // Rounds are shared by every player so they roll with the house seed
+        let config = &self.config.crash;
+        let mut tx = self.db_pool.begin().await?;
+        let mut roll = next_roll(&mut tx, SeedOwner::House).await?;
+        let crash_point = crash_point(config.house_edge, config.max_multiplier, &mut roll.rng);
+        let roll_id = roll
+            .record(
+                &mut tx,
+                None,
+                "crash",
+                &format!("{}:{}", config.house_edge, config.max_multiplier),
+                &format!("{:.2}", crash_point),
+            )
+            .await?;
+        tx.commit().await?;
+
+        round.flight = Some(Flight {
+            roll_id,
+            nonce: roll.nonce(),
+            crash_point,
+            started: Instant::now(),
+        });
+
+        Ok(())
+    }
+
+    /// Multiplier and bets of the round of a channel, None if it hasn't
+    /// taken off.
+    pub async fn crash_flight(&self, channel_id: u64) -> Option<CrashFlight> {
+        let rounds = self.crash_rounds.lock().await;
+        let round = rounds.get(&channel_id)?;
+        let flight = round.flight.as_ref()?;
+        let multiplier = self.flight_multiplier(flight);
+
+        Some(CrashFlight {
+            multiplier: multiplier.min(flight.crash_point),
+            crashed: multiplier >= flight.crash_point,
+            bets: round.bets.clone(),
+        })
+    }
+
+    /// Cash out the bet of a user at the current multiplier. The euros are
+    /// paid when the round is settled.
+    pub async fn crash_cash_out(&self, channel_id: u64, user_id: u64) -> ModelResult<CrashBet> {
+        let mut rounds = self.crash_rounds.lock().await;
+        let Some(round) = rounds.get_mut(&channel_id) else {
+            return Err(ModelError::InvalidValue(
+                "there is no crash round in this channel.".into(),
+            ));
+        };
+        let Some(flight) = &round.flight else {
+            return Err(ModelError::InvalidValue(
+                "the round hasn't taken off yet.".into(),
+            ));
+        };
+
+        let multiplier = self.flight_multiplier(flight);
+        if multiplier >= flight.crash_point {
+            return Err(ModelError::InvalidValue(
+                "too late, the round already crashed.".into(),
+            ));
+        }
+        let Some(bet) = round.bets.iter_mut().find(|bet| bet.user_id == user_id) else {
+            return Err(ModelError::InvalidValue(
+                "you have no bet in this round.".into(),
+            ));
+        };
+        if bet.cashed_out.is_some() {
+            return Err(ModelError::InvalidValue("you already cashed out.".into()));
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE crash_bets SET cashed_out = $3
+            WHERE channel_id = $1 AND user_id = $2 AND cashed_out IS NULL
+            "#,
+        )
+        .bind(channel_id.to_string())
+        .bind(user_id.to_string())
+        .bind(multiplier)
+        .execute(&self.db_pool)
+        .await?;
+        bet.cashed_out = Some(multiplier);
+        Ok(bet.clone())
+    }
+
+    /// Close the crashed round of a channel and pay every bet cashed out in
+    /// a single transaction. Returns None if no round took off.
+    pub async fn crash_settle(&self, channel_id: u64) -> ModelResult<Option<CrashResult>> {
+        let mut rounds = self.crash_rounds.lock().await;
+        if rounds
+            .get(&channel_id)
+            .is_none_or(|round| round.flight.is_none())
+        {
+            return Ok(None);
+        }
+        let Some(CrashRound {
+            flight: Some(flight),
+            ..
+        }) = rounds.remove(&channel_id)
+        else {
+            return Ok(None);
+        };
+
+        let mut tx = self.db_pool.begin().await?;
+        let bets = take_crash_bets(&mut tx, Some(channel_id)).await?;
+        info!(
+            "Crash in channel {} crashed at {:.2}",
+            channel_id, flight.crash_point
+        );
+
+        for bet in bets.iter() {
+            insert_gamble_outcome(&mut tx, bet.user_id, "crash", bet.amount, bet.payout()).await?;
+            let Some(multiplier) = bet.cashed_out else {
+                continue;
+            };
+            deposit(&mut tx, bet.user_id, bet.payout()).await?;
+            insert_ledger_entry(
+                &mut tx,
+                bet.user_id,
+                bet.payout(),
+                Some("crash"),
+                &format!("Crash cash out at x{:.2}", multiplier),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(Some(CrashResult {
+            crash_point: flight.crash_point,
+            bets,
+            roll_id: flight.roll_id,
+            nonce: flight.nonce,
+        }))
+    }
+
+    /// Settle the bets of crash rounds that were interrupted by a restart.
+    /// Bets cashed out before the restart are paid, the others get their
+    /// bet back since the round never crashed for them.
+    pub async fn refund_crash_bets(&self) -> ModelResult<Vec<CrashBet>> {
+        let mut tx = self.db_pool.begin().await?;
+        let bets = take_crash_bets(&mut tx, None).await?;
+        return_crash_bets(&mut tx, &bets).await?;
+        tx.commit().await?;
+
+        Ok(bets)
+    }
+
+    /// Cancel the round of a channel that couldn't take off, refunding its
+    /// bets. Rounds in the air are left to `crash_settle`.
+    pub async fn crash_cancel(&self, channel_id: u64) -> ModelResult<Vec<CrashBet>> {
+        let mut rounds = self.crash_rounds.lock().await;
+        if rounds
+            .get(&channel_id)
+            .is_some_and(|round| round.flight.is_some())
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        let bets = take_crash_bets(&mut tx, Some(channel_id)).await?;
+        return_crash_bets(&mut tx, &bets).await?;
+        tx.commit().await?;
+        rounds.remove(&channel_id);
+
+        info!("Crash round in channel {} was cancelled", channel_id);
+        Ok(bets)
+    }
+}
+
+/// Give back bets of a round that never crashed for them: cashed out bets
+/// are paid, the others refunded.
+async fn return_crash_bets(conn: &mut SqliteConnection, bets: &[CrashBet]) -> ModelResult<()> {
+    for bet in bets {
+        let (amount, description) = match bet.cashed_out {
+            Some(multiplier) => {
+                insert_gamble_outcome(conn, bet.user_id, "crash", bet.amount, bet.payout()).await?;
+                (
+                    bet.payout(),
+                    format!("Crash cash out at x{:.2}", multiplier),
+                )
+            }
+            None => (bet.amount, "Crash refund".to_string()),
+        };
+        deposit(conn, bet.user_id, amount).await?;
+        insert_ledger_entry(conn, bet.user_id, amount, Some("crash"), &description).await?;
+    }
+
+    Ok(())
+}
+
+/// Remove and return the bets of the round of `channel_id`, or of every
+/// channel if None.
+async fn take_crash_bets(
+    conn: &mut SqliteConnection,
+    channel_id: Option<u64>,
+) -> ModelResult<Vec<CrashBet>> {
+    let mut bet_rows: Vec<(i64, String, f64, Option<f64>)> = sqlx::query_as(
+        r#"
+        DELETE FROM crash_bets
+        WHERE $1 IS NULL OR channel_id = $1
+        RETURNING bet_id, user_id, amount, cashed_out
+        "#,
+    )
+    .bind(channel_id.map(|channel_id| channel_id.to_string()))
+    .fetch_all(conn)
+    .await?;
+    // Keep the order the bets were placed in
+    bet_rows.sort_by_key(|(bet_id, ..)| *bet_id);
+
+    bet_rows
+        .into_iter()
+        .map(|(_, user_id, amount, cashed_out)| {
+            Ok(CrashBet {
+                user_id: user_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?,
+                amount,
+                cashed_out,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{config::Config, model::FairRng};
+
+    #[test]
+    fn crash_point_bounds() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..10_000 {
+            let point = crash_point(0.03, 100f64, &mut rng);
+            assert!((1f64..=100f64).contains(&point));
+            // Rounded down to cents
+            assert_eq!((point * 100f64).round() / 100f64, point);
+        }
+
+        // A full edge crashes every round instantly, the max caps the others
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!((0..1_000).all(|_| crash_point(1f64, 100f64, &mut rng) == 1f64));
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!((0..1_000).all(|_| crash_point(0f64, 2f64, &mut rng) <= 2f64));
+    }
+
+    #[test]
+    fn crash_point_is_pinned_to_the_roll() {
+        let mut rng = FairRng::new("server-seed", "client-seed", 0);
+        let point = crash_point(0.03, 1000f64, &mut rng);
+        let mut rng = FairRng::new("server-seed", "client-seed", 0);
+        assert_eq!(crash_point(0.03, 1000f64, &mut rng), point);
+    }
+
+    #[test]
+    fn crash_multiplier_growth() {
+        assert_eq!(crash_multiplier(0.1, 0f64), 1f64);
+        // e^(0.1 * 10) = 2.718...
+        assert_eq!(crash_multiplier(0.1, 10f64), 2.71);
+        assert_eq!(crash_multiplier(0.2, 5f64), 2.71);
+        assert!(crash_multiplier(0.1, 20f64) > crash_multiplier(0.1, 19.9));
+    }
+
+    #[tokio::test]
+    async fn crash_round_is_recorded_at_launch() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        assert!(model.crash_bet(10, 1, 10f64).await.unwrap());
+        model.crash_launch(10).await.unwrap();
+        let (roll_id, outcome): (i64, String) =
+            sqlx::query_as("SELECT roll_id, outcome FROM fair_rolls WHERE game = 'crash'")
+                .fetch_one(&model.db_pool)
+                .await
+                .unwrap();
+
+        let result = model.crash_settle(10).await.unwrap().unwrap();
+        assert_eq!(result.roll_id, roll_id);
+        assert_eq!(format!("{:.2}", result.crash_point), outcome);
+        assert_eq!(result.bets.len(), 1);
+        let rolls: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM fair_rolls")
+            .fetch_one(&model.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(rolls, 1);
+    }
+
+    #[tokio::test]
+    async fn interrupted_crash_bets_are_settled() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        for user_id in [1, 2] {
+            model.create_bank_account(user_id).await.unwrap();
+            model.bless(user_id, 100f64).await.unwrap();
+            model.crash_bet(10, user_id, 10f64).await.unwrap();
+        }
+        sqlx::query("UPDATE crash_bets SET cashed_out = 2.5 WHERE user_id = '1'")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+
+        let bets = model.refund_crash_bets().await.unwrap();
+        assert_eq!(bets.len(), 2);
+        assert_eq!(model.balance(1).await.unwrap(), 115f64);
+        assert_eq!(model.balance(2).await.unwrap(), 100f64);
+        assert!(model.refund_crash_bets().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn crash_round_that_cant_take_off_is_refunded() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        for user_id in [1, 2] {
+            model.create_bank_account(user_id).await.unwrap();
+            model.bless(user_id, 100f64).await.unwrap();
+            model.crash_bet(10, user_id, 10f64).await.unwrap();
+        }
+
+        let bets = model.crash_cancel(10).await.unwrap();
+        assert_eq!(bets.len(), 2);
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+        assert_eq!(model.balance(2).await.unwrap(), 100f64);
+        assert!(model.crash_settle(10).await.unwrap().is_none());
+        // The next bet opens a new round
+        assert!(model.crash_bet(10, 1, 10f64).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn crash_round_in_the_air_is_not_cancelled() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        model.crash_bet(10, 1, 10f64).await.unwrap();
+        model.crash_launch(10).await.unwrap();
+        assert!(model.crash_cancel(10).await.unwrap().is_empty());
+        assert_eq!(model.balance(1).await.unwrap(), 90f64);
+        let result = model.crash_settle(10).await.unwrap().unwrap();
+        assert_eq!(result.bets.len(), 1);
+    }
+}
@@ -0,0 +1,591 @@
+use std::time::Duration;
+
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult, deposit, insert_ledger_entry, withdraw};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebtStatus {
+    Active,
+    /// Not repaid on the due date, collected as soon as the borrower has
+    /// the funds.
+    Overdue,
+    Repaid,
+}
+
+impl DebtStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DebtStatus::Active => "active",
+            DebtStatus::Overdue => "overdue",
+            DebtStatus::Repaid => "repaid",
+        }
+    }
+
+    fn from_str(status: &str) -> ModelResult<Self> {
+        match status {
+            "active" => Ok(DebtStatus::Active),
+            "overdue" => Ok(DebtStatus::Overdue),
+            "repaid" => Ok(DebtStatus::Repaid),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+}
+
+/// Euros lent between users, `owed` is the principal plus the interest
+/// agreed on the offer.
+pub struct Debt {
+    pub debt_id: i64,
+    pub lender_id: u64,
+    pub borrower_id: u64,
+    pub principal: f64,
+    pub interest: f64,
+    pub owed: f64,
+    /// UTC datetime the debt is collected at.
+    pub due_at: String,
+    /// Channel the debt was agreed in, reminders are sent there.
+    pub channel_id: u64,
+    pub status: DebtStatus,
+}
+
+/// Offer waiting for the borrower, with the lender's euros in escrow.
+pub struct LendOffer {
+    pub offer_id: i64,
+    pub lender_id: u64,
+    pub borrower_id: u64,
+    pub amount: f64,
+    pub interest: f64,
+    /// Seconds after acceptance the debt is due.
+    pub due_secs: u64,
+    pub channel_id: u64,
+}
+
+/// Euros collected from a borrower by the scheduler.
+pub struct DebtCollection {
+    pub debt: Debt,
+    pub collected: f64,
+}
+
+type DebtRow = (i64, String, String, f64, f64, f64, String, String, String);
+
+fn debt_from_row(
+    (debt_id, lender_id, borrower_id, principal, interest, owed, due_at, channel_id, status): DebtRow,
+) -> ModelResult<Debt> {
+    Ok(Debt {
+        debt_id,
+        lender_id: lender_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        borrower_id: borrower_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        principal,
+        interest,
+        owed,
+        due_at,
+        channel_id: channel_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        status: DebtStatus::from_str(&status)?,
+    })
+}
+
+const SELECT_DEBT: &str = r#"
+    SELECT debt_id, lender_id, borrower_id, principal, interest, owed, due_at, channel_id, status
+    FROM debts
+    "#;
+
+impl Model {
+    /// How long a lend offer waits to be accepted.
+    pub fn lend_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.debts.offer_timeout_secs)
+    }
+
+    /// Offer to lend `amount` euros to `borrower_id`, escrowing them until
+    /// the offer is accepted, declined or times out. Returns the offer id.
+    pub async fn lend_offer(
+        &self,
+        lender_id: u64,
+        borrower_id: u64,
+        amount: f64,
+        interest: f64,
+        due_secs: u64,
+        channel_id: u64,
+    ) -> ModelResult<i64> {
+        if lender_id == borrower_id {
+            return Err(ModelError::InvalidValue(
+                "you can't lend to yourself.".into(),
+            ));
+        }
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        if interest < 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'interest' can't be negative.".into(),
+            ));
+        }
+        // Check if lender and borrower accounts exists
+        self.balance(lender_id).await?;
+        self.balance(borrower_id).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, lender_id, amount).await?;
+        insert_ledger_entry(&mut tx, lender_id, -amount, None, "Lend offer").await?;
+        let offer_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO lend_offers (lender_id, borrower_id, amount, interest, due_secs, channel_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING offer_id
+            "#,
+        )
+        .bind(lender_id.to_string())
+        .bind(borrower_id.to_string())
+        .bind(amount)
+        .bind(interest)
+        .bind(due_secs as i64)
+        .bind(channel_id.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(offer_id)
+    }
+
+    /// Return the escrowed euros of a lend offer that was not accepted.
+    /// Returns None if the offer was already accepted or refunded.
+    pub async fn lend_refund(&self, offer_id: i64) -> ModelResult<Option<LendOffer>> {
+        let mut tx = self.db_pool.begin().await?;
+        let offers = take_lend_offers(&mut tx, Some(offer_id), None).await?;
+        refund_lend_offers(&mut tx, &offers).await?;
+        tx.commit().await?;
+
+        Ok(offers.into_iter().next())
+    }
+
+    /// Refund every offer that is still waiting for the borrower, to run
+    /// when the bot starts and no offer can be accepted anymore.
+    pub async fn refund_pending_lend_offers(&self) -> ModelResult<Vec<LendOffer>> {
+        let mut tx = self.db_pool.begin().await?;
+        let offers = take_lend_offers(&mut tx, None, None).await?;
+        refund_lend_offers(&mut tx, &offers).await?;
+        tx.commit().await?;
+
+        Ok(offers)
+    }
+
+    /// Refund the offers that were not accepted within the timeout.
+    pub async fn expire_lend_offers(&self) -> ModelResult<Vec<LendOffer>> {
+        let mut tx = self.db_pool.begin().await?;
+        let offers =
+            take_lend_offers(&mut tx, None, Some(self.config.debts.offer_timeout_secs)).await?;
+        refund_lend_offers(&mut tx, &offers).await?;
+        tx.commit().await?;
+
+        Ok(offers)
+    }
+
+    /// Accept a lend offer, paying the escrowed euros to the borrower who
+    /// owes them plus the interest once the offer's due time has passed.
+    pub async fn lend_accept(&self, offer_id: i64) -> ModelResult<Debt> {
+        let mut tx = self.db_pool.begin().await?;
+        // Taking the offer out of the table accepts it only once
+        let Some(LendOffer {
+            lender_id,
+            borrower_id,
+            amount,
+            interest,
+            due_secs,
+            channel_id,
+            ..
+        }) = take_lend_offers(&mut tx, Some(offer_id), None).await?.pop()
+        else {
+            return Err(ModelError::InvalidValue(
+                "this offer is no longer open.".into(),
+            ));
+        };
+        let debt_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO debts (lender_id, borrower_id, principal, interest, owed, due_at, channel_id)
+            VALUES ($1, $2, $3, $4, $5, datetime('now', $6), $7)
+            RETURNING debt_id
+            "#,
+        )
+        .bind(lender_id.to_string())
+        .bind(borrower_id.to_string())
+        .bind(amount)
+        .bind(interest)
+        .bind(amount * (1f64 + interest))
+        .bind(format!("+{} seconds", due_secs))
+        .bind(channel_id.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+        deposit(&mut tx, borrower_id, amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            borrower_id,
+            amount,
+            None,
+            &format!("Debt #{}", debt_id),
+        )
+        .await?;
+
+        let query = format!("{} WHERE debt_id = $1", SELECT_DEBT);
+        let row: DebtRow = sqlx::query_as(&query)
+            .bind(debt_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} lent {} euros to user {} in debt {}",
+            lender_id, amount, borrower_id, debt_id
+        );
+        debt_from_row(row)
+    }
+
+    /// Debts `user_id` owes or is owed that are not repaid yet, the ones
+    /// due first come first.
+    pub async fn debts(&self, user_id: u64) -> ModelResult<Vec<Debt>> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let query = format!(
+            "{} WHERE (lender_id = $1 OR borrower_id = $1) AND status <> 'repaid' ORDER BY due_at",
+            SELECT_DEBT
+        );
+        let rows: Vec<DebtRow> = sqlx::query_as(&query)
+            .bind(user_id.to_string())
+            .fetch_all(&self.db_pool)
+            .await?;
+
+        rows.into_iter().map(debt_from_row).collect()
+    }
+
+    /// Repay `amount` euros owed by `borrower_id` to `lender_id`, everything
+    /// owed if None. Debts due first are repaid first. Returns the debts
+    /// that were paid into.
+    pub async fn repay_debt(
+        &self,
+        borrower_id: u64,
+        lender_id: u64,
+        amount: Option<f64>,
+    ) -> ModelResult<Vec<Debt>> {
+        // Debts are read in the transaction so a collection running at the
+        // same time can't make them pay twice
+        let mut tx = self.db_pool.begin().await?;
+        let query = format!(
+            "{} WHERE borrower_id = $1 AND lender_id = $2 AND status <> 'repaid' ORDER BY due_at",
+            SELECT_DEBT
+        );
+        let rows: Vec<DebtRow> = sqlx::query_as(&query)
+            .bind(borrower_id.to_string())
+            .bind(lender_id.to_string())
+            .fetch_all(&mut *tx)
+            .await?;
+        let debts = rows
+            .into_iter()
+            .map(debt_from_row)
+            .collect::<ModelResult<Vec<_>>>()?;
+        if debts.is_empty() {
+            return Err(ModelError::InvalidValue(
+                "you owe nothing to this user.".into(),
+            ));
+        }
+        let total_owed: f64 = debts.iter().map(|debt| debt.owed).sum();
+        let mut remaining = amount.unwrap_or(total_owed).min(total_owed);
+        if remaining <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        withdraw(&mut tx, borrower_id, remaining).await?;
+        deposit(&mut tx, lender_id, remaining).await?;
+
+        let mut repaid = Vec::new();
+        for debt in debts {
+            if remaining <= 0f64 {
+                break;
+            }
+            // Paying everything settles each debt exactly, without rounding
+            // leftovers from the total
+            let payment = match amount {
+                Some(_) => remaining.min(debt.owed),
+                None => debt.owed,
+            };
+            remaining -= payment;
+            let debt = pay_debt(&mut tx, debt, payment, "repayment").await?;
+            repaid.push(debt);
+        }
+        tx.commit().await?;
+
+        info!(
+            "User {} repaid debts {:?} to user {}",
+            borrower_id,
+            repaid.iter().map(|debt| debt.debt_id).collect::<Vec<_>>(),
+            lender_id
+        );
+        Ok(repaid)
+    }
+
+    /// Scheduled job: flag the debts due within the reminder window that
+    /// were not reminded yet. Returns the debts to remind.
+    pub async fn debt_reminders(&self) -> ModelResult<Vec<Debt>> {
+        let mut tx = self.db_pool.begin().await?;
+        let query = format!(
+            "{} WHERE status = 'active' AND NOT reminded AND due_at <= datetime('now', $1)",
+            SELECT_DEBT
+        );
+        let rows: Vec<DebtRow> = sqlx::query_as(&query)
+            .bind(format!("+{} hours", self.config.debts.reminder_hours))
+            .fetch_all(&mut *tx)
+            .await?;
+        let debts = rows
+            .into_iter()
+            .map(debt_from_row)
+            .collect::<ModelResult<Vec<_>>>()?;
+        for debt in &debts {
+            sqlx::query(r#"UPDATE debts SET reminded = TRUE WHERE debt_id = $1"#)
+                .bind(debt.debt_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(debts)
+    }
+
+    /// Scheduled job: collect due and overdue debts from the balance of
+    /// their borrowers. Debts that can't be fully collected become overdue.
+    /// Returns the debts euros were collected from or that became overdue.
+    pub async fn collect_debts(&self) -> ModelResult<Vec<DebtCollection>> {
+        let debt_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+            SELECT debt_id FROM debts
+            WHERE (status = 'active' AND due_at <= CURRENT_TIMESTAMP) OR status = 'overdue'
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut collections = Vec::new();
+        for debt_id in debt_ids {
+            let mut tx = self.db_pool.begin().await?;
+            // Read again in the transaction, the debt may have been repaid
+            let query = format!("{} WHERE debt_id = $1 AND status <> 'repaid'", SELECT_DEBT);
+            let row: Option<DebtRow> = sqlx::query_as(&query)
+                .bind(debt_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let Some(row) = row else {
+                continue;
+            };
+            let debt = debt_from_row(row)?;
+            let balance: f64 = sqlx::query_scalar(r#"SELECT balance FROM bank WHERE user_id = $1"#)
+                .bind(debt.borrower_id.to_string())
+                .fetch_one(&mut *tx)
+                .await?;
+            let collected = balance.min(debt.owed).max(0f64);
+            if collected <= 0f64 && debt.status == DebtStatus::Overdue {
+                continue;
+            }
+            if collected > 0f64 {
+                withdraw(&mut tx, debt.borrower_id, collected).await?;
+                deposit(&mut tx, debt.lender_id, collected).await?;
+            }
+            let debt = pay_debt(&mut tx, debt, collected, "collection").await?;
+            tx.commit().await?;
+
+            info!(
+                "Collected {} euros of debt {}, it is now {}",
+                collected,
+                debt.debt_id,
+                debt.status.as_str()
+            );
+            collections.push(DebtCollection { debt, collected });
+        }
+
+        Ok(collections)
+    }
+}
+
+/// Record a `payment` of `debt` already moved from the borrower to the
+/// lender. Debts paid in full are repaid, the rest stay active until due.
+async fn pay_debt(
+    conn: &mut SqliteConnection,
+    debt: Debt,
+    payment: f64,
+    kind: &str,
+) -> ModelResult<Debt> {
+    if payment > 0f64 {
+        let description = format!("Debt #{} {}", debt.debt_id, kind);
+        insert_ledger_entry(conn, debt.borrower_id, -payment, None, &description).await?;
+        insert_ledger_entry(conn, debt.lender_id, payment, None, &description).await?;
+    }
+    // Never pay more than is still owed, whatever was read before
+    let row: Option<(f64, String)> = sqlx::query_as(
+        r#"
+        UPDATE debts
+        SET owed = owed - $2,
+            status = CASE
+                WHEN owed - $2 <= 0 THEN 'repaid'
+                WHEN due_at <= CURRENT_TIMESTAMP THEN 'overdue'
+                ELSE 'active'
+            END
+        WHERE debt_id = $1 AND status <> 'repaid' AND owed >= $2
+        RETURNING owed, status
+        "#,
+    )
+    .bind(debt.debt_id)
+    .bind(payment)
+    .fetch_optional(conn)
+    .await?;
+    let Some((owed, status)) = row else {
+        return Err(ModelError::InvalidValue(format!(
+            "debt #{} changed, try again.",
+            debt.debt_id
+        )));
+    };
+
+    Ok(Debt {
+        owed,
+        status: DebtStatus::from_str(&status)?,
+        ..debt
+    })
+}
+
+/// Remove and return the lend offer `offer_id`, or every offer if None.
+/// With `older_than_secs` only the offers waiting longer than that are
+/// taken.
+async fn take_lend_offers(
+    conn: &mut SqliteConnection,
+    offer_id: Option<i64>,
+    older_than_secs: Option<u64>,
+) -> ModelResult<Vec<LendOffer>> {
+    let mut offer_rows: Vec<(i64, String, String, f64, f64, i64, String)> = sqlx::query_as(
+        r#"
+        DELETE FROM lend_offers
+        WHERE ($1 IS NULL OR offer_id = $1)
+            AND ($2 IS NULL OR created_at <= datetime('now', '-' || $2 || ' seconds'))
+        RETURNING offer_id, lender_id, borrower_id, amount, interest, due_secs, channel_id
+        "#,
+    )
+    .bind(offer_id)
+    .bind(older_than_secs.map(|secs| secs as i64))
+    .fetch_all(conn)
+    .await?;
+    offer_rows.sort_by_key(|(offer_id, ..)| *offer_id);
+
+    offer_rows
+        .into_iter()
+        .map(
+            |(offer_id, lender_id, borrower_id, amount, interest, due_secs, channel_id)| {
+                Ok(LendOffer {
+                    offer_id,
+                    lender_id: lender_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+                    borrower_id: borrower_id
+                        .parse()
+                        .map_err(|_| ModelError::UnexpectedError)?,
+                    amount,
+                    interest,
+                    due_secs: due_secs as u64,
+                    channel_id: channel_id
+                        .parse()
+                        .map_err(|_| ModelError::UnexpectedError)?,
+                })
+            },
+        )
+        .collect()
+}
+
+async fn refund_lend_offers(conn: &mut SqliteConnection, offers: &[LendOffer]) -> ModelResult<()> {
+    for offer in offers {
+        deposit(&mut *conn, offer.lender_id, offer.amount).await?;
+        insert_ledger_entry(
+            &mut *conn,
+            offer.lender_id,
+            offer.amount,
+            None,
+            "Lend offer refund",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn model_with_users() -> Model {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        for user_id in [1, 2] {
+            model.create_bank_account(user_id).await.unwrap();
+            model.bless(user_id, 100f64).await.unwrap();
+        }
+        model
+    }
+
+    #[tokio::test]
+    async fn lend_offer_is_accepted_once() {
+        let model = model_with_users().await;
+        let offer_id = model
+            .lend_offer(1, 2, 50f64, 0.5, 60 * 60, 10)
+            .await
+            .unwrap();
+        assert_eq!(model.balance(1).await.unwrap(), 50f64);
+
+        let debt = model.lend_accept(offer_id).await.unwrap();
+        assert_eq!(debt.owed, 75f64);
+        assert_eq!(debt.channel_id, 10);
+        assert_eq!(model.balance(2).await.unwrap(), 150f64);
+
+        assert!(model.lend_accept(offer_id).await.is_err());
+        assert!(model.lend_refund(offer_id).await.unwrap().is_none());
+        assert_eq!(model.balance(1).await.unwrap(), 50f64);
+        assert_eq!(model.balance(2).await.unwrap(), 150f64);
+    }
+
+    #[tokio::test]
+    async fn pending_lend_offers_are_refunded() {
+        let model = model_with_users().await;
+        let offer_id = model.lend_offer(1, 2, 50f64, 0f64, 60, 10).await.unwrap();
+        assert!(model.expire_lend_offers().await.unwrap().is_empty());
+
+        sqlx::query("UPDATE lend_offers SET created_at = datetime('now', '-1 day')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+        let expired = model.expire_lend_offers().await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].offer_id, offer_id);
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+
+        model.lend_offer(1, 2, 30f64, 0f64, 60, 10).await.unwrap();
+        let refunded = model.refund_pending_lend_offers().await.unwrap();
+        assert_eq!(refunded.len(), 1);
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+    }
+
+    #[tokio::test]
+    async fn repaying_never_exceeds_what_is_owed() {
+        let model = model_with_users().await;
+        let offer_id = model.lend_offer(1, 2, 50f64, 0.5, 60, 10).await.unwrap();
+        model.lend_accept(offer_id).await.unwrap();
+
+        let debts = model.repay_debt(2, 1, Some(20f64)).await.unwrap();
+        assert_eq!(debts[0].owed, 55f64);
+        assert_eq!(debts[0].status, DebtStatus::Active);
+        let debts = model.repay_debt(2, 1, Some(500f64)).await.unwrap();
+        assert_eq!(debts[0].owed, 0f64);
+        assert_eq!(debts[0].status, DebtStatus::Repaid);
+        assert!(model.repay_debt(2, 1, None).await.is_err());
+
+        assert_eq!(model.balance(1).await.unwrap(), 125f64);
+        assert_eq!(model.balance(2).await.unwrap(), 75f64);
+    }
+}
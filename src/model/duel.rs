@@ -0,0 +1,396 @@
+use std::time::Duration;
+
+use rand::Rng;
+use sqlx::SqliteConnection;
+
+use super::{
+    Model, ModelError, ModelResult, SeedOwner, deposit, insert_gamble_outcome, insert_ledger_entry,
+    next_roll, withdraw,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuelGame {
+    CoinFlip,
+    Dice,
+    RockPaperScissors,
+}
+
+impl DuelGame {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DuelGame::CoinFlip => "coinflip",
+            DuelGame::Dice => "dice",
+            DuelGame::RockPaperScissors => "rps",
+        }
+    }
+
+    pub fn from_str(game: &str) -> ModelResult<Self> {
+        match game {
+            "coinflip" => Ok(DuelGame::CoinFlip),
+            "dice" => Ok(DuelGame::Dice),
+            "rps" => Ok(DuelGame::RockPaperScissors),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+}
+
+const RPS_MOVES: [&str; 3] = ["rock", "paper", "scissors"];
+
+/// Rounds played until there is a winner, as (challenger, opponent) plays.
+pub struct DuelOutcome {
+    pub rounds: Vec<(String, String)>,
+    pub challenger_wins: bool,
+}
+
+impl DuelOutcome {
+    fn to_record(&self) -> String {
+        let rounds: Vec<String> = self
+            .rounds
+            .iter()
+            .map(|(challenger, opponent)| format!("{}-{}", challenger, opponent))
+            .collect();
+        rounds.join(",")
+    }
+}
+
+/// Play a duel game, replaying tied rounds. The challenger is heads on a
+/// coin flip.
+pub fn duel_outcome(game: DuelGame, rng: &mut impl Rng) -> DuelOutcome {
+    let mut rounds = Vec::new();
+    let challenger_wins = loop {
+        match game {
+            DuelGame::CoinFlip => {
+                let heads = rng.random_bool(0.5);
+                let side = if heads { "heads" } else { "tails" };
+                rounds.push((side.to_string(), side.to_string()));
+                break heads;
+            }
+            DuelGame::Dice => {
+                let challenger = rng.random_range(1..=6);
+                let opponent = rng.random_range(1..=6);
+                rounds.push((challenger.to_string(), opponent.to_string()));
+                if challenger != opponent {
+                    break challenger > opponent;
+                }
+            }
+            DuelGame::RockPaperScissors => {
+                let challenger = rng.random_range(0..3);
+                let opponent = rng.random_range(0..3);
+                rounds.push((
+                    RPS_MOVES[challenger].to_string(),
+                    RPS_MOVES[opponent].to_string(),
+                ));
+                if challenger != opponent {
+                    // Each move beats the one before it
+                    break (challenger + 2) % 3 == opponent;
+                }
+            }
+        }
+    };
+
+    DuelOutcome {
+        rounds,
+        challenger_wins,
+    }
+}
+
+/// Recompute the recorded rounds of a duel.
+pub fn duel_replay(game: &str, rng: &mut impl Rng) -> ModelResult<String> {
+    Ok(duel_outcome(DuelGame::from_str(game)?, rng).to_record())
+}
+
+/// A challenge waiting for the opponent, with the challenger's stake in
+/// escrow.
+pub struct PendingDuel {
+    pub duel_id: i64,
+    pub challenger_id: u64,
+    pub opponent_id: u64,
+    pub amount: f64,
+    pub game: DuelGame,
+}
+
+pub struct DuelResult {
+    pub outcome: DuelOutcome,
+    pub winner_id: u64,
+    /// Euros paid to the winner, both stakes minus the house fee.
+    pub payout: f64,
+    pub fee: f64,
+    pub roll_id: i64,
+}
+
+impl Model {
+    /// How long a duel challenge waits to be accepted.
+    pub fn duel_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.duel.timeout_secs)
+    }
+
+    /// Challenge `opponent_id` to a duel, escrowing the challenger's stake
+    /// until the duel is accepted, declined or times out. Returns the duel id.
+    pub async fn duel_challenge(
+        &self,
+        challenger_id: u64,
+        opponent_id: u64,
+        amount: f64,
+        game: DuelGame,
+    ) -> ModelResult<i64> {
+        if challenger_id == opponent_id {
+            return Err(ModelError::InvalidValue("you can't duel yourself.".into()));
+        }
+        self.check_bet(challenger_id, amount).await?;
+        // Check if opponent account exists
+        self.balance(opponent_id).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, challenger_id, amount).await?;
+        insert_ledger_entry(&mut tx, challenger_id, -amount, Some("duel"), "Duel stake").await?;
+        let duel_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO duels (challenger_id, opponent_id, amount, game)
+            VALUES ($1, $2, $3, $4)
+            RETURNING duel_id
+            "#,
+        )
+        .bind(challenger_id.to_string())
+        .bind(opponent_id.to_string())
+        .bind(amount)
+        .bind(game.as_str())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(duel_id)
+    }
+
+    /// Return the escrowed stake of a duel that was not accepted. Returns
+    /// None if the duel was already settled or refunded.
+    pub async fn duel_refund(&self, duel_id: i64) -> ModelResult<Option<PendingDuel>> {
+        let mut tx = self.db_pool.begin().await?;
+        let duels = take_duels(&mut tx, Some(duel_id), None).await?;
+        refund_duels(&mut tx, &duels).await?;
+        tx.commit().await?;
+
+        Ok(duels.into_iter().next())
+    }
+
+    /// Refund every duel that is still waiting for the opponent, to run
+    /// when the bot starts and no challenge can be accepted anymore.
+    pub async fn refund_pending_duels(&self) -> ModelResult<Vec<PendingDuel>> {
+        let mut tx = self.db_pool.begin().await?;
+        let duels = take_duels(&mut tx, None, None).await?;
+        refund_duels(&mut tx, &duels).await?;
+        tx.commit().await?;
+
+        Ok(duels)
+    }
+
+    /// Refund the duels that were not accepted within the timeout.
+    pub async fn expire_duels(&self) -> ModelResult<Vec<PendingDuel>> {
+        let mut tx = self.db_pool.begin().await?;
+        let duels = take_duels(&mut tx, None, Some(self.config.duel.timeout_secs)).await?;
+        refund_duels(&mut tx, &duels).await?;
+        tx.commit().await?;
+
+        Ok(duels)
+    }
+
+    /// Accept a duel, escrowing the opponent's stake and playing it with the
+    /// house seed. The winner gets both stakes minus the house fee.
+    pub async fn duel_accept(&self, duel_id: i64) -> ModelResult<DuelResult> {
+        let duel: Option<(String, f64)> =
+            sqlx::query_as("SELECT opponent_id, amount FROM duels WHERE duel_id = $1")
+                .bind(duel_id)
+                .fetch_optional(&self.db_pool)
+                .await?;
+        let Some((opponent_id, amount)) = duel else {
+            return Err(ModelError::InvalidValue(
+                "this duel is no longer open.".into(),
+            ));
+        };
+        let opponent_id = opponent_id
+            .parse::<u64>()
+            .map_err(|_| ModelError::UnexpectedError)?;
+        self.check_bet(opponent_id, amount).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        // Taking the duel out of the table settles it only once
+        let Some(PendingDuel {
+            challenger_id,
+            opponent_id,
+            amount,
+            game,
+            ..
+        }) = take_duels(&mut tx, Some(duel_id), None).await?.pop()
+        else {
+            return Err(ModelError::InvalidValue(
+                "this duel is no longer open.".into(),
+            ));
+        };
+        withdraw(&mut tx, opponent_id, amount).await?;
+        insert_ledger_entry(&mut tx, opponent_id, -amount, Some("duel"), "Duel stake").await?;
+
+        // Both players take part in the roll so it uses the house seed
+        let mut roll = next_roll(&mut tx, SeedOwner::House).await?;
+        let outcome = duel_outcome(game, &mut roll.rng);
+        let roll_id = roll
+            .record(
+                &mut tx,
+                Some(challenger_id),
+                "duel",
+                game.as_str(),
+                &outcome.to_record(),
+            )
+            .await?;
+
+        let winner_id = if outcome.challenger_wins {
+            challenger_id
+        } else {
+            opponent_id
+        };
+        let fee = 2f64 * amount * self.config.duel.fee;
+        let payout = 2f64 * amount - fee;
+        deposit(&mut tx, winner_id, payout).await?;
+        insert_ledger_entry(&mut tx, winner_id, payout, Some("duel"), "Duel winnings").await?;
+        for user_id in [challenger_id, opponent_id] {
+            let user_payout = if user_id == winner_id { payout } else { 0f64 };
+            insert_gamble_outcome(&mut tx, user_id, "duel", amount, user_payout).await?;
+        }
+        tx.commit().await?;
+
+        Ok(DuelResult {
+            outcome,
+            winner_id,
+            payout,
+            fee,
+            roll_id,
+        })
+    }
+}
+
+/// Remove and return the pending duel `duel_id`, or every pending duel if
+/// None. With `older_than_secs` only the duels waiting longer than that are
+/// taken.
+async fn take_duels(
+    conn: &mut SqliteConnection,
+    duel_id: Option<i64>,
+    older_than_secs: Option<u64>,
+) -> ModelResult<Vec<PendingDuel>> {
+    let mut duel_rows: Vec<(i64, String, String, f64, String)> = sqlx::query_as(
+        r#"
+        DELETE FROM duels
+        WHERE ($1 IS NULL OR duel_id = $1)
+            AND ($2 IS NULL OR created_at <= datetime('now', '-' || $2 || ' seconds'))
+        RETURNING duel_id, challenger_id, opponent_id, amount, game
+        "#,
+    )
+    .bind(duel_id)
+    .bind(older_than_secs.map(|secs| secs as i64))
+    .fetch_all(conn)
+    .await?;
+    duel_rows.sort_by_key(|(duel_id, ..)| *duel_id);
+
+    duel_rows
+        .into_iter()
+        .map(|(duel_id, challenger_id, opponent_id, amount, game)| {
+            Ok(PendingDuel {
+                duel_id,
+                challenger_id: challenger_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?,
+                opponent_id: opponent_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?,
+                amount,
+                game: DuelGame::from_str(&game)?,
+            })
+        })
+        .collect()
+}
+
+async fn refund_duels(conn: &mut SqliteConnection, duels: &[PendingDuel]) -> ModelResult<()> {
+    for duel in duels {
+        deposit(&mut *conn, duel.challenger_id, duel.amount).await?;
+        insert_ledger_entry(
+            &mut *conn,
+            duel.challenger_id,
+            duel.amount,
+            Some("duel"),
+            "Duel refund",
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn model_with_players() -> Model {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        for user_id in [1, 2] {
+            model.create_bank_account(user_id).await.unwrap();
+            model.bless(user_id, 100f64).await.unwrap();
+        }
+        model
+    }
+
+    #[tokio::test]
+    async fn duel_is_settled_once() {
+        let model = model_with_players().await;
+        let duel_id = model
+            .duel_challenge(1, 2, 10f64, DuelGame::Dice)
+            .await
+            .unwrap();
+        assert_eq!(model.balance(1).await.unwrap(), 90f64);
+
+        let result = model.duel_accept(duel_id).await.unwrap();
+        let total = model.balance(1).await.unwrap() + model.balance(2).await.unwrap();
+        assert_eq!(total, 180f64 + result.payout);
+
+        // Neither a second accept nor a late refund pays again
+        assert!(model.duel_accept(duel_id).await.is_err());
+        assert!(model.duel_refund(duel_id).await.unwrap().is_none());
+        let after = model.balance(1).await.unwrap() + model.balance(2).await.unwrap();
+        assert_eq!(after, total);
+    }
+
+    #[tokio::test]
+    async fn pending_duels_are_refunded() {
+        let model = model_with_players().await;
+        let duel_id = model
+            .duel_challenge(1, 2, 10f64, DuelGame::CoinFlip)
+            .await
+            .unwrap();
+
+        // The challenge is younger than the timeout
+        assert!(model.expire_duels().await.unwrap().is_empty());
+
+        let refunded = model.refund_pending_duels().await.unwrap();
+        assert_eq!(refunded.len(), 1);
+        assert_eq!(refunded[0].duel_id, duel_id);
+        assert_eq!(refunded[0].game, DuelGame::CoinFlip);
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+        assert!(model.duel_accept(duel_id).await.is_err());
+        assert_eq!(model.balance(2).await.unwrap(), 100f64);
+    }
+
+    #[tokio::test]
+    async fn expired_duels_are_refunded() {
+        let model = model_with_players().await;
+        let duel_id = model
+            .duel_challenge(1, 2, 10f64, DuelGame::RockPaperScissors)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE duels SET created_at = datetime('now', '-1 day')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+
+        let expired = model.expire_duels().await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].duel_id, duel_id);
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+    }
+}
@@ -0,0 +1,532 @@
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use sqlx::SqliteConnection;
+
+use super::{
+    Model, ModelError, ModelResult, blackjack_replay, coin_flip_outcome, crash_point, duel_replay,
+    lottery_ticket, roulette_number, slot_stops,
+};
+
+/// Deterministic RNG of a single bet. Bytes are HMAC-SHA256 blocks keyed by
+/// the server seed over `client_seed:nonce:round`, so anyone knowing the
+/// seeds and nonce can recompute the outcome of the bet.
+#[derive(Debug, Clone)]
+pub struct FairRng {
+    server_seed: String,
+    client_seed: String,
+    nonce: i64,
+    round: u64,
+    block: [u8; 32],
+    offset: usize,
+}
+
+impl FairRng {
+    pub fn new(server_seed: &str, client_seed: &str, nonce: i64) -> Self {
+        Self {
+            server_seed: server_seed.into(),
+            client_seed: client_seed.into(),
+            nonce,
+            round: 0,
+            block: [0; 32],
+            offset: 32,
+        }
+    }
+
+    fn next_block(&mut self) {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.server_seed.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}:{}", self.client_seed, self.nonce, self.round).as_bytes());
+        self.block = mac.finalize().into_bytes().into();
+        self.round += 1;
+        self.offset = 0;
+    }
+}
+
+impl RngCore for FairRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        for byte in dst {
+            if self.offset == self.block.len() {
+                self.next_block();
+            }
+            *byte = self.block[self.offset];
+            self.offset += 1;
+        }
+    }
+}
+
+/// Who a seed pair belongs to. Games shared by several users, like a
+/// roulette round, roll with the house seeds.
+#[derive(Debug, Clone, Copy)]
+pub enum SeedOwner {
+    User(u64),
+    House,
+}
+
+impl SeedOwner {
+    fn key(&self) -> String {
+        match self {
+            SeedOwner::User(user_id) => user_id.to_string(),
+            SeedOwner::House => "house".into(),
+        }
+    }
+}
+
+/// Server and client seed pair. Only the hash of the server seed is shown
+/// until the pair is rotated, committing to it before any bet is placed.
+pub struct FairnessSeed {
+    pub seed_id: i64,
+    pub server_seed: String,
+    pub server_seed_hash: String,
+    pub client_seed: String,
+    /// Nonce of the next bet.
+    pub nonce: i64,
+    pub revealed: bool,
+}
+
+/// Outcome of a past bet along with the seeds it was rolled with.
+pub struct FairRoll {
+    pub roll_id: i64,
+    pub game: String,
+    pub outcome: String,
+    pub nonce: i64,
+    pub seed: FairnessSeed,
+}
+
+/// A past roll and the outcome recomputed from its revealed seeds.
+pub struct Verification {
+    pub roll: FairRoll,
+    pub recomputed: String,
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        self.roll.outcome == self.recomputed
+    }
+}
+
+/// RNG for the next bet of a seed pair. The outcome must be stored with
+/// `record` once the bet is settled.
+#[derive(Debug, Clone)]
+pub(super) struct Roll {
+    seed_id: i64,
+    nonce: i64,
+    pub rng: FairRng,
+}
+
+impl Roll {
+    /// Nonce of the seed pair this roll used.
+    pub fn nonce(&self) -> i64 {
+        self.nonce
+    }
+
+    /// Store the outcome of the bet so it can be verified later. `params`
+    /// holds whatever the game needs besides the RNG to recompute it.
+    pub async fn record(
+        &self,
+        conn: &mut SqliteConnection,
+        user_id: Option<u64>,
+        game: &str,
+        params: &str,
+        outcome: &str,
+    ) -> ModelResult<i64> {
+        let roll_id = sqlx::query_scalar(
+            r#"
+            INSERT INTO fair_rolls (seed_id, nonce, user_id, game, params, outcome)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING roll_id
+            "#,
+        )
+        .bind(self.seed_id)
+        .bind(self.nonce)
+        .bind(user_id.map(|user_id| user_id.to_string()))
+        .bind(game)
+        .bind(params)
+        .bind(outcome)
+        .fetch_one(conn)
+        .await?;
+
+        Ok(roll_id)
+    }
+}
+
+#[cfg(test)]
+impl Roll {
+    /// Roll of an unsaved seed pair.
+    pub(super) fn new(server_seed: &str, client_seed: &str, nonce: i64) -> Self {
+        Self {
+            seed_id: 0,
+            nonce,
+            rng: FairRng::new(server_seed, client_seed, nonce),
+        }
+    }
+}
+
+fn hash_seed(server_seed: &str) -> String {
+    hex::encode(Sha256::digest(server_seed.as_bytes()))
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::rng();
+    hex::encode((0..bytes).map(|_| rng.random::<u8>()).collect::<Vec<u8>>())
+}
+
+type SeedRow = (i64, String, String, String, i64, bool);
+
+fn seed_from_row(
+    (seed_id, server_seed, server_seed_hash, client_seed, nonce, revealed): SeedRow,
+) -> FairnessSeed {
+    FairnessSeed {
+        seed_id,
+        server_seed,
+        server_seed_hash,
+        client_seed,
+        nonce,
+        revealed,
+    }
+}
+
+async fn insert_seed(
+    conn: &mut SqliteConnection,
+    owner: SeedOwner,
+    client_seed: &str,
+) -> ModelResult<()> {
+    let server_seed = random_hex(32);
+    sqlx::query(
+        r#"
+        INSERT INTO fairness_seeds (owner, server_seed, server_seed_hash, client_seed)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(owner.key())
+    .bind(&server_seed)
+    .bind(hash_seed(&server_seed))
+    .bind(client_seed)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Active seed pair of `owner`, creating one if it has none yet.
+async fn active_seed(conn: &mut SqliteConnection, owner: SeedOwner) -> ModelResult<FairnessSeed> {
+    let query = r#"
+        SELECT seed_id, server_seed, server_seed_hash, client_seed, nonce, revealed
+        FROM fairness_seeds
+        WHERE owner = $1 AND NOT revealed
+        "#;
+    let seed: Option<SeedRow> = sqlx::query_as(query)
+        .bind(owner.key())
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some(seed) = seed {
+        return Ok(seed_from_row(seed));
+    }
+
+    insert_seed(&mut *conn, owner, &random_hex(8)).await?;
+    let seed: SeedRow = sqlx::query_as(query)
+        .bind(owner.key())
+        .fetch_one(conn)
+        .await?;
+    Ok(seed_from_row(seed))
+}
+
+/// Take the next nonce of the active seed pair of `owner`.
+pub(super) async fn next_roll(conn: &mut SqliteConnection, owner: SeedOwner) -> ModelResult<Roll> {
+    let seed = active_seed(&mut *conn, owner).await?;
+    sqlx::query(r#"UPDATE fairness_seeds SET nonce = nonce + 1 WHERE seed_id = $1"#)
+        .bind(seed.seed_id)
+        .execute(conn)
+        .await?;
+
+    Ok(Roll {
+        seed_id: seed.seed_id,
+        nonce: seed.nonce,
+        rng: FairRng::new(&seed.server_seed, &seed.client_seed, seed.nonce),
+    })
+}
+
+/// Recompute the outcome of a game from the RNG of its roll.
+fn replay(game: &str, params: &str, rng: &mut FairRng) -> ModelResult<String> {
+    let invalid_params = || ModelError::InvalidValue("roll parameters are corrupted.".into());
+    let outcome = match game {
+        "coinflip" => match coin_flip_outcome(rng) {
+            true => "heads".into(),
+            false => "tails".into(),
+        },
+        "roulette" => roulette_number(rng).to_string(),
+        "slots" => {
+            let reel_lengths = params
+                .split(',')
+                .map(|length| length.parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid_params())?;
+            let stops: Vec<String> = slot_stops(&reel_lengths, rng)
+                .iter()
+                .map(usize::to_string)
+                .collect();
+            stops.join(",")
+        }
+        "blackjack" => {
+            let (decks, cards) = params.split_once(':').ok_or_else(invalid_params)?;
+            let decks = decks.parse().map_err(|_| invalid_params())?;
+            let cards = cards.parse().map_err(|_| invalid_params())?;
+            blackjack_replay(decks, cards, rng)
+        }
+        "crash" => {
+            let (house_edge, max_multiplier) = params.split_once(':').ok_or_else(invalid_params)?;
+            let house_edge = house_edge.parse().map_err(|_| invalid_params())?;
+            let max_multiplier = max_multiplier.parse().map_err(|_| invalid_params())?;
+            format!("{:.2}", crash_point(house_edge, max_multiplier, rng))
+        }
+        "lottery" => {
+            let tickets = params.parse().map_err(|_| invalid_params())?;
+            lottery_ticket(tickets, rng).to_string()
+        }
+        "duel" => duel_replay(params, rng).map_err(|_| invalid_params())?,
+        _ => return Err(invalid_params()),
+    };
+
+    Ok(outcome)
+}
+
+impl Model {
+    /// Active seed pair of `owner`, the server seed must not be shown.
+    pub async fn fairness_seed(&self, owner: SeedOwner) -> ModelResult<FairnessSeed> {
+        let mut conn = self.db_pool.acquire().await?;
+        active_seed(&mut conn, owner).await
+    }
+
+    /// Reveal the active seed pair of `owner` and replace it with a new
+    /// server seed and `client_seed`, or the previous client seed if None.
+    /// Refused while a game rolled with the seed is still unsettled. Returns
+    /// the revealed and the new seed pair.
+    pub async fn rotate_seed(
+        &self,
+        owner: SeedOwner,
+        client_seed: Option<&str>,
+    ) -> ModelResult<(FairnessSeed, FairnessSeed)> {
+        if client_seed.is_some_and(|seed| seed.is_empty() || seed.len() > 64) {
+            return Err(ModelError::InvalidValue(
+                "'client_seed' must have between 1 and 64 characters.".into(),
+            ));
+        }
+
+        // Games stay locked until the new seed is active, so none can roll
+        // with the seed being revealed.
+        let blackjack_games = self.blackjack_games.lock().await;
+        let crash_rounds = self.crash_rounds.lock().await;
+
+        let mut tx = self.db_pool.begin().await?;
+        let mut revealed = active_seed(&mut tx, owner).await?;
+        sqlx::query(
+            r#"
+            UPDATE fairness_seeds SET revealed = TRUE, revealed_at = CURRENT_TIMESTAMP
+            WHERE seed_id = $1
+            "#,
+        )
+        .bind(revealed.seed_id)
+        .execute(&mut *tx)
+        .await?;
+        revealed.revealed = true;
+
+        // Roulette wagers are checked after the update so a bet can't be
+        // placed while the transaction is open.
+        let unsettled = match owner {
+            SeedOwner::User(user_id) => blackjack_games.contains_key(&user_id),
+            SeedOwner::House => {
+                let roulette_wagers: i64 =
+                    sqlx::query_scalar(r#"SELECT COUNT(*) FROM roulette_wagers"#)
+                        .fetch_one(&mut *tx)
+                        .await?;
+                roulette_wagers > 0 || !crash_rounds.is_empty()
+            }
+        };
+        if unsettled {
+            return Err(ModelError::InvalidValue(
+                "games rolled with this seed are still in progress, rotate it once they are settled.".into(),
+            ));
+        }
+
+        insert_seed(&mut tx, owner, client_seed.unwrap_or(&revealed.client_seed)).await?;
+        let seed = active_seed(&mut tx, owner).await?;
+        tx.commit().await?;
+
+        Ok((revealed, seed))
+    }
+
+    /// Recompute the outcome of a past roll. Only rolls of revealed seed
+    /// pairs can be verified.
+    pub async fn verify_roll(&self, roll_id: i64) -> ModelResult<Verification> {
+        let roll: Option<(String, String, String, i64, i64)> = sqlx::query_as(
+            r#"
+            SELECT game, params, outcome, nonce, seed_id
+            FROM fair_rolls
+            WHERE roll_id = $1
+            "#,
+        )
+        .bind(roll_id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+        let Some((game, params, outcome, nonce, seed_id)) = roll else {
+            return Err(ModelError::InvalidValue(format!(
+                "roll #{} does not exist.",
+                roll_id
+            )));
+        };
+
+        let seed: SeedRow = sqlx::query_as(
+            r#"
+            SELECT seed_id, server_seed, server_seed_hash, client_seed, nonce, revealed
+            FROM fairness_seeds
+            WHERE seed_id = $1
+            "#,
+        )
+        .bind(seed_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+        let seed = seed_from_row(seed);
+        if !seed.revealed {
+            return Err(ModelError::InvalidValue(
+                "the server seed of this roll is not revealed yet, rotate it first.".into(),
+            ));
+        }
+
+        let mut rng = FairRng::new(&seed.server_seed, &seed.client_seed, nonce);
+        let recomputed = replay(&game, &params, &mut rng)?;
+
+        Ok(Verification {
+            roll: FairRoll {
+                roll_id,
+                game,
+                outcome,
+                nonce,
+                seed,
+            },
+            recomputed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn fair_rng_is_pinned_to_hmac_blocks() {
+        // Expected values are the little endian bytes of
+        // HMAC-SHA256("server-seed", "client-seed:<nonce>:<round>").
+        let mut rng = FairRng::new("server-seed", "client-seed", 0);
+        assert_eq!(rng.next_u32(), 1230607220);
+        assert_eq!(rng.next_u64(), 15199710361378314675);
+
+        assert_eq!(
+            FairRng::new("server-seed", "client-seed", 1).next_u32(),
+            2808947626
+        );
+        assert_eq!(
+            FairRng::new("server-seed", "client-seed", 7).next_u32(),
+            3240940817
+        );
+    }
+
+    #[test]
+    fn fair_rng_continues_with_the_next_round() {
+        let mut bytes = [0; 40];
+        FairRng::new("server-seed", "client-seed", 0).fill_bytes(&mut bytes);
+
+        assert_eq!(
+            hex::encode(bytes),
+            "74935949b359c7ca0a38f0d2d6f710fc0434dccdeb3963035382b3f970c2c6dfbb26839f027c7b8a"
+        );
+    }
+
+    #[test]
+    fn server_seed_hash() {
+        assert_eq!(
+            hash_seed("server-seed"),
+            "91024ec49c5bec0b689e42892526320fce08337205c91de94c7a588c20d08eeb"
+        );
+    }
+
+    #[test]
+    fn replay_matches_the_rolled_outcome() {
+        let mut rng = FairRng::new("server-seed", "client-seed", 3);
+        let number = roulette_number(&mut rng);
+        let stops = slot_stops(&[20, 20, 20], &mut rng);
+
+        let mut rng = FairRng::new("server-seed", "client-seed", 3);
+        assert_eq!(
+            replay("roulette", "", &mut rng).unwrap(),
+            number.to_string()
+        );
+        let stops: Vec<String> = stops.iter().map(usize::to_string).collect();
+        assert_eq!(
+            replay("slots", "20,20,20", &mut rng).unwrap(),
+            stops.join(",")
+        );
+        assert!(replay("slots", "twenty", &mut rng).is_err());
+        assert!(replay("poker", "", &mut rng).is_err());
+    }
+
+    #[tokio::test]
+    async fn verify_roll_after_rotation() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+
+        let mut tx = model.db_pool.begin().await.unwrap();
+        let mut roll = next_roll(&mut tx, SeedOwner::House).await.unwrap();
+        let number = roulette_number(&mut roll.rng);
+        let roll_id = roll
+            .record(&mut tx, None, "roulette", "", &number.to_string())
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // The seed is still secret
+        assert!(model.verify_roll(roll_id).await.is_err());
+
+        let (revealed, seed) = model.rotate_seed(SeedOwner::House, None).await.unwrap();
+        assert_eq!(revealed.server_seed_hash, hash_seed(&revealed.server_seed));
+        assert_eq!(revealed.nonce, 1);
+        assert_eq!(seed.nonce, 0);
+        assert_ne!(seed.server_seed_hash, revealed.server_seed_hash);
+
+        let verification = model.verify_roll(roll_id).await.unwrap();
+        assert!(verification.is_valid());
+        assert_eq!(verification.recomputed, number.to_string());
+        assert_eq!(verification.roll.nonce, 0);
+    }
+
+    #[tokio::test]
+    async fn rotation_waits_for_open_rounds() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        model
+            .roulette_bet(10, 1, crate::model::RouletteBet::Red, 5f64)
+            .await
+            .unwrap();
+        assert!(model.rotate_seed(SeedOwner::House, None).await.is_err());
+        // Users' own seeds don't roll roulette
+        assert!(model.rotate_seed(SeedOwner::User(1), None).await.is_ok());
+
+        let spin = model.roulette_spin(10).await.unwrap().unwrap();
+        assert_eq!(spin.nonce, 0);
+        assert!(model.rotate_seed(SeedOwner::House, None).await.is_ok());
+        assert!(model.verify_roll(spin.roll_id).await.unwrap().is_valid());
+    }
+}
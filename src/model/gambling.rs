@@ -0,0 +1,415 @@
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult};
+
+/// Seconds before a loosened limit applies, tightening is immediate.
+const LOOSENING_DELAY_SECS: u64 = 24 * 60 * 60;
+
+/// Responsible gambling limits of a user. Losses are the net result of
+/// gambling ledger entries over the last 24 hours and 7 days.
+pub struct GamblingLimits {
+    pub daily_loss_limit: Option<f64>,
+    pub weekly_loss_limit: Option<f64>,
+    pub max_bet: Option<f64>,
+    /// UTC datetime gambling is blocked until, None if not self-excluded.
+    pub excluded_until: Option<String>,
+    pub daily_loss: f64,
+    pub weekly_loss: f64,
+    /// Maximum bet set by the admins for every user.
+    pub server_max_bet: Option<f64>,
+    /// Loosened limits waiting for `pending_at`, 0 removes the limit.
+    pub pending_daily_loss_limit: Option<f64>,
+    pub pending_weekly_loss_limit: Option<f64>,
+    pub pending_max_bet: Option<f64>,
+    /// UTC datetime the pending limits apply at.
+    pub pending_at: Option<String>,
+}
+
+type LimitsRow = (
+    Option<f64>,
+    Option<f64>,
+    Option<f64>,
+    Option<String>,
+    Option<f64>,
+    Option<f64>,
+    Option<f64>,
+    Option<String>,
+);
+
+/// New limit and pending limit after a user requests `requested`. A stricter
+/// limit applies right away and drops a pending one, a looser one waits.
+fn plan_limit(
+    requested: Option<f64>,
+    current: Option<f64>,
+    pending: Option<f64>,
+) -> (Option<f64>, Option<f64>, bool) {
+    let Some(requested) = requested else {
+        return (current, pending, false);
+    };
+    let requested = (requested > 0f64).then_some(requested);
+    let tightens = match (requested, current) {
+        (Some(requested), Some(current)) => requested <= current,
+        (Some(_), None) | (None, None) => true,
+        (None, Some(_)) => false,
+    };
+
+    if tightens {
+        (requested, None, false)
+    } else {
+        (current, Some(requested.unwrap_or(0f64)), true)
+    }
+}
+
+impl Model {
+    pub async fn gambling_limits(&self, user_id: u64) -> ModelResult<GamblingLimits> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        // Loosened limits that waited long enough apply now
+        sqlx::query(
+            r#"
+            UPDATE gambling_limits SET
+                daily_loss_limit = CASE WHEN pending_daily_loss_limit IS NULL
+                    THEN daily_loss_limit ELSE NULLIF(pending_daily_loss_limit, 0) END,
+                weekly_loss_limit = CASE WHEN pending_weekly_loss_limit IS NULL
+                    THEN weekly_loss_limit ELSE NULLIF(pending_weekly_loss_limit, 0) END,
+                max_bet = CASE WHEN pending_max_bet IS NULL
+                    THEN max_bet ELSE NULLIF(pending_max_bet, 0) END,
+                pending_daily_loss_limit = NULL,
+                pending_weekly_loss_limit = NULL,
+                pending_max_bet = NULL,
+                pending_at = NULL
+            WHERE user_id = $1 AND pending_at <= CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(user_id.to_string())
+        .execute(&self.db_pool)
+        .await?;
+
+        let limits: Option<LimitsRow> = sqlx::query_as(
+            r#"
+                SELECT daily_loss_limit, weekly_loss_limit, max_bet,
+                    CASE WHEN excluded_until > CURRENT_TIMESTAMP THEN excluded_until END,
+                    pending_daily_loss_limit, pending_weekly_loss_limit, pending_max_bet,
+                    pending_at
+                FROM gambling_limits
+                WHERE user_id = $1
+                "#,
+        )
+        .bind(user_id.to_string())
+        .fetch_optional(&self.db_pool)
+        .await?;
+        let (
+            daily_loss_limit,
+            weekly_loss_limit,
+            max_bet,
+            excluded_until,
+            pending_daily_loss_limit,
+            pending_weekly_loss_limit,
+            pending_max_bet,
+            pending_at,
+        ) = limits.unwrap_or_default();
+
+        let (daily_result, weekly_result): (f64, f64) = sqlx::query_as(
+            r#"
+            SELECT
+                COALESCE(SUM(CASE WHEN timestamp > datetime('now', '-1 day') THEN amount END), 0.0),
+                COALESCE(SUM(amount), 0.0)
+            FROM ledger
+            WHERE user_id = $1 AND game IS NOT NULL AND timestamp > datetime('now', '-7 days')
+            "#,
+        )
+        .bind(user_id.to_string())
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(GamblingLimits {
+            daily_loss_limit,
+            weekly_loss_limit,
+            max_bet,
+            excluded_until,
+            daily_loss: (-daily_result).max(0f64),
+            weekly_loss: (-weekly_result).max(0f64),
+            server_max_bet: self.server_max_bet().await?,
+            pending_daily_loss_limit,
+            pending_weekly_loss_limit,
+            pending_max_bet,
+            pending_at,
+        })
+    }
+
+    /// Update the limits of a user, None keeps the current limit and 0
+    /// removes it. Lowering or adding a limit applies right away, raising
+    /// or removing one only after `LOOSENING_DELAY_SECS`.
+    pub async fn set_gambling_limits(
+        &self,
+        user_id: u64,
+        daily_loss_limit: Option<f64>,
+        weekly_loss_limit: Option<f64>,
+        max_bet: Option<f64>,
+    ) -> ModelResult<GamblingLimits> {
+        if [daily_loss_limit, weekly_loss_limit, max_bet]
+            .iter()
+            .flatten()
+            .any(|limit| *limit < 0f64)
+        {
+            return Err(ModelError::InvalidValue("limits can't be negative.".into()));
+        }
+
+        let current = self.gambling_limits(user_id).await?;
+        let (daily_loss_limit, pending_daily_loss_limit, daily_loosened) = plan_limit(
+            daily_loss_limit,
+            current.daily_loss_limit,
+            current.pending_daily_loss_limit,
+        );
+        let (weekly_loss_limit, pending_weekly_loss_limit, weekly_loosened) = plan_limit(
+            weekly_loss_limit,
+            current.weekly_loss_limit,
+            current.pending_weekly_loss_limit,
+        );
+        let (max_bet, pending_max_bet, max_bet_loosened) =
+            plan_limit(max_bet, current.max_bet, current.pending_max_bet);
+        // Loosening again restarts the delay of every pending limit
+        let loosened = daily_loosened || weekly_loosened || max_bet_loosened;
+        let has_pending = pending_daily_loss_limit.is_some()
+            || pending_weekly_loss_limit.is_some()
+            || pending_max_bet.is_some();
+        sqlx::query(
+            r#"
+            INSERT INTO gambling_limits (
+                user_id, daily_loss_limit, weekly_loss_limit, max_bet,
+                pending_daily_loss_limit, pending_weekly_loss_limit, pending_max_bet, pending_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, CASE WHEN $8 THEN datetime('now', $9) END)
+            ON CONFLICT (user_id) DO UPDATE SET
+                daily_loss_limit = excluded.daily_loss_limit,
+                weekly_loss_limit = excluded.weekly_loss_limit,
+                max_bet = excluded.max_bet,
+                pending_daily_loss_limit = excluded.pending_daily_loss_limit,
+                pending_weekly_loss_limit = excluded.pending_weekly_loss_limit,
+                pending_max_bet = excluded.pending_max_bet,
+                pending_at = CASE
+                    WHEN $8 THEN excluded.pending_at
+                    WHEN $10 THEN pending_at
+                END
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(daily_loss_limit)
+        .bind(weekly_loss_limit)
+        .bind(max_bet)
+        .bind(pending_daily_loss_limit)
+        .bind(pending_weekly_loss_limit)
+        .bind(pending_max_bet)
+        .bind(loosened)
+        .bind(format!("+{} seconds", LOOSENING_DELAY_SECS))
+        .bind(has_pending)
+        .execute(&self.db_pool)
+        .await?;
+
+        info!("User {} updated their gambling limits", user_id);
+        self.gambling_limits(user_id).await
+    }
+
+    /// Block every gambling command for `user_id` during `duration_secs`.
+    /// An exclusion can be extended but never shortened. Returns the UTC
+    /// datetime the exclusion ends at.
+    pub async fn self_exclude(&self, user_id: u64, duration_secs: u64) -> ModelResult<String> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let excluded_until = sqlx::query_scalar(
+            r#"
+            INSERT INTO gambling_limits (user_id, excluded_until)
+            VALUES ($1, datetime('now', $2))
+            ON CONFLICT (user_id) DO UPDATE SET excluded_until = MAX(
+                COALESCE(excluded_until, excluded.excluded_until),
+                excluded.excluded_until
+            )
+            RETURNING excluded_until
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(format!("+{} seconds", duration_secs))
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        info!("User {} self-excluded until {}", user_id, excluded_until);
+        Ok(excluded_until)
+    }
+
+    pub async fn server_max_bet(&self) -> ModelResult<Option<f64>> {
+        let max_bet: Option<String> =
+            sqlx::query_scalar(r#"SELECT value FROM settings WHERE key = 'max_bet'"#)
+                .fetch_optional(&self.db_pool)
+                .await?;
+
+        Ok(max_bet.and_then(|max_bet| max_bet.parse().ok()))
+    }
+
+    /// ADMIN: Limit the bets of every user, None removes the limit.
+    pub async fn set_server_max_bet(&self, max_bet: Option<f64>) -> ModelResult<()> {
+        match max_bet {
+            Some(max_bet) if max_bet <= 0f64 => {
+                return Err(ModelError::InvalidValue(
+                    "'max_bet' must be positive.".into(),
+                ));
+            }
+            Some(max_bet) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO settings (key, value) VALUES ('max_bet', $1)
+                    ON CONFLICT (key) DO UPDATE SET value = excluded.value
+                    "#,
+                )
+                .bind(max_bet.to_string())
+                .execute(&self.db_pool)
+                .await?;
+            }
+            None => {
+                sqlx::query(r#"DELETE FROM settings WHERE key = 'max_bet'"#)
+                    .execute(&self.db_pool)
+                    .await?;
+            }
+        }
+
+        info!("Server max bet set to {:?}", max_bet);
+        Ok(())
+    }
+
+    /// Check a bet against the self-exclusion and limits of `user_id`, every
+    /// gambling game goes through `check_bet` which calls this.
+    pub(super) async fn check_gambling_limits(&self, user_id: u64, bet: f64) -> ModelResult<()> {
+        let limits = self.gambling_limits(user_id).await?;
+
+        if let Some(excluded_until) = limits.excluded_until {
+            return Err(ModelError::InvalidValue(format!(
+                "you are self-excluded from gambling until {} UTC.",
+                excluded_until
+            )));
+        }
+        if let Some(max_bet) = limits.server_max_bet
+            && bet > max_bet
+        {
+            return Err(ModelError::InvalidValue(format!(
+                "bets are limited to `{}` euros on this server.",
+                max_bet
+            )));
+        }
+        if let Some(max_bet) = limits.max_bet
+            && bet > max_bet
+        {
+            return Err(ModelError::InvalidValue(format!(
+                "you limited your bets to `{}` euros.",
+                max_bet
+            )));
+        }
+        if let Some(limit) = limits.daily_loss_limit
+            && limits.daily_loss + bet > limit
+        {
+            return Err(ModelError::InvalidValue(format!(
+                "this bet could exceed your daily loss limit of `{}` euros, you lost `{}` euros in the last 24 hours.",
+                limit, limits.daily_loss
+            )));
+        }
+        if let Some(limit) = limits.weekly_loss_limit
+            && limits.weekly_loss + bet > limit
+        {
+            return Err(ModelError::InvalidValue(format!(
+                "this bet could exceed your weekly loss limit of `{}` euros, you lost `{}` euros in the last 7 days.",
+                limit, limits.weekly_loss
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn plan_limit_tightens_now_and_loosens_later() {
+        assert_eq!(
+            plan_limit(None, Some(10f64), Some(20f64)),
+            (Some(10f64), Some(20f64), false)
+        );
+        assert_eq!(
+            plan_limit(Some(5f64), Some(10f64), Some(20f64)),
+            (Some(5f64), None, false)
+        );
+        assert_eq!(
+            plan_limit(Some(5f64), None, None),
+            (Some(5f64), None, false)
+        );
+        assert_eq!(
+            plan_limit(Some(20f64), Some(10f64), None),
+            (Some(10f64), Some(20f64), true)
+        );
+        assert_eq!(
+            plan_limit(Some(0f64), Some(10f64), None),
+            (Some(10f64), Some(0f64), true)
+        );
+        assert_eq!(plan_limit(Some(0f64), None, None), (None, None, false));
+    }
+
+    #[tokio::test]
+    async fn loosened_limits_wait_for_the_delay() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+
+        let limits = model
+            .set_gambling_limits(1, Some(50f64), None, Some(10f64))
+            .await
+            .unwrap();
+        assert_eq!(limits.daily_loss_limit, Some(50f64));
+        assert_eq!(limits.max_bet, Some(10f64));
+        assert!(limits.pending_at.is_none());
+
+        let limits = model
+            .set_gambling_limits(1, Some(0f64), None, Some(100f64))
+            .await
+            .unwrap();
+        assert_eq!(limits.daily_loss_limit, Some(50f64));
+        assert_eq!(limits.max_bet, Some(10f64));
+        assert_eq!(limits.pending_daily_loss_limit, Some(0f64));
+        assert_eq!(limits.pending_max_bet, Some(100f64));
+        assert!(limits.pending_at.is_some());
+        assert!(model.check_gambling_limits(1, 20f64).await.is_err());
+
+        sqlx::query("UPDATE gambling_limits SET pending_at = datetime('now', '-1 second')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+        let limits = model.gambling_limits(1).await.unwrap();
+        assert_eq!(limits.daily_loss_limit, None);
+        assert_eq!(limits.max_bet, Some(100f64));
+        assert!(limits.pending_max_bet.is_none());
+        assert!(limits.pending_at.is_none());
+        assert!(model.check_gambling_limits(1, 20f64).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn tightening_drops_a_pending_limit() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+
+        model
+            .set_gambling_limits(1, None, Some(100f64), None)
+            .await
+            .unwrap();
+        model
+            .set_gambling_limits(1, None, Some(500f64), None)
+            .await
+            .unwrap();
+        let limits = model
+            .set_gambling_limits(1, None, Some(80f64), None)
+            .await
+            .unwrap();
+        assert_eq!(limits.weekly_loss_limit, Some(80f64));
+        assert!(limits.pending_weekly_loss_limit.is_none());
+        assert!(limits.pending_at.is_none());
+    }
+}
@@ -0,0 +1,762 @@
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult, deposit, insert_ledger_entry, withdraw};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoanStatus {
+    Active,
+    /// Not repaid on the due date, trading is frozen until it is.
+    Defaulted,
+    Repaid,
+}
+
+impl LoanStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoanStatus::Active => "active",
+            LoanStatus::Defaulted => "defaulted",
+            LoanStatus::Repaid => "repaid",
+        }
+    }
+
+    fn from_str(status: &str) -> ModelResult<Self> {
+        match status {
+            "active" => Ok(LoanStatus::Active),
+            "defaulted" => Ok(LoanStatus::Defaulted),
+            "repaid" => Ok(LoanStatus::Repaid),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+}
+
+/// Loan granted by the bank, `owed` grows by `rate` every day until repaid.
+pub struct BankLoan {
+    pub loan_id: i64,
+    pub user_id: u64,
+    pub principal: f64,
+    pub rate: f64,
+    pub owed: f64,
+    /// UTC datetime the loan is collected at.
+    pub due_at: String,
+    pub status: LoanStatus,
+}
+
+type LoanRow = (i64, String, f64, f64, f64, String, String);
+
+fn loan_from_row(
+    (loan_id, user_id, principal, rate, owed, due_at, status): LoanRow,
+) -> ModelResult<BankLoan> {
+    Ok(BankLoan {
+        loan_id,
+        user_id: user_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        principal,
+        rate,
+        owed,
+        due_at,
+        status: LoanStatus::from_str(&status)?,
+    })
+}
+
+const SELECT_LOAN: &str = r#"
+    SELECT loan_id, user_id, principal, rate, owed, due_at, status
+    FROM bank_loans
+    "#;
+
+/// Compound the interest earned by the savings of `user_id` since they
+/// were last accrued, so deposits earn for the time they were held. Returns
+/// the savings after the interest.
+async fn accrue_savings(conn: &mut SqliteConnection, user_id: u64, rate: f64) -> ModelResult<f64> {
+    // Savings older than this column accrue from the last daily run
+    let (savings, days): (f64, f64) = sqlx::query_as(
+        r#"
+        SELECT savings, MAX(julianday('now') - julianday(COALESCE(
+            savings_accrued_at,
+            (SELECT value FROM settings WHERE key = 'interest_accrued_on'),
+            'now'
+        )), 0)
+        FROM bank
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id.to_string())
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(ModelError::BankAccountNotFound(user_id))?;
+
+    let interest = savings * ((1f64 + rate).powf(days) - 1f64);
+    let savings: f64 = sqlx::query_scalar(
+        r#"
+        UPDATE bank SET savings = savings + $2, savings_accrued_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1
+        RETURNING savings
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(interest)
+    .fetch_one(&mut *conn)
+    .await?;
+    if interest > 0f64 {
+        insert_ledger_entry(conn, user_id, interest, None, "Savings interest").await?;
+    }
+
+    Ok(savings)
+}
+
+/// Compound the interest of a loan since it was last accrued. Returns the
+/// loan with what is owed now.
+async fn accrue_loan(conn: &mut SqliteConnection, loan_id: i64) -> ModelResult<BankLoan> {
+    // Loans older than this column accrue from the last daily run
+    let (loan_id, user_id, principal, rate, owed, due_at, status, days): (
+        i64,
+        String,
+        f64,
+        f64,
+        f64,
+        String,
+        String,
+        f64,
+    ) = sqlx::query_as(
+        r#"
+        SELECT loan_id, user_id, principal, rate, owed, due_at, status,
+            MAX(julianday('now') - julianday(COALESCE(
+                accrued_at,
+                (SELECT value FROM settings WHERE key = 'interest_accrued_on'),
+                'now'
+            )), 0)
+        FROM bank_loans
+        WHERE loan_id = $1
+        "#,
+    )
+    .bind(loan_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let loan = loan_from_row((loan_id, user_id, principal, rate, owed, due_at, status))?;
+
+    let interest = loan.owed * ((1f64 + loan.rate).powf(days) - 1f64);
+    sqlx::query(
+        r#"
+        UPDATE bank_loans SET owed = owed + $2, accrued_at = CURRENT_TIMESTAMP
+        WHERE loan_id = $1
+        "#,
+    )
+    .bind(loan.loan_id)
+    .bind(interest)
+    .execute(&mut *conn)
+    .await?;
+    if interest > 0f64 {
+        insert_ledger_entry(
+            conn,
+            loan.user_id,
+            -interest,
+            None,
+            &format!("Loan #{} interest", loan.loan_id),
+        )
+        .await?;
+    }
+
+    Ok(BankLoan {
+        owed: loan.owed + interest,
+        ..loan
+    })
+}
+
+impl Model {
+    /// Channel defaulted loans are announced in.
+    pub fn interest_channel(&self) -> Option<u64> {
+        self.config.interest.channel_id
+    }
+
+    /// Daily savings and loan interest rates, as (savings_rate, loan_rate).
+    pub async fn interest_rates(&self) -> ModelResult<(f64, f64)> {
+        let rates: Vec<(String, String)> = sqlx::query_as(
+            r#"SELECT key, value FROM settings WHERE key IN ('savings_rate', 'loan_rate')"#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+        let rate = |key: &str, default: f64| {
+            rates
+                .iter()
+                .find(|(rate_key, _)| rate_key == key)
+                .and_then(|(_, value)| value.parse().ok())
+                .unwrap_or(default)
+        };
+
+        let config = &self.config.interest;
+        Ok((
+            rate("savings_rate", config.savings_rate),
+            rate("loan_rate", config.loan_rate),
+        ))
+    }
+
+    /// ADMIN: Change the daily interest rates, None keeps the current rate.
+    /// Loans keep the rate they were taken with.
+    pub async fn set_interest_rates(
+        &self,
+        savings_rate: Option<f64>,
+        loan_rate: Option<f64>,
+    ) -> ModelResult<(f64, f64)> {
+        let rates = [("savings_rate", savings_rate), ("loan_rate", loan_rate)];
+        if rates
+            .iter()
+            .any(|(_, rate)| rate.is_some_and(|rate| rate < 0f64))
+        {
+            return Err(ModelError::InvalidValue("rates can't be negative.".into()));
+        }
+
+        for (key, rate) in rates {
+            let Some(rate) = rate else {
+                continue;
+            };
+            sqlx::query(
+                r#"
+                INSERT INTO settings (key, value) VALUES ($1, $2)
+                ON CONFLICT (key) DO UPDATE SET value = excluded.value
+                "#,
+            )
+            .bind(key)
+            .bind(rate.to_string())
+            .execute(&self.db_pool)
+            .await?;
+        }
+
+        info!(
+            "Interest rates set to {:?} savings and {:?} loans",
+            savings_rate, loan_rate
+        );
+        self.interest_rates().await
+    }
+
+    pub async fn savings(&self, user_id: u64) -> ModelResult<f64> {
+        sqlx::query_scalar(r#"SELECT savings FROM bank WHERE user_id = $1"#)
+            .bind(user_id.to_string())
+            .fetch_optional(&self.db_pool)
+            .await?
+            .ok_or(ModelError::BankAccountNotFound(user_id))
+    }
+
+    /// Move `amount` euros from the balance to savings. Returns the new
+    /// savings.
+    pub async fn savings_deposit(&self, user_id: u64, amount: f64) -> ModelResult<f64> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        // Savings are collected too, but not before the balance
+        if let Some(loan_id) = self.defaulted_loan(user_id).await? {
+            return Err(ModelError::InvalidValue(format!(
+                "savings deposits are frozen until your defaulted loan #{} is repaid.",
+                loan_id
+            )));
+        }
+        let (savings_rate, _) = self.interest_rates().await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        accrue_savings(&mut tx, user_id, savings_rate).await?;
+        withdraw(&mut tx, user_id, amount).await?;
+        let savings = sqlx::query_scalar(
+            r#"UPDATE bank SET savings = savings + $2 WHERE user_id = $1 RETURNING savings"#,
+        )
+        .bind(user_id.to_string())
+        .bind(amount)
+        .fetch_one(&mut *tx)
+        .await?;
+        insert_ledger_entry(&mut tx, user_id, -amount, None, "Savings deposit").await?;
+        tx.commit().await?;
+
+        Ok(savings)
+    }
+
+    /// Move `amount` euros from savings back to the balance. Returns the new
+    /// savings.
+    pub async fn savings_withdraw(&self, user_id: u64, amount: f64) -> ModelResult<f64> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        let (savings_rate, _) = self.interest_rates().await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        accrue_savings(&mut tx, user_id, savings_rate).await?;
+        let savings: Option<f64> = sqlx::query_scalar(
+            r#"
+            UPDATE bank SET savings = savings - $2
+            WHERE user_id = $1 AND savings >= $2
+            RETURNING savings
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(amount)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(savings) = savings else {
+            return Err(ModelError::InvalidValue(
+                "you don't have that much in savings.".into(),
+            ));
+        };
+        deposit(&mut tx, user_id, amount).await?;
+        insert_ledger_entry(&mut tx, user_id, amount, None, "Savings withdrawal").await?;
+        tx.commit().await?;
+
+        Ok(savings)
+    }
+
+    /// Loan of `user_id` that is not repaid yet.
+    pub async fn bank_loan(&self, user_id: u64) -> ModelResult<Option<BankLoan>> {
+        let query = format!("{} WHERE user_id = $1 AND status <> 'repaid'", SELECT_LOAN);
+        let row: Option<LoanRow> = sqlx::query_as(&query)
+            .bind(user_id.to_string())
+            .fetch_optional(&self.db_pool)
+            .await?;
+
+        row.map(loan_from_row).transpose()
+    }
+
+    /// Borrow `amount` euros from the bank at the current loan rate. Users
+    /// can only have one loan at a time.
+    pub async fn take_loan(&self, user_id: u64, amount: f64) -> ModelResult<BankLoan> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let config = &self.config.interest;
+        if amount <= 0f64 || amount > config.max_loan {
+            return Err(ModelError::InvalidValue(format!(
+                "loans must be between 0 and {} euros.",
+                config.max_loan
+            )));
+        }
+        if let Some(loan) = self.bank_loan(user_id).await? {
+            return Err(ModelError::InvalidValue(format!(
+                "you must repay loan #{} before taking another.",
+                loan.loan_id
+            )));
+        }
+        let (_, loan_rate) = self.interest_rates().await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        // Checked again on insert, a concurrent loan may have been taken
+        let loan_id: Option<i64> = sqlx::query_scalar(
+            r#"
+            INSERT INTO bank_loans (user_id, principal, rate, owed, due_at)
+            SELECT $1, $2, $3, $2, datetime('now', $4)
+            WHERE NOT EXISTS (
+                SELECT 1 FROM bank_loans WHERE user_id = $1 AND status <> 'repaid'
+            )
+            RETURNING loan_id
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(amount)
+        .bind(loan_rate)
+        .bind(format!("+{} days", config.loan_days))
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(loan_id) = loan_id else {
+            return Err(ModelError::InvalidValue(
+                "you must repay your loan before taking another.".into(),
+            ));
+        };
+        deposit(&mut tx, user_id, amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            amount,
+            None,
+            &format!("Loan #{}", loan_id),
+        )
+        .await?;
+
+        let query = format!("{} WHERE loan_id = $1", SELECT_LOAN);
+        let row: LoanRow = sqlx::query_as(&query)
+            .bind(loan_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!("User {} took loan {} of {} euros", user_id, loan_id, amount);
+        loan_from_row(row)
+    }
+
+    /// Repay `amount` euros of the loan of `user_id`, everything owed if
+    /// None.
+    pub async fn repay_loan(&self, user_id: u64, amount: Option<f64>) -> ModelResult<BankLoan> {
+        let Some(loan) = self.bank_loan(user_id).await? else {
+            return Err(ModelError::InvalidValue(
+                "you have no loan to repay.".into(),
+            ));
+        };
+        if amount.is_some_and(|amount| amount <= 0f64) {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        // Interest runs until the moment of the payment
+        let loan = accrue_loan(&mut tx, loan.loan_id).await?;
+        if loan.status == LoanStatus::Repaid {
+            return Err(ModelError::InvalidValue(
+                "you have no loan to repay.".into(),
+            ));
+        }
+        let payment = amount.unwrap_or(loan.owed).min(loan.owed);
+        withdraw(&mut tx, user_id, payment).await?;
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            -payment,
+            None,
+            &format!("Loan #{} repayment", loan.loan_id),
+        )
+        .await?;
+        let status = if payment >= loan.owed {
+            LoanStatus::Repaid
+        } else {
+            loan.status
+        };
+        sqlx::query(r#"UPDATE bank_loans SET owed = owed - $2, status = $3 WHERE loan_id = $1"#)
+            .bind(loan.loan_id)
+            .bind(payment)
+            .bind(status.as_str())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} repaid {} euros of loan {}",
+            user_id, payment, loan.loan_id
+        );
+        Ok(BankLoan {
+            owed: loan.owed - payment,
+            status,
+            ..loan
+        })
+    }
+
+    /// Id of the defaulted loan of `user_id`, if any.
+    async fn defaulted_loan(&self, user_id: u64) -> ModelResult<Option<i64>> {
+        Ok(sqlx::query_scalar(
+            r#"SELECT loan_id FROM bank_loans WHERE user_id = $1 AND status = 'defaulted'"#,
+        )
+        .bind(user_id.to_string())
+        .fetch_optional(&self.db_pool)
+        .await?)
+    }
+
+    /// Fails if `user_id` has a defaulted loan, which freezes trading until
+    /// it is repaid.
+    pub(super) async fn check_trading_allowed(&self, user_id: u64) -> ModelResult<()> {
+        match self.defaulted_loan(user_id).await? {
+            Some(loan_id) => Err(ModelError::InvalidValue(format!(
+                "trading is frozen until your defaulted loan #{} is repaid.",
+                loan_id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Scheduled job: once a day, accrue the interest of savings and loans
+    /// for the time since each was last accrued, then collect the loans
+    /// that are due from their users. Returns the loans that were repaid or
+    /// defaulted by the collection.
+    pub async fn accrue_interest(&self) -> ModelResult<Vec<BankLoan>> {
+        let (savings_rate, _) = self.interest_rates().await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        // The first run only records the date, there is nothing to accrue
+        let days: i64 = sqlx::query_scalar(
+            r#"
+            SELECT CAST(julianday(DATE('now')) - julianday(COALESCE(
+                (SELECT value FROM settings WHERE key = 'interest_accrued_on'),
+                DATE('now')
+            )) AS INTEGER)
+            "#,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        if days > 0 {
+            let savers: Vec<String> =
+                sqlx::query_scalar(r#"SELECT user_id FROM bank WHERE savings > 0"#)
+                    .fetch_all(&mut *tx)
+                    .await?;
+            for user_id in savers {
+                let user_id = user_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?;
+                accrue_savings(&mut tx, user_id, savings_rate).await?;
+            }
+
+            let loan_ids: Vec<i64> =
+                sqlx::query_scalar(r#"SELECT loan_id FROM bank_loans WHERE status <> 'repaid'"#)
+                    .fetch_all(&mut *tx)
+                    .await?;
+            for loan_id in loan_ids {
+                accrue_loan(&mut tx, loan_id).await?;
+            }
+            info!("Accrued interest, {} days since the last run", days);
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO settings (key, value) VALUES ('interest_accrued_on', DATE('now'))
+            ON CONFLICT (key) DO UPDATE SET value = excluded.value
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.collect_loans().await
+    }
+
+    /// Collect due and defaulted loans from the balance of their users,
+    /// then from their savings. Loans that can't be fully collected are
+    /// defaulted.
+    async fn collect_loans(&self) -> ModelResult<Vec<BankLoan>> {
+        let loan_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+            SELECT loan_id FROM bank_loans
+            WHERE (status = 'active' AND due_at <= CURRENT_TIMESTAMP) OR status = 'defaulted'
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut settled = Vec::new();
+        for loan_id in loan_ids {
+            let mut tx = self.db_pool.begin().await?;
+            // Read again in the transaction, the loan may have been repaid
+            let query = format!("{} WHERE loan_id = $1 AND status <> 'repaid'", SELECT_LOAN);
+            let row: Option<LoanRow> = sqlx::query_as(&query)
+                .bind(loan_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            let Some(row) = row else {
+                continue;
+            };
+            let loan = loan_from_row(row)?;
+            let (balance, savings): (f64, f64) =
+                sqlx::query_as(r#"SELECT balance, savings FROM bank WHERE user_id = $1"#)
+                    .bind(loan.user_id.to_string())
+                    .fetch_one(&mut *tx)
+                    .await?;
+            let payment = balance.min(loan.owed);
+            if payment > 0f64 {
+                withdraw(&mut tx, loan.user_id, payment).await?;
+                insert_ledger_entry(
+                    &mut tx,
+                    loan.user_id,
+                    -payment,
+                    None,
+                    &format!("Loan #{} collection", loan.loan_id),
+                )
+                .await?;
+            }
+
+            let savings_payment = savings.min(loan.owed - payment);
+            if savings_payment > 0f64 {
+                sqlx::query(r#"UPDATE bank SET savings = savings - $2 WHERE user_id = $1"#)
+                    .bind(loan.user_id.to_string())
+                    .bind(savings_payment)
+                    .execute(&mut *tx)
+                    .await?;
+                insert_ledger_entry(
+                    &mut tx,
+                    loan.user_id,
+                    -savings_payment,
+                    None,
+                    &format!("Loan #{} collection from savings", loan.loan_id),
+                )
+                .await?;
+            }
+
+            let payment = payment + savings_payment;
+            let status = if payment >= loan.owed {
+                LoanStatus::Repaid
+            } else {
+                LoanStatus::Defaulted
+            };
+            sqlx::query(
+                r#"UPDATE bank_loans SET owed = owed - $2, status = $3 WHERE loan_id = $1"#,
+            )
+            .bind(loan.loan_id)
+            .bind(payment)
+            .bind(status.as_str())
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            if status != loan.status {
+                info!("Loan {} is now {}", loan.loan_id, status.as_str());
+                settled.push(BankLoan {
+                    owed: loan.owed - payment,
+                    status,
+                    ..loan
+                });
+            }
+        }
+
+        Ok(settled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn model_with_loan() -> (Model, BankLoan) {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        let loan = model.take_loan(1, 100f64).await.unwrap();
+        (model, loan)
+    }
+
+    async fn set_loan_due(model: &Model) {
+        sqlx::query("UPDATE bank_loans SET due_at = datetime('now', '-1 minute')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+    }
+
+    fn is_frozen<T>(result: ModelResult<T>) -> bool {
+        matches!(result, Err(ModelError::InvalidValue(message)) if message.contains("frozen"))
+    }
+
+    #[tokio::test]
+    async fn only_one_loan_is_taken_at_a_time() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+
+        let (first, second) = tokio::join!(model.take_loan(1, 100f64), model.take_loan(1, 100f64));
+        assert!(first.is_ok() != second.is_ok());
+        let loans: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM bank_loans")
+            .fetch_one(&model.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(loans, 1);
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+
+        // Interest accrued since the loan was taken is owed too
+        model.bless(1, 10f64).await.unwrap();
+        model.repay_loan(1, None).await.unwrap();
+        assert!(model.take_loan(1, 50f64).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn due_loans_are_collected_from_savings() {
+        let (model, loan) = model_with_loan().await;
+        model.bless(1, 20f64).await.unwrap();
+        model.savings_deposit(1, 80f64).await.unwrap();
+        set_loan_due(&model).await;
+
+        let settled = model.accrue_interest().await.unwrap();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].loan_id, loan.loan_id);
+        assert_eq!(settled[0].status, LoanStatus::Repaid);
+        assert_eq!(model.balance(1).await.unwrap(), 0f64);
+        assert_eq!(model.savings(1).await.unwrap(), 20f64);
+    }
+
+    #[tokio::test]
+    async fn defaulted_loans_freeze_savings_and_trading() {
+        let (model, _) = model_with_loan().await;
+        model.create_bank_account(2).await.unwrap();
+        model.savings_deposit(1, 50f64).await.unwrap();
+        model.bless(1, -100f64).await.unwrap();
+        set_loan_due(&model).await;
+
+        let settled = model.accrue_interest().await.unwrap();
+        assert_eq!(settled[0].status, LoanStatus::Defaulted);
+        assert_eq!(settled[0].owed, 50f64);
+        assert_eq!(model.savings(1).await.unwrap(), 0f64);
+
+        model.bless(1, 10f64).await.unwrap();
+        assert!(is_frozen(model.savings_deposit(1, 10f64).await));
+        assert!(is_frozen(model.give_coin(1, 2, "BTC", 1f64).await));
+        assert!(is_frozen(
+            model.add_liquidity(1, Some(1), "BEN", 10f64).await
+        ));
+        let tournament_id = model
+            .create_tournament(
+                "Cup",
+                "2999-01-01 00:00",
+                "2999-02-01 00:00",
+                100f64,
+                &[],
+                1,
+            )
+            .await
+            .unwrap();
+        assert!(is_frozen(model.join_tournament(tournament_id, 1).await));
+
+        assert!(model.repay_loan(1, None).await.is_err());
+        model.bless(1, 100f64).await.unwrap();
+        model.repay_loan(1, None).await.unwrap();
+        assert!(model.savings_deposit(1, 10f64).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn savings_accrue_for_the_time_held() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        for user_id in [1, 2] {
+            model.create_bank_account(user_id).await.unwrap();
+            model.bless(user_id, 100f64).await.unwrap();
+            model.savings_deposit(user_id, 100f64).await.unwrap();
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO settings (key, value) VALUES ('interest_accrued_on', DATE('now', '-1 day'))
+            "#,
+        )
+        .execute(&model.db_pool)
+        .await
+        .unwrap();
+        // User 1 saved for a whole day, user 2 only for half of it
+        sqlx::query(
+            "UPDATE bank SET savings_accrued_at = datetime('now', '-1 day') WHERE user_id = '1'",
+        )
+        .execute(&model.db_pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "UPDATE bank SET savings_accrued_at = datetime('now', '-12 hours') WHERE user_id = '2'",
+        )
+        .execute(&model.db_pool)
+        .await
+        .unwrap();
+
+        model.accrue_interest().await.unwrap();
+        let (rate, _) = model.interest_rates().await.unwrap();
+        let full_day = model.savings(1).await.unwrap() - 100f64;
+        let half_day = model.savings(2).await.unwrap() - 100f64;
+        assert!((full_day - 100f64 * rate).abs() < 1e-4);
+        assert!((half_day - 100f64 * ((1f64 + rate).powf(0.5) - 1f64)).abs() < 1e-4);
+
+        // Accruing again the same day adds nothing
+        model.accrue_interest().await.unwrap();
+        assert_eq!(model.savings(1).await.unwrap(), 100f64 + full_day);
+    }
+
+    #[tokio::test]
+    async fn loans_accrue_until_repaid() {
+        let (model, _) = model_with_loan().await;
+        sqlx::query("UPDATE bank_loans SET accrued_at = datetime('now', '-2 days')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        let loan = model.repay_loan(1, None).await.unwrap();
+        assert_eq!(loan.status, LoanStatus::Repaid);
+        let owed = 100f64 * (1f64 + loan.rate).powi(2);
+        assert!((model.balance(1).await.unwrap() - (200f64 - owed)).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,401 @@
+use rand::Rng;
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{
+    Model, ModelError, ModelResult, SeedOwner, deposit, insert_gamble_outcome, insert_ledger_entry,
+    next_roll, withdraw,
+};
+use crate::config::LotteryConfig;
+
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+pub struct LotteryDraw {
+    pub draw_id: i64,
+    /// UTC datetime of the draw.
+    pub draw_time: String,
+    pub pot: f64,
+    pub tickets: i64,
+    pub winner_id: Option<u64>,
+    pub winning_ticket: Option<i64>,
+    pub prize: Option<f64>,
+    pub roll_id: Option<i64>,
+}
+
+/// Winning ticket of a draw, tickets are numbered from 1 in purchase order.
+pub fn lottery_ticket(tickets: i64, rng: &mut impl Rng) -> i64 {
+    rng.random_range(1..=tickets)
+}
+
+type DrawRow = (
+    i64,
+    String,
+    f64,
+    i64,
+    Option<String>,
+    Option<i64>,
+    Option<f64>,
+    Option<i64>,
+);
+
+fn draw_from_row(
+    (draw_id, draw_time, pot, tickets, winner_id, winning_ticket, prize, roll_id): DrawRow,
+) -> LotteryDraw {
+    LotteryDraw {
+        draw_id,
+        draw_time,
+        pot,
+        tickets,
+        winner_id: winner_id.and_then(|user_id| user_id.parse().ok()),
+        winning_ticket,
+        prize,
+        roll_id,
+    }
+}
+
+const SELECT_DRAW: &str = r#"
+    SELECT draw_id, draw_time, pot,
+        (SELECT COALESCE(SUM(tickets), 0) FROM lottery_tickets WHERE draw_id = d.draw_id),
+        winner_id, winning_ticket, prize, roll_id
+    FROM lottery_draws d
+    "#;
+
+/// SQLite modifiers to the next draw time from the start of today, and to
+/// the draw after that.
+fn schedule_modifiers(config: &LotteryConfig) -> ModelResult<(String, String, &'static str)> {
+    let invalid_schedule = || {
+        ModelError::InvalidValue(
+            "the lottery schedule is not configured, 'draw_day' must be a weekday or daily and 'draw_time' like 20:00.".into(),
+        )
+    };
+
+    let draw_day = config.draw_day.to_lowercase();
+    let (day, period) = match WEEKDAYS.iter().position(|weekday| *weekday == draw_day) {
+        Some(weekday) => (format!("weekday {}", weekday), "+7 days"),
+        None if draw_day == "daily" => ("+0 days".to_string(), "+1 days"),
+        None => return Err(invalid_schedule()),
+    };
+
+    let (hours, minutes) = config
+        .draw_time
+        .split_once(':')
+        .ok_or_else(invalid_schedule)?;
+    let (Ok(hours), Ok(minutes)) = (hours.parse::<u32>(), minutes.parse::<u32>()) else {
+        return Err(invalid_schedule());
+    };
+    if hours > 23 || minutes > 59 {
+        return Err(invalid_schedule());
+    }
+
+    Ok((day, format!("+{:02}:{:02}", hours, minutes), period))
+}
+
+/// Draw that is selling tickets, creating one for the next scheduled time if
+/// there is none.
+async fn open_draw(
+    conn: &mut SqliteConnection,
+    config: &LotteryConfig,
+) -> ModelResult<LotteryDraw> {
+    let query = format!(
+        "{} WHERE NOT drawn AND draw_time > CURRENT_TIMESTAMP ORDER BY draw_time LIMIT 1",
+        SELECT_DRAW
+    );
+    let draw: Option<DrawRow> = sqlx::query_as(&query).fetch_optional(&mut *conn).await?;
+    if let Some(draw) = draw {
+        return Ok(draw_from_row(draw));
+    }
+
+    let (day, time, period) = schedule_modifiers(config)?;
+    sqlx::query(
+        r#"
+        WITH next AS (SELECT datetime('now', 'start of day', $1, $2) AS draw_time)
+        INSERT INTO lottery_draws (draw_time)
+        SELECT CASE
+            WHEN draw_time > CURRENT_TIMESTAMP THEN draw_time
+            ELSE datetime(draw_time, $3)
+        END
+        FROM next
+        "#,
+    )
+    .bind(day)
+    .bind(time)
+    .bind(period)
+    .execute(&mut *conn)
+    .await?;
+
+    let draw: DrawRow = sqlx::query_as(&query).fetch_one(conn).await?;
+    Ok(draw_from_row(draw))
+}
+
+impl Model {
+    pub fn lottery_ticket_price(&self) -> f64 {
+        self.config.lottery.ticket_price
+    }
+
+    /// Channel lottery results are announced in.
+    pub fn lottery_channel(&self) -> Option<u64> {
+        self.config.lottery.channel_id
+    }
+
+    /// Buy tickets of the next draw, adding their price to the pot.
+    pub async fn lottery_buy(&self, user_id: u64, tickets: u32) -> ModelResult<LotteryDraw> {
+        if tickets == 0 {
+            return Err(ModelError::InvalidValue(
+                "'tickets' must be positive.".into(),
+            ));
+        }
+        let cost = tickets as f64 * self.config.lottery.ticket_price;
+        self.check_bet(user_id, cost).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        let draw = open_draw(&mut tx, &self.config.lottery).await?;
+        withdraw(&mut tx, user_id, cost).await?;
+        insert_ledger_entry(&mut tx, user_id, -cost, Some("lottery"), "Lottery tickets").await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO lottery_tickets (draw_id, user_id, tickets)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(draw.draw_id)
+        .bind(user_id.to_string())
+        .bind(tickets)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(r#"UPDATE lottery_draws SET pot = pot + $2 WHERE draw_id = $1"#)
+            .bind(draw.draw_id)
+            .bind(cost)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} bought {} lottery tickets for draw {}",
+            user_id, tickets, draw.draw_id
+        );
+        Ok(LotteryDraw {
+            pot: draw.pot + cost,
+            tickets: draw.tickets + tickets as i64,
+            ..draw
+        })
+    }
+
+    /// Next draw and the number of tickets `user_id` holds in it.
+    pub async fn lottery_info(&self, user_id: u64) -> ModelResult<(LotteryDraw, i64)> {
+        let mut conn = self.db_pool.acquire().await?;
+        let draw = open_draw(&mut conn, &self.config.lottery).await?;
+        let user_tickets = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(tickets), 0)
+            FROM lottery_tickets
+            WHERE draw_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(draw.draw_id)
+        .bind(user_id.to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok((draw, user_tickets))
+    }
+
+    /// Latest `limit` draws with a winner, newest first.
+    pub async fn lottery_history(&self, limit: u32) -> ModelResult<Vec<LotteryDraw>> {
+        let query = format!(
+            "{} WHERE drawn ORDER BY draw_time DESC LIMIT $1",
+            SELECT_DRAW
+        );
+        let draws: Vec<DrawRow> = sqlx::query_as(&query)
+            .bind(limit)
+            .fetch_all(&self.db_pool)
+            .await?;
+
+        Ok(draws.into_iter().map(draw_from_row).collect())
+    }
+
+    /// Scheduled job: draw the lotteries that reached their draw time. The
+    /// winner is picked by ticket with the house seed and paid the pot minus
+    /// the house cut. Draws without tickets are discarded.
+    pub async fn draw_lotteries(&self) -> ModelResult<Vec<LotteryDraw>> {
+        let draw_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+            SELECT draw_id FROM lottery_draws
+            WHERE NOT drawn AND draw_time <= CURRENT_TIMESTAMP
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut finished_draws = Vec::new();
+        for draw_id in draw_ids {
+            // Claim the draw before paying, so it is only drawn once
+            let mut tx = self.db_pool.begin().await?;
+            let res = sqlx::query(
+                r#"UPDATE lottery_draws SET drawn = TRUE WHERE draw_id = $1 AND NOT drawn"#,
+            )
+            .bind(draw_id)
+            .execute(&mut *tx)
+            .await?;
+            if res.rows_affected() == 0 {
+                continue;
+            }
+            let query = format!("{} WHERE draw_id = $1", SELECT_DRAW);
+            let row: DrawRow = sqlx::query_as(&query)
+                .bind(draw_id)
+                .fetch_one(&mut *tx)
+                .await?;
+            let draw = draw_from_row(row);
+            if draw.tickets == 0 {
+                sqlx::query(r#"DELETE FROM lottery_draws WHERE draw_id = $1"#)
+                    .bind(draw.draw_id)
+                    .execute(&mut *tx)
+                    .await?;
+                tx.commit().await?;
+                continue;
+            }
+
+            let mut roll = next_roll(&mut tx, SeedOwner::House).await?;
+            let winning_ticket = lottery_ticket(draw.tickets, &mut roll.rng);
+            let roll_id = roll
+                .record(
+                    &mut tx,
+                    None,
+                    "lottery",
+                    &draw.tickets.to_string(),
+                    &winning_ticket.to_string(),
+                )
+                .await?;
+
+            // Purchases own consecutive ticket numbers in the order they were made
+            let winner_id: String = sqlx::query_scalar(
+                r#"
+                SELECT user_id
+                FROM (
+                    SELECT user_id, SUM(tickets) OVER (ORDER BY purchase_id) AS last_ticket
+                    FROM lottery_tickets
+                    WHERE draw_id = $1
+                )
+                WHERE last_ticket >= $2
+                ORDER BY last_ticket
+                LIMIT 1
+                "#,
+            )
+            .bind(draw.draw_id)
+            .bind(winning_ticket)
+            .fetch_one(&mut *tx)
+            .await?;
+            let winner_id = winner_id.parse().map_err(|_| ModelError::UnexpectedError)?;
+
+            let prize = draw.pot * (1f64 - self.config.lottery.house_cut);
+            deposit(&mut tx, winner_id, prize).await?;
+            insert_ledger_entry(&mut tx, winner_id, prize, Some("lottery"), "Lottery prize")
+                .await?;
+
+            // Tickets of a draw are sold at the same price, so each player
+            // bet their share of the pot.
+            let players: Vec<(String, i64)> = sqlx::query_as(
+                r#"
+                SELECT user_id, SUM(tickets)
+                FROM lottery_tickets
+                WHERE draw_id = $1
+                GROUP BY user_id
+                "#,
+            )
+            .bind(draw.draw_id)
+            .fetch_all(&mut *tx)
+            .await?;
+            for (user_id, tickets) in players {
+                let user_id = user_id.parse().map_err(|_| ModelError::UnexpectedError)?;
+                let bet = draw.pot * tickets as f64 / draw.tickets as f64;
+                let payout = if user_id == winner_id { prize } else { 0f64 };
+                insert_gamble_outcome(&mut tx, user_id, "lottery", bet, payout).await?;
+            }
+            sqlx::query(
+                r#"
+                UPDATE lottery_draws
+                SET winner_id = $2, winning_ticket = $3, prize = $4, roll_id = $5
+                WHERE draw_id = $1
+                "#,
+            )
+            .bind(draw.draw_id)
+            .bind(winner_id.to_string())
+            .bind(winning_ticket)
+            .bind(prize)
+            .bind(roll_id)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            info!(
+                "Lottery draw {} won by user {} for {} euros",
+                draw.draw_id, winner_id, prize
+            );
+            finished_draws.push(LotteryDraw {
+                winner_id: Some(winner_id),
+                winning_ticket: Some(winning_ticket),
+                prize: Some(prize),
+                roll_id: Some(roll_id),
+                ..draw
+            });
+        }
+
+        Ok(finished_draws)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn lottery_is_drawn_once() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+        let draw = model.lottery_buy(1, 2).await.unwrap();
+        assert_eq!(draw.pot, 20f64);
+        sqlx::query("UPDATE lottery_draws SET draw_time = datetime('now', '-1 minute')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+
+        let (first, second) = tokio::join!(model.draw_lotteries(), model.draw_lotteries());
+        let mut draws = first.unwrap();
+        draws.extend(second.unwrap());
+        assert_eq!(draws.len(), 1);
+        assert_eq!(draws[0].winner_id, Some(1));
+        let prize = draws[0].prize.unwrap();
+        assert_eq!(model.balance(1).await.unwrap(), 80f64 + prize);
+        assert!(model.draw_lotteries().await.unwrap().is_empty());
+        assert_eq!(model.lottery_history(10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn lottery_without_tickets_is_discarded() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.lottery_info(1).await.unwrap();
+        sqlx::query("UPDATE lottery_draws SET draw_time = datetime('now', '-1 minute')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+
+        assert!(model.draw_lotteries().await.unwrap().is_empty());
+        assert!(model.lottery_history(10).await.unwrap().is_empty());
+        let draws: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM lottery_draws")
+            .fetch_one(&model.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(draws, 0);
+    }
+}
@@ -0,0 +1,503 @@
+use tracing::info;
+
+use super::{
+    Model, ModelError, ModelResult, deposit, insert_gamble_outcome, insert_ledger_entry, withdraw,
+};
+
+pub struct MarketOutcome {
+    pub outcome_id: i64,
+    pub name: String,
+    /// Euros bet on this outcome.
+    pub pool: f64,
+}
+
+/// Prediction market priced parimutuel: the bets on every outcome form a
+/// single pot shared by the winners in proportion to their bets.
+pub struct Market {
+    pub market_id: i64,
+    pub question: String,
+    pub creator_id: u64,
+    /// UTC datetime bets close at.
+    pub closes_at: String,
+    pub closed: bool,
+    pub resolved: bool,
+    /// None if the market was cancelled or is not resolved.
+    pub winning_outcome: Option<i64>,
+    pub outcomes: Vec<MarketOutcome>,
+}
+
+impl Market {
+    /// Euros bet on every outcome.
+    pub fn pool(&self) -> f64 {
+        self.outcomes.iter().map(|outcome| outcome.pool).sum()
+    }
+
+    /// Implied probability of an outcome, its share of the pot.
+    pub fn probability(&self, outcome: &MarketOutcome) -> f64 {
+        match self.pool() {
+            pool if pool > 0f64 => outcome.pool / pool,
+            _ => 1f64 / self.outcomes.len() as f64,
+        }
+    }
+
+    /// Find an outcome by name or by its number, starting at 1.
+    fn find_outcome(&self, outcome: &str) -> ModelResult<&MarketOutcome> {
+        let outcome = outcome.trim();
+        let by_number = outcome
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| self.outcomes.get(number.wrapping_sub(1)));
+        by_number
+            .or_else(|| {
+                self.outcomes
+                    .iter()
+                    .find(|market_outcome| market_outcome.name.eq_ignore_ascii_case(outcome))
+            })
+            .ok_or_else(|| {
+                ModelError::InvalidValue(format!(
+                    "market #{} has no outcome '{}'.",
+                    self.market_id, outcome
+                ))
+            })
+    }
+}
+
+/// A settled market and the euros paid to every user.
+pub struct MarketResolution {
+    pub market: Market,
+    pub payouts: Vec<(u64, f64)>,
+    /// Whether the bets were refunded because nobody bet on the outcome.
+    pub refunded: bool,
+}
+
+type MarketRow = (i64, String, String, String, bool, bool, Option<i64>);
+
+impl Model {
+    /// MOD: Create a market on `question` with at least two `outcomes`,
+    /// taking bets until `closes_at`, a UTC datetime (ie: 2025-01-31 18:00).
+    pub async fn create_market(
+        &self,
+        creator_id: u64,
+        question: &str,
+        outcomes: &[String],
+        closes_at: &str,
+    ) -> ModelResult<i64> {
+        if question.trim().is_empty() {
+            return Err(ModelError::InvalidValue(
+                "'question' can't be empty.".into(),
+            ));
+        }
+        if outcomes.len() < 2 || outcomes.iter().any(|outcome| outcome.is_empty()) {
+            return Err(ModelError::InvalidValue(
+                "a market needs at least two named outcomes.".into(),
+            ));
+        }
+        for (index, outcome) in outcomes.iter().enumerate() {
+            if outcomes[..index]
+                .iter()
+                .any(|other| other.eq_ignore_ascii_case(outcome))
+            {
+                return Err(ModelError::InvalidValue(format!(
+                    "outcome '{}' is repeated.",
+                    outcome
+                )));
+            }
+        }
+
+        // Normalize the datetime to the format used by CURRENT_TIMESTAMP so
+        // it can be compared.
+        let (closes_at, is_future): (Option<String>, Option<bool>) =
+            sqlx::query_as(r#"SELECT datetime($1), datetime($1) > CURRENT_TIMESTAMP"#)
+                .bind(closes_at)
+                .fetch_one(&self.db_pool)
+                .await?;
+        let (Some(closes_at), Some(is_future)) = (closes_at, is_future) else {
+            return Err(ModelError::InvalidValue(
+                "dates must be formatted as YYYY-MM-DD HH:MM.".into(),
+            ));
+        };
+        if !is_future {
+            return Err(ModelError::InvalidValue(
+                "'closes' must be in the future.".into(),
+            ));
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        let market_id = sqlx::query_scalar(
+            r#"
+            INSERT INTO markets (question, creator_id, closes_at)
+            VALUES ($1, $2, $3)
+            RETURNING market_id
+            "#,
+        )
+        .bind(question.trim())
+        .bind(creator_id.to_string())
+        .bind(closes_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        for outcome in outcomes {
+            sqlx::query(r#"INSERT INTO market_outcomes (market_id, name) VALUES ($1, $2)"#)
+                .bind(market_id)
+                .bind(outcome)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        info!("User {} created market {}", creator_id, market_id);
+        Ok(market_id)
+    }
+
+    pub async fn market(&self, market_id: i64) -> ModelResult<Market> {
+        let row: Option<MarketRow> = sqlx::query_as(
+            r#"
+            SELECT market_id, question, creator_id, closes_at,
+                resolved OR closes_at <= CURRENT_TIMESTAMP, resolved, winning_outcome
+            FROM markets
+            WHERE market_id = $1
+            "#,
+        )
+        .bind(market_id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+        let Some((market_id, question, creator_id, closes_at, closed, resolved, winning_outcome)) =
+            row
+        else {
+            return Err(ModelError::InvalidValue(format!(
+                "market #{} does not exist.",
+                market_id
+            )));
+        };
+
+        let outcomes: Vec<(i64, String, f64)> = sqlx::query_as(
+            r#"
+            SELECT o.outcome_id, o.name, COALESCE(SUM(b.amount), 0.0)
+            FROM market_outcomes o
+            LEFT JOIN market_bets b ON b.outcome_id = o.outcome_id
+            WHERE o.market_id = $1
+            GROUP BY o.outcome_id
+            ORDER BY o.outcome_id
+            "#,
+        )
+        .bind(market_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(Market {
+            market_id,
+            question,
+            creator_id: creator_id
+                .parse()
+                .map_err(|_| ModelError::UnexpectedError)?,
+            closes_at,
+            closed,
+            resolved,
+            winning_outcome,
+            outcomes: outcomes
+                .into_iter()
+                .map(|(outcome_id, name, pool)| MarketOutcome {
+                    outcome_id,
+                    name,
+                    pool,
+                })
+                .collect(),
+        })
+    }
+
+    /// Markets that are not resolved yet, by closing time.
+    pub async fn markets(&self) -> ModelResult<Vec<Market>> {
+        let market_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+            SELECT market_id
+            FROM markets
+            WHERE NOT resolved
+            ORDER BY closes_at
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut markets = Vec::new();
+        for market_id in market_ids {
+            markets.push(self.market(market_id).await?);
+        }
+
+        Ok(markets)
+    }
+
+    /// Bet `amount` euros on an outcome of an open market.
+    pub async fn market_bet(
+        &self,
+        user_id: u64,
+        market_id: i64,
+        outcome: &str,
+        amount: f64,
+    ) -> ModelResult<Market> {
+        let market = self.market(market_id).await?;
+        if market.closed {
+            return Err(ModelError::InvalidValue(format!(
+                "market #{} is closed for bets.",
+                market_id
+            )));
+        }
+        let outcome = market.find_outcome(outcome)?;
+        self.check_bet(user_id, amount).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, user_id, amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            -amount,
+            Some("market"),
+            &format!("Market #{} bet on {}", market_id, outcome.name),
+        )
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO market_bets (market_id, outcome_id, user_id, amount)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(market_id)
+        .bind(outcome.outcome_id)
+        .bind(user_id.to_string())
+        .bind(amount)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} bet {} euros on {} in market {}",
+            user_id, amount, outcome.name, market_id
+        );
+        self.market(market_id).await
+    }
+
+    /// MOD: Resolve a market to `outcome`, closing it if still open. The pot
+    /// minus the market fee is shared by the winners in proportion to their
+    /// bets, every bet is refunded if nobody bet on the outcome.
+    pub async fn resolve_market(
+        &self,
+        market_id: i64,
+        outcome: &str,
+    ) -> ModelResult<MarketResolution> {
+        let market = self.market(market_id).await?;
+        if market.resolved {
+            return Err(ModelError::InvalidValue(format!(
+                "market #{} is already resolved.",
+                market_id
+            )));
+        }
+        let winning_outcome = market.find_outcome(outcome)?.outcome_id;
+
+        let mut tx = self.db_pool.begin().await?;
+        // Claim the market first so a concurrent resolve or cancel pays nothing
+        let claimed = sqlx::query(
+            r#"
+            UPDATE markets SET resolved = TRUE, winning_outcome = $2
+            WHERE market_id = $1 AND NOT resolved
+            "#,
+        )
+        .bind(market_id)
+        .bind(winning_outcome)
+        .execute(&mut *tx)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue(format!(
+                "market #{} is already resolved.",
+                market_id
+            )));
+        }
+
+        // Pools are summed again in the transaction to include late bets
+        let (pot, winning_pool): (f64, f64) = sqlx::query_as(
+            r#"
+            SELECT
+                COALESCE(SUM(amount), 0.0),
+                COALESCE(SUM(CASE WHEN outcome_id = $2 THEN amount END), 0.0)
+            FROM market_bets
+            WHERE market_id = $1
+            "#,
+        )
+        .bind(market_id)
+        .bind(winning_outcome)
+        .fetch_one(&mut *tx)
+        .await?;
+        let refunded = winning_pool <= 0f64;
+        let bets: Vec<(String, f64)> = sqlx::query_as(
+            r#"
+            SELECT user_id, SUM(amount)
+            FROM market_bets
+            WHERE market_id = $1 AND ($2 OR outcome_id = $3)
+            GROUP BY user_id
+            "#,
+        )
+        .bind(market_id)
+        .bind(refunded)
+        .bind(winning_outcome)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut payouts = Vec::new();
+        for (user_id, amount) in bets {
+            let user_id = user_id
+                .parse::<u64>()
+                .map_err(|_| ModelError::UnexpectedError)?;
+            let payout = if refunded {
+                amount
+            } else {
+                pot * (1f64 - self.config.markets.fee) * amount / winning_pool
+            };
+            deposit(&mut tx, user_id, payout).await?;
+            insert_ledger_entry(
+                &mut tx,
+                user_id,
+                payout,
+                Some("market"),
+                &format!("Market #{} payout", market_id),
+            )
+            .await?;
+            payouts.push((user_id, payout));
+        }
+
+        // Refunded bets are neither won nor lost
+        if !refunded {
+            let bets: Vec<(String, f64)> = sqlx::query_as(
+                r#"
+                SELECT user_id, SUM(amount)
+                FROM market_bets
+                WHERE market_id = $1
+                GROUP BY user_id
+                "#,
+            )
+            .bind(market_id)
+            .fetch_all(&mut *tx)
+            .await?;
+            for (user_id, bet) in bets {
+                let user_id = user_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?;
+                let payout = payouts
+                    .iter()
+                    .find(|(winner_id, _)| *winner_id == user_id)
+                    .map_or(0f64, |(_, payout)| *payout);
+                insert_gamble_outcome(&mut tx, user_id, "market", bet, payout).await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        info!("Market {} resolved to {}", market_id, winning_outcome);
+        payouts.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(MarketResolution {
+            market: self.market(market_id).await?,
+            payouts,
+            refunded,
+        })
+    }
+
+    /// MOD: Cancel an unresolved market, refunding every bet.
+    pub async fn cancel_market(&self, market_id: i64) -> ModelResult<Market> {
+        let market = self.market(market_id).await?;
+        if market.resolved {
+            return Err(ModelError::InvalidValue(format!(
+                "market #{} is already resolved.",
+                market_id
+            )));
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        let claimed = sqlx::query(
+            r#"
+            UPDATE markets SET resolved = TRUE
+            WHERE market_id = $1 AND NOT resolved
+            "#,
+        )
+        .bind(market_id)
+        .execute(&mut *tx)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue(format!(
+                "market #{} is already resolved.",
+                market_id
+            )));
+        }
+
+        let bets: Vec<(String, f64)> = sqlx::query_as(
+            r#"
+            SELECT user_id, SUM(amount)
+            FROM market_bets
+            WHERE market_id = $1
+            GROUP BY user_id
+            "#,
+        )
+        .bind(market_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for (user_id, amount) in bets {
+            let user_id = user_id
+                .parse::<u64>()
+                .map_err(|_| ModelError::UnexpectedError)?;
+            deposit(&mut tx, user_id, amount).await?;
+            insert_ledger_entry(
+                &mut tx,
+                user_id,
+                amount,
+                Some("market"),
+                &format!("Market #{} refund", market_id),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!("Market {} cancelled", market_id);
+        self.market(market_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn market_with_bets() -> (Model, i64) {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        for user_id in [1, 2] {
+            model.create_bank_account(user_id).await.unwrap();
+            model.bless(user_id, 100f64).await.unwrap();
+        }
+        let outcomes = ["yes".to_string(), "no".to_string()];
+        let market_id = model
+            .create_market(1, "Will it rain?", &outcomes, "2999-01-01 00:00")
+            .await
+            .unwrap();
+        model.market_bet(1, market_id, "yes", 10f64).await.unwrap();
+        model.market_bet(2, market_id, "no", 30f64).await.unwrap();
+        (model, market_id)
+    }
+
+    #[tokio::test]
+    async fn market_is_resolved_once() {
+        let (model, market_id) = market_with_bets().await;
+
+        let resolution = model.resolve_market(market_id, "yes").await.unwrap();
+        assert!(!resolution.refunded);
+        assert_eq!(resolution.payouts, vec![(1, 40f64)]);
+        assert!(model.resolve_market(market_id, "yes").await.is_err());
+        assert!(model.cancel_market(market_id).await.is_err());
+        assert_eq!(model.balance(1).await.unwrap(), 130f64);
+        assert_eq!(model.balance(2).await.unwrap(), 70f64);
+    }
+
+    #[tokio::test]
+    async fn market_is_cancelled_once() {
+        let (model, market_id) = market_with_bets().await;
+
+        model.cancel_market(market_id).await.unwrap();
+        assert!(model.cancel_market(market_id).await.is_err());
+        assert!(model.resolve_market(market_id, "no").await.is_err());
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+        assert_eq!(model.balance(2).await.unwrap(), 100f64);
+    }
+}
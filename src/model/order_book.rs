@@ -0,0 +1,467 @@
+use sqlx::SqliteConnection;
+use tracing::trace;
+
+use super::{
+    Model, ModelError, ModelResult, cost_basis, deposit, insert_transaction, owned_coins, withdraw,
+};
+
+/// Amounts below this are considered fully filled.
+const DUST: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderSide {
+    Bid,
+    Ask,
+}
+
+impl OrderSide {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OrderSide::Bid => "bid",
+            OrderSide::Ask => "ask",
+        }
+    }
+
+    fn from_str(side: &str) -> ModelResult<Self> {
+        match side {
+            "bid" => Ok(OrderSide::Bid),
+            "ask" => Ok(OrderSide::Ask),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+}
+
+pub struct Order {
+    pub order_id: i64,
+    pub coin_symbol: String,
+    pub side: OrderSide,
+    pub price: f64,
+    pub amount: f64,
+}
+
+/// A match between the incoming order and a resting order.
+pub struct Fill {
+    pub maker_user_id: u64,
+    pub price: f64,
+    pub amount: f64,
+}
+
+pub struct PlacedOrder {
+    /// Id of the resting order, None if the order was completely filled.
+    pub order_id: Option<i64>,
+    pub fills: Vec<Fill>,
+    pub remaining: f64,
+    /// Own crossing orders cancelled to prevent trading with yourself.
+    pub cancelled: Vec<i64>,
+}
+
+/// Aggregated amount per price level, best prices first.
+pub struct OrderBookDepth {
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+impl Model {
+    /// Place a limit order in the internal order book. The order is matched
+    /// against crossing orders from other users first (price-time priority)
+    /// and whatever is left rests in the book with its funds or coins
+    /// escrowed. Crossing orders of the same user are cancelled instead of
+    /// matched. Everything happens in a single db transaction.
+    pub async fn place_order(
+        &self,
+        user_id: u64,
+        coin_symbol: &str,
+        side: OrderSide,
+        price: f64,
+        amount: f64,
+    ) -> ModelResult<PlacedOrder> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+        self.check_trading_allowed(user_id).await?;
+
+        if price <= 0f64 {
+            return Err(ModelError::InvalidValue("'price' must be positive.".into()));
+        }
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        // Indirectly checks if symbol is valid.
+        let coin_symbol = self.coin_info(coin_symbol).await?.symbol.to_uppercase();
+
+        let mut tx = self.db_pool.begin().await?;
+
+        // Escrow euros for bids and coins for asks.
+        match side {
+            OrderSide::Bid => {
+                withdraw(&mut tx, user_id, price * amount).await?;
+            }
+            OrderSide::Ask => {
+                if owned_coins(&mut tx, user_id, &coin_symbol).await? < amount {
+                    return Err(ModelError::InsuficientCoins);
+                }
+                let basis = cost_basis(&mut tx, user_id, &coin_symbol).await?;
+                insert_transaction(&mut tx, user_id, &coin_symbol, -amount, basis).await?;
+            }
+        }
+
+        let cancelled = cancel_crossing_orders(&mut tx, user_id, &coin_symbol, side, price).await?;
+        let fills = match_order(&mut tx, user_id, &coin_symbol, side, price, amount).await?;
+        let remaining = amount - fills.iter().map(|fill| fill.amount).sum::<f64>();
+
+        let order_id = if remaining > DUST {
+            let order_id = sqlx::query_scalar(
+                r#"
+                INSERT INTO orders (user_id, coin_symbol, side, price, amount)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING order_id
+                "#,
+            )
+            .bind(user_id.to_string())
+            .bind(&coin_symbol)
+            .bind(side.as_str())
+            .bind(price)
+            .bind(remaining)
+            .fetch_one(&mut *tx)
+            .await?;
+            Some(order_id)
+        } else {
+            // Return the escrow of a leftover too small to rest in the book.
+            if remaining > 0f64 {
+                refund_order(&mut tx, user_id, &coin_symbol, side, price, remaining).await?;
+            }
+            None
+        };
+
+        tx.commit().await?;
+
+        Ok(PlacedOrder {
+            order_id,
+            fills,
+            remaining: remaining.max(0f64),
+            cancelled,
+        })
+    }
+
+    /// Cancel an open order and return its escrowed funds or coins.
+    pub async fn cancel_order(&self, user_id: u64, order_id: i64) -> ModelResult<Order> {
+        let mut tx = self.db_pool.begin().await?;
+
+        let order_row: Option<(String, String, f64, f64)> = sqlx::query_as(
+            r#"
+            DELETE FROM orders
+            WHERE order_id = $1 AND user_id = $2
+            RETURNING coin_symbol, side, price, amount
+            "#,
+        )
+        .bind(order_id)
+        .bind(user_id.to_string())
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some((coin_symbol, side, price, amount)) = order_row else {
+            return Err(ModelError::InvalidValue("order does not exist.".into()));
+        };
+        let side = OrderSide::from_str(&side)?;
+
+        refund_order(&mut tx, user_id, &coin_symbol, side, price, amount).await?;
+
+        tx.commit().await?;
+
+        Ok(Order {
+            order_id,
+            coin_symbol,
+            side,
+            price,
+            amount,
+        })
+    }
+
+    /// Open orders of a user.
+    pub async fn user_orders(&self, user_id: u64) -> ModelResult<Vec<Order>> {
+        let orders_data: Vec<(i64, String, String, f64, f64)> = sqlx::query_as(
+            r#"
+            SELECT order_id, coin_symbol, side, price, amount
+            FROM orders
+            WHERE user_id = $1
+            ORDER BY order_id
+            "#,
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        orders_data
+            .into_iter()
+            .map(|(order_id, coin_symbol, side, price, amount)| {
+                Ok(Order {
+                    order_id,
+                    coin_symbol,
+                    side: OrderSide::from_str(&side)?,
+                    price,
+                    amount,
+                })
+            })
+            .collect()
+    }
+
+    /// Order book depth for `coin_symbol`, limited to `levels` price levels
+    /// on each side.
+    pub async fn order_book(&self, coin_symbol: &str, levels: u32) -> ModelResult<OrderBookDepth> {
+        let coin_symbol = coin_symbol.to_uppercase();
+
+        let bids = sqlx::query_as(
+            r#"
+            SELECT price, SUM(amount)
+            FROM orders
+            WHERE coin_symbol = $1 AND side = 'bid'
+            GROUP BY price
+            ORDER BY price DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(&coin_symbol)
+        .bind(levels)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let asks = sqlx::query_as(
+            r#"
+            SELECT price, SUM(amount)
+            FROM orders
+            WHERE coin_symbol = $1 AND side = 'ask'
+            GROUP BY price
+            ORDER BY price ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(&coin_symbol)
+        .bind(levels)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(OrderBookDepth { bids, asks })
+    }
+}
+
+/// Return the escrowed funds or coins of an order to its owner.
+async fn refund_order(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    coin_symbol: &str,
+    side: OrderSide,
+    price: f64,
+    amount: f64,
+) -> ModelResult<()> {
+    match side {
+        OrderSide::Bid => {
+            deposit(conn, user_id, price * amount).await?;
+        }
+        OrderSide::Ask => {
+            // Returning coins at the current average cost keeps the
+            // user's cost basis unchanged.
+            let basis = cost_basis(conn, user_id, coin_symbol).await?;
+            insert_transaction(conn, user_id, coin_symbol, amount, basis).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Cancel resting orders of `user_id` that would cross an incoming order of
+/// the same user and refund them. Returns the ids of the cancelled orders.
+async fn cancel_crossing_orders(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    coin_symbol: &str,
+    side: OrderSide,
+    price: f64,
+) -> ModelResult<Vec<i64>> {
+    let query = match side {
+        OrderSide::Bid => {
+            r#"
+            DELETE FROM orders
+            WHERE coin_symbol = $1 AND side = 'ask' AND user_id = $2 AND price <= $3
+            RETURNING order_id, side, price, amount
+            "#
+        }
+        OrderSide::Ask => {
+            r#"
+            DELETE FROM orders
+            WHERE coin_symbol = $1 AND side = 'bid' AND user_id = $2 AND price >= $3
+            RETURNING order_id, side, price, amount
+            "#
+        }
+    };
+    let cancelled_orders: Vec<(i64, String, f64, f64)> = sqlx::query_as(query)
+        .bind(coin_symbol)
+        .bind(user_id.to_string())
+        .bind(price)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut cancelled = Vec::new();
+    for (order_id, side, price, amount) in cancelled_orders {
+        trace!("Cancelling self crossing order {} of {}", order_id, user_id);
+        refund_order(
+            conn,
+            user_id,
+            coin_symbol,
+            OrderSide::from_str(&side)?,
+            price,
+            amount,
+        )
+        .await?;
+        cancelled.push(order_id);
+    }
+
+    Ok(cancelled)
+}
+
+/// Match an incoming order against resting orders of other users and settle
+/// every fill. Trades execute at the resting order's price.
+async fn match_order(
+    conn: &mut SqliteConnection,
+    taker_user_id: u64,
+    coin_symbol: &str,
+    side: OrderSide,
+    price: f64,
+    amount: f64,
+) -> ModelResult<Vec<Fill>> {
+    let query = match side {
+        OrderSide::Bid => {
+            r#"
+            SELECT order_id, user_id, price, amount
+            FROM orders
+            WHERE coin_symbol = $1 AND side = 'ask' AND user_id <> $2 AND price <= $3
+            ORDER BY price ASC, order_id ASC
+            "#
+        }
+        OrderSide::Ask => {
+            r#"
+            SELECT order_id, user_id, price, amount
+            FROM orders
+            WHERE coin_symbol = $1 AND side = 'bid' AND user_id <> $2 AND price >= $3
+            ORDER BY price DESC, order_id ASC
+            "#
+        }
+    };
+    let resting_orders: Vec<(i64, String, f64, f64)> = sqlx::query_as(query)
+        .bind(coin_symbol)
+        .bind(taker_user_id.to_string())
+        .bind(price)
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut fills = Vec::new();
+    let mut remaining = amount;
+    for (maker_order_id, maker_user_id, maker_price, maker_amount) in resting_orders {
+        if remaining <= DUST {
+            break;
+        }
+
+        let maker_user_id = maker_user_id
+            .parse::<u64>()
+            .map_err(|_| ModelError::UnexpectedError)?;
+        let fill_amount = remaining.min(maker_amount);
+        let (buyer_id, seller_id, maker_side) = match side {
+            OrderSide::Bid => (taker_user_id, maker_user_id, OrderSide::Ask),
+            OrderSide::Ask => (maker_user_id, taker_user_id, OrderSide::Bid),
+        };
+
+        trace!(
+            "Order book fill of {} {} at {} between {} and {}",
+            fill_amount, coin_symbol, maker_price, buyer_id, seller_id
+        );
+
+        // Both sides already have their part escrowed, so the buyer gets the
+        // coins and the seller gets the euros.
+        insert_transaction(conn, buyer_id, coin_symbol, fill_amount, maker_price).await?;
+        deposit(conn, seller_id, fill_amount * maker_price).await?;
+
+        // A bid taker escrowed at its own limit price, refund the difference.
+        if side == OrderSide::Bid && price > maker_price {
+            deposit(conn, taker_user_id, fill_amount * (price - maker_price)).await?;
+        }
+
+        if maker_amount - fill_amount > DUST {
+            sqlx::query(r#"UPDATE orders SET amount = amount - $2 WHERE order_id = $1"#)
+                .bind(maker_order_id)
+                .bind(fill_amount)
+                .execute(&mut *conn)
+                .await?;
+        } else {
+            // Return the escrow of a leftover too small to rest in the book,
+            // like the taker's.
+            let leftover = maker_amount - fill_amount;
+            if leftover > 0f64 {
+                refund_order(
+                    conn,
+                    maker_user_id,
+                    coin_symbol,
+                    maker_side,
+                    maker_price,
+                    leftover,
+                )
+                .await?;
+            }
+            sqlx::query(r#"DELETE FROM orders WHERE order_id = $1"#)
+                .bind(maker_order_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        remaining -= fill_amount;
+        fills.push(Fill {
+            maker_user_id,
+            price: maker_price,
+            amount: fill_amount,
+        });
+    }
+
+    Ok(fills)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn maker_dust_escrow_is_refunded() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.create_bank_account(2).await.unwrap();
+        // A resting bid of user 2, its euros already escrowed
+        let maker_amount = 1f64 + DUST / 2f64;
+        sqlx::query(
+            r#"
+            INSERT INTO orders (user_id, coin_symbol, side, price, amount)
+            VALUES ('2', 'BTC', 'bid', 10, $1)
+            "#,
+        )
+        .bind(maker_amount)
+        .execute(&model.db_pool)
+        .await
+        .unwrap();
+
+        let mut conn = model.db_pool.acquire().await.unwrap();
+        let fills = match_order(&mut conn, 1, "BTC", OrderSide::Ask, 10f64, 1f64)
+            .await
+            .unwrap();
+        drop(conn);
+        assert_eq!(fills.len(), 1);
+        let orders: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM orders")
+            .fetch_one(&model.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(orders, 0);
+        assert_eq!(model.balance(1).await.unwrap(), 10f64);
+        assert_eq!(
+            model.balance(2).await.unwrap(),
+            10f64 * (maker_amount - 1f64)
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::time::Duration;
+
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult, deposit, insert_ledger_entry, withdraw};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaymentRequestStatus {
+    Pending,
+    Paid,
+    Declined,
+    /// Not answered before the request timed out.
+    Expired,
+}
+
+impl PaymentRequestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentRequestStatus::Pending => "pending",
+            PaymentRequestStatus::Paid => "paid",
+            PaymentRequestStatus::Declined => "declined",
+            PaymentRequestStatus::Expired => "expired",
+        }
+    }
+
+    fn from_str(status: &str) -> ModelResult<Self> {
+        match status {
+            "pending" => Ok(PaymentRequestStatus::Pending),
+            "paid" => Ok(PaymentRequestStatus::Paid),
+            "declined" => Ok(PaymentRequestStatus::Declined),
+            "expired" => Ok(PaymentRequestStatus::Expired),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+}
+
+/// Euros `requester_id` asks `payer_id` to pay, transferred once the payer
+/// accepts.
+pub struct PaymentRequest {
+    pub request_id: i64,
+    pub requester_id: u64,
+    pub payer_id: u64,
+    pub amount: f64,
+    pub reason: String,
+    pub status: PaymentRequestStatus,
+}
+
+type PaymentRequestRow = (i64, String, String, f64, String, String);
+
+fn payment_request_from_row(
+    (request_id, requester_id, payer_id, amount, reason, status): PaymentRequestRow,
+) -> ModelResult<PaymentRequest> {
+    Ok(PaymentRequest {
+        request_id,
+        requester_id: requester_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        payer_id: payer_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        amount,
+        reason,
+        status: PaymentRequestStatus::from_str(&status)?,
+    })
+}
+
+const SELECT_PAYMENT_REQUEST: &str = r#"
+    SELECT request_id, requester_id, payer_id, amount, reason, status
+    FROM payment_requests
+    "#;
+
+impl Model {
+    /// How long payment requests wait to be answered.
+    pub fn payment_request_timeout(&self) -> Duration {
+        Duration::from_secs(self.config.payments.request_timeout_secs)
+    }
+
+    /// Ask `payer_id` to pay `amount` euros to `requester_id`.
+    pub async fn request_payment(
+        &self,
+        requester_id: u64,
+        payer_id: u64,
+        amount: f64,
+        reason: &str,
+    ) -> ModelResult<PaymentRequest> {
+        if requester_id == payer_id {
+            return Err(ModelError::InvalidValue(
+                "you can't request euros from yourself.".into(),
+            ));
+        }
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        // Check if requester and payer accounts exists
+        self.balance(requester_id).await?;
+        self.balance(payer_id).await?;
+
+        let row: PaymentRequestRow = sqlx::query_as(
+            r#"
+            INSERT INTO payment_requests (requester_id, payer_id, amount, reason)
+            VALUES ($1, $2, $3, $4)
+            RETURNING request_id, requester_id, payer_id, amount, reason, status
+            "#,
+        )
+        .bind(requester_id.to_string())
+        .bind(payer_id.to_string())
+        .bind(amount)
+        .bind(reason)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        payment_request_from_row(row)
+    }
+
+    /// Split a bill of `amount` euros evenly between `requester_id` and
+    /// `payer_ids`, requesting one share from every payer.
+    pub async fn split_bill(
+        &self,
+        requester_id: u64,
+        payer_ids: &[u64],
+        amount: f64,
+        reason: &str,
+    ) -> ModelResult<Vec<PaymentRequest>> {
+        let mut payer_ids = payer_ids
+            .iter()
+            .copied()
+            .filter(|payer_id| *payer_id != requester_id)
+            .collect::<Vec<_>>();
+        payer_ids.sort_unstable();
+        payer_ids.dedup();
+        if payer_ids.is_empty() {
+            return Err(ModelError::InvalidValue(
+                "mention at least one other user to split with.".into(),
+            ));
+        }
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        // Check if every account exists
+        self.balance(requester_id).await?;
+        for payer_id in &payer_ids {
+            self.balance(*payer_id).await?;
+        }
+
+        // The requester pays their own share
+        let share = amount / (payer_ids.len() + 1) as f64;
+        let mut tx = self.db_pool.begin().await?;
+        let split_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO bill_splits (requester_id, amount, reason)
+            VALUES ($1, $2, $3)
+            RETURNING split_id
+            "#,
+        )
+        .bind(requester_id.to_string())
+        .bind(amount)
+        .bind(reason)
+        .fetch_one(&mut *tx)
+        .await?;
+        for payer_id in &payer_ids {
+            sqlx::query(
+                r#"
+                INSERT INTO payment_requests (requester_id, payer_id, amount, reason, split_id)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+            )
+            .bind(requester_id.to_string())
+            .bind(payer_id.to_string())
+            .bind(share)
+            .bind(reason)
+            .bind(split_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        let query = format!(
+            "{} WHERE split_id = $1 ORDER BY request_id",
+            SELECT_PAYMENT_REQUEST
+        );
+        let rows: Vec<PaymentRequestRow> = sqlx::query_as(&query)
+            .bind(split_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} split a bill of {} euros with {} users",
+            requester_id,
+            amount,
+            payer_ids.len()
+        );
+        rows.into_iter().map(payment_request_from_row).collect()
+    }
+
+    /// Pay a pending request, transferring its euros from the payer to the
+    /// requester.
+    pub async fn pay_payment_request(&self, request_id: i64) -> ModelResult<PaymentRequest> {
+        let mut tx = self.db_pool.begin().await?;
+        let request =
+            answer_payment_request(&mut tx, request_id, PaymentRequestStatus::Paid).await?;
+        let description = format!("Payment request #{}", request.request_id);
+        withdraw(&mut tx, request.payer_id, request.amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            request.payer_id,
+            -request.amount,
+            None,
+            &description,
+        )
+        .await?;
+        deposit(&mut tx, request.requester_id, request.amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            request.requester_id,
+            request.amount,
+            None,
+            &description,
+        )
+        .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} paid request {} of {} euros to user {}",
+            request.payer_id, request.request_id, request.amount, request.requester_id
+        );
+        Ok(request)
+    }
+
+    /// Decline a pending request, or expire it if it timed out.
+    pub async fn close_payment_request(
+        &self,
+        request_id: i64,
+        status: PaymentRequestStatus,
+    ) -> ModelResult<PaymentRequest> {
+        let mut tx = self.db_pool.begin().await?;
+        let request = answer_payment_request(&mut tx, request_id, status).await?;
+        tx.commit().await?;
+
+        Ok(request)
+    }
+
+    /// Expire every pending request, to run when the bot starts and their
+    /// buttons can't be answered anymore.
+    pub async fn expire_pending_payment_requests(&self) -> ModelResult<Vec<PaymentRequest>> {
+        let rows: Vec<PaymentRequestRow> = sqlx::query_as(
+            r#"
+            UPDATE payment_requests SET status = 'expired'
+            WHERE status = 'pending'
+            RETURNING request_id, requester_id, payer_id, amount, reason, status
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        rows.into_iter().map(payment_request_from_row).collect()
+    }
+}
+
+/// Set the status of a request that is still pending, so it can only be
+/// answered once.
+async fn answer_payment_request(
+    conn: &mut SqliteConnection,
+    request_id: i64,
+    status: PaymentRequestStatus,
+) -> ModelResult<PaymentRequest> {
+    let row: Option<PaymentRequestRow> = sqlx::query_as(
+        r#"
+        UPDATE payment_requests SET status = $2
+        WHERE request_id = $1 AND status = 'pending'
+        RETURNING request_id, requester_id, payer_id, amount, reason, status
+        "#,
+    )
+    .bind(request_id)
+    .bind(status.as_str())
+    .fetch_optional(conn)
+    .await?;
+
+    match row {
+        Some(row) => payment_request_from_row(row),
+        None => Err(ModelError::InvalidValue(
+            "this request was already answered.".into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn payment_request_is_paid_once() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.create_bank_account(2).await.unwrap();
+        model.bless(2, 100f64).await.unwrap();
+
+        let request = model.request_payment(1, 2, 40f64, "Lunch").await.unwrap();
+        let paid = model.pay_payment_request(request.request_id).await.unwrap();
+        assert_eq!(paid.status, PaymentRequestStatus::Paid);
+        assert!(model.pay_payment_request(request.request_id).await.is_err());
+        assert_eq!(model.balance(1).await.unwrap(), 40f64);
+        assert_eq!(model.balance(2).await.unwrap(), 60f64);
+    }
+
+    #[tokio::test]
+    async fn pending_payment_requests_expire_on_restart() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.create_bank_account(2).await.unwrap();
+        model.create_bank_account(3).await.unwrap();
+        model.bless(2, 100f64).await.unwrap();
+
+        let paid = model.request_payment(1, 2, 10f64, "Paid").await.unwrap();
+        model.pay_payment_request(paid.request_id).await.unwrap();
+        let split = model
+            .split_bill(1, &[2, 3], 30f64, "Split bill")
+            .await
+            .unwrap();
+
+        let expired = model.expire_pending_payment_requests().await.unwrap();
+        assert_eq!(
+            expired.iter().map(|r| r.request_id).collect::<Vec<_>>(),
+            split.iter().map(|r| r.request_id).collect::<Vec<_>>()
+        );
+        assert!(
+            expired
+                .iter()
+                .all(|r| r.status == PaymentRequestStatus::Expired)
+        );
+        assert!(
+            model
+                .pay_payment_request(split[0].request_id)
+                .await
+                .is_err()
+        );
+        assert!(
+            model
+                .expire_pending_payment_requests()
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(model.balance(2).await.unwrap(), 90f64);
+    }
+}
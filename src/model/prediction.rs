@@ -0,0 +1,333 @@
+use tracing::{info, warn};
+
+use super::{
+    Model, ModelError, ModelResult, deposit, insert_gamble_outcome, insert_ledger_entry, withdraw,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceDirection {
+    Up,
+    Down,
+}
+
+impl PriceDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceDirection::Up => "up",
+            PriceDirection::Down => "down",
+        }
+    }
+
+    fn from_str(direction: &str) -> ModelResult<Self> {
+        match direction {
+            "up" => Ok(PriceDirection::Up),
+            "down" => Ok(PriceDirection::Down),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+}
+
+/// Bet on the price of a coin going up or down before `expires_at`.
+pub struct PricePrediction {
+    pub prediction_id: i64,
+    pub user_id: u64,
+    pub coin_symbol: String,
+    pub direction: PriceDirection,
+    pub amount: f64,
+    pub entry_price: f64,
+    /// UTC datetime the prediction is resolved at.
+    pub expires_at: String,
+    pub channel_id: u64,
+    pub exit_price: Option<f64>,
+    /// Euros paid back once resolved, the bet is refunded if the price
+    /// didn't move.
+    pub payout: Option<f64>,
+}
+
+/// Parse a duration like `90s`, `30m`, `1h`, `2d` or `1w` into seconds.
+pub fn parse_duration(duration: &str) -> ModelResult<u64> {
+    let invalid_duration = || {
+        ModelError::InvalidValue(
+            "durations must be a number followed by s, m, h, d or w (ie: 1h).".into(),
+        )
+    };
+
+    let duration = duration.trim().to_lowercase();
+    let unit_start = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid_duration)?;
+    let (amount, unit) = duration.split_at(unit_start);
+    let amount = amount.parse::<u64>().map_err(|_| invalid_duration())?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid_duration()),
+    };
+
+    amount
+        .checked_mul(unit_secs)
+        .filter(|secs| *secs > 0)
+        .ok_or_else(invalid_duration)
+}
+
+type PredictionRow = (
+    i64,
+    String,
+    String,
+    String,
+    f64,
+    f64,
+    String,
+    String,
+    Option<f64>,
+    Option<f64>,
+);
+
+fn prediction_from_row(row: PredictionRow) -> ModelResult<PricePrediction> {
+    let (
+        prediction_id,
+        user_id,
+        coin_symbol,
+        direction,
+        amount,
+        entry_price,
+        expires_at,
+        channel_id,
+        exit_price,
+        payout,
+    ) = row;
+    Ok(PricePrediction {
+        prediction_id,
+        user_id: user_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        coin_symbol,
+        direction: PriceDirection::from_str(&direction)?,
+        amount,
+        entry_price,
+        expires_at,
+        channel_id: channel_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        exit_price,
+        payout,
+    })
+}
+
+const SELECT_PREDICTION: &str = r#"
+    SELECT prediction_id, user_id, coin_symbol, direction, amount, entry_price, expires_at,
+        channel_id, exit_price, payout
+    FROM price_predictions
+    "#;
+
+impl Model {
+    /// Bet `amount` euros on the price of `coin_symbol` going up or down in
+    /// the next `duration_secs` seconds. The bet is resolved by the
+    /// scheduler, paying the configured multiplier if the prediction was
+    /// right.
+    pub async fn predict(
+        &self,
+        user_id: u64,
+        channel_id: u64,
+        coin_symbol: &str,
+        direction: PriceDirection,
+        amount: f64,
+        duration_secs: u64,
+    ) -> ModelResult<PricePrediction> {
+        let config = &self.config.predict;
+        if duration_secs < config.min_duration_secs || duration_secs > config.max_duration_secs {
+            return Err(ModelError::InvalidValue(format!(
+                "'duration' must be between {} and {} seconds.",
+                config.min_duration_secs, config.max_duration_secs
+            )));
+        }
+        self.check_bet(user_id, amount).await?;
+
+        // Server token prices move with their own trades, so a user could
+        // swap the pool to win their prediction.
+        let coin_symbol = coin_symbol.to_uppercase();
+        if self.server_token(&coin_symbol).await?.is_some() {
+            return Err(ModelError::InvalidValue(
+                "predictions are only open on market coins, not server tokens.".into(),
+            ));
+        }
+
+        // Price is fetched before the db transaction is open.
+        let entry_price = self.market_coin_info(&coin_symbol).await?.current_price;
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, user_id, amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            -amount,
+            Some("predict"),
+            &format!("Prediction on {} {}", coin_symbol, direction.as_str()),
+        )
+        .await?;
+        let prediction_id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO price_predictions (user_id, coin_symbol, direction, amount, entry_price, expires_at, channel_id)
+            VALUES ($1, $2, $3, $4, $5, datetime('now', $6), $7)
+            RETURNING prediction_id
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(&coin_symbol)
+        .bind(direction.as_str())
+        .bind(amount)
+        .bind(entry_price)
+        .bind(format!("+{} seconds", duration_secs))
+        .bind(channel_id.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let query = format!("{} WHERE prediction_id = $1", SELECT_PREDICTION);
+        let row: PredictionRow = sqlx::query_as(&query)
+            .bind(prediction_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} predicted {} {} with {} euros",
+            user_id,
+            coin_symbol,
+            direction.as_str(),
+            amount
+        );
+        prediction_from_row(row)
+    }
+
+    /// Scheduled job: resolve the predictions that expired against the
+    /// current price. Predictions whose price can't be fetched are retried
+    /// on the next run.
+    pub async fn resolve_predictions(&self) -> ModelResult<Vec<PricePrediction>> {
+        let query = format!(
+            "{} WHERE NOT resolved AND expires_at <= CURRENT_TIMESTAMP",
+            SELECT_PREDICTION
+        );
+        let rows: Vec<PredictionRow> = sqlx::query_as(&query).fetch_all(&self.db_pool).await?;
+
+        let mut resolved = Vec::new();
+        for row in rows {
+            let prediction = prediction_from_row(row)?;
+            let exit_price = match self.market_coin_info(&prediction.coin_symbol).await {
+                Ok(coin_info) => coin_info.current_price,
+                Err(error) => {
+                    warn!(
+                        "Could not price prediction {}: {}",
+                        prediction.prediction_id, error
+                    );
+                    continue;
+                }
+            };
+
+            let won = match prediction.direction {
+                PriceDirection::Up => exit_price > prediction.entry_price,
+                PriceDirection::Down => exit_price < prediction.entry_price,
+            };
+            let payout = if exit_price == prediction.entry_price {
+                prediction.amount
+            } else if won {
+                prediction.amount * self.config.predict.multiplier
+            } else {
+                0f64
+            };
+
+            let mut tx = self.db_pool.begin().await?;
+            if payout > 0f64 {
+                deposit(&mut tx, prediction.user_id, payout).await?;
+                insert_ledger_entry(
+                    &mut tx,
+                    prediction.user_id,
+                    payout,
+                    Some("predict"),
+                    &format!(
+                        "Prediction on {} {} payout",
+                        prediction.coin_symbol,
+                        prediction.direction.as_str()
+                    ),
+                )
+                .await?;
+            }
+            insert_gamble_outcome(
+                &mut tx,
+                prediction.user_id,
+                "predict",
+                prediction.amount,
+                payout,
+            )
+            .await?;
+            sqlx::query(
+                r#"
+                UPDATE price_predictions SET resolved = TRUE, exit_price = $2, payout = $3
+                WHERE prediction_id = $1
+                "#,
+            )
+            .bind(prediction.prediction_id)
+            .bind(exit_price)
+            .bind(payout)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            resolved.push(PricePrediction {
+                exit_price: Some(exit_price),
+                payout: Some(payout),
+                ..prediction
+            });
+        }
+
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90s").unwrap(), 90);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("1h").unwrap(), 60 * 60);
+        assert_eq!(parse_duration("2d").unwrap(), 2 * 24 * 60 * 60);
+        assert_eq!(parse_duration("1w").unwrap(), 7 * 24 * 60 * 60);
+        assert_eq!(parse_duration(" 2H ").unwrap(), 2 * 60 * 60);
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid() {
+        for duration in ["", "h", "10", "0m", "-1h", "1.5h", "1y", "1 h", "1hh"] {
+            assert!(parse_duration(duration).is_err(), "{}", duration);
+        }
+        // Overflowing durations are rejected instead of wrapping
+        assert!(parse_duration("18446744073709551615w").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+    }
+
+    #[tokio::test]
+    async fn predict_rejects_server_tokens() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO server_tokens (coin_symbol, name, euro_reserve, token_reserve, total_shares, guild_id)
+            VALUES ('BEN', 'Ben Coin', 100.0, 1000.0, 100.0, '1')
+            "#,
+        )
+        .execute(&model.db_pool)
+        .await
+        .unwrap();
+
+        let result = model
+            .predict(1, 10, "ben", PriceDirection::Up, 10f64, 60 * 60)
+            .await;
+        assert!(matches!(result, Err(ModelError::InvalidValue(_))));
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+    }
+}
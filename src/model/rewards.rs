@@ -0,0 +1,482 @@
+use chrono::{
+    DateTime, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult, insert_ledger_entry};
+use crate::config::RewardsConfig;
+
+/// A claimed daily reward and the streak of consecutive days it extended.
+pub struct DailyReward {
+    pub amount: f64,
+    pub streak: u32,
+    /// Whether a missed day reset the streak before this claim.
+    pub streak_reset: bool,
+}
+
+pub enum DailyClaim {
+    Claimed(DailyReward),
+    AlreadyClaimed,
+}
+
+/// Result of a daily claim and the seconds until the next one is available.
+pub struct DailyStatus {
+    pub claim: DailyClaim,
+    pub next_claim_secs: u64,
+}
+
+/// Days of a user start at `reset_time` in `timezone`. Users can pick their
+/// own timezone, the reset time is set per guild.
+pub struct DailySchedule {
+    pub timezone: Tz,
+    pub reset_time: NaiveTime,
+}
+
+impl DailySchedule {
+    /// Day `datetime` belongs to, days start at the reset time.
+    fn day_of(&self, datetime: DateTime<Utc>) -> NaiveDate {
+        let local = datetime.with_timezone(&self.timezone).naive_local();
+        (local - TimeDelta::seconds(self.reset_time.num_seconds_from_midnight() as i64)).date()
+    }
+
+    /// UTC datetime `day` starts at.
+    fn start_of(&self, day: NaiveDate) -> DateTime<Utc> {
+        let local = day.and_time(self.reset_time);
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            // Reset time skipped by a daylight saving change, start once the
+            // clocks jumped forward
+            .or_else(|| {
+                self.timezone
+                    .from_local_datetime(&(local + TimeDelta::hours(1)))
+                    .earliest()
+            })
+            .map_or_else(|| local.and_utc(), |start| start.with_timezone(&Utc))
+    }
+}
+
+/// Parse a timezone name from the tz database (ie: Europe/Lisbon).
+pub fn parse_timezone(timezone: &str) -> ModelResult<Tz> {
+    timezone.trim().parse().map_err(|_| {
+        ModelError::InvalidValue(format!(
+            "unknown timezone '{}', use a name like Europe/Lisbon.",
+            timezone
+        ))
+    })
+}
+
+/// Parse a time of day like 00:00 or 18:30.
+pub fn parse_reset_time(reset_time: &str) -> ModelResult<NaiveTime> {
+    NaiveTime::parse_from_str(reset_time.trim(), "%H:%M").map_err(|_| {
+        ModelError::InvalidValue("reset times must be formatted as HH:MM (ie: 00:00).".into())
+    })
+}
+
+/// Parse a datetime stored by SQLite, dates are read as midnight.
+fn parse_db_datetime(datetime: &str) -> ModelResult<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| {
+            NaiveDate::parse_from_str(datetime, "%Y-%m-%d")
+                .map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .map(|datetime| datetime.and_utc())
+        .map_err(|_| ModelError::UnexpectedError)
+}
+
+/// Bonus rewards that can be claimed once per calendar period.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RewardPeriod {
+    Weekly,
+    Monthly,
+}
+
+impl RewardPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RewardPeriod::Weekly => "weekly",
+            RewardPeriod::Monthly => "monthly",
+        }
+    }
+
+    /// Bank column with the time of the last claim.
+    fn column(&self) -> &'static str {
+        match self {
+            RewardPeriod::Weekly => "last_weekly",
+            RewardPeriod::Monthly => "last_monthly",
+        }
+    }
+
+    /// SQLite expression of the date the period of `datetime` starts on,
+    /// weeks start on monday.
+    fn start_of(&self, datetime: &str) -> String {
+        match self {
+            RewardPeriod::Weekly => format!("DATE({}, '-6 days', 'weekday 1')", datetime),
+            RewardPeriod::Monthly => format!("DATE({}, 'start of month')", datetime),
+        }
+    }
+}
+
+/// Euros paid by a daily claim on the `streak`th consecutive day.
+pub fn daily_reward(config: &RewardsConfig, streak: u32) -> f64 {
+    let bonus = config.streak_bonus * streak.saturating_sub(1) as f64;
+    (config.daily_amount + bonus).min(config.max_daily_amount)
+}
+
+impl Model {
+    /// Daily schedule of `user_id`: their own timezone if set, otherwise
+    /// the one of the guild, falling back to the config.
+    pub async fn daily_schedule(
+        &self,
+        user_id: u64,
+        guild_id: Option<u64>,
+    ) -> ModelResult<DailySchedule> {
+        let row: Option<(Option<String>, Option<String>, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT b.timezone, g.timezone, g.reset_time
+            FROM bank b
+            LEFT JOIN guild_settings g ON g.guild_id = $2
+            WHERE b.user_id = $1
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(guild_id.map(|guild_id| guild_id.to_string()))
+        .fetch_optional(&self.db_pool)
+        .await?;
+        let Some((user_timezone, guild_timezone, guild_reset_time)) = row else {
+            return Err(ModelError::BankAccountNotFound(user_id));
+        };
+
+        let config = &self.config.rewards;
+        let timezone = user_timezone
+            .or(guild_timezone)
+            .unwrap_or_else(|| config.timezone.clone());
+        let reset_time = guild_reset_time.unwrap_or_else(|| config.reset_time.clone());
+        Ok(DailySchedule {
+            timezone: parse_timezone(&timezone)?,
+            reset_time: parse_reset_time(&reset_time)?,
+        })
+    }
+
+    /// Set the timezone daily rewards of `user_id` reset in, None goes back
+    /// to the guild timezone.
+    pub async fn set_user_timezone(&self, user_id: u64, timezone: Option<&str>) -> ModelResult<()> {
+        let timezone = timezone
+            .map(|timezone| parse_timezone(timezone).map(|timezone| timezone.name()))
+            .transpose()?;
+        let res = sqlx::query(r#"UPDATE bank SET timezone = $2 WHERE user_id = $1"#)
+            .bind(user_id.to_string())
+            .bind(timezone)
+            .execute(&self.db_pool)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Err(ModelError::BankAccountNotFound(user_id));
+        }
+
+        info!("User {} set their timezone to {:?}", user_id, timezone);
+        Ok(())
+    }
+
+    /// ADMIN: Set the timezone and reset time of daily rewards in a guild,
+    /// None keeps the current setting.
+    pub async fn set_guild_schedule(
+        &self,
+        guild_id: u64,
+        timezone: Option<&str>,
+        reset_time: Option<&str>,
+    ) -> ModelResult<()> {
+        let timezone = timezone
+            .map(|timezone| parse_timezone(timezone).map(|timezone| timezone.name()))
+            .transpose()?;
+        let reset_time = reset_time
+            .map(|reset_time| {
+                parse_reset_time(reset_time).map(|time| time.format("%H:%M").to_string())
+            })
+            .transpose()?;
+        sqlx::query(
+            r#"
+            INSERT INTO guild_settings (guild_id, timezone, reset_time)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (guild_id) DO UPDATE SET
+                timezone = COALESCE(excluded.timezone, timezone),
+                reset_time = COALESCE(excluded.reset_time, reset_time)
+            "#,
+        )
+        .bind(guild_id.to_string())
+        .bind(timezone)
+        .bind(&reset_time)
+        .execute(&self.db_pool)
+        .await?;
+
+        info!(
+            "Guild {} daily schedule set to {:?} at {:?}",
+            guild_id, timezone, reset_time
+        );
+        Ok(())
+    }
+
+    /// Claim the daily reward, once per day of the user schedule. Claiming
+    /// on consecutive days grows the reward up to the configured cap.
+    pub async fn daily(&self, user_id: u64, guild_id: Option<u64>) -> ModelResult<DailyStatus> {
+        let schedule = self.daily_schedule(user_id, guild_id).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        let (last_daily, next_daily_at, streak): (String, Option<String>, u32) = sqlx::query_as(
+            r#"SELECT last_daily, next_daily_at, daily_streak FROM bank WHERE user_id = $1"#,
+        )
+        .bind(user_id.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+
+        // The next claim is fixed when claiming so changing the timezone
+        // can't open a new day early. Accounts that never claimed since
+        // fall back to the day of their last claim.
+        let now = Utc::now();
+        let today = schedule.day_of(now);
+        let next_daily_at = match next_daily_at {
+            Some(next_daily_at) => parse_db_datetime(&next_daily_at)?,
+            None => {
+                let last_day = schedule.day_of(parse_db_datetime(&last_daily)?);
+                schedule.start_of(last_day + Days::new(1))
+            }
+        };
+        if now < next_daily_at {
+            return Ok(DailyStatus {
+                claim: DailyClaim::AlreadyClaimed,
+                next_claim_secs: (next_daily_at - now).num_seconds().max(0) as u64,
+            });
+        }
+        let next_claim = schedule.start_of(today + Days::new(1));
+        let next_claim_secs = (next_claim - now).num_seconds().max(0) as u64;
+
+        // Claims on the next day, or within the grace window after it, keep
+        // the streak going
+        let grace = TimeDelta::hours(self.config.rewards.grace_hours as i64);
+        let keeps_streak = now <= next_daily_at + TimeDelta::days(1) + grace;
+        let streak_reset = streak > 0 && !keeps_streak;
+        let streak = if keeps_streak { streak + 1 } else { 1 };
+        let amount = daily_reward(&self.config.rewards, streak);
+
+        // Only the first of concurrent claims updates the row
+        let res = sqlx::query(
+            r#"
+            UPDATE bank
+            SET last_daily = $3,
+                daily_streak = $4,
+                balance = balance + $5,
+                next_daily_at = $6
+            WHERE user_id = $1 AND last_daily = $2
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(&last_daily)
+        .bind(now.format("%Y-%m-%d %H:%M:%S").to_string())
+        .bind(streak)
+        .bind(amount)
+        .bind(next_claim.format("%Y-%m-%d %H:%M:%S").to_string())
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Ok(DailyStatus {
+                claim: DailyClaim::AlreadyClaimed,
+                next_claim_secs,
+            });
+        }
+        insert_ledger_entry(&mut tx, user_id, amount, None, "Daily reward").await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} claimed the daily reward on a {} day streak",
+            user_id, streak
+        );
+        Ok(DailyStatus {
+            claim: DailyClaim::Claimed(DailyReward {
+                amount,
+                streak,
+                streak_reset,
+            }),
+            next_claim_secs,
+        })
+    }
+
+    /// Claim the weekly or monthly bonus. Returns the euros paid, or None if
+    /// it was already claimed this period.
+    pub async fn claim_bonus(
+        &self,
+        user_id: u64,
+        period: RewardPeriod,
+    ) -> ModelResult<Option<f64>> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let (amount, description) = match period {
+            RewardPeriod::Weekly => (self.config.rewards.weekly_amount, "Weekly bonus"),
+            RewardPeriod::Monthly => (self.config.rewards.monthly_amount, "Monthly bonus"),
+        };
+        let column = period.column();
+        let query = format!(
+            r#"
+            UPDATE bank
+            SET {} = CURRENT_TIMESTAMP,
+                balance = balance + $2
+            WHERE user_id = $1
+            AND ({} IS NULL OR {} <> {})
+            "#,
+            column,
+            column,
+            period.start_of(column),
+            period.start_of("'now'")
+        );
+
+        let mut tx = self.db_pool.begin().await?;
+        let res = sqlx::query(&query)
+            .bind(user_id.to_string())
+            .bind(amount)
+            .execute(&mut *tx)
+            .await?;
+        if res.rows_affected() == 0 {
+            return Ok(None);
+        }
+        insert_ledger_entry(&mut tx, user_id, amount, None, description).await?;
+        tx.commit().await?;
+
+        info!("User {} claimed the {} bonus", user_id, period.as_str());
+        Ok(Some(amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn schedule_in(timezone: &str, reset_time: &str) -> DailySchedule {
+        DailySchedule {
+            timezone: parse_timezone(timezone).unwrap(),
+            reset_time: parse_reset_time(reset_time).unwrap(),
+        }
+    }
+
+    fn utc(datetime: &str) -> DateTime<Utc> {
+        parse_db_datetime(datetime).unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn daily_reward_grows_with_the_streak() {
+        let config = RewardsConfig::default();
+        assert_eq!(daily_reward(&config, 1), 100f64);
+        assert_eq!(daily_reward(&config, 2), 110f64);
+        assert_eq!(daily_reward(&config, 10), 190f64);
+        // A reset streak pays like the first day
+        assert_eq!(daily_reward(&config, 0), 100f64);
+    }
+
+    #[test]
+    fn daily_reward_is_capped() {
+        let config = RewardsConfig::default();
+        assert_eq!(daily_reward(&config, 21), 300f64);
+        assert_eq!(daily_reward(&config, 1000), 300f64);
+        assert_eq!(daily_reward(&config, u32::MAX), 300f64);
+
+        let config = RewardsConfig {
+            daily_amount: 50.0,
+            streak_bonus: 0.0,
+            max_daily_amount: 300.0,
+            ..Default::default()
+        };
+        assert_eq!(daily_reward(&config, 30), 50f64);
+    }
+
+    #[test]
+    fn day_of_starts_at_the_reset_time() {
+        let schedule = schedule_in("UTC", "18:00");
+        assert_eq!(
+            schedule.day_of(utc("2024-05-10 17:59:59")),
+            date("2024-05-09")
+        );
+        assert_eq!(
+            schedule.day_of(utc("2024-05-10 18:00:00")),
+            date("2024-05-10")
+        );
+
+        // 16:00 UTC is already 01:00 of the next day in Tokyo
+        let schedule = schedule_in("Asia/Tokyo", "00:00");
+        assert_eq!(
+            schedule.day_of(utc("2024-05-10 14:59:59")),
+            date("2024-05-10")
+        );
+        assert_eq!(
+            schedule.day_of(utc("2024-05-10 16:00:00")),
+            date("2024-05-11")
+        );
+    }
+
+    #[test]
+    fn start_of_is_in_utc() {
+        let schedule = schedule_in("UTC", "18:00");
+        assert_eq!(
+            schedule.start_of(date("2024-05-10")),
+            utc("2024-05-10 18:00:00")
+        );
+
+        let schedule = schedule_in("Europe/Lisbon", "00:00");
+        assert_eq!(
+            schedule.start_of(date("2024-01-10")),
+            utc("2024-01-10 00:00:00")
+        );
+        assert_eq!(
+            schedule.start_of(date("2024-07-10")),
+            utc("2024-07-09 23:00:00")
+        );
+
+        for day in ["2024-01-10", "2024-07-10", "2024-03-31", "2024-10-27"] {
+            assert_eq!(schedule.day_of(schedule.start_of(date(day))), date(day));
+        }
+    }
+
+    #[test]
+    fn start_of_handles_daylight_saving() {
+        // 01:30 doesn't exist in Lisbon when the clocks jump to 02:00
+        let schedule = schedule_in("Europe/Lisbon", "01:30");
+        assert_eq!(
+            schedule.start_of(date("2024-03-31")),
+            utc("2024-03-31 01:30:00")
+        );
+        // and happens twice when they go back, the first one counts
+        assert_eq!(
+            schedule.start_of(date("2024-10-27")),
+            utc("2024-10-27 00:30:00")
+        );
+    }
+
+    #[tokio::test]
+    async fn timezone_change_keeps_the_next_claim() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+
+        let status = model.daily(1, None).await.unwrap();
+        assert!(matches!(status.claim, DailyClaim::Claimed(_)));
+        for timezone in ["Pacific/Pago_Pago", "Pacific/Kiritimati"] {
+            model.set_user_timezone(1, Some(timezone)).await.unwrap();
+            let status = model.daily(1, None).await.unwrap();
+            assert!(matches!(status.claim, DailyClaim::AlreadyClaimed));
+        }
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+
+        sqlx::query("UPDATE bank SET next_daily_at = datetime('now', '-1 hour')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+        let status = model.daily(1, None).await.unwrap();
+        let DailyClaim::Claimed(reward) = status.claim else {
+            panic!("daily reward was not claimed");
+        };
+        assert_eq!(reward.streak, 2);
+        assert_eq!(reward.amount, 110f64);
+    }
+}
@@ -0,0 +1,407 @@
+use std::fmt;
+
+use rand::Rng;
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{
+    Model, ModelError, ModelResult, SeedOwner, deposit, insert_gamble_outcome, insert_ledger_entry,
+    next_roll, withdraw,
+};
+
+const RED_NUMBERS: [u8; 18] = [
+    1, 3, 5, 7, 9, 12, 14, 16, 18, 19, 21, 23, 25, 27, 30, 32, 34, 36,
+];
+
+/// European roulette bets. Numbers go from 0 to 36 and dozens from 1 to 3.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RouletteBet {
+    Straight(u8),
+    Split(u8, u8),
+    Red,
+    Black,
+    Odd,
+    Even,
+    Dozen(u8),
+}
+
+fn is_red(number: u8) -> bool {
+    RED_NUMBERS.contains(&number)
+}
+
+/// Whether two numbers are next to each other on the table layout, where
+/// rows are 1-2-3, 4-5-6, ... and 0 touches the first row.
+fn is_adjacent(a: u8, b: u8) -> bool {
+    let (low, high) = (a.min(b), a.max(b));
+    match low {
+        0 => (1..=3).contains(&high),
+        _ => high - low == 3 || (high - low == 1 && low % 3 != 0),
+    }
+}
+
+impl RouletteBet {
+    /// Parse a bet like `17`, `17-20`, `red`, `black`, `odd`, `even` or
+    /// `dozen1` to `dozen3`.
+    pub fn parse(bet: &str) -> ModelResult<Self> {
+        let bet = bet.trim().to_lowercase();
+        let parse_number = |number: &str| match number.trim().parse::<u8>() {
+            Ok(number) if number <= 36 => Ok(number),
+            _ => Err(ModelError::InvalidValue(
+                "numbers must be between 0 and 36.".into(),
+            )),
+        };
+
+        let roulette_bet = match bet.as_str() {
+            "red" => RouletteBet::Red,
+            "black" => RouletteBet::Black,
+            "odd" => RouletteBet::Odd,
+            "even" => RouletteBet::Even,
+            "dozen1" | "1st12" => RouletteBet::Dozen(1),
+            "dozen2" | "2nd12" => RouletteBet::Dozen(2),
+            "dozen3" | "3rd12" => RouletteBet::Dozen(3),
+            _ => match bet.split_once(['-', '/']) {
+                Some((a, b)) => {
+                    let (a, b) = (parse_number(a)?, parse_number(b)?);
+                    if !is_adjacent(a, b) {
+                        return Err(ModelError::InvalidValue(
+                            "split numbers must be next to each other on the table.".into(),
+                        ));
+                    }
+                    RouletteBet::Split(a.min(b), a.max(b))
+                }
+                None if bet.chars().all(|c| c.is_ascii_digit()) => {
+                    RouletteBet::Straight(parse_number(&bet)?)
+                }
+                None => {
+                    return Err(ModelError::InvalidValue(
+                        "'bet' must be a number, a split (17-20), red, black, odd, even or dozen1 to dozen3.".into(),
+                    ));
+                }
+            },
+        };
+
+        Ok(roulette_bet)
+    }
+
+    /// Bet in the format accepted by `parse`.
+    fn to_record(self) -> String {
+        match self {
+            RouletteBet::Dozen(dozen) => format!("dozen{}", dozen),
+            bet => bet.to_string(),
+        }
+    }
+
+    /// Winnings per euro bet, not counting the returned bet.
+    fn payout(&self) -> f64 {
+        match self {
+            RouletteBet::Straight(_) => 35f64,
+            RouletteBet::Split(_, _) => 17f64,
+            RouletteBet::Dozen(_) => 2f64,
+            RouletteBet::Red | RouletteBet::Black | RouletteBet::Odd | RouletteBet::Even => 1f64,
+        }
+    }
+
+    fn wins(&self, number: u8) -> bool {
+        match *self {
+            RouletteBet::Straight(n) => number == n,
+            RouletteBet::Split(a, b) => number == a || number == b,
+            // Zero loses every outside bet
+            _ if number == 0 => false,
+            RouletteBet::Red => is_red(number),
+            RouletteBet::Black => !is_red(number),
+            RouletteBet::Odd => number % 2 == 1,
+            RouletteBet::Even => number.is_multiple_of(2),
+            RouletteBet::Dozen(dozen) => (number - 1) / 12 + 1 == dozen,
+        }
+    }
+}
+
+impl fmt::Display for RouletteBet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouletteBet::Straight(number) => write!(f, "{}", number),
+            RouletteBet::Split(a, b) => write!(f, "{}-{}", a, b),
+            RouletteBet::Red => write!(f, "red"),
+            RouletteBet::Black => write!(f, "black"),
+            RouletteBet::Odd => write!(f, "odd"),
+            RouletteBet::Even => write!(f, "even"),
+            RouletteBet::Dozen(dozen) => write!(f, "dozen {}", dozen),
+        }
+    }
+}
+
+pub struct RouletteWager {
+    pub user_id: u64,
+    pub bet: RouletteBet,
+    pub amount: f64,
+    /// Euros paid back including the bet, 0 if lost.
+    pub payout: f64,
+}
+
+/// Winning number of a round and every settled wager.
+pub struct RouletteSpin {
+    pub number: u8,
+    pub wagers: Vec<RouletteWager>,
+    /// Provably fair roll of the house seed the wheel was spun with.
+    pub roll_id: i64,
+    /// House seed nonce of the roll.
+    pub nonce: i64,
+}
+
+impl RouletteSpin {
+    pub fn color(&self) -> &'static str {
+        match self.number {
+            0 => "green",
+            number if is_red(number) => "red",
+            _ => "black",
+        }
+    }
+}
+
+/// Spin a European roulette wheel.
+pub fn roulette_number(rng: &mut impl Rng) -> u8 {
+    rng.random_range(0..=36)
+}
+
+impl Model {
+    /// Seconds a roulette round stays open for bets.
+    pub fn roulette_round_secs(&self) -> u64 {
+        self.config.roulette.round_secs
+    }
+
+    /// Place a bet on the open round of a channel, deducting it from the
+    /// user balance. Returns true if this bet opened a new round, which must
+    /// then be spun with `roulette_spin` once the round is over.
+    pub async fn roulette_bet(
+        &self,
+        channel_id: u64,
+        user_id: u64,
+        bet: RouletteBet,
+        amount: f64,
+    ) -> ModelResult<bool> {
+        self.check_bet(user_id, amount).await?;
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, user_id, amount).await?;
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            -amount,
+            Some("roulette"),
+            &format!("Roulette bet on {}", bet),
+        )
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO roulette_wagers (channel_id, user_id, bet, amount)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(channel_id.to_string())
+        .bind(user_id.to_string())
+        .bind(bet.to_record())
+        .bind(amount)
+        .execute(&mut *tx)
+        .await?;
+        let round_wagers: i64 =
+            sqlx::query_scalar(r#"SELECT COUNT(*) FROM roulette_wagers WHERE channel_id = $1"#)
+                .bind(channel_id.to_string())
+                .fetch_one(&mut *tx)
+                .await?;
+        tx.commit().await?;
+
+        info!(
+            "User {} bet {} euros on {} in roulette",
+            user_id, amount, bet
+        );
+
+        Ok(round_wagers == 1)
+    }
+
+    /// Close the round of a channel, spin the wheel and pay every winning
+    /// bet in a single transaction. Returns None if there was no open round.
+    pub async fn roulette_spin(&self, channel_id: u64) -> ModelResult<Option<RouletteSpin>> {
+        let mut tx = self.db_pool.begin().await?;
+        let mut wagers = take_wagers(&mut tx, Some(channel_id)).await?;
+        if wagers.is_empty() {
+            return Ok(None);
+        }
+
+        // Rounds are shared by every player so they spin with the house seed
+        let mut roll = next_roll(&mut tx, SeedOwner::House).await?;
+        let number = roulette_number(&mut roll.rng);
+        let roll_id = roll
+            .record(&mut tx, None, "roulette", "", &number.to_string())
+            .await?;
+        info!("Roulette in channel {} landed on {}", channel_id, number);
+
+        for wager in wagers.iter_mut() {
+            if wager.bet.wins(number) {
+                wager.payout = wager.amount * (wager.bet.payout() + 1f64);
+                deposit(&mut tx, wager.user_id, wager.payout).await?;
+                insert_ledger_entry(
+                    &mut tx,
+                    wager.user_id,
+                    wager.payout,
+                    Some("roulette"),
+                    &format!("Roulette payout on {}", wager.bet),
+                )
+                .await?;
+            }
+            insert_gamble_outcome(
+                &mut tx,
+                wager.user_id,
+                "roulette",
+                wager.amount,
+                wager.payout,
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(Some(RouletteSpin {
+            number,
+            wagers,
+            roll_id,
+            nonce: roll.nonce(),
+        }))
+    }
+
+    /// Refund the bets of rounds left unspun by a restart. Returns the
+    /// refunded wagers.
+    pub async fn refund_roulette_wagers(&self) -> ModelResult<Vec<RouletteWager>> {
+        let mut tx = self.db_pool.begin().await?;
+        let wagers = take_wagers(&mut tx, None).await?;
+        for wager in wagers.iter() {
+            deposit(&mut tx, wager.user_id, wager.amount).await?;
+            insert_ledger_entry(
+                &mut tx,
+                wager.user_id,
+                wager.amount,
+                Some("roulette"),
+                &format!("Roulette refund on {}", wager.bet),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(wagers)
+    }
+}
+
+/// Remove and return the wagers of the open round of `channel_id`, or of
+/// every channel if None.
+async fn take_wagers(
+    conn: &mut SqliteConnection,
+    channel_id: Option<u64>,
+) -> ModelResult<Vec<RouletteWager>> {
+    let mut wager_rows: Vec<(i64, String, String, f64)> = sqlx::query_as(
+        r#"
+        DELETE FROM roulette_wagers
+        WHERE $1 IS NULL OR channel_id = $1
+        RETURNING wager_id, user_id, bet, amount
+        "#,
+    )
+    .bind(channel_id.map(|channel_id| channel_id.to_string()))
+    .fetch_all(conn)
+    .await?;
+    // Keep the order the bets were placed in
+    wager_rows.sort_by_key(|(wager_id, ..)| *wager_id);
+
+    wager_rows
+        .into_iter()
+        .map(|(_, user_id, bet, amount)| {
+            Ok(RouletteWager {
+                user_id: user_id
+                    .parse::<u64>()
+                    .map_err(|_| ModelError::UnexpectedError)?,
+                bet: RouletteBet::parse(&bet)?,
+                amount,
+                payout: 0f64,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bets() {
+        assert_eq!(RouletteBet::parse("17").unwrap(), RouletteBet::Straight(17));
+        assert_eq!(
+            RouletteBet::parse("20-17").unwrap(),
+            RouletteBet::Split(17, 20)
+        );
+        assert_eq!(RouletteBet::parse("0/2").unwrap(), RouletteBet::Split(0, 2));
+        assert_eq!(RouletteBet::parse(" RED ").unwrap(), RouletteBet::Red);
+        assert_eq!(RouletteBet::parse("2nd12").unwrap(), RouletteBet::Dozen(2));
+        assert!(RouletteBet::parse("37").is_err());
+        assert!(RouletteBet::parse("3-4").is_err());
+        assert!(RouletteBet::parse("1-5").is_err());
+        assert!(RouletteBet::parse("green").is_err());
+    }
+
+    #[test]
+    fn records_parse_back() {
+        let bets = [
+            RouletteBet::Straight(0),
+            RouletteBet::Split(5, 8),
+            RouletteBet::Red,
+            RouletteBet::Black,
+            RouletteBet::Odd,
+            RouletteBet::Even,
+            RouletteBet::Dozen(3),
+        ];
+        for bet in bets {
+            assert_eq!(RouletteBet::parse(&bet.to_record()).unwrap(), bet);
+        }
+    }
+
+    #[test]
+    fn winning_numbers() {
+        assert!(RouletteBet::Straight(17).wins(17));
+        assert!(!RouletteBet::Straight(17).wins(18));
+        assert!(RouletteBet::Split(17, 20).wins(20));
+        assert!(RouletteBet::Red.wins(1));
+        assert!(RouletteBet::Black.wins(2));
+        assert!(RouletteBet::Odd.wins(35));
+        assert!(RouletteBet::Even.wins(36));
+        assert!(RouletteBet::Dozen(1).wins(12));
+        assert!(RouletteBet::Dozen(2).wins(13));
+        assert!(RouletteBet::Dozen(3).wins(36));
+        assert!(!RouletteBet::Dozen(3).wins(24));
+
+        // Zero only wins bets placed on it
+        assert!(RouletteBet::Straight(0).wins(0));
+        for bet in [
+            RouletteBet::Red,
+            RouletteBet::Black,
+            RouletteBet::Odd,
+            RouletteBet::Even,
+            RouletteBet::Dozen(1),
+        ] {
+            assert!(!bet.wins(0));
+        }
+    }
+
+    #[test]
+    fn every_bet_has_the_same_house_edge() {
+        // Expected return per euro on a single zero wheel is 36/37.
+        let bets = [
+            RouletteBet::Straight(7),
+            RouletteBet::Split(7, 8),
+            RouletteBet::Red,
+            RouletteBet::Odd,
+            RouletteBet::Dozen(2),
+        ];
+        for bet in bets {
+            let returned: f64 = (0..=36)
+                .filter(|number| bet.wins(*number))
+                .map(|_| bet.payout() + 1f64)
+                .sum();
+            assert!((returned / 37f64 - 36f64 / 37f64).abs() < 1e-12, "{}", bet);
+        }
+    }
+}
@@ -0,0 +1,675 @@
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult, deposit, insert_ledger_entry, withdraw};
+
+/// Item sold in the shop. Consumable items stack and are used up,
+/// permanent items can only be owned once, unless buying them again extends
+/// their timed role.
+pub struct ShopItem {
+    pub item_id: i64,
+    pub name: String,
+    pub description: String,
+    pub price: f64,
+    /// Items left to sell, None if unlimited.
+    pub stock: Option<i64>,
+    /// Discord role granted to buyers.
+    pub role_id: Option<u64>,
+    /// Seconds the role is granted for, None if forever.
+    pub duration_secs: Option<u64>,
+    pub consumable: bool,
+}
+
+/// Item owned by a user.
+pub struct InventoryItem {
+    pub item: ShopItem,
+    pub quantity: i64,
+}
+
+/// Timed role granted by a shop item.
+pub struct RoleGrant {
+    pub grant_id: i64,
+    pub user_id: u64,
+    pub guild_id: u64,
+    pub role_id: u64,
+    /// Channel the item was bought in, failures are reported there.
+    pub channel_id: u64,
+}
+
+type ShopItemRow = (
+    i64,
+    String,
+    String,
+    f64,
+    Option<i64>,
+    Option<String>,
+    Option<i64>,
+    bool,
+);
+
+type InventoryRow = (
+    i64,
+    String,
+    String,
+    f64,
+    Option<i64>,
+    Option<String>,
+    Option<i64>,
+    bool,
+    i64,
+);
+
+type RoleGrantRow = (i64, String, String, String, String);
+
+fn shop_item_from_row(
+    (item_id, name, description, price, stock, role_id, duration_secs, consumable): ShopItemRow,
+) -> ModelResult<ShopItem> {
+    Ok(ShopItem {
+        item_id,
+        name,
+        description,
+        price,
+        stock,
+        role_id: role_id
+            .map(|role_id| role_id.parse().map_err(|_| ModelError::UnexpectedError))
+            .transpose()?,
+        duration_secs: duration_secs.map(|duration_secs| duration_secs as u64),
+        consumable,
+    })
+}
+
+fn role_grant_from_row(
+    (grant_id, user_id, guild_id, role_id, channel_id): RoleGrantRow,
+) -> ModelResult<RoleGrant> {
+    Ok(RoleGrant {
+        grant_id,
+        user_id: user_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        guild_id: guild_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        role_id: role_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        channel_id: channel_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+    })
+}
+
+const SELECT_SHOP_ITEM: &str = r#"
+    SELECT item_id, name, description, price, stock, role_id, duration_secs, consumable
+    FROM shop_items
+    "#;
+
+impl Model {
+    /// Item for sale named `name`, ignoring case.
+    pub async fn shop_item(&self, name: &str) -> ModelResult<ShopItem> {
+        let query = format!(
+            "{} WHERE name = $1 COLLATE NOCASE AND active",
+            SELECT_SHOP_ITEM
+        );
+        let row: Option<ShopItemRow> = sqlx::query_as(&query)
+            .bind(name.trim())
+            .fetch_optional(&self.db_pool)
+            .await?;
+
+        match row {
+            Some(row) => shop_item_from_row(row),
+            None => Err(ModelError::InvalidValue(format!(
+                "there is no item named '{}' in the shop.",
+                name
+            ))),
+        }
+    }
+
+    /// Items for sale, cheapest first.
+    pub async fn shop_items(&self) -> ModelResult<Vec<ShopItem>> {
+        let query = format!("{} WHERE active ORDER BY price, name", SELECT_SHOP_ITEM);
+        let rows: Vec<ShopItemRow> = sqlx::query_as(&query).fetch_all(&self.db_pool).await?;
+
+        rows.into_iter().map(shop_item_from_row).collect()
+    }
+
+    /// ADMIN: Put a new item for sale.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_shop_item(
+        &self,
+        name: &str,
+        description: &str,
+        price: f64,
+        stock: Option<i64>,
+        role_id: Option<u64>,
+        duration_secs: Option<u64>,
+        consumable: bool,
+    ) -> ModelResult<ShopItem> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ModelError::InvalidValue("'name' can't be empty.".into()));
+        }
+        if price < 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'price' can't be negative.".into(),
+            ));
+        }
+        if stock.is_some_and(|stock| stock < 0) {
+            return Err(ModelError::InvalidValue(
+                "'stock' can't be negative.".into(),
+            ));
+        }
+        if duration_secs.is_some() && role_id.is_none() {
+            return Err(ModelError::InvalidValue(
+                "only items that grant a role can have a duration.".into(),
+            ));
+        }
+        if self.shop_item(name).await.is_ok() {
+            return Err(ModelError::InvalidValue(format!(
+                "there is already an item named '{}' in the shop.",
+                name
+            )));
+        }
+
+        let row: ShopItemRow = sqlx::query_as(
+            r#"
+            INSERT INTO shop_items (name, description, price, stock, role_id, duration_secs, consumable)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING item_id, name, description, price, stock, role_id, duration_secs, consumable
+            "#,
+        )
+        .bind(name)
+        .bind(description)
+        .bind(price)
+        .bind(stock)
+        .bind(role_id.map(|role_id| role_id.to_string()))
+        .bind(duration_secs.map(|duration_secs| duration_secs as i64))
+        .bind(consumable)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        info!("Added item '{}' to the shop", name);
+        shop_item_from_row(row)
+    }
+
+    /// ADMIN: Stop selling an item, users keep the ones they own.
+    pub async fn remove_shop_item(&self, name: &str) -> ModelResult<ShopItem> {
+        let item = self.shop_item(name).await?;
+        sqlx::query(r#"UPDATE shop_items SET active = FALSE WHERE item_id = $1"#)
+            .bind(item.item_id)
+            .execute(&self.db_pool)
+            .await?;
+
+        info!("Removed item '{}' from the shop", item.name);
+        Ok(item)
+    }
+
+    /// ADMIN: Set the stock of an item, None for unlimited.
+    pub async fn restock_shop_item(&self, name: &str, stock: Option<i64>) -> ModelResult<ShopItem> {
+        if stock.is_some_and(|stock| stock < 0) {
+            return Err(ModelError::InvalidValue(
+                "'stock' can't be negative.".into(),
+            ));
+        }
+        let item = self.shop_item(name).await?;
+        sqlx::query(r#"UPDATE shop_items SET stock = $2 WHERE item_id = $1"#)
+            .bind(item.item_id)
+            .bind(stock)
+            .execute(&self.db_pool)
+            .await?;
+
+        info!("Item '{}' stock set to {:?}", item.name, stock);
+        Ok(ShopItem { stock, ..item })
+    }
+
+    /// Buy `quantity` of an item. Returns the item and how many of it the
+    /// user owns now.
+    pub async fn buy_item(
+        &self,
+        user_id: u64,
+        name: &str,
+        quantity: i64,
+    ) -> ModelResult<(ShopItem, i64)> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        if quantity <= 0 {
+            return Err(ModelError::InvalidValue(
+                "'quantity' must be positive.".into(),
+            ));
+        }
+        let item = self.shop_item(name).await?;
+        let timed_role = item.role_id.is_some() && item.duration_secs.is_some();
+        let owned_once = !item.consumable && !timed_role;
+        let owned_once_error = || {
+            ModelError::InvalidValue(format!(
+                "'{}' is permanent, it can only be owned once.",
+                item.name
+            ))
+        };
+        if owned_once && quantity > 1 {
+            return Err(owned_once_error());
+        }
+
+        let mut tx = self.db_pool.begin().await?;
+        // Unlimited items have no stock to take from
+        let res = sqlx::query(
+            r#"
+            UPDATE shop_items SET stock = stock - $2
+            WHERE item_id = $1 AND stock >= $2
+            "#,
+        )
+        .bind(item.item_id)
+        .bind(quantity)
+        .execute(&mut *tx)
+        .await?;
+        if item.stock.is_some() && res.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue(format!(
+                "there are not enough '{}' left in stock.",
+                item.name
+            )));
+        }
+        let cost = item.price * quantity as f64;
+        if cost > 0f64 {
+            withdraw(&mut tx, user_id, cost).await?;
+            insert_ledger_entry(
+                &mut tx,
+                user_id,
+                -cost,
+                None,
+                &format!("Bought {} {}", quantity, item.name),
+            )
+            .await?;
+        }
+        // Items owned once are only added if the user has none yet
+        let owned: Option<i64> = sqlx::query_scalar(
+            r#"
+            INSERT INTO inventory (user_id, item_id, quantity)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, item_id) DO UPDATE SET quantity = quantity + excluded.quantity
+            WHERE NOT $4 OR inventory.quantity + excluded.quantity <= 1
+            RETURNING quantity
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(item.item_id)
+        .bind(quantity)
+        .bind(owned_once)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(owned) = owned else {
+            return Err(owned_once_error());
+        };
+        tx.commit().await?;
+
+        info!(
+            "User {} bought {} '{}' for {} euros",
+            user_id, quantity, item.name, cost
+        );
+        Ok((item, owned))
+    }
+
+    /// Items owned by `user_id`.
+    pub async fn inventory(&self, user_id: u64) -> ModelResult<Vec<InventoryItem>> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let rows: Vec<InventoryRow> = sqlx::query_as(
+                r#"
+                SELECT s.item_id, s.name, s.description, s.price, s.stock, s.role_id, s.duration_secs, s.consumable, i.quantity
+                FROM inventory i
+                JOIN shop_items s ON s.item_id = i.item_id
+                WHERE i.user_id = $1 AND i.quantity > 0
+                ORDER BY s.name
+                "#,
+            )
+            .bind(user_id.to_string())
+            .fetch_all(&self.db_pool)
+            .await?;
+
+        rows.into_iter()
+            .map(
+                |(
+                    item_id,
+                    name,
+                    description,
+                    price,
+                    stock,
+                    role_id,
+                    duration_secs,
+                    consumable,
+                    quantity,
+                )| {
+                    Ok(InventoryItem {
+                        item: shop_item_from_row((
+                            item_id,
+                            name,
+                            description,
+                            price,
+                            stock,
+                            role_id,
+                            duration_secs,
+                            consumable,
+                        ))?,
+                        quantity,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Use up one consumable item. Returns the item and how many are left.
+    pub async fn use_item(&self, user_id: u64, name: &str) -> ModelResult<(String, i64)> {
+        let row: Option<(String, i64)> = sqlx::query_as(
+            r#"
+            UPDATE inventory SET quantity = quantity - 1
+            WHERE user_id = $1 AND quantity > 0 AND item_id IN (
+                SELECT item_id FROM shop_items WHERE name = $2 COLLATE NOCASE AND consumable
+            )
+            RETURNING (SELECT name FROM shop_items s WHERE s.item_id = inventory.item_id), quantity
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(name.trim())
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        match row {
+            Some((name, quantity)) => {
+                info!("User {} used '{}'", user_id, name);
+                Ok((name, quantity))
+            }
+            None => Err(ModelError::InvalidValue(format!(
+                "you have no consumable item named '{}'.",
+                name
+            ))),
+        }
+    }
+
+    /// Undo a purchase whose role couldn't be granted, returning the euros,
+    /// the stock and the items.
+    pub async fn refund_item(&self, user_id: u64, item_id: i64, quantity: i64) -> ModelResult<()> {
+        let mut tx = self.db_pool.begin().await?;
+        let (name, price): (String, f64) =
+            sqlx::query_as(r#"SELECT name, price FROM shop_items WHERE item_id = $1"#)
+                .bind(item_id)
+                .fetch_one(&mut *tx)
+                .await?;
+        sqlx::query(
+            r#"
+            UPDATE inventory SET quantity = quantity - $3
+            WHERE user_id = $1 AND item_id = $2
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(item_id)
+        .bind(quantity)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"UPDATE shop_items SET stock = stock + $2 WHERE item_id = $1 AND stock IS NOT NULL"#,
+        )
+        .bind(item_id)
+        .bind(quantity)
+        .execute(&mut *tx)
+        .await?;
+        let cost = price * quantity as f64;
+        if cost > 0f64 {
+            deposit(&mut tx, user_id, cost).await?;
+            insert_ledger_entry(
+                &mut tx,
+                user_id,
+                cost,
+                None,
+                &format!("Refunded {} {}", quantity, name),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        info!("Refunded {} '{}' to user {}", quantity, name, user_id);
+        Ok(())
+    }
+
+    /// Record the role granted by a timed item so it can be removed when it
+    /// expires. Buying the role again while it is granted extends it, from
+    /// now if it already expired but couldn't be removed yet.
+    /// Returns the UTC datetime the role expires at.
+    pub async fn record_role_grant(
+        &self,
+        user_id: u64,
+        guild_id: u64,
+        channel_id: u64,
+        item: &ShopItem,
+        quantity: i64,
+    ) -> ModelResult<String> {
+        let (Some(role_id), Some(duration_secs)) = (item.role_id, item.duration_secs) else {
+            return Err(ModelError::InvalidValue(format!(
+                "'{}' doesn't grant a timed role.",
+                item.name
+            )));
+        };
+        let modifier = format!("+{} seconds", duration_secs as i64 * quantity);
+
+        let mut tx = self.db_pool.begin().await?;
+        let extended: Option<String> = sqlx::query_scalar(
+            r#"
+            UPDATE role_grants
+            SET expires_at = datetime(MAX(expires_at, CURRENT_TIMESTAMP), $4), removal_failed = FALSE
+            WHERE user_id = $1 AND guild_id = $2 AND role_id = $3 AND active
+            RETURNING expires_at
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(guild_id.to_string())
+        .bind(role_id.to_string())
+        .bind(&modifier)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let expires_at = match extended {
+            Some(expires_at) => expires_at,
+            None => {
+                sqlx::query_scalar(
+                    r#"
+                    INSERT INTO role_grants (user_id, guild_id, role_id, item_id, channel_id, expires_at)
+                    VALUES ($1, $2, $3, $4, $5, datetime('now', $6))
+                    RETURNING expires_at
+                    "#,
+                )
+                .bind(user_id.to_string())
+                .bind(guild_id.to_string())
+                .bind(role_id.to_string())
+                .bind(item.item_id)
+                .bind(channel_id.to_string())
+                .bind(&modifier)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+        };
+        tx.commit().await?;
+
+        info!(
+            "Role {} of user {} expires at {}",
+            role_id, user_id, expires_at
+        );
+        Ok(expires_at)
+    }
+
+    /// Scheduled job: role grants that expired, to remove from their users.
+    /// They stay active until `end_role_grant` is called, so failed removals
+    /// are retried.
+    pub async fn expired_role_grants(&self) -> ModelResult<Vec<RoleGrant>> {
+        let rows: Vec<RoleGrantRow> = sqlx::query_as(
+            r#"
+            SELECT grant_id, user_id, guild_id, role_id, channel_id
+            FROM role_grants
+            WHERE active AND expires_at <= CURRENT_TIMESTAMP
+            ORDER BY grant_id
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        rows.into_iter().map(role_grant_from_row).collect()
+    }
+
+    /// End a role grant once its role was removed, taking its item from the
+    /// inventory so it can be bought again. Grants extended in the meantime
+    /// are kept.
+    pub async fn end_role_grant(&self, grant_id: i64) -> ModelResult<()> {
+        let mut tx = self.db_pool.begin().await?;
+        let ended: Option<(String, i64)> = sqlx::query_as(
+            r#"
+            UPDATE role_grants SET active = FALSE
+            WHERE grant_id = $1 AND active AND expires_at <= CURRENT_TIMESTAMP
+            RETURNING user_id, item_id
+            "#,
+        )
+        .bind(grant_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some((user_id, item_id)) = ended {
+            sqlx::query(r#"DELETE FROM inventory WHERE user_id = $1 AND item_id = $2"#)
+                .bind(&user_id)
+                .bind(item_id)
+                .execute(&mut *tx)
+                .await?;
+            info!("Role grant {} of user {} ended", grant_id, user_id);
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Record that the role of a grant couldn't be removed. Returns true the
+    /// first time, so the failure is only reported once.
+    pub async fn fail_role_grant_removal(&self, grant_id: i64) -> ModelResult<bool> {
+        let res = sqlx::query(
+            r#"
+            UPDATE role_grants SET removal_failed = TRUE
+            WHERE grant_id = $1 AND NOT removal_failed
+            "#,
+        )
+        .bind(grant_id)
+        .execute(&self.db_pool)
+        .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn expire_grants(model: &Model) {
+        sqlx::query("UPDATE role_grants SET expires_at = datetime('now', '-1 hour')")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+    }
+
+    async fn timed_role_model() -> (Model, ShopItem) {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+        let item = model
+            .add_shop_item("VIP", "VIP role", 10f64, None, Some(5), Some(3600), false)
+            .await
+            .unwrap();
+        (model, item)
+    }
+
+    #[tokio::test]
+    async fn buying_timed_role_again_extends_it() {
+        let (model, item) = timed_role_model().await;
+
+        model.buy_item(1, "VIP", 1).await.unwrap();
+        let first = model.record_role_grant(1, 2, 3, &item, 1).await.unwrap();
+        let (_, owned) = model.buy_item(1, "VIP", 1).await.unwrap();
+        assert_eq!(owned, 2);
+        let second = model.record_role_grant(1, 2, 3, &item, 1).await.unwrap();
+        let extended: String = sqlx::query_scalar("SELECT datetime($1, '+3600 seconds')")
+            .bind(&first)
+            .fetch_one(&model.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(second, extended);
+        assert_eq!(model.balance(1).await.unwrap(), 80f64);
+
+        // Permanent items without a timed role are still owned once
+        model
+            .add_shop_item("Badge", "A badge", 1f64, None, None, None, false)
+            .await
+            .unwrap();
+        model.buy_item(1, "Badge", 1).await.unwrap();
+        assert!(model.buy_item(1, "Badge", 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn role_grant_ends_after_removal() {
+        let (model, item) = timed_role_model().await;
+
+        model.buy_item(1, "VIP", 1).await.unwrap();
+        model.record_role_grant(1, 2, 3, &item, 1).await.unwrap();
+        assert!(model.expired_role_grants().await.unwrap().is_empty());
+
+        expire_grants(&model).await;
+        let grants = model.expired_role_grants().await.unwrap();
+        assert_eq!(grants.len(), 1);
+        assert_eq!(grants[0].role_id, 5);
+
+        // A failed removal is retried and reported once
+        assert!(
+            model
+                .fail_role_grant_removal(grants[0].grant_id)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !model
+                .fail_role_grant_removal(grants[0].grant_id)
+                .await
+                .unwrap()
+        );
+        assert_eq!(model.expired_role_grants().await.unwrap().len(), 1);
+        assert_eq!(model.inventory(1).await.unwrap().len(), 1);
+
+        model.end_role_grant(grants[0].grant_id).await.unwrap();
+        assert!(model.expired_role_grants().await.unwrap().is_empty());
+        assert!(model.inventory(1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_role_grant_is_extended_from_now() {
+        let (model, item) = timed_role_model().await;
+
+        model.buy_item(1, "VIP", 1).await.unwrap();
+        model.record_role_grant(1, 2, 3, &item, 1).await.unwrap();
+        expire_grants(&model).await;
+        let grant_id = model.expired_role_grants().await.unwrap()[0].grant_id;
+        model.fail_role_grant_removal(grant_id).await.unwrap();
+
+        // Bought again before the role could be removed
+        model.buy_item(1, "VIP", 1).await.unwrap();
+        model.record_role_grant(1, 2, 3, &item, 1).await.unwrap();
+        assert!(model.expired_role_grants().await.unwrap().is_empty());
+        model.end_role_grant(grant_id).await.unwrap();
+        assert_eq!(model.inventory(1).await.unwrap().len(), 1);
+        // The next failure is reported again
+        assert!(model.fail_role_grant_removal(grant_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn permanent_item_is_bought_once() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+        model
+            .add_shop_item("Badge", "A badge", 10f64, None, None, None, false)
+            .await
+            .unwrap();
+
+        assert!(model.buy_item(1, "Badge", 2).await.is_err());
+        let (first, second) =
+            tokio::join!(model.buy_item(1, "Badge", 1), model.buy_item(1, "Badge", 1));
+        assert!(first.is_ok() != second.is_ok());
+        let items = model.inventory(1).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].quantity, 1);
+        assert_eq!(model.balance(1).await.unwrap(), 90f64);
+    }
+}
@@ -0,0 +1,286 @@
+use rand::Rng;
+use sqlx::SqliteConnection;
+use tracing::info;
+
+use super::{
+    Model, ModelError, ModelResult, SeedOwner, deposit, insert_gamble_outcome, insert_ledger_entry,
+    next_roll, withdraw,
+};
+use crate::config::SlotsConfig;
+
+/// Result of a slot machine spin. `payout` includes the jackpot if won.
+pub struct SlotsSpin {
+    pub symbols: Vec<String>,
+    pub payout: f64,
+    pub jackpot_won: Option<f64>,
+    /// Jackpot pool after the spin.
+    pub jackpot: f64,
+    pub roll_id: i64,
+}
+
+/// Returns of a simulated run per euro bet.
+pub struct SlotsSimulation {
+    pub spins: u64,
+    /// Share of bets paid back by the paytable.
+    pub paytable_rtp: f64,
+    /// Share of bets added to the jackpot pool, which is eventually paid back.
+    pub jackpot_rtp: f64,
+    /// Share of bets paid back by the seed the jackpot restarts from after
+    /// every win, which depends on the bet size.
+    pub seed_rtp: f64,
+    /// Share of spins that pay something.
+    pub hit_frequency: f64,
+    pub jackpot_hits: u64,
+}
+
+impl SlotsSimulation {
+    pub fn rtp(&self) -> f64 {
+        self.paytable_rtp + self.jackpot_rtp + self.seed_rtp
+    }
+}
+
+/// Stop position of each reel given their lengths.
+pub fn slot_stops(reel_lengths: &[usize], rng: &mut impl Rng) -> Vec<usize> {
+    reel_lengths
+        .iter()
+        .map(|length| rng.random_range(0..*length))
+        .collect()
+}
+
+fn reel_lengths(config: &SlotsConfig) -> ModelResult<Vec<usize>> {
+    if config.reels.is_empty() || config.reels.iter().any(|reel| reel.is_empty()) {
+        return Err(ModelError::InvalidValue(
+            "the slot machine reels are not configured.".into(),
+        ));
+    }
+
+    Ok(config.reels.iter().map(Vec::len).collect())
+}
+
+/// Symbols shown by the reels stopped at `stops`.
+fn reel_symbols<'a>(config: &'a SlotsConfig, stops: &[usize]) -> Vec<&'a str> {
+    config
+        .reels
+        .iter()
+        .zip(stops)
+        .map(|(reel, stop)| reel[*stop].as_str())
+        .collect()
+}
+
+/// Euros paid per euro bet by the first matching payline.
+fn line_multiplier(config: &SlotsConfig, symbols: &[&str]) -> f64 {
+    config
+        .paytable
+        .iter()
+        .find(|payline| {
+            payline.symbols.len() == symbols.len()
+                && payline
+                    .symbols
+                    .iter()
+                    .zip(symbols)
+                    .all(|(expected, symbol)| expected == "*" || expected == symbol)
+        })
+        .map_or(0f64, |payline| payline.multiplier)
+}
+
+fn is_jackpot(config: &SlotsConfig, symbols: &[&str]) -> bool {
+    symbols
+        .iter()
+        .all(|symbol| *symbol == config.jackpot_symbol)
+}
+
+/// Chance of showing the jackpot symbol on every reel.
+fn jackpot_probability(config: &SlotsConfig) -> f64 {
+    config
+        .reels
+        .iter()
+        .map(|reel| {
+            let jackpot_stops = reel
+                .iter()
+                .filter(|symbol| **symbol == config.jackpot_symbol)
+                .count();
+            jackpot_stops as f64 / reel.len() as f64
+        })
+        .product()
+}
+
+/// Spin the configured machine `spins` times betting `bet` euros to measure
+/// its return to player.
+pub fn simulate_slots(config: &SlotsConfig, spins: u64, bet: f64) -> ModelResult<SlotsSimulation> {
+    if spins == 0 {
+        return Err(ModelError::InvalidValue("'spins' must be positive.".into()));
+    }
+    if bet <= 0f64 {
+        return Err(ModelError::InvalidValue("'bet' must be positive.".into()));
+    }
+
+    let reel_lengths = reel_lengths(config)?;
+    let mut rng = rand::rng();
+    let mut paid = 0f64;
+    let mut contributed = 0f64;
+    let mut hits = 0u64;
+    let mut jackpot_hits = 0u64;
+    for _ in 0..spins {
+        let symbols = reel_symbols(config, &slot_stops(&reel_lengths, &mut rng));
+        let multiplier = line_multiplier(config, &symbols);
+        if multiplier > 0f64 {
+            hits += 1;
+        }
+        if is_jackpot(config, &symbols) {
+            jackpot_hits += 1;
+        } else {
+            contributed += (1f64 - multiplier).max(0f64) * config.jackpot_share;
+        }
+        paid += multiplier;
+    }
+
+    Ok(SlotsSimulation {
+        spins,
+        paytable_rtp: paid / spins as f64,
+        jackpot_rtp: contributed / spins as f64,
+        // Every jackpot win is paid on top of the contributions with the
+        // seed the house puts back in the pool.
+        seed_rtp: jackpot_probability(config) * config.jackpot_seed / bet,
+        hit_frequency: hits as f64 / spins as f64,
+        jackpot_hits,
+    })
+}
+
+/// Current slots jackpot, creating it from the seed if it doesn't exist.
+async fn slots_jackpot(conn: &mut SqliteConnection, seed: f64) -> ModelResult<f64> {
+    sqlx::query(
+        r#"
+        INSERT OR IGNORE INTO jackpots (game, amount)
+        VALUES ('slots', $1)
+        "#,
+    )
+    .bind(seed)
+    .execute(&mut *conn)
+    .await?;
+
+    let jackpot = sqlx::query_scalar(r#"SELECT amount FROM jackpots WHERE game = 'slots'"#)
+        .fetch_one(conn)
+        .await?;
+
+    Ok(jackpot)
+}
+
+async fn set_slots_jackpot(conn: &mut SqliteConnection, amount: f64) -> ModelResult<()> {
+    sqlx::query(r#"UPDATE jackpots SET amount = $1 WHERE game = 'slots'"#)
+        .bind(amount)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+impl Model {
+    /// Spin the slot machine betting `bet` euros. Lost bets feed the
+    /// progressive jackpot, which is won by showing the jackpot symbol on
+    /// every reel.
+    pub async fn slots_spin(&self, user_id: u64, bet: f64) -> ModelResult<SlotsSpin> {
+        self.check_bet(user_id, bet).await?;
+
+        let config = &self.config.slots;
+        let reel_lengths = reel_lengths(config)?;
+
+        let mut tx = self.db_pool.begin().await?;
+        withdraw(&mut tx, user_id, bet).await?;
+        insert_ledger_entry(&mut tx, user_id, -bet, Some("slots"), "Slots bet").await?;
+
+        let mut roll = next_roll(&mut tx, SeedOwner::User(user_id)).await?;
+        let stops = slot_stops(&reel_lengths, &mut roll.rng);
+        let join = |values: &[usize]| {
+            let values: Vec<String> = values.iter().map(usize::to_string).collect();
+            values.join(",")
+        };
+        let roll_id = roll
+            .record(
+                &mut tx,
+                Some(user_id),
+                "slots",
+                &join(&reel_lengths),
+                &join(&stops),
+            )
+            .await?;
+        let symbols = reel_symbols(config, &stops);
+        let mut payout = bet * line_multiplier(config, &symbols);
+
+        let mut jackpot = slots_jackpot(&mut tx, config.jackpot_seed).await?;
+        let jackpot_won = if is_jackpot(config, &symbols) {
+            info!(
+                "User {} won the slots jackpot of {} euros",
+                user_id, jackpot
+            );
+            let jackpot_won = jackpot;
+            payout += jackpot_won;
+            jackpot = config.jackpot_seed;
+            Some(jackpot_won)
+        } else {
+            jackpot += (bet - payout).max(0f64) * config.jackpot_share;
+            None
+        };
+        set_slots_jackpot(&mut tx, jackpot).await?;
+
+        if payout > 0f64 {
+            deposit(&mut tx, user_id, payout).await?;
+            insert_ledger_entry(&mut tx, user_id, payout, Some("slots"), "Slots payout").await?;
+        }
+        insert_gamble_outcome(&mut tx, user_id, "slots", bet, payout).await?;
+        tx.commit().await?;
+
+        Ok(SlotsSpin {
+            symbols: symbols.into_iter().map(String::from).collect(),
+            payout,
+            jackpot_won,
+            jackpot,
+            roll_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SlotsPayline;
+
+    fn config() -> SlotsConfig {
+        let strip = vec!["💎".to_string(), "🍒".to_string()];
+        SlotsConfig {
+            reels: vec![strip.clone(), strip],
+            paytable: vec![SlotsPayline {
+                symbols: vec!["🍒".into(), "*".into()],
+                multiplier: 1f64,
+            }],
+            jackpot_symbol: "💎".into(),
+            jackpot_seed: 100f64,
+            jackpot_share: 0.5f64,
+            target_rtp: 0f64,
+        }
+    }
+
+    #[test]
+    fn paylines_match_wildcards() {
+        let config = config();
+
+        assert_eq!(line_multiplier(&config, &["🍒", "💎"]), 1f64);
+        assert_eq!(line_multiplier(&config, &["💎", "🍒"]), 0f64);
+        assert!(is_jackpot(&config, &["💎", "💎"]));
+        assert!(!is_jackpot(&config, &["💎", "🍒"]));
+    }
+
+    #[test]
+    fn seed_is_part_of_the_rtp() {
+        let config = config();
+        assert_eq!(jackpot_probability(&config), 0.25f64);
+
+        let simulation = simulate_slots(&config, 1000, 10f64).unwrap();
+        // A quarter of the spins win the 100 euros seed on a 10 euros bet.
+        assert_eq!(simulation.seed_rtp, 2.5f64);
+        assert_eq!(
+            simulation.rtp(),
+            simulation.paytable_rtp + simulation.jackpot_rtp + 2.5f64
+        );
+        assert!(simulate_slots(&config, 1000, 0f64).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult};
+
+/// How often a standing order pays its recipient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl OrderFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderFrequency::Daily => "daily",
+            OrderFrequency::Weekly => "weekly",
+            OrderFrequency::Monthly => "monthly",
+        }
+    }
+
+    fn from_str(frequency: &str) -> ModelResult<Self> {
+        match frequency {
+            "daily" => Ok(OrderFrequency::Daily),
+            "weekly" => Ok(OrderFrequency::Weekly),
+            "monthly" => Ok(OrderFrequency::Monthly),
+            _ => Err(ModelError::UnexpectedError),
+        }
+    }
+
+    /// SQLite datetime modifier of one period.
+    fn modifier(&self) -> &'static str {
+        match self {
+            OrderFrequency::Daily => "+1 day",
+            OrderFrequency::Weekly => "+7 days",
+            OrderFrequency::Monthly => "+1 month",
+        }
+    }
+}
+
+/// Recurring transfer of `amount` euros from `user_id` to `recipient_id`.
+pub struct StandingOrder {
+    pub order_id: i64,
+    pub user_id: u64,
+    pub recipient_id: u64,
+    pub amount: f64,
+    pub frequency: OrderFrequency,
+    /// UTC datetime of the next payment.
+    pub next_run_at: String,
+    /// Channel the order was created in, failures are reported there.
+    pub channel_id: u64,
+}
+
+/// Payment of a standing order made by the scheduler, `error` is set if
+/// it failed.
+pub struct StandingOrderRun {
+    pub order: StandingOrder,
+    pub error: Option<ModelError>,
+}
+
+type StandingOrderRow = (i64, String, String, f64, String, String, String);
+
+fn standing_order_from_row(
+    (order_id, user_id, recipient_id, amount, frequency, next_run_at, channel_id): StandingOrderRow,
+) -> ModelResult<StandingOrder> {
+    Ok(StandingOrder {
+        order_id,
+        user_id: user_id.parse().map_err(|_| ModelError::UnexpectedError)?,
+        recipient_id: recipient_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        amount,
+        frequency: OrderFrequency::from_str(&frequency)?,
+        next_run_at,
+        channel_id: channel_id
+            .parse()
+            .map_err(|_| ModelError::UnexpectedError)?,
+    })
+}
+
+const SELECT_STANDING_ORDER: &str = r#"
+    SELECT order_id, user_id, recipient_id, amount, frequency, next_run_at, channel_id
+    FROM standing_orders
+    "#;
+
+impl Model {
+    /// Pay `amount` euros to `recipient_id` every period, starting one
+    /// period from now.
+    pub async fn create_standing_order(
+        &self,
+        user_id: u64,
+        recipient_id: u64,
+        amount: f64,
+        frequency: OrderFrequency,
+        channel_id: u64,
+    ) -> ModelResult<StandingOrder> {
+        if user_id == recipient_id {
+            return Err(ModelError::InvalidValue("you can't pay yourself.".into()));
+        }
+        if amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+        // Check if user and recipient accounts exists
+        self.balance(user_id).await?;
+        self.balance(recipient_id).await?;
+
+        let row: StandingOrderRow = sqlx::query_as(
+            r#"
+            INSERT INTO standing_orders (user_id, recipient_id, amount, frequency, next_run_at, channel_id)
+            VALUES ($1, $2, $3, $4, datetime('now', $5), $6)
+            RETURNING order_id, user_id, recipient_id, amount, frequency, next_run_at, channel_id
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(recipient_id.to_string())
+        .bind(amount)
+        .bind(frequency.as_str())
+        .bind(frequency.modifier())
+        .bind(channel_id.to_string())
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        let order = standing_order_from_row(row)?;
+        info!(
+            "User {} created standing order {} of {} euros {} to user {}",
+            user_id,
+            order.order_id,
+            amount,
+            frequency.as_str(),
+            recipient_id
+        );
+        Ok(order)
+    }
+
+    /// Active standing orders of `user_id`, as payer or recipient.
+    pub async fn standing_orders(&self, user_id: u64) -> ModelResult<Vec<StandingOrder>> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let query = format!(
+            "{} WHERE (user_id = $1 OR recipient_id = $1) AND active ORDER BY next_run_at",
+            SELECT_STANDING_ORDER
+        );
+        let rows: Vec<StandingOrderRow> = sqlx::query_as(&query)
+            .bind(user_id.to_string())
+            .fetch_all(&self.db_pool)
+            .await?;
+
+        rows.into_iter().map(standing_order_from_row).collect()
+    }
+
+    /// Cancel a standing order, only the payer can cancel it.
+    pub async fn cancel_standing_order(&self, user_id: u64, order_id: i64) -> ModelResult<()> {
+        let res = sqlx::query(
+            r#"
+            UPDATE standing_orders SET active = FALSE
+            WHERE order_id = $1 AND user_id = $2 AND active
+            "#,
+        )
+        .bind(order_id)
+        .bind(user_id.to_string())
+        .execute(&self.db_pool)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue(format!(
+                "you have no standing order #{}.",
+                order_id
+            )));
+        }
+
+        info!("User {} cancelled standing order {}", user_id, order_id);
+        Ok(())
+    }
+
+    /// Scheduled job: pay the standing orders that are due. Failed payments
+    /// are skipped until the next period. Returns every payment attempted.
+    pub async fn run_standing_orders(&self) -> ModelResult<Vec<StandingOrderRun>> {
+        let query = format!(
+            "{} WHERE active AND next_run_at <= CURRENT_TIMESTAMP",
+            SELECT_STANDING_ORDER
+        );
+        let rows: Vec<StandingOrderRow> = sqlx::query_as(&query).fetch_all(&self.db_pool).await?;
+
+        let mut runs = Vec::new();
+        for order in rows.into_iter().map(standing_order_from_row) {
+            let order = order?;
+
+            // Claim the period before paying, so an order is never paid twice
+            // for the same period. Periods missed while the bot was offline
+            // are skipped
+            let next_run_at: Option<String> = sqlx::query_scalar(
+                r#"
+                UPDATE standing_orders
+                SET next_run_at = CASE
+                    WHEN datetime(next_run_at, $2) > CURRENT_TIMESTAMP THEN datetime(next_run_at, $2)
+                    ELSE datetime('now', $2)
+                END
+                WHERE order_id = $1 AND active AND next_run_at = $3
+                RETURNING next_run_at
+                "#,
+            )
+            .bind(order.order_id)
+            .bind(order.frequency.modifier())
+            .bind(&order.next_run_at)
+            .fetch_optional(&self.db_pool)
+            .await?;
+            let Some(next_run_at) = next_run_at else {
+                continue;
+            };
+
+            let error = match self
+                .give(order.user_id, order.recipient_id, order.amount)
+                .await
+            {
+                Ok(_) => None,
+                Err(ModelError::UnexpectedError) => return Err(ModelError::UnexpectedError),
+                Err(error) => Some(error),
+            };
+
+            match &error {
+                Some(error) => info!("Standing order {} failed: {}", order.order_id, error),
+                None => info!("Standing order {} paid", order.order_id),
+            }
+            runs.push(StandingOrderRun {
+                order: StandingOrder {
+                    next_run_at,
+                    ..order
+                },
+                error,
+            });
+        }
+
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    async fn make_due(model: &Model, order_id: i64) {
+        sqlx::query(
+            "UPDATE standing_orders SET next_run_at = datetime('now', '-1 hour') WHERE order_id = $1",
+        )
+        .bind(order_id)
+        .execute(&model.db_pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn standing_order_pays_once_per_period() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.create_bank_account(2).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        let order = model
+            .create_standing_order(1, 2, 10f64, OrderFrequency::Daily, 3)
+            .await
+            .unwrap();
+        assert!(model.run_standing_orders().await.unwrap().is_empty());
+
+        make_due(&model, order.order_id).await;
+        let runs = model.run_standing_orders().await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].error.is_none());
+        assert!(model.run_standing_orders().await.unwrap().is_empty());
+        assert_eq!(model.balance(1).await.unwrap(), 90f64);
+        assert_eq!(model.balance(2).await.unwrap(), 10f64);
+    }
+
+    #[tokio::test]
+    async fn concurrent_standing_order_runs_pay_once() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.create_bank_account(2).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        let order = model
+            .create_standing_order(1, 2, 10f64, OrderFrequency::Weekly, 3)
+            .await
+            .unwrap();
+        make_due(&model, order.order_id).await;
+        let (first, second) =
+            tokio::join!(model.run_standing_orders(), model.run_standing_orders());
+        assert_eq!(first.unwrap().len() + second.unwrap().len(), 1);
+        assert_eq!(model.balance(1).await.unwrap(), 90f64);
+        assert_eq!(model.balance(2).await.unwrap(), 10f64);
+    }
+
+    #[tokio::test]
+    async fn cancelled_standing_order_is_not_paid() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        model.create_bank_account(2).await.unwrap();
+        model.bless(1, 100f64).await.unwrap();
+
+        let order = model
+            .create_standing_order(1, 2, 10f64, OrderFrequency::Monthly, 3)
+            .await
+            .unwrap();
+        make_due(&model, order.order_id).await;
+        model
+            .cancel_standing_order(1, order.order_id)
+            .await
+            .unwrap();
+        assert!(model.run_standing_orders().await.unwrap().is_empty());
+        assert_eq!(model.balance(1).await.unwrap(), 100f64);
+    }
+}
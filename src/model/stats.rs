@@ -0,0 +1,150 @@
+use sqlx::SqliteConnection;
+
+use super::{Model, ModelError, ModelResult};
+
+/// Gambling record of a user over every settled bet. Bets paid back exactly
+/// count as neither a win nor a loss.
+pub struct GambleStats {
+    pub bets: i64,
+    pub wagered: f64,
+    /// Euros won minus euros lost.
+    pub net: f64,
+    pub wins: i64,
+    pub losses: i64,
+    pub biggest_win: f64,
+    pub biggest_loss: f64,
+    /// Consecutive wins if positive, consecutive losses if negative.
+    pub streak: i64,
+}
+
+impl GambleStats {
+    /// Share of bets won, between 0 and 1.
+    pub fn win_rate(&self) -> f64 {
+        match self.bets {
+            0 => 0f64,
+            bets => self.wins as f64 / bets as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GambleRanking {
+    Luckiest,
+    Unluckiest,
+}
+
+type StatsRow = (i64, f64, f64, i64, i64, f64, f64);
+
+/// Record a settled bet of `bet` euros that paid back `payout` euros,
+/// including the bet.
+pub(super) async fn insert_gamble_outcome(
+    conn: &mut SqliteConnection,
+    user_id: u64,
+    game: &str,
+    bet: f64,
+    payout: f64,
+) -> ModelResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO gamble_outcomes (user_id, game, bet, payout)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(user_id.to_string())
+    .bind(game)
+    .bind(bet)
+    .bind(payout)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+impl Model {
+    pub async fn gamble_stats(&self, user_id: u64) -> ModelResult<GambleStats> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+
+        let (bets, wagered, net, wins, losses, biggest_win, biggest_loss): StatsRow =
+            sqlx::query_as(
+                r#"
+                SELECT
+                    COUNT(*),
+                    COALESCE(SUM(bet), 0.0),
+                    COALESCE(SUM(payout - bet), 0.0),
+                    COUNT(CASE WHEN payout > bet THEN 1 END),
+                    COUNT(CASE WHEN payout < bet THEN 1 END),
+                    COALESCE(MAX(payout - bet), 0.0),
+                    COALESCE(MIN(payout - bet), 0.0)
+                FROM gamble_outcomes
+                WHERE user_id = $1
+                "#,
+            )
+            .bind(user_id.to_string())
+            .fetch_one(&self.db_pool)
+            .await?;
+
+        // Streak is counted from the latest bet, skipping bets paid back
+        let results: Vec<bool> = sqlx::query_scalar(
+            r#"
+            SELECT payout > bet
+            FROM gamble_outcomes
+            WHERE user_id = $1 AND payout <> bet
+            ORDER BY outcome_id DESC
+            "#,
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.db_pool)
+        .await?;
+        let streak = match results.first() {
+            Some(&won) => {
+                let length = results.iter().take_while(|result| **result == won).count() as i64;
+                if won { length } else { -length }
+            }
+            None => 0,
+        };
+
+        Ok(GambleStats {
+            bets,
+            wagered,
+            net,
+            wins,
+            losses,
+            biggest_win: biggest_win.max(0f64),
+            biggest_loss: (-biggest_loss).max(0f64),
+            streak,
+        })
+    }
+
+    /// Top `limit` users by net gambling result, as (user_id, net, wagered).
+    pub async fn gamble_leaderboard(
+        &self,
+        ranking: GambleRanking,
+        limit: u32,
+    ) -> ModelResult<Vec<(u64, f64, f64)>> {
+        let order = match ranking {
+            GambleRanking::Luckiest => "DESC",
+            GambleRanking::Unluckiest => "ASC",
+        };
+        let rows: Vec<(String, f64, f64)> = sqlx::query_as(&format!(
+            r#"
+            SELECT user_id, SUM(payout - bet) AS net, SUM(bet)
+            FROM gamble_outcomes
+            GROUP BY user_id
+            ORDER BY net {}
+            LIMIT $1
+            "#,
+            order
+        ))
+        .bind(limit)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(user_id, net, wagered)| {
+                let user_id = user_id.parse().map_err(|_| ModelError::UnexpectedError)?;
+                Ok((user_id, net, wagered))
+            })
+            .collect()
+    }
+}
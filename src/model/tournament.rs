@@ -0,0 +1,451 @@
+use std::collections::HashMap;
+
+use tracing::{info, warn};
+
+use super::{Account, Model, ModelError, ModelResult, deposit};
+
+pub struct Tournament {
+    pub tournament_id: i64,
+    pub name: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub starting_capital: f64,
+    pub prizes: Vec<f64>,
+    pub channel_id: u64,
+    pub finished: bool,
+}
+
+/// Position of a participant, by account value in euros and return over the
+/// starting capital.
+pub struct Standing {
+    pub user_id: u64,
+    pub value: f64,
+    pub return_pct: f64,
+}
+
+/// A tournament that ended and the prizes paid to its winners.
+pub struct FinishedTournament {
+    pub tournament: Tournament,
+    pub payouts: Vec<(u64, f64)>,
+}
+
+type TournamentRow = (i64, String, String, String, f64, String, String, bool);
+
+fn tournament_from_row(row: TournamentRow) -> ModelResult<Tournament> {
+    let (tournament_id, name, start_time, end_time, starting_capital, prizes, channel_id, finished) =
+        row;
+    Ok(Tournament {
+        tournament_id,
+        name,
+        start_time,
+        end_time,
+        starting_capital,
+        prizes: prizes
+            .split(',')
+            .filter(|prize| !prize.is_empty())
+            .map(|prize| prize.parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        channel_id: channel_id
+            .parse::<u64>()
+            .map_err(|_| ModelError::UnexpectedError)?,
+        finished,
+    })
+}
+
+impl Model {
+    /// ADMIN: Create a tournament. `start_time` and `end_time` are UTC
+    /// datetimes (ie: 2025-01-31 18:00), `prizes` are paid by rank.
+    pub async fn create_tournament(
+        &self,
+        name: &str,
+        start_time: &str,
+        end_time: &str,
+        starting_capital: f64,
+        prizes: &[f64],
+        channel_id: u64,
+    ) -> ModelResult<i64> {
+        if starting_capital <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'capital' must be positive.".into(),
+            ));
+        }
+        if prizes.iter().any(|prize| *prize < 0f64) {
+            return Err(ModelError::InvalidValue(
+                "'prizes' can't be negative.".into(),
+            ));
+        }
+
+        // Normalize datetimes to the format used by CURRENT_TIMESTAMP so
+        // they can be compared.
+        let (start_time, end_time): (Option<String>, Option<String>) =
+            sqlx::query_as(r#"SELECT datetime($1), datetime($2)"#)
+                .bind(start_time)
+                .bind(end_time)
+                .fetch_one(&self.db_pool)
+                .await?;
+        let (Some(start_time), Some(end_time)) = (start_time, end_time) else {
+            return Err(ModelError::InvalidValue(
+                "dates must be formatted as YYYY-MM-DD HH:MM.".into(),
+            ));
+        };
+        if end_time <= start_time {
+            return Err(ModelError::InvalidValue(
+                "'end' must be after 'start'.".into(),
+            ));
+        }
+
+        let prizes = prizes
+            .iter()
+            .map(|prize| prize.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let tournament_id = sqlx::query_scalar(
+            r#"
+            INSERT INTO tournaments (name, start_time, end_time, starting_capital, prizes, channel_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING tournament_id
+            "#,
+        )
+        .bind(name)
+        .bind(start_time)
+        .bind(end_time)
+        .bind(starting_capital)
+        .bind(prizes)
+        .bind(channel_id.to_string())
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(tournament_id)
+    }
+
+    pub async fn tournament(&self, tournament_id: i64) -> ModelResult<Tournament> {
+        let row: Option<TournamentRow> = sqlx::query_as(
+            r#"
+            SELECT tournament_id, name, start_time, end_time, starting_capital, prizes, channel_id, finished
+            FROM tournaments
+            WHERE tournament_id = $1
+            "#,
+        )
+        .bind(tournament_id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        tournament_from_row(row.ok_or(ModelError::InvalidValue(
+            "tournament does not exist.".into(),
+        ))?)
+    }
+
+    /// Tournaments that did not finish yet.
+    pub async fn tournaments(&self) -> ModelResult<Vec<Tournament>> {
+        let rows: Vec<TournamentRow> = sqlx::query_as(
+            r#"
+            SELECT tournament_id, name, start_time, end_time, starting_capital, prizes, channel_id, finished
+            FROM tournaments
+            WHERE NOT finished
+            ORDER BY start_time
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        rows.into_iter().map(tournament_from_row).collect()
+    }
+
+    /// Join a tournament that did not end yet, creating an isolated account
+    /// with the starting capital.
+    pub async fn join_tournament(&self, tournament_id: i64, user_id: u64) -> ModelResult<()> {
+        // Check if user account exists
+        self.balance(user_id).await?;
+        self.check_trading_allowed(user_id).await?;
+
+        let has_ended: Option<bool> = sqlx::query_scalar(
+            r#"
+            SELECT finished OR end_time <= CURRENT_TIMESTAMP
+            FROM tournaments
+            WHERE tournament_id = $1
+            "#,
+        )
+        .bind(tournament_id)
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        match has_ended {
+            None => {
+                return Err(ModelError::InvalidValue(
+                    "tournament does not exist.".into(),
+                ));
+            }
+            Some(true) => {
+                return Err(ModelError::InvalidValue(
+                    "tournament has already ended.".into(),
+                ));
+            }
+            Some(false) => {}
+        }
+
+        let res = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO tournament_accounts (tournament_id, user_id, balance)
+            SELECT tournament_id, $2, starting_capital
+            FROM tournaments
+            WHERE tournament_id = $1
+            "#,
+        )
+        .bind(tournament_id)
+        .bind(user_id.to_string())
+        .execute(&self.db_pool)
+        .await?;
+
+        if res.rows_affected() == 0 {
+            return Err(ModelError::InvalidValue(
+                "you already joined this tournament.".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Tournament account of a participant, only available while the
+    /// tournament is running.
+    async fn active_tournament_account(
+        &self,
+        tournament_id: i64,
+        user_id: u64,
+    ) -> ModelResult<Account> {
+        // Tournament prizes are real euros, so defaulted users sit them out
+        self.check_trading_allowed(user_id).await?;
+
+        let status: Option<(bool, bool, bool)> = sqlx::query_as(
+            r#"
+            SELECT
+                start_time <= CURRENT_TIMESTAMP,
+                NOT finished AND end_time > CURRENT_TIMESTAMP,
+                EXISTS (
+                    SELECT 1 FROM tournament_accounts a
+                    WHERE a.tournament_id = t.tournament_id AND a.user_id = $2
+                )
+            FROM tournaments t
+            WHERE tournament_id = $1
+            "#,
+        )
+        .bind(tournament_id)
+        .bind(user_id.to_string())
+        .fetch_optional(&self.db_pool)
+        .await?;
+
+        match status {
+            None => Err(ModelError::InvalidValue(
+                "tournament does not exist.".into(),
+            )),
+            Some((false, _, _)) => Err(ModelError::InvalidValue(
+                "tournament has not started yet.".into(),
+            )),
+            Some((_, false, _)) => Err(ModelError::InvalidValue(
+                "tournament has already ended.".into(),
+            )),
+            Some((_, _, false)) => Err(ModelError::InvalidValue(
+                "you did not join this tournament.".into(),
+            )),
+            Some((true, true, true)) => Ok(Account::Tournament {
+                tournament_id,
+                user_id,
+            }),
+        }
+    }
+
+    async fn check_tournament_coin(&self, coin_symbol: &str) -> ModelResult<()> {
+        // Paper money must not move the real server token pools.
+        if self.server_token(coin_symbol).await?.is_some() {
+            return Err(ModelError::InvalidValue(
+                "server tokens can't be traded in tournaments.".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn tournament_buy(
+        &self,
+        tournament_id: i64,
+        user_id: u64,
+        coin_symbol: &str,
+        euro_amount: f64,
+    ) -> ModelResult<(f64, f64)> {
+        let account = self
+            .active_tournament_account(tournament_id, user_id)
+            .await?;
+        self.check_tournament_coin(coin_symbol).await?;
+        self.trade_buy(account, coin_symbol, euro_amount).await
+    }
+
+    pub async fn tournament_sell(
+        &self,
+        tournament_id: i64,
+        user_id: u64,
+        coin_symbol: &str,
+        euro_amount: Option<f64>,
+    ) -> ModelResult<(f64, f64)> {
+        let account = self
+            .active_tournament_account(tournament_id, user_id)
+            .await?;
+        self.check_tournament_coin(coin_symbol).await?;
+        self.trade_sell(account, coin_symbol, euro_amount).await
+    }
+
+    pub async fn tournament_portfolio(
+        &self,
+        tournament_id: i64,
+        user_id: u64,
+    ) -> ModelResult<(f64, Vec<(String, f64, f64)>)> {
+        let account = Account::Tournament {
+            tournament_id,
+            user_id,
+        };
+        let balance = {
+            let mut conn = self.db_pool.acquire().await?;
+            account
+                .balance(&mut conn)
+                .await
+                .map_err(|_| ModelError::InvalidValue("you did not join this tournament.".into()))?
+        };
+
+        Ok((balance, self.account_portfolio(account).await?))
+    }
+
+    /// Tournament leaderboard by return percentage. Running tournaments are
+    /// valued at current prices, finished ones at their final value.
+    pub async fn tournament_standings(
+        &self,
+        tournament_id: i64,
+    ) -> ModelResult<(Tournament, Vec<Standing>)> {
+        let tournament = self.tournament(tournament_id).await?;
+
+        let accounts: Vec<(String, f64, Option<f64>)> = sqlx::query_as(
+            r#"
+            SELECT user_id, balance, final_value
+            FROM tournament_accounts
+            WHERE tournament_id = $1
+            "#,
+        )
+        .bind(tournament_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        // Fetch each coin price only once.
+        let mut prices: HashMap<String, f64> = HashMap::new();
+        let mut standings = Vec::new();
+        for (user_id, balance, final_value) in accounts {
+            let user_id = user_id
+                .parse::<u64>()
+                .map_err(|_| ModelError::UnexpectedError)?;
+
+            let value = match final_value {
+                Some(final_value) => final_value,
+                None => {
+                    let holdings = {
+                        let mut conn = self.db_pool.acquire().await?;
+                        Account::Tournament {
+                            tournament_id,
+                            user_id,
+                        }
+                        .holdings(&mut conn)
+                        .await?
+                    };
+
+                    let mut value = balance;
+                    for (coin_symbol, total_amount) in holdings {
+                        let price = match prices.get(&coin_symbol) {
+                            Some(price) => *price,
+                            None => {
+                                let price = self.coin_info(&coin_symbol).await?.current_price;
+                                prices.insert(coin_symbol, price);
+                                price
+                            }
+                        };
+                        value += total_amount * price;
+                    }
+                    value
+                }
+            };
+
+            standings.push(Standing {
+                user_id,
+                value,
+                return_pct: (value / tournament.starting_capital - 1f64) * 100f64,
+            });
+        }
+        standings.sort_by(|a, b| b.return_pct.total_cmp(&a.return_pct));
+
+        Ok((tournament, standings))
+    }
+
+    /// Scheduled job: settle tournaments that reached their end time, paying
+    /// prizes into the winners' bank balance.
+    pub async fn finish_tournaments(&self) -> ModelResult<Vec<FinishedTournament>> {
+        let tournament_ids: Vec<i64> = sqlx::query_scalar(
+            r#"
+            SELECT tournament_id
+            FROM tournaments
+            WHERE NOT finished AND end_time <= CURRENT_TIMESTAMP
+            "#,
+        )
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let mut finished_tournaments = Vec::new();
+        for tournament_id in tournament_ids {
+            // Prices are fetched before the db transaction is open.
+            let (tournament, standings) = match self.tournament_standings(tournament_id).await {
+                Ok(tournament_standings) => tournament_standings,
+                Err(error) => {
+                    warn!("Could not value tournament {}: {}", tournament_id, error);
+                    continue;
+                }
+            };
+
+            let mut tx = self.db_pool.begin().await?;
+
+            // Claim the tournament first so it is only paid out once.
+            let claimed = sqlx::query(
+                r#"UPDATE tournaments SET finished = TRUE WHERE tournament_id = $1 AND NOT finished"#,
+            )
+            .bind(tournament_id)
+            .execute(&mut *tx)
+            .await?;
+            if claimed.rows_affected() == 0 {
+                continue;
+            }
+
+            for standing in &standings {
+                sqlx::query(
+                    r#"
+                    UPDATE tournament_accounts SET final_value = $3
+                    WHERE tournament_id = $1 AND user_id = $2
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(standing.user_id.to_string())
+                .bind(standing.value)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            let mut payouts = Vec::new();
+            for (standing, prize) in standings.iter().zip(&tournament.prizes) {
+                deposit(&mut tx, standing.user_id, *prize).await?;
+                payouts.push((standing.user_id, *prize));
+            }
+
+            tx.commit().await?;
+
+            info!("Tournament {} finished", tournament.name);
+            finished_tournaments.push(FinishedTournament {
+                tournament,
+                payouts,
+            });
+        }
+
+        Ok(finished_tournaments)
+    }
+}
@@ -0,0 +1,385 @@
+use sqlx::SqliteConnection;
+use tracing::trace;
+
+use super::{Model, ModelError, ModelResult, deposit, insert_transaction, owned_coins, withdraw};
+
+/// Where a trade is settled: the user's bank account or their isolated
+/// tournament account.
+#[derive(Debug, Clone, Copy)]
+pub enum Account {
+    Bank(u64),
+    Tournament { tournament_id: i64, user_id: u64 },
+}
+
+/// Coins and euros exchanged by a trade at a given price.
+#[derive(Debug, Clone, Copy)]
+pub struct Execution {
+    pub coin_amount: f64,
+    pub euro_amount: f64,
+    pub price: f64,
+}
+
+/// Execute a buy of `euro_amount` euros at `price` with `balance` euros
+/// available.
+pub fn execute_buy(balance: f64, euro_amount: f64, price: f64) -> ModelResult<Execution> {
+    // Check if amount is positive
+    if euro_amount <= 0f64 {
+        return Err(ModelError::InvalidValue(
+            "'amount' must be positive.".into(),
+        ));
+    }
+    if balance < euro_amount {
+        return Err(ModelError::InsuficientFunds);
+    }
+
+    Ok(Execution {
+        coin_amount: euro_amount / price,
+        euro_amount,
+        price,
+    })
+}
+
+/// Execute a sell of `euro_amount` euros worth of coins at `price` with
+/// `owned_coin_amount` coins available.
+pub fn execute_sell(
+    owned_coin_amount: f64,
+    euro_amount: f64,
+    price: f64,
+) -> ModelResult<Execution> {
+    // Check if amount is positive (we are selling a positive ammount)
+    if euro_amount <= 0f64 {
+        return Err(ModelError::InvalidValue(
+            "'amount' must be positive.".into(),
+        ));
+    }
+
+    // Only proceed with transaction if user has at least the amount of
+    // coins intended to sell.
+    let coin_amount = euro_amount / price;
+    if owned_coin_amount < coin_amount {
+        return Err(ModelError::InsuficientCoins);
+    }
+
+    Ok(Execution {
+        coin_amount,
+        euro_amount,
+        price,
+    })
+}
+
+/// Execute a sell of every owned coin at `price`.
+pub fn execute_sell_all(owned_coin_amount: f64, price: f64) -> ModelResult<Execution> {
+    // Only proceed with transaction if user has positive amount of coins
+    // intended to sell.
+    if owned_coin_amount <= 0f64 {
+        return Err(ModelError::InsuficientCoins);
+    }
+
+    Ok(Execution {
+        coin_amount: owned_coin_amount,
+        euro_amount: owned_coin_amount * price,
+        price,
+    })
+}
+
+impl Account {
+    pub async fn balance(&self, conn: &mut SqliteConnection) -> ModelResult<f64> {
+        let balance = match self {
+            Account::Bank(user_id) => {
+                sqlx::query_scalar(r#"SELECT balance FROM bank WHERE user_id = $1"#)
+                    .bind(user_id.to_string())
+                    .fetch_optional(conn)
+                    .await?
+            }
+            Account::Tournament {
+                tournament_id,
+                user_id,
+            } => {
+                sqlx::query_scalar(
+                    r#"
+                    SELECT balance FROM tournament_accounts
+                    WHERE tournament_id = $1 AND user_id = $2
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(user_id.to_string())
+                .fetch_optional(conn)
+                .await?
+            }
+        };
+
+        balance.ok_or(ModelError::BankAccountNotFound(self.user_id()))
+    }
+
+    pub fn user_id(&self) -> u64 {
+        match self {
+            Account::Bank(user_id) => *user_id,
+            Account::Tournament { user_id, .. } => *user_id,
+        }
+    }
+
+    async fn withdraw(&self, conn: &mut SqliteConnection, amount: f64) -> ModelResult<f64> {
+        match self {
+            Account::Bank(user_id) => withdraw(conn, *user_id, amount).await,
+            Account::Tournament {
+                tournament_id,
+                user_id,
+            } => {
+                let new_balance = sqlx::query_scalar(
+                    r#"
+                    UPDATE tournament_accounts SET balance = balance - $3
+                    WHERE tournament_id = $1 AND user_id = $2 AND balance >= $3
+                    RETURNING balance
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(user_id.to_string())
+                .bind(amount)
+                .fetch_optional(conn)
+                .await?;
+
+                new_balance.ok_or(ModelError::InsuficientFunds)
+            }
+        }
+    }
+
+    async fn deposit(&self, conn: &mut SqliteConnection, amount: f64) -> ModelResult<f64> {
+        match self {
+            Account::Bank(user_id) => deposit(conn, *user_id, amount).await,
+            Account::Tournament {
+                tournament_id,
+                user_id,
+            } => {
+                let new_balance = sqlx::query_scalar(
+                    r#"
+                    UPDATE tournament_accounts SET balance = balance + $3
+                    WHERE tournament_id = $1 AND user_id = $2
+                    RETURNING balance
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(user_id.to_string())
+                .bind(amount)
+                .fetch_optional(conn)
+                .await?;
+
+                new_balance.ok_or(ModelError::BankAccountNotFound(*user_id))
+            }
+        }
+    }
+
+    pub async fn owned_coins(
+        &self,
+        conn: &mut SqliteConnection,
+        coin_symbol: &str,
+    ) -> ModelResult<f64> {
+        match self {
+            Account::Bank(user_id) => owned_coins(conn, *user_id, coin_symbol).await,
+            Account::Tournament {
+                tournament_id,
+                user_id,
+            } => {
+                let owned_coin_amount = sqlx::query_scalar(
+                    r#"
+                    SELECT COALESCE(SUM(amount), 0.0)
+                    FROM tournament_transactions
+                    WHERE tournament_id = $1 AND user_id = $2 AND coin_symbol = $3
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(user_id.to_string())
+                .bind(coin_symbol)
+                .fetch_one(conn)
+                .await?;
+
+                Ok(owned_coin_amount)
+            }
+        }
+    }
+
+    /// Coins with a positive amount owned by this account.
+    pub async fn holdings(&self, conn: &mut SqliteConnection) -> ModelResult<Vec<(String, f64)>> {
+        let holdings = match self {
+            Account::Bank(user_id) => {
+                sqlx::query_as(
+                    r#"
+                    SELECT coin_symbol, SUM(amount) AS total_amount
+                    FROM transactions
+                    WHERE user_id = $1
+                    GROUP BY coin_symbol
+                    HAVING total_amount > 0
+                    "#,
+                )
+                .bind(user_id.to_string())
+                .fetch_all(conn)
+                .await?
+            }
+            Account::Tournament {
+                tournament_id,
+                user_id,
+            } => {
+                sqlx::query_as(
+                    r#"
+                    SELECT coin_symbol, SUM(amount) AS total_amount
+                    FROM tournament_transactions
+                    WHERE tournament_id = $1 AND user_id = $2
+                    GROUP BY coin_symbol
+                    HAVING total_amount > 0
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(user_id.to_string())
+                .fetch_all(conn)
+                .await?
+            }
+        };
+
+        Ok(holdings)
+    }
+
+    async fn insert_transaction(
+        &self,
+        conn: &mut SqliteConnection,
+        coin_symbol: &str,
+        amount: f64,
+        price: f64,
+    ) -> ModelResult<()> {
+        match self {
+            Account::Bank(user_id) => {
+                insert_transaction(conn, *user_id, coin_symbol, amount, price).await
+            }
+            Account::Tournament {
+                tournament_id,
+                user_id,
+            } => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO tournament_transactions (tournament_id, user_id, coin_symbol, amount, price)
+                    VALUES ($1, $2, $3, $4, $5)
+                    "#,
+                )
+                .bind(tournament_id)
+                .bind(user_id.to_string())
+                .bind(coin_symbol)
+                .bind(amount)
+                .bind(price)
+                .execute(conn)
+                .await?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Model {
+    /// Buy `euro_amount` euros of a coin at the current price, returns
+    /// amount of coins bought and price.
+    pub(super) async fn trade_buy(
+        &self,
+        account: Account,
+        coin_symbol: &str,
+        euro_amount: f64,
+    ) -> ModelResult<(f64, f64)> {
+        // Check if amount is positive before requesting the price.
+        if euro_amount <= 0f64 {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        // Get current coin price. Indirectly also checks if symbol is valid.
+        let coin_info = self.coin_info(coin_symbol).await?;
+        let coin_symbol = coin_symbol.to_uppercase();
+
+        let mut tx = self.db_pool.begin().await?;
+
+        let balance = account.balance(&mut tx).await?;
+        let execution = execute_buy(balance, euro_amount, coin_info.current_price)?;
+
+        trace!(
+            "Creating transaction of {} {}",
+            execution.coin_amount, coin_symbol
+        );
+        account
+            .insert_transaction(
+                &mut tx,
+                &coin_symbol,
+                execution.coin_amount,
+                execution.price,
+            )
+            .await?;
+        account.withdraw(&mut tx, execution.euro_amount).await?;
+
+        tx.commit().await?;
+
+        Ok((execution.coin_amount, execution.price))
+    }
+
+    /// Sell `euro_amount` euros of a coin at the current price, returns
+    /// amount of coins sold and price.
+    pub(super) async fn trade_sell(
+        &self,
+        account: Account,
+        coin_symbol: &str,
+        euro_amount: Option<f64>,
+    ) -> ModelResult<(f64, f64)> {
+        // Check if amount is positive before requesting the price.
+        if euro_amount.is_some_and(|euro_amount| euro_amount <= 0f64) {
+            return Err(ModelError::InvalidValue(
+                "'amount' must be positive.".into(),
+            ));
+        }
+
+        // Get current coin price. Indirectly also checks if symbol is valid.
+        let coin_info = self.coin_info(coin_symbol).await?;
+        let coin_symbol = coin_symbol.to_uppercase();
+
+        let mut tx = self.db_pool.begin().await?;
+
+        let owned_coin_amount = account.owned_coins(&mut tx, &coin_symbol).await?;
+        let execution = match euro_amount {
+            Some(euro_amount) => {
+                execute_sell(owned_coin_amount, euro_amount, coin_info.current_price)?
+            }
+            None => execute_sell_all(owned_coin_amount, coin_info.current_price)?,
+        };
+
+        trace!(
+            "Creating transaction of {} {}",
+            -execution.coin_amount, coin_symbol
+        );
+        account
+            .insert_transaction(
+                &mut tx,
+                &coin_symbol,
+                -execution.coin_amount,
+                execution.price,
+            )
+            .await?;
+        account.deposit(&mut tx, execution.euro_amount).await?;
+
+        tx.commit().await?;
+
+        Ok((execution.coin_amount, execution.price))
+    }
+
+    /// Coins owned by an account with their current value in euros.
+    pub(super) async fn account_portfolio(
+        &self,
+        account: Account,
+    ) -> ModelResult<Vec<(String, f64, f64)>> {
+        let holdings = {
+            let mut conn = self.db_pool.acquire().await?;
+            account.holdings(&mut conn).await?
+        };
+
+        let mut portfolio_data = Vec::new();
+        for (coin_symbol, total_amount) in holdings {
+            let current_price = self.coin_info(&coin_symbol).await?.current_price;
+            portfolio_data.push((coin_symbol, total_amount, total_amount * current_price));
+        }
+
+        Ok(portfolio_data)
+    }
+}
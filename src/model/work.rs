@@ -0,0 +1,210 @@
+use rand::Rng;
+use tracing::info;
+
+use super::{Model, ModelError, ModelResult, insert_ledger_entry};
+use crate::config::{Job, WorkConfig};
+
+/// A worked shift and the job level it was worked at.
+pub struct WorkShift {
+    pub job: String,
+    pub pay: f64,
+    pub level: u32,
+    /// Whether this shift reached a new job level.
+    pub leveled_up: bool,
+}
+
+pub enum WorkStatus {
+    Worked(WorkShift),
+    /// Still resting from the last shift.
+    OnCooldown,
+}
+
+/// Result of a shift and the seconds until the next one is available.
+pub struct WorkResult {
+    pub status: WorkStatus,
+    pub next_work_secs: u64,
+}
+
+/// Job level reached after working `shifts` shifts, starting at 1.
+pub fn work_level(config: &WorkConfig, shifts: u32) -> u32 {
+    1 + shifts / config.shifts_per_level.max(1)
+}
+
+impl Model {
+    /// Shifts worked by `user_id` and their job level.
+    pub async fn work_level(&self, user_id: u64) -> ModelResult<(u32, u32)> {
+        let shifts: Option<u32> =
+            sqlx::query_scalar(r#"SELECT work_shifts FROM bank WHERE user_id = $1"#)
+                .bind(user_id.to_string())
+                .fetch_optional(&self.db_pool)
+                .await?;
+        let Some(shifts) = shifts else {
+            return Err(ModelError::BankAccountNotFound(user_id));
+        };
+
+        Ok((shifts, work_level(&self.config.work, shifts)))
+    }
+
+    /// Every configured job, lowest level first.
+    pub fn jobs(&self) -> Vec<&Job> {
+        let mut jobs = self.config.work.jobs.iter().collect::<Vec<_>>();
+        jobs.sort_by_key(|job| job.level);
+        jobs
+    }
+
+    /// Work a shift of `job`, or of the highest level job unlocked if None.
+    pub async fn work(&self, user_id: u64, job: Option<&str>) -> ModelResult<WorkResult> {
+        let (shifts, level) = self.work_level(user_id).await?;
+        let job = match job {
+            Some(name) => {
+                let name = name.trim();
+                let Some(job) = self
+                    .jobs()
+                    .into_iter()
+                    .find(|job| job.name.eq_ignore_ascii_case(name))
+                else {
+                    return Err(ModelError::InvalidValue(format!(
+                        "there is no job named '{}'.",
+                        name
+                    )));
+                };
+                if job.level > level {
+                    return Err(ModelError::InvalidValue(format!(
+                        "'{}' unlocks at job level {}, you are level {}.",
+                        job.name, job.level, level
+                    )));
+                }
+                job
+            }
+            None => match self.jobs().into_iter().rfind(|job| job.level <= level) {
+                Some(job) => job,
+                None => {
+                    return Err(ModelError::InvalidValue(
+                        "there are no jobs available.".into(),
+                    ));
+                }
+            },
+        };
+
+        let pay = if job.max_pay > job.min_pay {
+            rand::rng().random_range(job.min_pay..=job.max_pay)
+        } else {
+            job.min_pay
+        };
+        let pay = (pay * 100f64).round() / 100f64;
+
+        // Only the first of concurrent shifts updates the row
+        let mut tx = self.db_pool.begin().await?;
+        let res = sqlx::query(
+            r#"
+            UPDATE bank
+            SET work_shifts = work_shifts + 1,
+                work_available_at = datetime('now', $2),
+                balance = balance + $3
+            WHERE user_id = $1
+            AND (work_available_at IS NULL OR work_available_at <= CURRENT_TIMESTAMP)
+            "#,
+        )
+        .bind(user_id.to_string())
+        .bind(format!("+{} seconds", job.cooldown_secs))
+        .bind(pay)
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            let next_work_secs: i64 = sqlx::query_scalar(
+                r#"
+                SELECT CAST(MAX(0, strftime('%s', work_available_at) - strftime('%s', 'now')) AS INTEGER)
+                FROM bank
+                WHERE user_id = $1
+                "#,
+            )
+            .bind(user_id.to_string())
+            .fetch_one(&mut *tx)
+            .await?;
+            return Ok(WorkResult {
+                status: WorkStatus::OnCooldown,
+                next_work_secs: next_work_secs as u64,
+            });
+        }
+        insert_ledger_entry(
+            &mut tx,
+            user_id,
+            pay,
+            None,
+            &format!("Worked as {}", job.name),
+        )
+        .await?;
+        tx.commit().await?;
+
+        let new_level = work_level(&self.config.work, shifts + 1);
+        info!("User {} worked as {} for {} euros", user_id, job.name, pay);
+        Ok(WorkResult {
+            status: WorkStatus::Worked(WorkShift {
+                job: job.name.clone(),
+                pay,
+                level: new_level,
+                leveled_up: new_level > level,
+            }),
+            next_work_secs: job.cooldown_secs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn work_level_per_shifts() {
+        let config = WorkConfig::default();
+        assert_eq!(work_level(&config, 0), 1);
+        assert_eq!(work_level(&config, 9), 1);
+        assert_eq!(work_level(&config, 10), 2);
+        assert_eq!(work_level(&config, 65), 7);
+
+        // A level every shift rather than dividing by zero
+        let config = WorkConfig {
+            shifts_per_level: 0,
+            jobs: Vec::new(),
+        };
+        assert_eq!(work_level(&config, 0), 1);
+        assert_eq!(work_level(&config, 3), 4);
+    }
+
+    #[tokio::test]
+    async fn work_levels_up_and_unlocks_jobs() {
+        let model = Model::in_memory(Config::default()).await.unwrap();
+        model.create_bank_account(1).await.unwrap();
+        assert_eq!(model.work_level(1).await.unwrap(), (0, 1));
+        assert!(model.work_level(2).await.is_err());
+        assert!(model.work(1, Some("Barista")).await.is_err());
+
+        sqlx::query("UPDATE bank SET work_shifts = 9 WHERE user_id = '1'")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+        let result = model.work(1, None).await.unwrap();
+        let WorkStatus::Worked(shift) = result.status else {
+            panic!("expected a worked shift");
+        };
+        assert_eq!(shift.job, "Cashier");
+        assert_eq!(shift.level, 2);
+        assert!(shift.leveled_up);
+        assert_eq!(model.work_level(1).await.unwrap(), (10, 2));
+        assert!(matches!(
+            model.work(1, None).await.unwrap().status,
+            WorkStatus::OnCooldown
+        ));
+
+        sqlx::query("UPDATE bank SET work_available_at = NULL WHERE user_id = '1'")
+            .execute(&model.db_pool)
+            .await
+            .unwrap();
+        let WorkStatus::Worked(shift) = model.work(1, None).await.unwrap().status else {
+            panic!("expected a worked shift");
+        };
+        assert_eq!(shift.job, "Barista");
+        assert!(!shift.leveled_up);
+    }
+}
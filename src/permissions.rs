@@ -34,5 +34,5 @@ pub async fn is_admin(ctx: crate::Context<'_>) -> Result<bool, crate::Error> {
 }
 
 pub async fn is_mod(ctx: crate::Context<'_>) -> Result<bool, crate::Error> {
-    has_permission(ctx, Permission::Admin).await
+    has_permission(ctx, Permission::Mod).await
 }
@@ -0,0 +1,325 @@
+use std::{sync::Arc, time::Duration};
+
+use poise::serenity_prelude::{
+    ChannelId, Error as SerenityError, GuildId, Http, RoleId, StatusCode, UserId,
+};
+use tracing::{error, info};
+
+use crate::model::{DebtStatus, LoanStatus, Model};
+
+/// How often scheduled jobs are checked.
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Runs the periodic jobs of the bot (tournament payouts, ...) until the
+/// process exits.
+pub async fn run(model: Arc<Model>, http: Arc<Http>) {
+    let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = finish_tournaments(&model, &http).await {
+            error!("Error while finishing tournaments: {:?}", error);
+        }
+        if let Err(error) = draw_lotteries(&model, &http).await {
+            error!("Error while drawing lotteries: {:?}", error);
+        }
+        if let Err(error) = resolve_predictions(&model, &http).await {
+            error!("Error while resolving predictions: {:?}", error);
+        }
+        if let Err(error) = accrue_interest(&model, &http).await {
+            error!("Error while accruing interest: {:?}", error);
+        }
+        if let Err(error) = collect_debts(&model, &http).await {
+            error!("Error while collecting debts: {:?}", error);
+        }
+        if let Err(error) = run_standing_orders(&model, &http).await {
+            error!("Error while running standing orders: {:?}", error);
+        }
+        if let Err(error) = expire_roles(&model, &http).await {
+            error!("Error while expiring roles: {:?}", error);
+        }
+        if let Err(error) = expire_duels(&model).await {
+            error!("Error while expiring duels: {:?}", error);
+        }
+        if let Err(error) = expire_lend_offers(&model).await {
+            error!("Error while expiring lend offers: {:?}", error);
+        }
+    }
+}
+
+/// Refund the bets of games that were still in progress when the bot
+/// stopped. Must run before any command is handled.
+pub async fn refund_interrupted_games(model: &Model) {
+    match model.refund_blackjack_games().await {
+        Ok(refunds) => {
+            for (user_id, bet) in refunds {
+                info!(
+                    "Refunded {} euros of an interrupted blackjack game to {}",
+                    bet, user_id
+                );
+            }
+        }
+        Err(error) => error!("Error while refunding blackjack games: {:?}", error),
+    }
+    match model.refund_roulette_wagers().await {
+        Ok(wagers) => {
+            for wager in wagers {
+                info!(
+                    "Refunded {} euros of an interrupted roulette round to {}",
+                    wager.amount, wager.user_id
+                );
+            }
+        }
+        Err(error) => error!("Error while refunding roulette wagers: {:?}", error),
+    }
+    match model.refund_crash_bets().await {
+        Ok(bets) => {
+            for bet in bets {
+                info!(
+                    "Settled {} euros of an interrupted crash round to {}",
+                    bet.cashed_out.map_or(bet.amount, |_| bet.payout()),
+                    bet.user_id
+                );
+            }
+        }
+        Err(error) => error!("Error while settling crash bets: {:?}", error),
+    }
+    match model.refund_pending_duels().await {
+        Ok(duels) => {
+            for duel in duels {
+                info!(
+                    "Refunded {} euros of an interrupted duel #{} to {}",
+                    duel.amount, duel.duel_id, duel.challenger_id
+                );
+            }
+        }
+        Err(error) => error!("Error while refunding duels: {:?}", error),
+    }
+    match model.refund_pending_lend_offers().await {
+        Ok(offers) => {
+            for offer in offers {
+                info!(
+                    "Refunded {} euros of interrupted lend offer #{} to {}",
+                    offer.amount, offer.offer_id, offer.lender_id
+                );
+            }
+        }
+        Err(error) => error!("Error while refunding lend offers: {:?}", error),
+    }
+    match model.expire_pending_payment_requests().await {
+        Ok(requests) => {
+            for request in requests {
+                info!(
+                    "Expired interrupted payment request #{} of {} euros",
+                    request.request_id, request.amount
+                );
+            }
+        }
+        Err(error) => error!("Error while expiring payment requests: {:?}", error),
+    }
+}
+
+async fn finish_tournaments(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for finished in model.finish_tournaments().await? {
+        let mut output = format!("Tournament **{}** has ended!\n", finished.tournament.name);
+        for (user_id, prize) in &finished.payouts {
+            output.push_str(format!("- <@{}> won `{}` euros\n", user_id, prize).as_str());
+        }
+        ChannelId::new(finished.tournament.channel_id)
+            .say(http, output)
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn draw_lotteries(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for draw in model.draw_lotteries().await? {
+        let Some(channel_id) = model.lottery_channel() else {
+            continue;
+        };
+        ChannelId::new(channel_id)
+            .say(
+                http,
+                format!(
+                    "🎟️ **Lottery Draw #{}**\n<@{}> won the pot of `{}` euros with ticket {} of {}!\n-# Roll #{}",
+                    draw.draw_id,
+                    draw.winner_id.unwrap_or_default(),
+                    draw.prize.unwrap_or_default(),
+                    draw.winning_ticket.unwrap_or_default(),
+                    draw.tickets,
+                    draw.roll_id.unwrap_or_default()
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn resolve_predictions(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for prediction in model.resolve_predictions().await? {
+        let payout = prediction.payout.unwrap_or_default();
+        let result = if payout > prediction.amount {
+            format!("won `{}` euros :euro:", payout)
+        } else if payout > 0f64 {
+            "got the bet back, the price didn't move".to_string()
+        } else {
+            format!("lost `{}` euros", prediction.amount)
+        };
+        ChannelId::new(prediction.channel_id)
+            .say(
+                http,
+                format!(
+                    "<@{}> **{}** {} prediction: `{}` → `{}` euros, you {}",
+                    prediction.user_id,
+                    prediction.coin_symbol,
+                    prediction.direction.as_str(),
+                    prediction.entry_price,
+                    prediction.exit_price.unwrap_or_default(),
+                    result
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn accrue_interest(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for loan in model.accrue_interest().await? {
+        let Some(channel_id) = model.interest_channel() else {
+            continue;
+        };
+        let output = match loan.status {
+            LoanStatus::Defaulted => format!(
+                "<@{}> defaulted on loan #{}, `{}` euros are still owed. Trading is frozen until it is repaid.",
+                loan.user_id, loan.loan_id, loan.owed
+            ),
+            _ => format!(
+                "<@{}> loan #{} was collected from their balance",
+                loan.user_id, loan.loan_id
+            ),
+        };
+        ChannelId::new(channel_id).say(http, output).await?;
+    }
+
+    Ok(())
+}
+
+async fn collect_debts(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for debt in model.debt_reminders().await? {
+        ChannelId::new(debt.channel_id)
+            .say(
+                http,
+                format!(
+                    "<@{}> reminder: debt #{} of `{}` euros to <@{}> is due at {} UTC",
+                    debt.borrower_id, debt.debt_id, debt.owed, debt.lender_id, debt.due_at
+                ),
+            )
+            .await?;
+    }
+
+    for collection in model.collect_debts().await? {
+        let debt = collection.debt;
+        let output = match debt.status {
+            DebtStatus::Repaid => format!(
+                "<@{}> debt #{} to <@{}> was collected from their balance",
+                debt.borrower_id, debt.debt_id, debt.lender_id
+            ),
+            _ => format!(
+                "<@{}> debt #{} to <@{}> is overdue, `{}` euros were collected and `{}` euros are still owed",
+                debt.borrower_id, debt.debt_id, debt.lender_id, collection.collected, debt.owed
+            ),
+        };
+        ChannelId::new(debt.channel_id).say(http, output).await?;
+    }
+
+    Ok(())
+}
+
+async fn run_standing_orders(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for run in model.run_standing_orders().await? {
+        let Some(error) = run.error else {
+            continue;
+        };
+        let order = run.order;
+        ChannelId::new(order.channel_id)
+            .say(
+                http,
+                format!(
+                    "<@{}> standing order #{} of `{}` euros to <@{}> failed: {}\n-# Next payment at {} UTC",
+                    order.user_id, order.order_id, order.amount, order.recipient_id, error, order.next_run_at
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Remove the roles that expired. Grants whose role couldn't be removed stay
+/// active and are retried on the next tick, the failure is reported once.
+async fn expire_roles(model: &Model, http: &Http) -> anyhow::Result<()> {
+    for grant in model.expired_role_grants().await? {
+        match http
+            .remove_member_role(
+                GuildId::new(grant.guild_id),
+                UserId::new(grant.user_id),
+                RoleId::new(grant.role_id),
+                Some("Shop role expired"),
+            )
+            .await
+        {
+            Ok(()) => model.end_role_grant(grant.grant_id).await?,
+            // The member left or the role was deleted, there is nothing to remove
+            Err(SerenityError::Http(error))
+                if error.status_code() == Some(StatusCode::NOT_FOUND) =>
+            {
+                model.end_role_grant(grant.grant_id).await?
+            }
+            Err(error) => {
+                error!("Failed to remove role {}: {}", grant.role_id, error);
+                if model.fail_role_grant_removal(grant.grant_id).await? {
+                    ChannelId::new(grant.channel_id)
+                        .say(
+                            http,
+                            format!(
+                                "<@{}> your <@&{}> role expired, but I couldn't remove it. Admins, please check my permissions",
+                                grant.user_id, grant.role_id
+                            ),
+                        )
+                        .await?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Refund duels whose challenge message is gone, the command refunds the
+/// others itself when the timeout is reached.
+async fn expire_duels(model: &Model) -> anyhow::Result<()> {
+    for duel in model.expire_duels().await? {
+        info!(
+            "Refunded {} euros of expired duel #{} to {}",
+            duel.amount, duel.duel_id, duel.challenger_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Refund lend offers whose message is gone, the command refunds the others
+/// itself when the timeout is reached.
+async fn expire_lend_offers(model: &Model) -> anyhow::Result<()> {
+    for offer in model.expire_lend_offers().await? {
+        info!(
+            "Refunded {} euros of expired lend offer #{} to {}",
+            offer.amount, offer.offer_id, offer.lender_id
+        );
+    }
+
+    Ok(())
+}